@@ -16,11 +16,20 @@ use std::f32::consts::PI;
 
 // Audio Constants
 // 256 harmonics fully cover (below the 44.1 kHz Nyquist) every key whose
-// fundamental is ≥ ~86 Hz (down to ~E2); above that range `max_harmonic_for_key`
+// fundamental is ≥ ~86 Hz (down to ~E2); above that range `max_harmonic_for_freq`
 // caps per key anyway. Shared by both Synth and Analysis modes (the per-harmonic
 // param array, the engine's amp/phase grid, and `analyze_and_load`).
 pub const NUM_HARMONICS: usize = 256;
-pub const NUM_KEYS: usize = 88;
+/// One key per MIDI note: key `n` is MIDI note `n` (0 = C-1, 69 = A4, 127 = G9).
+pub const NUM_KEYS: usize = 128;
+
+/// Key of A4, the tuning reference.
+pub const REFERENCE_KEY: usize = 69;
+/// Default frequency (Hz) of [`REFERENCE_KEY`].
+pub const DEFAULT_REFERENCE_PITCH: f32 = 440.0;
+
+/// Key whose waveform the assembled chart previews (A2, 110 Hz at A4 = 440 Hz).
+pub const PREVIEW_KEY: usize = 45;
 
 // Parameter Defaults and Ranges
 pub static NUM_OF_BUCKETS_DEFAULT: usize = 70;
@@ -40,17 +49,19 @@ pub const TWO_PI: f32 = 2.0 * PI;
 pub const SAMPLE_RATE: f64 = 44100.0;
 pub const NYQUIST_FREQUENCY: f64 = SAMPLE_RATE / 2.0;
 
-/// Calculate the maximum usable harmonic number for a given piano key
+/// Equal-tempered fundamental (Hz) of `key` with A4 at `reference_pitch`:
+/// each key is a factor 2^(1/12) above the one below.
+pub fn key_frequency(key: usize, reference_pitch: f64) -> f64 {
+    reference_pitch * 2f64.powf((key as f64 - REFERENCE_KEY as f64) / 12.0)
+}
+
+/// Calculate the maximum usable harmonic number for a fundamental
 /// to prevent aliasing (harmonic frequency must be below Nyquist frequency)
-pub fn max_harmonic_for_key(key: usize) -> usize {
-    if key >= NUM_KEYS {
+pub fn max_harmonic_for_freq(fundamental_freq: f64) -> usize {
+    if fundamental_freq <= 0.0 {
         return 0;
     }
 
-    // Calculate the fundamental frequency for the given key
-    // A0 (key 0) is 27.5 Hz and each key increases by the factor 2^(1/12)
-    let fundamental_freq = 27.5 * 2f64.powf(key as f64 / 12.0);
-
     // Calculate maximum harmonic number that stays below Nyquist frequency
     let max_harmonic = (NYQUIST_FREQUENCY / fundamental_freq).floor() as usize;
 
@@ -58,6 +69,13 @@ pub fn max_harmonic_for_key(key: usize) -> usize {
     max_harmonic.min(NUM_HARMONICS)
 }
 
+/// Scientific pitch name of a key, e.g. `"A0"`, `"C#4"`, `"C8"`
+/// (key = MIDI note, 0 = C-1).
+pub fn key_name(key: usize) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[key % 12], key as i32 / 12 - 1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,7 +83,10 @@ mod tests {
     #[test]
     fn test_audio_constants() {
         assert_eq!(NUM_HARMONICS, 256);
-        assert_eq!(NUM_KEYS, 88);
+        assert_eq!(NUM_KEYS, 128);
+        assert_eq!(key_frequency(REFERENCE_KEY, 440.0), 440.0);
+        assert!((key_frequency(21, 440.0) - 27.5).abs() < 1e-9);
+        assert!((key_frequency(81, 415.0) - 830.0).abs() < 1e-9);
     }
 
     #[test]
@@ -101,22 +122,34 @@ mod tests {
     }
 
     #[test]
-    fn test_max_harmonic_for_key() {
+    fn test_max_harmonic_for_freq() {
         // Test lower keys - should allow many harmonics
-        let low_key_max = max_harmonic_for_key(0); // A0 = 27.5 Hz
+        let low_key_max = max_harmonic_for_freq(27.5); // A0
         assert!(low_key_max > 50, "Low keys should allow many harmonics, got {}", low_key_max);
 
         // Test high keys - should limit harmonics
-        let high_key_max = max_harmonic_for_key(87); // C8 = ~4186 Hz
+        let high_key_max = max_harmonic_for_freq(4186.0); // C8
         assert!(high_key_max < 10, "High keys should limit harmonics to prevent aliasing, got {}", high_key_max);
 
         // Test that higher keys have fewer allowed harmonics
-        let mid_key_max = max_harmonic_for_key(48); // C4 = ~261 Hz
+        let mid_key_max = max_harmonic_for_freq(440.0); // A4
         assert!(mid_key_max < low_key_max, "Higher keys should have fewer allowed harmonics");
         assert!(high_key_max < mid_key_max, "Highest keys should have the fewest allowed harmonics");
 
+        // The top MIDI note still keeps its fundamental
+        assert_eq!(max_harmonic_for_freq(key_frequency(NUM_KEYS - 1, 440.0)), 1);
+
         // Test boundary condition
-        assert_eq!(max_harmonic_for_key(NUM_KEYS), 0, "Invalid key should return 0");
+        assert_eq!(max_harmonic_for_freq(0.0), 0, "No fundamental should return 0");
+    }
+
+    #[test]
+    fn test_key_name() {
+        assert_eq!(key_name(0), "C-1");
+        assert_eq!(key_name(21), "A0");
+        assert_eq!(key_name(61), "C#4");
+        assert_eq!(key_name(108), "C8");
+        assert_eq!(key_name(NUM_KEYS - 1), "G9");
     }
 
     #[test]
@@ -32,6 +32,7 @@
 //! `base_freq` (legacy behaviour).
 
 use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Which way the compute engine is driven.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -257,6 +258,37 @@ pub fn analyze_subtrack(
     num_harmonics: usize,
     max_buckets: usize,
 ) -> AnalysisResult {
+    analyze_subtrack_with_progress(
+        samples,
+        sample_rate,
+        base_freq,
+        contour,
+        num_buckets,
+        num_harmonics,
+        max_buckets,
+        &mut |_, _| {},
+        None,
+    )
+    .expect("analysis without a cancel flag always completes")
+}
+
+/// [`analyze_subtrack`] for a background worker: `progress(done, total)` is
+/// called after every bucket of the DFT pass (and once up front with
+/// `done == 0`), and the analysis bails out with `None` as soon as `cancel` is
+/// raised — the same early-out the key-buffer render takes on
+/// `computation_cancel`.
+#[allow(clippy::too_many_arguments)]
+pub fn analyze_subtrack_with_progress(
+    samples: &[f32],
+    sample_rate: f32,
+    base_freq: f32,
+    contour: &[f32],
+    num_buckets: usize,
+    num_harmonics: usize,
+    max_buckets: usize,
+    progress: &mut dyn FnMut(usize, usize),
+    cancel: Option<&AtomicBool>,
+) -> Option<AnalysisResult> {
     let num_harmonics = num_harmonics.max(1);
     let base_freq = base_freq.max(1.0);
     let nyquist = sample_rate * 0.5;
@@ -271,8 +303,10 @@ pub fn analyze_subtrack(
         specs.iter().map(|s| sample_rate / s.local_freq.max(1.0)).collect();
     let pitch_ratio: Vec<f32> = specs.iter().map(|s| s.local_freq / base_freq).collect();
 
+    progress(0, buckets);
     if len < 2 {
-        return AnalysisResult { amplitude, phase, bucket_periods, pitch_ratio };
+        progress(buckets, buckets);
+        return Some(AnalysisResult { amplitude, phase, bucket_periods, pitch_ratio });
     }
 
     // Hann windows are cached per length: in period-synchronous mode every
@@ -290,6 +324,9 @@ pub fn analyze_subtrack(
     let mut raw_phase = vec![vec![0.0f32; buckets]; num_harmonics]; // ψ (sin convention)
     let mut global_max = 0.0f32;
     for (b, spec) in specs.iter().enumerate() {
+        if cancel.is_some_and(|c| c.load(Ordering::Relaxed)) {
+            return None;
+        }
         let win_len = spec.win_len.min(len);
         let (hann, wsum) = hann_cache.entry(win_len).or_insert_with(|| {
             let h: Vec<f32> = (0..win_len)
@@ -327,6 +364,7 @@ pub fn analyze_subtrack(
                 global_max = amp;
             }
         }
+        progress(b + 1, buckets);
     }
 
     // Grid-relative amplitude gate: quiet but real sustained harmonics survive,
@@ -359,13 +397,81 @@ pub fn analyze_subtrack(
         }
     }
 
-    AnalysisResult { amplitude, phase, bucket_periods, pitch_ratio }
+    Some(AnalysisResult { amplitude, phase, bucket_periods, pitch_ratio })
+}
+
+/// Analyse a wavetable: consecutive single-cycle frames of `frame_len`
+/// samples, one bucket each (a trailing partial frame is dropped). Frames are
+/// already cycle-aligned, so instead of the per-bucket DFT above each frame
+/// gets one real FFT: harmonic `n` is bin `n + 1`, read back with the same
+/// sine convention the resynthesis inverse FFT writes, and keeps its absolute
+/// phase. Pitch ratios are flat. `None` if there is not a single whole frame.
+pub fn analyze_wavetable(samples: &[f32], frame_len: usize, num_harmonics: usize) -> Option<AnalysisResult> {
+    if frame_len < 4 || samples.len() < frame_len {
+        return None;
+    }
+    let num_frames = samples.len() / frame_len;
+    let nyq = frame_len / 2;
+    let harmonics = num_harmonics.min(nyq);
+    let mut planner = realfft::RealFftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(frame_len);
+    let mut input = fft.make_input_vec();
+    let mut spectrum = fft.make_output_vec();
+
+    let mut amplitude = vec![vec![0.0f32; num_frames]; num_harmonics];
+    let mut phase = vec![vec![0.0f32; num_frames]; num_harmonics];
+    for b in 0..num_frames {
+        input.copy_from_slice(&samples[b * frame_len..(b + 1) * frame_len]);
+        fft.process(&mut input, &mut spectrum).ok()?;
+        for h in 0..harmonics {
+            let k = h + 1;
+            let c = spectrum[k];
+            let (amp, ph) = if k == nyq && frame_len % 2 == 0 {
+                // Real-only Nyquist bin: A·sin φ = re / N; keep the sign in φ.
+                let v = c.re / frame_len as f32;
+                (v.abs(), if v < 0.0 { 1.5 * PI } else { 0.5 * PI })
+            } else {
+                // A·sin(2πkt/N + φ) → X_k = (N/2)·A·e^{i(φ − π/2)}.
+                (2.0 * c.norm() / frame_len as f32, c.im.atan2(c.re) + 0.5 * PI)
+            };
+            amplitude[h][b] = amp.clamp(0.0, 1.0);
+            phase[h][b] = if amp > 0.0 { ph.rem_euclid(2.0 * PI) } else { 0.0 };
+        }
+    }
+
+    Some(AnalysisResult {
+        amplitude,
+        phase,
+        bucket_periods: vec![frame_len as f32; num_frames],
+        pitch_ratio: vec![1.0; num_frames],
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn wavetable_frames_read_back_harmonic_amp_and_phase() {
+        let n = 256;
+        // Frame 0: harmonic 1 (bin 1) at 0.5, phase 1.0. Frame 1: harmonic 3 at 0.25.
+        let mut samples: Vec<f32> = (0..n)
+            .map(|t| 0.5 * (2.0 * PI * t as f32 / n as f32 + 1.0).sin())
+            .collect();
+        samples.extend((0..n).map(|t| 0.25 * (2.0 * PI * 3.0 * t as f32 / n as f32).sin()));
+        samples.extend([0.1; 10]); // partial frame, ignored
+
+        let res = analyze_wavetable(&samples, n, 8).unwrap();
+        assert_eq!((res.num_harmonics(), res.num_buckets()), (8, 2));
+        assert!((res.amplitude[0][0] - 0.5).abs() < 1e-4);
+        assert!((res.phase[0][0] - 1.0).abs() < 1e-3);
+        assert!(res.amplitude[2][0] < 1e-4);
+        assert!((res.amplitude[2][1] - 0.25).abs() < 1e-4);
+        assert!(res.phase[2][1].abs() < 1e-3 || (res.phase[2][1] - 2.0 * PI).abs() < 1e-3);
+        assert_eq!(res.pitch_ratio, vec![1.0, 1.0]);
+        assert!(analyze_wavetable(&samples[..n - 1], n, 8).is_none());
+    }
+
     #[test]
     fn execution_mode_roundtrip() {
         assert_eq!(ExecutionMode::from_u8(ExecutionMode::Synth.as_u8()), ExecutionMode::Synth);
@@ -373,6 +479,27 @@ mod tests {
         assert_eq!(ExecutionMode::default(), ExecutionMode::Synth);
     }
 
+    #[test]
+    fn progress_reaches_total_and_cancel_aborts() {
+        let sr = 44100.0;
+        let samples: Vec<f32> = (0..22050)
+            .map(|i| (2.0 * PI * 220.0 * i as f32 / sr).sin())
+            .collect();
+
+        let mut last = (usize::MAX, 0);
+        let res = analyze_subtrack_with_progress(
+            &samples, sr, 220.0, &[], 0, 8, 2000, &mut |d, t| last = (d, t), None,
+        )
+        .expect("not cancelled");
+        assert_eq!(last, (res.num_buckets(), res.num_buckets()));
+
+        let cancel = AtomicBool::new(true);
+        let aborted = analyze_subtrack_with_progress(
+            &samples, sr, 220.0, &[], 0, 8, 2000, &mut |_, _| {}, Some(&cancel),
+        );
+        assert!(aborted.is_none());
+    }
+
     #[test]
     fn pure_sine_lands_in_first_harmonic() {
         let sr = 44100.0;
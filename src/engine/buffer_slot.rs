@@ -0,0 +1,139 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lock-free slot holding an immutable, shared snapshot: one key's rendered
+//! buffer, or the grid streaming voices play from.
+//!
+//! The background thread publishes a fresh `Arc` with a single pointer swap;
+//! the audio thread takes its own reference with an atomic increment, so
+//! starting a note or a block neither locks, copies samples nor allocates.
+
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The slot of one key's rendered buffer.
+pub type BufferSlot = ArcSlot<[f32]>;
+
+pub struct ArcSlot<T: ?Sized> {
+    /// Boxed snapshot (an `Arc<[f32]>` is a fat pointer, which an `AtomicPtr`
+    /// can't hold); null when the slot is empty.
+    ptr: AtomicPtr<Arc<T>>,
+    /// Readers between loading `ptr` and cloning the `Arc` behind it. A
+    /// writer that swapped the pointer out waits for this to drain before
+    /// freeing the old box.
+    readers: AtomicUsize,
+    /// Send and Sync only as far as the `Arc`s it hands out are.
+    _snapshots: PhantomData<Arc<T>>,
+}
+
+impl<T: ?Sized> ArcSlot<T> {
+    pub fn new() -> Self {
+        Self { ptr: AtomicPtr::new(ptr::null_mut()), readers: AtomicUsize::new(0), _snapshots: PhantomData }
+    }
+
+    /// The current snapshot, if any. Wait-free; never allocates.
+    pub fn load(&self) -> Option<Arc<T>> {
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        let current = self.ptr.load(Ordering::SeqCst);
+        // SAFETY: a writer frees the box behind a pointer it swapped out only
+        // once `readers` is back to zero, which can't happen before the
+        // `fetch_sub` below; so the box outlives this clone.
+        let snapshot = unsafe { current.as_ref() }.cloned();
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+        snapshot
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ptr.load(Ordering::SeqCst).is_null()
+    }
+
+    /// Publish `snapshot` and return the one it replaces. Readers see either
+    /// the old or the new one, never a mix.
+    pub fn swap(&self, snapshot: Option<Arc<T>>) -> Option<Arc<T>> {
+        let new = snapshot.map_or(ptr::null_mut(), |b| Box::into_raw(Box::new(b)));
+        let old = self.ptr.swap(new, Ordering::SeqCst);
+        if old.is_null() {
+            return None;
+        }
+        // Anyone still cloning from `old` registered before the swap.
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            std::thread::yield_now();
+        }
+        // SAFETY: `old` came from `Box::into_raw` and, with no reader left
+        // holding it, this is its only owner.
+        Some(*unsafe { Box::from_raw(old) })
+    }
+
+    pub fn store(&self, snapshot: Option<Arc<T>>) {
+        self.swap(snapshot);
+    }
+}
+
+impl<T: ?Sized> Default for ArcSlot<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> Drop for ArcSlot<T> {
+    fn drop(&mut self) {
+        let current = *self.ptr.get_mut();
+        if !current.is_null() {
+            // SAFETY: `&mut self` rules out readers; the box is ours.
+            drop(unsafe { Box::from_raw(current) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_returns_the_previous_buffer() {
+        let slot = BufferSlot::new();
+        assert!(slot.is_empty() && slot.load().is_none());
+        assert!(slot.swap(Some(Arc::from(vec![1.0f32, 2.0]))).is_none());
+        let first = slot.load().unwrap();
+        assert_eq!(&*first, &[1.0, 2.0]);
+
+        let old = slot.swap(Some(Arc::from(vec![3.0f32]))).unwrap();
+        assert!(Arc::ptr_eq(&old, &first));
+        assert_eq!(&*slot.load().unwrap(), &[3.0]);
+        slot.store(None);
+        assert!(slot.is_empty());
+    }
+
+    #[test]
+    fn readers_never_see_a_freed_buffer() {
+        let slot = Arc::new(BufferSlot::new());
+        slot.store(Some(Arc::from(vec![0.0f32; 64])));
+        let writer = {
+            let slot = slot.clone();
+            std::thread::spawn(move || {
+                for i in 1..2000 {
+                    slot.store(Some(Arc::from(vec![i as f32; 64])));
+                }
+            })
+        };
+        for _ in 0..20_000 {
+            let buffer = slot.load().unwrap();
+            assert!(buffer.iter().all(|&s| s == buffer[0]));
+        }
+        writer.join().unwrap();
+        assert_eq!(slot.load().unwrap()[0], 1999.0);
+    }
+}
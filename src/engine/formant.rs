@@ -0,0 +1,133 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Formant-preserving transposition of analysed grids.
+//!
+//! An analysed grid stores one amplitude per harmonic, so played on another
+//! key its spectrum moves with the pitch and a voice turns into a chipmunk.
+//! Each bucket's harmonics sample the source's spectral envelope: harmonic
+//! `h` sits at `(h + 1) * base_freq`. Re-sampling that envelope at the played
+//! key's harmonic frequencies keeps the formants where they were; a formant
+//! shift moves the envelope up or down on its own.
+
+/// How key buffers of analysed grids treat the spectral envelope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormantSettings {
+    /// Re-sample harmonic amplitudes so formants stay at fixed frequencies.
+    pub preserve: bool,
+    /// Formant shift in semitones (with `preserve`): positive moves the
+    /// envelope up.
+    pub shift_semitones: f32,
+}
+
+impl Default for FormantSettings {
+    fn default() -> Self {
+        Self { preserve: false, shift_semitones: 0.0 }
+    }
+}
+
+impl FormantSettings {
+    pub fn shift_factor(&self) -> f32 {
+        2f32.powf(self.shift_semitones / 12.0)
+    }
+}
+
+/// Amplitudes `ampl` (`[harmonic][bucket]`, analysed at `source_freq`)
+/// re-sampled from their spectral envelope for a note at `played_freq`, with
+/// the envelope scaled by `shift_factor`. The envelope is linear between
+/// neighbouring harmonics, flat below the fundamental and silent above the
+/// top harmonic. A bucket never gets louder in total than it was, so the many
+/// harmonics of a low note do not pile up into clipping.
+pub fn preserve_formants(ampl: &[Vec<f32>], source_freq: f32, played_freq: f32, shift_factor: f32) -> Vec<Vec<f32>> {
+    resample_formants(ampl, played_freq / (source_freq * shift_factor))
+}
+
+/// [`preserve_formants`] by the ratio `scale` of the played note's pitch to
+/// the (shifted) source's: harmonic `h` of the note falls on source harmonic
+/// `(h + 1) * scale - 1`.
+pub fn resample_formants(ampl: &[Vec<f32>], scale: f32) -> Vec<Vec<f32>> {
+    let num_buckets = ampl.first().map(|r| r.len()).unwrap_or(0);
+    if !(scale.is_finite() && scale > 0.0) {
+        return ampl.to_vec();
+    }
+    let mut out = vec![vec![0.0; num_buckets]; ampl.len()];
+    for b in 0..num_buckets {
+        let gain = formant_gain(ampl, b, scale);
+        for (h, row) in out.iter_mut().enumerate() {
+            row[b] = formant_amp(ampl, b, h, scale) * gain;
+        }
+    }
+    out
+}
+
+/// Harmonic `h`'s amplitude in bucket `b` of [`resample_formants`], before
+/// the bucket's [`formant_gain`]. Streaming voices read it per sample.
+pub fn formant_amp(ampl: &[Vec<f32>], b: usize, h: usize, scale: f32) -> f32 {
+    envelope_at(ampl, b, (h + 1) as f32 * scale - 1.0)
+}
+
+/// Gain of bucket `b` in [`resample_formants`]: below 1 where the re-sampled
+/// harmonics would add up to more than the source's.
+pub fn formant_gain(ampl: &[Vec<f32>], b: usize, scale: f32) -> f32 {
+    let before: f32 = ampl.iter().map(|r| r[b]).sum();
+    let after: f32 = (0..ampl.len()).map(|h| formant_amp(ampl, b, h, scale)).sum();
+    if after > before && after > 0.0 {
+        before / after
+    } else {
+        1.0
+    }
+}
+
+/// Bucket `b`'s envelope at fractional harmonic index `pos`.
+fn envelope_at(ampl: &[Vec<f32>], b: usize, pos: f32) -> f32 {
+    if pos <= 0.0 {
+        return ampl.first().map_or(0.0, |r| r[b]);
+    }
+    let (lo, t) = (pos.floor() as usize, pos.fract());
+    let at = |h: usize| ampl.get(h).map_or(0.0, |r| r[b]);
+    at(lo) + t * (at(lo + 1) - at(lo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formants_stay_put_an_octave_up() {
+        // A formant on the source's 4th harmonic (4 * 100 Hz).
+        let mut ampl = vec![vec![0.0]; 10];
+        for (row, a) in ampl.iter_mut().zip([0.1, 0.1, 0.1, 0.5, 0.1]) {
+            row[0] = a;
+        }
+        let up = preserve_formants(&ampl, 100.0, 200.0, 1.0);
+        // Played at 200 Hz, 400 Hz is the 2nd harmonic.
+        assert!((up[1][0] - 0.5).abs() < 1e-6);
+        assert!((up[2][0] - 0.0).abs() < 1e-6);
+
+        // Shifted up an octave, at the source pitch, it moves to 800 Hz.
+        let shifted = preserve_formants(&ampl, 100.0, 100.0, 2.0);
+        let peak = (0..10).max_by(|&a, &b| shifted[a][0].total_cmp(&shifted[b][0])).unwrap();
+        assert_eq!(peak, 7);
+        assert_eq!(preserve_formants(&ampl, 100.0, 100.0, 1.0), ampl);
+    }
+
+    #[test]
+    fn lower_notes_do_not_get_louder() {
+        let ampl: Vec<Vec<f32>> = (0..8).map(|h| vec![0.4 / (h + 1) as f32]).collect();
+        let down = preserve_formants(&ampl, 400.0, 100.0, 1.0);
+        let (before, after): (f32, f32) = (ampl.iter().map(|r| r[0]).sum(), down.iter().map(|r| r[0]).sum());
+        assert!(after <= before + 1e-6);
+        assert!((FormantSettings { preserve: true, shift_semitones: 12.0 }.shift_factor() - 2.0).abs() < 1e-6);
+    }
+}
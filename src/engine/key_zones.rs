@@ -0,0 +1,169 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Key zones: several analysed grids per instance, each recorded at a root
+//! key and played across a range of keys, so a multisampled instrument is
+//! not stretched from one recording over the whole keyboard.
+//!
+//! Zone 0 is the live grid, rooted at its analysed pitch unless set
+//! otherwise. Zones `1..MAX_KEY_ZONES` hold further analysed grids
+//! ([`super::LoadedGrid`]). In Analysis mode each key renders from the zone
+//! [`select_zones`] picks, optionally blending the amplitudes of the zones
+//! rooted either side of it. Zones apply to the live grid's key buffers;
+//! velocity layers above 0 and streaming voices are not zoned.
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{NUM_KEYS, REFERENCE_KEY};
+
+/// Zone slots per instance, counting zone 0 (the live grid).
+pub const MAX_KEY_ZONES: usize = 8;
+
+/// The keys a zone is played for and the key its grid was recorded at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KeyZone {
+    pub root_key: usize,
+    /// Inclusive key range.
+    pub lo_key: usize,
+    pub hi_key: usize,
+}
+
+impl KeyZone {
+    /// A zone rooted at `root_key` over `a..=b` (in either order), all
+    /// clamped to the keyboard.
+    pub fn new(root_key: usize, a: usize, b: usize) -> Self {
+        let last = NUM_KEYS - 1;
+        Self { root_key: root_key.min(last), lo_key: a.min(b).min(last), hi_key: a.max(b).min(last) }
+    }
+
+    /// A zone rooted at `root_key` spanning the whole keyboard.
+    pub fn rooted_at(root_key: usize) -> Self {
+        Self::new(root_key, 0, NUM_KEYS - 1)
+    }
+
+    pub fn contains(&self, key: usize) -> bool {
+        (self.lo_key..=self.hi_key).contains(&key)
+    }
+}
+
+impl Default for KeyZone {
+    /// Rooted at A4, everywhere.
+    fn default() -> Self {
+        Self::rooted_at(REFERENCE_KEY)
+    }
+}
+
+/// The key whose fundamental in `key_frequencies` (Hz, one per key) is
+/// nearest `freq` (Hz) on a log scale.
+pub fn nearest_key(freq: f32, key_frequencies: &[f64]) -> usize {
+    if freq <= 0.0 {
+        return KeyZone::default().root_key;
+    }
+    let distance = |f: f64| (f / freq as f64).log2().abs();
+    key_frequencies
+        .iter()
+        .enumerate()
+        .filter(|(_, &f)| f > 0.0)
+        .min_by(|(_, &a), (_, &b)| distance(a).total_cmp(&distance(b)))
+        .map_or(KeyZone::default().root_key, |(key, _)| key)
+}
+
+/// Which zones a key renders from: `primary`'s grid, with its amplitudes
+/// blended towards `secondary`'s by `mix`. Without blending `mix == 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZonePick {
+    pub primary: usize,
+    pub secondary: usize,
+    pub mix: f32,
+}
+
+impl ZonePick {
+    pub fn single(zone: usize) -> Self {
+        Self { primary: zone, secondary: zone, mix: 0.0 }
+    }
+}
+
+/// Choose the zones for `key` among `zones` (one per zone slot, `None` for
+/// an empty slot): of the zones whose range holds the key (or, if none does,
+/// all of them) the one rooted nearest plays. With `interpolate`, a key
+/// between the roots of two such zones blends their amplitudes by its
+/// distance from each root, the nearer zone supplying phases and timing.
+/// Falls back to zone 0 when no slot is in use.
+pub fn select_zones(zones: &[Option<KeyZone>], key: usize, interpolate: bool) -> ZonePick {
+    let in_use = || zones.iter().enumerate().filter_map(|(i, z)| z.map(|z| (i, z)));
+    let covering = in_use().any(|(_, z)| z.contains(key));
+    let candidates = || in_use().filter(move |(_, z)| !covering || z.contains(key));
+    let distance = |z: &KeyZone| z.root_key.abs_diff(key);
+
+    let Some((nearest, _)) = candidates().min_by_key(|(_, z)| distance(z)) else {
+        return ZonePick::single(0);
+    };
+    if !interpolate {
+        return ZonePick::single(nearest);
+    }
+    let below = candidates().filter(|(_, z)| z.root_key <= key).max_by_key(|(_, z)| z.root_key);
+    let above = candidates().filter(|(_, z)| z.root_key > key).min_by_key(|(_, z)| z.root_key);
+    let (Some((lo, zl)), Some((hi, zh))) = (below, above) else {
+        return ZonePick::single(nearest);
+    };
+    if zl.root_key == key {
+        return ZonePick::single(lo);
+    }
+    let t = (key - zl.root_key) as f32 / (zh.root_key - zl.root_key) as f32;
+    if t <= 0.5 {
+        ZonePick { primary: lo, secondary: hi, mix: t }
+    } else {
+        ZonePick { primary: hi, secondary: lo, mix: 1.0 - t }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::key_frequency;
+
+    #[test]
+    fn nearest_key_follows_the_keyboard() {
+        let freqs: Vec<f64> = (0..NUM_KEYS).map(|k| key_frequency(k, 440.0)).collect();
+        assert_eq!(nearest_key(27.5, &freqs), 21);
+        assert_eq!(nearest_key(440.0, &freqs), 69);
+        assert_eq!(nearest_key(452.0, &freqs), 69);
+        assert_eq!(nearest_key(100_000.0, &freqs), NUM_KEYS - 1);
+        assert_eq!(nearest_key(1.0, &freqs), 0);
+        let flat: Vec<f64> = (0..NUM_KEYS).map(|k| key_frequency(k, 415.0)).collect();
+        assert_eq!(nearest_key(415.0, &flat), 69);
+        assert_eq!(KeyZone::new(300, 60, 10), KeyZone { root_key: NUM_KEYS - 1, lo_key: 10, hi_key: 60 });
+    }
+
+    #[test]
+    fn select_zones_prefers_covering_zones_then_the_nearest_root() {
+        let zones = [Some(KeyZone::new(24, 0, 35)), Some(KeyZone::new(60, 36, 87)), None];
+        assert_eq!(select_zones(&zones, 35, false), ZonePick::single(0));
+        assert_eq!(select_zones(&zones, 36, false), ZonePick::single(1));
+
+        let gap = [Some(KeyZone::new(10, 0, 20)), Some(KeyZone::new(70, 60, 87))];
+        assert_eq!(select_zones(&gap, 30, false), ZonePick::single(0));
+        assert_eq!(select_zones(&gap, 50, false), ZonePick::single(1));
+        assert_eq!(select_zones(&[None, None], 30, false), ZonePick::single(0));
+    }
+
+    #[test]
+    fn select_zones_interpolates_between_neighbouring_roots() {
+        let zones = [Some(KeyZone::rooted_at(20)), Some(KeyZone::rooted_at(40)), Some(KeyZone::rooted_at(80))];
+        assert_eq!(select_zones(&zones, 25, true), ZonePick { primary: 0, secondary: 1, mix: 0.25 });
+        assert_eq!(select_zones(&zones, 70, true), ZonePick { primary: 2, secondary: 1, mix: 0.25 });
+        assert_eq!(select_zones(&zones, 40, true), ZonePick::single(1));
+        assert_eq!(select_zones(&zones, 85, true), ZonePick::single(2));
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Analysed grids held beside the live one — velocity layers and key zones
+//! above 0 — and where a newly loaded grid goes.
+
+use crate::constants::NUM_HARMONICS;
+use crate::params::analysis_state::{pack_rows, unpack_rows, GridState};
+use super::{KeyZone, VelocityRange};
+
+/// An analysed grid as loaded (display-normalised amplitudes, like the live
+/// grid's analysis snapshot), played as is: no editing, but the live grid's
+/// per-harmonic enable flags apply.
+#[derive(Clone, Default)]
+pub struct LoadedGrid {
+    /// `amplitude[harmonic][bucket]`, `NUM_HARMONICS` rows.
+    pub amplitude: Vec<Vec<f32>>,
+    pub phase: Vec<Vec<f32>>,
+    /// Per-bucket `f_local / base_freq`, as for the live grid.
+    pub pitch_ratio: Vec<f32>,
+    pub base_freq: f32,
+    /// Source duration (seconds): every key played from this grid lasts this long.
+    pub duration_secs: f32,
+    /// Sample rate (Hz) of the analysed audio; informational.
+    pub source_sample_rate: f32,
+}
+
+impl LoadedGrid {
+    /// A grid from analysed rows. As in `SynthComputeEngine::load_analysis`,
+    /// harmonics beyond `NUM_HARMONICS` are dropped, missing ones and short
+    /// rows are zero-filled, and missing pitch ratios are flat.
+    pub fn new(
+        amplitude: &[Vec<f32>],
+        phase: &[Vec<f32>],
+        pitch_ratio: &[f32],
+        base_freq: f32,
+        duration_secs: f32,
+        source_sample_rate: f32,
+    ) -> Self {
+        let buckets = amplitude.first().map(|r| r.len()).unwrap_or(0).max(1);
+        let row_of = |src: &[Vec<f32>], h: usize| -> Vec<f32> {
+            let src = src.get(h);
+            (0..buckets)
+                .map(|b| src.and_then(|r| r.get(b)).copied().unwrap_or(0.0))
+                .collect()
+        };
+        Self {
+            amplitude: (0..NUM_HARMONICS).map(|h| row_of(amplitude, h)).collect(),
+            phase: (0..NUM_HARMONICS).map(|h| row_of(phase, h)).collect(),
+            pitch_ratio: (0..buckets).map(|b| pitch_ratio.get(b).copied().unwrap_or(1.0)).collect(),
+            base_freq: base_freq.max(0.0),
+            duration_secs: duration_secs.max(0.0),
+            source_sample_rate,
+        }
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        self.amplitude.first().map(|r| r.len()).unwrap_or(0)
+    }
+
+    /// The grid as persisted.
+    pub fn to_state(&self) -> GridState {
+        GridState {
+            duration_secs: self.duration_secs,
+            base_freq: self.base_freq,
+            source_sample_rate: self.source_sample_rate,
+            num_buckets: self.num_buckets(),
+            amplitude: pack_rows(&self.amplitude),
+            phase: pack_rows(&self.phase),
+            pitch_ratio: self.pitch_ratio.clone(),
+        }
+    }
+
+    pub fn from_state(state: &GridState) -> Self {
+        Self::new(
+            &unpack_rows(&state.amplitude, state.num_buckets),
+            &unpack_rows(&state.phase, state.num_buckets),
+            &state.pitch_ratio,
+            state.base_freq,
+            state.duration_secs,
+            state.source_sample_rate,
+        )
+    }
+}
+
+/// Where a loaded or analysed grid goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GridSlot {
+    /// The live grid, keeping its velocity range and key zone.
+    Live,
+    /// Velocity layer `n` (0 = the live grid), played for the given range.
+    VelocityLayer(usize, VelocityRange),
+    /// Key zone `n` (0 = the live grid), played for the given keys.
+    KeyZone(usize, KeyZone),
+}
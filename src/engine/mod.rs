@@ -13,11 +13,31 @@
 // limitations under the License.
 
 pub mod analysis;
+pub mod buffer_slot;
+pub mod formant;
+pub mod key_zones;
+pub mod loaded_grid;
+pub mod oscillator;
+pub mod pitch;
+pub mod render_mode;
 pub mod shared_params;
 pub mod synth_compute_engine;
+pub mod tuning;
+pub mod velocity_layers;
 pub mod chart_type;
 
-pub use analysis::{analyze_subtrack, normalize_for_display, AnalysisResult, ExecutionMode};
+pub use analysis::{
+    analyze_subtrack, analyze_subtrack_with_progress, analyze_wavetable, normalize_for_display,
+    AnalysisResult, ExecutionMode,
+};
+pub use buffer_slot::{ArcSlot, BufferSlot};
+pub use formant::{formant_amp, formant_gain, preserve_formants, resample_formants, FormantSettings};
+pub use pitch::resolve_pitch;
+pub use render_mode::RenderMode;
 pub use shared_params::SharedParams;
 pub use synth_compute_engine::SynthComputeEngine;
+pub use tuning::Tuning;
+pub use key_zones::{nearest_key, select_zones, KeyZone, ZonePick, MAX_KEY_ZONES};
+pub use loaded_grid::{GridSlot, LoadedGrid};
+pub use velocity_layers::{pack_range, select_layers, unpack_range, LayerBlend, VelocityRange, MAX_VELOCITY_LAYERS};
 pub use chart_type::ChartType;
\ No newline at end of file
@@ -0,0 +1,333 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sample-by-sample oscillator bank over the amp/phase grid.
+//!
+//! One sinusoidal oscillator per harmonic runs across the whole note; the
+//! grid is read afresh for every sample, with amplitude, phase and pitch
+//! ratio interpolated between bucket centres. It backs both the
+//! continuous-phase offline render ([`super::RenderMode::Continuous`]) and
+//! streaming playback, where `process()` synthesises voices on the fly
+//! instead of playing precomputed key buffers, so grid edits are heard on
+//! the next sample and memory no longer grows with note length.
+
+use std::f32::consts::PI;
+use crate::constants::TWO_PI;
+use super::formant::{formant_amp, formant_gain};
+
+/// Borrowed view of the grid an [`OscillatorBank`] reads from.
+#[derive(Clone, Copy)]
+pub struct GridView<'a> {
+    /// `ampl[harmonic][bucket]`, already normalised for playback.
+    pub ampl: &'a [Vec<f32>],
+    /// `phase[harmonic][bucket]`, radians.
+    pub phase: &'a [Vec<f32>],
+    pub ampl_enabled: &'a [bool],
+    pub phase_enabled: &'a [bool],
+    /// Per-bucket pitch ratio; empty means flat.
+    pub ratios: &'a [f32],
+}
+
+impl GridView<'_> {
+    pub fn num_buckets(&self) -> usize {
+        self.ampl.first().map(|r| r.len()).unwrap_or(0)
+    }
+
+    fn ratio_at(&self, bucket: usize) -> f64 {
+        self.ratios.get(bucket).copied().unwrap_or(1.0).max(1e-3) as f64
+    }
+}
+
+/// Owned copy of the playback grid that streaming voices read, published
+/// whole by the engine after every grid change (see
+/// `SynthComputeEngine::publish_stream_grid`), so the audio thread never
+/// locks the live grid.
+#[derive(Default)]
+pub struct StreamGrid {
+    pub ampl: Vec<Vec<f32>>,
+    pub phase: Vec<Vec<f32>>,
+    pub ampl_enabled: Vec<bool>,
+    pub phase_enabled: Vec<bool>,
+    pub ratios: Vec<f32>,
+    /// Playback length in samples at the time of publishing (see
+    /// `target_samples_for`); `0` in Synth mode.
+    pub target_samples: usize,
+    /// Period (samples) the grid's formants are kept at (see
+    /// `SynthComputeEngine`'s `formant_period`); `None` without formant
+    /// preservation.
+    pub formant_period: Option<f32>,
+}
+
+impl StreamGrid {
+    pub fn view(&self) -> GridView<'_> {
+        GridView {
+            ampl: &self.ampl,
+            phase: &self.phase,
+            ampl_enabled: &self.ampl_enabled,
+            phase_enabled: &self.phase_enabled,
+            ratios: &self.ratios,
+        }
+    }
+}
+
+/// Phase-continuous oscillators for one note of one key.
+#[derive(Clone)]
+pub struct OscillatorBank {
+    base_period: usize,
+    /// Note length in samples; the bucket timeline spans it.
+    len: usize,
+    /// Samples over which the bucket centres are spread.
+    span: f64,
+    /// Samples produced so far.
+    pos: usize,
+    /// Pitch multiplier from bends and tuning expressions (1 = the key's
+    /// pitch); the bucket timeline is unaffected.
+    pitch: f64,
+    /// Accumulated phase per harmonic, in cycles, kept in [0, 1). Its length
+    /// is the number of harmonics the note may use.
+    cycles: Vec<f64>,
+    /// Formant re-sampling of the grid's amplitudes for this note (see
+    /// [`Self::set_formant`]); `None` plays them as they are.
+    formant: Option<f32>,
+    /// [`formant_gain`] of the two buckets read last, keyed by the grid rows
+    /// and first bucket they were computed for.
+    formant_gains: ((usize, usize), (f32, f32)),
+}
+
+impl OscillatorBank {
+    /// A note of `len` samples at `base_period` samples per cycle, with the
+    /// bucket centres spread over `span` samples. Harmonics are limited to
+    /// `max_harmonic` and to those that stay below Nyquist at the highest
+    /// pitch ratio in `grid`, so no partial crosses it mid-note.
+    pub fn new(grid: &GridView, base_period: usize, max_harmonic: usize, len: usize, span: f64) -> Self {
+        let mut bank = Self::with_capacity(0);
+        bank.reset(grid, base_period, max_harmonic, len, span);
+        bank
+    }
+
+    /// A silent bank with room for `harmonics` oscillators, so a later
+    /// [`Self::reset`] within that limit doesn't allocate.
+    pub fn with_capacity(harmonics: usize) -> Self {
+        Self {
+            base_period: 1,
+            len: 0,
+            span: 1.0,
+            pos: 0,
+            pitch: 1.0,
+            cycles: Vec::with_capacity(harmonics),
+            formant: None,
+            formant_gains: NO_FORMANT_GAINS,
+        }
+    }
+
+    /// Restart the bank for a new note, as [`Self::new`] would build it,
+    /// reusing its storage.
+    pub fn reset(&mut self, grid: &GridView, base_period: usize, max_harmonic: usize, len: usize, span: f64) {
+        self.base_period = base_period.max(1);
+        self.len = len;
+        self.span = span.max(1.0);
+        self.pos = 0;
+        self.pitch = 1.0;
+        self.set_formant(None);
+        self.cycles.clear();
+        self.cycles.resize(Self::harmonic_limit(grid, base_period, max_harmonic), 0.0);
+    }
+
+    /// Carry the note on at `base_period` samples per cycle (a mono legato
+    /// note change): running oscillators keep their phases and the bucket
+    /// timeline its position and length.
+    pub fn retarget(&mut self, grid: &GridView, base_period: usize, max_harmonic: usize) {
+        self.base_period = base_period.max(1);
+        self.cycles.resize(Self::harmonic_limit(grid, base_period, max_harmonic), 0.0);
+    }
+
+    fn harmonic_limit(grid: &GridView, base_period: usize, max_harmonic: usize) -> usize {
+        let max_ratio = (0..grid.num_buckets()).map(|b| grid.ratio_at(b)).fold(1e-3, f64::max);
+        let nyquist_h = (base_period as f64 / (2.0 * max_ratio)) as usize;
+        grid.ampl.len().min(max_harmonic).min(nyquist_h)
+    }
+
+    /// Note length in samples.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Jump the bucket timeline to sample `pos` of the note; the
+    /// oscillators keep their phases.
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// Re-sample the grid's amplitudes by `scale` as the key buffers of
+    /// analysed grids are with formant preservation (see
+    /// [`super::resample_formants`]); `None` plays them as they are.
+    pub fn set_formant(&mut self, scale: Option<f32>) {
+        self.formant = scale.filter(|s| s.is_finite() && *s > 0.0);
+        self.formant_gains = NO_FORMANT_GAINS;
+    }
+
+    /// [`formant_gain`] of buckets `b0` and `b1 = b0 + 1` (or `b0` at the end).
+    fn formant_gains(&mut self, grid: &GridView, b0: usize, b1: usize, scale: f32) -> (f32, f32) {
+        let key = (grid.ampl.as_ptr() as usize, b0);
+        if self.formant_gains.0 != key {
+            self.formant_gains = (key, (formant_gain(grid.ampl, b0, scale), formant_gain(grid.ampl, b1, scale)));
+        }
+        self.formant_gains.1
+    }
+
+    /// Bend the note by `pitch` (a frequency ratio). Harmonics a bend up
+    /// pushes past Nyquist fall silent.
+    pub fn set_pitch(&mut self, pitch: f64) {
+        self.pitch = pitch.max(1e-3);
+    }
+
+    /// Produce the next sample, clamped to [-1, 1]. Past the end of the note
+    /// the bucket timeline wraps to the start when `repeat` is set and holds
+    /// the last bucket otherwise; the oscillators keep running either way.
+    pub fn next_sample(&mut self, grid: &GridView, repeat: bool) -> f32 {
+        let num_buckets = grid.num_buckets();
+        if num_buckets == 0 || self.len == 0 {
+            return 0.0;
+        }
+        let t = if repeat { self.pos % self.len } else { self.pos.min(self.len - 1) };
+        self.pos += 1;
+
+        let at = ((t as f64 + 0.5) / self.span * num_buckets as f64 - 0.5).clamp(0.0, (num_buckets - 1) as f64);
+        let b0 = at as usize;
+        let b1 = (b0 + 1).min(num_buckets - 1);
+        let frac = at - b0 as f64;
+        let ratio = grid.ratio_at(b0) + (grid.ratio_at(b1) - grid.ratio_at(b0)) * frac;
+        let frac = frac as f32;
+        let step = ratio * self.pitch / self.base_period as f64;
+        let formant = self.formant.map(|scale| (scale, self.formant_gains(grid, b0, b1, scale)));
+
+        let mut sample = 0.0f32;
+        for (n, acc) in self.cycles.iter_mut().enumerate() {
+            if grid.ampl_enabled.get(n).copied().unwrap_or(false) && (n + 1) as f64 * step <= 0.5 {
+                let (a0, a1) = match formant {
+                    Some((scale, (g0, g1))) => {
+                        (formant_amp(grid.ampl, b0, n, scale) * g0, formant_amp(grid.ampl, b1, n, scale) * g1)
+                    }
+                    None => (grid.ampl[n][b0], grid.ampl[n][b1]),
+                };
+                let amp = a0 + (a1 - a0) * frac;
+                if amp != 0.0 {
+                    let ph = if grid.phase_enabled.get(n).copied().unwrap_or(false) {
+                        lerp_phase(grid.phase[n][b0], grid.phase[n][b1], frac)
+                    } else {
+                        0.0
+                    };
+                    sample += amp * (TWO_PI * *acc as f32 + ph).sin();
+                }
+            }
+            *acc = (*acc + (n + 1) as f64 * step).fract();
+        }
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+/// `OscillatorBank::formant_gains` before any bucket was read.
+const NO_FORMANT_GAINS: ((usize, usize), (f32, f32)) = ((0, usize::MAX), (1.0, 1.0));
+
+/// Linear interpolation between two phases (radians) along the shorter arc.
+fn lerp_phase(from: f32, to: f32, t: f32) -> f32 {
+    let delta = (to - from + PI).rem_euclid(TWO_PI) - PI;
+    from + delta * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_single_harmonic_is_a_plain_sine() {
+        let ampl = vec![vec![0.5; 4]];
+        let phase = vec![vec![1.0; 4]];
+        let on = [true];
+        let grid = GridView { ampl: &ampl, phase: &phase, ampl_enabled: &on, phase_enabled: &on, ratios: &[] };
+        let mut bank = OscillatorBank::new(&grid, 50, 8, 200, 200.0);
+        for i in 0..200 {
+            let expected = 0.5 * (TWO_PI * i as f32 / 50.0 + 1.0).sin();
+            assert!((bank.next_sample(&grid, false) - expected).abs() < 1e-4, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn pitch_bends_the_oscillators_and_drops_aliasing_harmonics() {
+        let ampl = vec![vec![0.5; 4], vec![0.0; 4], vec![0.0; 4], vec![0.25; 4]];
+        let phase = vec![vec![0.0; 4]; 4];
+        let on = [true; 4];
+        let grid = GridView { ampl: &ampl, phase: &phase, ampl_enabled: &on, phase_enabled: &on, ratios: &[] };
+        // Harmonic 4 of a 10-sample period sits at 0.4 cycles per sample:
+        // an octave up it would alias, so only the fundamental is left.
+        let mut bank = OscillatorBank::new(&grid, 10, 8, 100, 100.0);
+        bank.set_pitch(2.0);
+        for i in 0..100 {
+            let expected = 0.5 * (TWO_PI * i as f32 / 5.0).sin();
+            assert!((bank.next_sample(&grid, false) - expected).abs() < 1e-4, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn retarget_keeps_phases_and_position() {
+        let ampl = vec![vec![0.5; 4]; 8];
+        let phase = vec![vec![0.0; 4]; 8];
+        let on = [true; 8];
+        let grid = GridView { ampl: &ampl, phase: &phase, ampl_enabled: &on, phase_enabled: &on, ratios: &[] };
+        let mut bank = OscillatorBank::new(&grid, 8, 8, 100, 100.0);
+        assert_eq!(bank.cycles.len(), 4);
+        (0..3).for_each(|_| { bank.next_sample(&grid, false); });
+        let fundamental = bank.cycles[0];
+        bank.retarget(&grid, 16, 8);
+        assert_eq!((bank.cycles.len(), bank.cycles[0], bank.pos, bank.len), (8, fundamental, 3, 100));
+    }
+
+    #[test]
+    fn phase_interpolation_takes_the_shorter_arc() {
+        assert!((lerp_phase(6.0, 0.2, 0.5) - (6.0 + (0.2 + TWO_PI - 6.0) * 0.5)).abs() < 1e-5);
+        assert!((lerp_phase(1.0, 2.0, 0.25) - 1.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn harmonics_above_nyquist_at_the_peak_ratio_are_dropped() {
+        let ampl = vec![vec![0.1; 2]; 10];
+        let phase = vec![vec![0.0; 2]; 10];
+        let on = [true; 10];
+        let ratios = [1.0, 2.0];
+        let grid = GridView { ampl: &ampl, phase: &phase, ampl_enabled: &on, phase_enabled: &on, ratios: &ratios };
+        // 16 samples per cycle at ratio 2: only harmonics 1..=8 fit.
+        assert_eq!(OscillatorBank::new(&grid, 32, 64, 100, 100.0).cycles.len(), 8);
+        assert_eq!(OscillatorBank::new(&grid, 32, 5, 100, 100.0).cycles.len(), 5);
+    }
+
+    #[test]
+    fn reset_restarts_the_note_in_place() {
+        let ampl = vec![vec![0.5; 4]; 3];
+        let phase = vec![vec![0.0; 4]; 3];
+        let on = [true; 3];
+        let grid = GridView { ampl: &ampl, phase: &phase, ampl_enabled: &on, phase_enabled: &on, ratios: &[] };
+        let fresh: Vec<f32> = {
+            let mut bank = OscillatorBank::new(&grid, 40, 8, 100, 100.0);
+            (0..50).map(|_| bank.next_sample(&grid, false)).collect()
+        };
+        let mut bank = OscillatorBank::with_capacity(8);
+        let storage = bank.cycles.as_ptr();
+        bank.reset(&grid, 30, 8, 60, 60.0);
+        (0..17).for_each(|_| { bank.next_sample(&grid, false); });
+        bank.reset(&grid, 40, 8, 100, 100.0);
+        assert_eq!(bank.cycles.as_ptr(), storage, "no reallocation");
+        let replayed: Vec<f32> = (0..50).map(|_| bank.next_sample(&grid, false)).collect();
+        assert_eq!(replayed, fresh);
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Built-in fundamental-frequency tracker for Analysis mode.
+//!
+//! The harmonic DFT in [`super::analysis`] is only meaningful at the right
+//! fundamental. A host that knows the pitch passes `base_freq` and a
+//! per-position contour; for everyone else this YIN estimator (de Cheveigné &
+//! Kawahara, 2002) derives both from the audio itself. The YIN difference
+//! function is built from an FFT cross-correlation plus running energies, so
+//! tracking costs a few FFTs per 10 ms hop rather than a quadratic scan.
+
+use realfft::RealFftPlanner;
+
+/// Search range (Hz) when no base frequency is known: from A0 (the lowest piano
+/// key) to well past any fundamental worth resynthesising.
+pub const MIN_PITCH_HZ: f32 = 27.5;
+pub const MAX_PITCH_HZ: f32 = 2000.0;
+/// With a caller-supplied base frequency the search is narrowed to half an
+/// octave either side of it, which rules out octave errors.
+const HINT_SPAN: f32 = std::f32::consts::SQRT_2;
+/// YIN absolute threshold on the cumulative-mean-normalised difference: the
+/// first dip below it is taken as the period.
+const YIN_THRESHOLD: f32 = 0.15;
+/// A frame whose deepest dip stays above this is treated as unvoiced.
+const VOICING_LIMIT: f32 = 0.35;
+/// Frames quieter than this fraction of the loudest frame's RMS are unvoiced.
+const SILENCE_REL: f32 = 0.05;
+/// Contour resolution: one estimate per this many seconds of input.
+const HOP_SECS: f32 = 0.01;
+
+/// Fundamental estimated from audio.
+#[derive(Debug, Clone)]
+pub struct PitchTrack {
+    /// Median fundamental over the voiced frames (Hz).
+    pub base_freq: f32,
+    /// Per-position fundamental (absolute Hz), uniformly spaced across the
+    /// input — the layout [`super::analyze_subtrack`] expects of `contour`.
+    /// Unvoiced stretches are bridged from their voiced neighbours.
+    pub contour: Vec<f32>,
+}
+
+/// Track the fundamental of `samples`. `hint`, if positive, narrows the search
+/// to half an octave around it. Returns `None` when the input is too short for
+/// the search range or has no voiced frame at all.
+pub fn track_pitch(samples: &[f32], sample_rate: f32, hint: Option<f32>) -> Option<PitchTrack> {
+    if sample_rate <= 0.0 {
+        return None;
+    }
+    let (lo, hi) = match hint.filter(|&f| f > 0.0) {
+        Some(f) => (f / HINT_SPAN, f * HINT_SPAN),
+        None => (MIN_PITCH_HZ, MAX_PITCH_HZ),
+    };
+    let hi = hi.min(sample_rate * 0.25);
+    let tau_min = ((sample_rate / hi).floor() as usize).max(2);
+    let tau_max = ((sample_rate / lo).ceil() as usize).max(tau_min + 2);
+    // Integration window = one longest period; a frame also needs the lag.
+    let win = tau_max;
+    let frame_len = win + tau_max;
+    let len = samples.len();
+    if len < frame_len {
+        return None;
+    }
+
+    // Zero-padded past `frame_len + win` so the circular correlation's
+    // negative lags never wrap onto the positive ones we read.
+    let fft_len = (frame_len + win).next_power_of_two();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(fft_len);
+    let inverse = planner.plan_fft_inverse(fft_len);
+    let mut frame_buf = forward.make_input_vec();
+    let mut head_buf = forward.make_input_vec();
+    let mut frame_spec = forward.make_output_vec();
+    let mut head_spec = forward.make_output_vec();
+    let mut corr = inverse.make_output_vec();
+
+    let hop = ((HOP_SECS * sample_rate) as usize).max(1);
+    let num_frames = (len / hop).max(1);
+    let mut estimates: Vec<Option<f32>> = Vec::with_capacity(num_frames);
+    let mut rms = Vec::with_capacity(num_frames);
+    let mut diff = vec![0.0f32; tau_max + 1];
+    let mut cmnd = vec![1.0f32; tau_max + 1];
+    let mut energy = vec![0.0f32; frame_len + 1];
+
+    for i in 0..num_frames {
+        // Frame i describes position i·len/num_frames, matching how the
+        // analyser interpolates a uniformly spaced contour.
+        let center = i * len / num_frames;
+        let start = center.saturating_sub(frame_len / 2).min(len - frame_len);
+        let frame = &samples[start..start + frame_len];
+
+        for (k, &x) in frame.iter().enumerate() {
+            energy[k + 1] = energy[k] + x * x;
+        }
+        frame_buf.fill(0.0);
+        frame_buf[..frame_len].copy_from_slice(frame);
+        head_buf.fill(0.0);
+        head_buf[..win].copy_from_slice(&frame[..win]);
+        if forward.process(&mut frame_buf, &mut frame_spec).is_err()
+            || forward.process(&mut head_buf, &mut head_spec).is_err()
+        {
+            return None;
+        }
+        for (f, h) in frame_spec.iter_mut().zip(head_spec.iter()) {
+            *f *= h.conj();
+        }
+        // The DC and Nyquist bins of a real signal's spectrum are real.
+        frame_spec[0].im = 0.0;
+        if let Some(last) = frame_spec.last_mut() {
+            last.im = 0.0;
+        }
+        if inverse.process(&mut frame_spec, &mut corr).is_err() {
+            return None;
+        }
+        let scale = 1.0 / fft_len as f32;
+
+        // d(τ) = Σ (x_j − x_{j+τ})² = e(0..W) + e(τ..τ+W) − 2·r(τ)
+        let e0 = energy[win];
+        for tau in 1..=tau_max {
+            let e_tau = energy[tau + win] - energy[tau];
+            diff[tau] = (e0 + e_tau - 2.0 * corr[tau] * scale).max(0.0);
+        }
+        let mut running = 0.0f32;
+        for tau in 1..=tau_max {
+            running += diff[tau];
+            cmnd[tau] = if running > 0.0 { diff[tau] * tau as f32 / running } else { 1.0 };
+        }
+
+        rms.push((e0 / win as f32).sqrt());
+        estimates.push(pick_period(&cmnd, tau_min, tau_max).map(|tau| sample_rate / tau));
+    }
+
+    // Near-silent frames carry no pitch, whatever their dips say.
+    let loudest = rms.iter().cloned().fold(0.0f32, f32::max);
+    for (estimate, &level) in estimates.iter_mut().zip(&rms) {
+        if level < loudest * SILENCE_REL {
+            *estimate = None;
+        }
+    }
+
+    let mut voiced: Vec<f32> = estimates.iter().flatten().copied().collect();
+    if voiced.is_empty() {
+        return None;
+    }
+    let base_freq = median(&mut voiced);
+    let contour = median_filter(&bridge_gaps(&estimates), 2);
+    Some(PitchTrack { base_freq, contour })
+}
+
+/// YIN period pick on the normalised difference: the first dip below
+/// [`YIN_THRESHOLD`] (followed down to its local minimum), else the global
+/// minimum if it is deep enough to call voiced. Refined by parabolic
+/// interpolation; returns the period in (fractional) samples.
+fn pick_period(cmnd: &[f32], tau_min: usize, tau_max: usize) -> Option<f32> {
+    let mut best = None;
+    let mut tau = tau_min;
+    while tau <= tau_max {
+        if cmnd[tau] < YIN_THRESHOLD {
+            while tau < tau_max && cmnd[tau + 1] < cmnd[tau] {
+                tau += 1;
+            }
+            best = Some(tau);
+            break;
+        }
+        tau += 1;
+    }
+    let tau = match best {
+        Some(tau) => tau,
+        None => {
+            let tau = (tau_min..=tau_max).min_by(|&a, &b| cmnd[a].total_cmp(&cmnd[b]))?;
+            if cmnd[tau] > VOICING_LIMIT {
+                return None;
+            }
+            tau
+        }
+    };
+
+    if tau > 1 && tau < tau_max {
+        let (a, b, c) = (cmnd[tau - 1], cmnd[tau], cmnd[tau + 1]);
+        let denom = a - 2.0 * b + c;
+        if denom.abs() > f32::EPSILON {
+            let shift = (0.5 * (a - c) / denom).clamp(-0.5, 0.5);
+            return Some(tau as f32 + shift);
+        }
+    }
+    Some(tau as f32)
+}
+
+/// Replace unvoiced (`None`) frames by linear interpolation between the
+/// nearest voiced ones, holding the first/last voiced value at the edges.
+/// Callers guarantee at least one voiced frame.
+fn bridge_gaps(estimates: &[Option<f32>]) -> Vec<f32> {
+    let voiced: Vec<(usize, f32)> = estimates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| e.map(|f| (i, f)))
+        .collect();
+    let mut out = Vec::with_capacity(estimates.len());
+    let mut next = 0;
+    for i in 0..estimates.len() {
+        while next < voiced.len() && voiced[next].0 < i {
+            next += 1;
+        }
+        let value = match (next.checked_sub(1).map(|p| voiced[p]), voiced.get(next).copied()) {
+            (_, Some((j, f))) if j == i => f,
+            (Some((i0, f0)), Some((i1, f1))) => {
+                f0 + (f1 - f0) * (i - i0) as f32 / (i1 - i0) as f32
+            }
+            (Some((_, f)), None) | (None, Some((_, f))) => f,
+            (None, None) => 0.0,
+        };
+        out.push(value);
+    }
+    out
+}
+
+/// Sliding median of half-width `radius`: removes isolated octave jumps
+/// without flattening real vibrato.
+fn median_filter(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut window = Vec::with_capacity(2 * radius + 1);
+    (0..values.len())
+        .map(|i| {
+            let lo = i.saturating_sub(radius);
+            let hi = (i + radius + 1).min(values.len());
+            window.clear();
+            window.extend_from_slice(&values[lo..hi]);
+            median(&mut window)
+        })
+        .collect()
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+/// The pitch inputs an analysis actually runs with.
+#[derive(Debug, Clone)]
+pub struct ResolvedPitch {
+    pub base_freq: f32,
+    pub contour: Vec<f32>,
+    /// Whether the built-in tracker supplied the contour (and, if the caller
+    /// gave none, the base frequency).
+    pub detected: bool,
+}
+
+/// Fill in whatever pitch information the caller left out:
+///
+/// * `base_freq > 0` and a contour – used as given.
+/// * `base_freq <= 0` and a contour – base is the contour's median.
+/// * empty contour – tracked from the audio, narrowed around `base_freq` when
+///   one is given; a positive `base_freq` is kept as the transpose reference.
+///
+/// Returns `None` only if a base frequency is needed but the input has no
+/// voiced frames. With a given base and an untrackable input the analysis
+/// falls back to the legacy flat contour.
+pub fn resolve_pitch(
+    samples: &[f32],
+    sample_rate: f32,
+    base_freq: f32,
+    contour: &[f32],
+) -> Option<ResolvedPitch> {
+    if !contour.is_empty() {
+        let base_freq = if base_freq > 0.0 {
+            base_freq
+        } else {
+            let mut voiced: Vec<f32> = contour.iter().copied().filter(|&f| f > 0.0).collect();
+            if voiced.is_empty() {
+                return None;
+            }
+            median(&mut voiced)
+        };
+        return Some(ResolvedPitch { base_freq, contour: contour.to_vec(), detected: false });
+    }
+
+    let hint = (base_freq > 0.0).then_some(base_freq);
+    match track_pitch(samples, sample_rate, hint) {
+        Some(track) => Some(ResolvedPitch {
+            base_freq: hint.unwrap_or(track.base_freq),
+            contour: track.contour,
+            detected: true,
+        }),
+        None => hint.map(|base_freq| ResolvedPitch { base_freq, contour: Vec::new(), detected: false }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn tone(sr: f32, f: f32, secs: f32, harmonics: usize) -> Vec<f32> {
+        (0..(sr * secs) as usize)
+            .map(|i| {
+                let t = i as f32 / sr;
+                (1..=harmonics)
+                    .map(|k| (2.0 * PI * f * k as f32 * t).sin() / k as f32)
+                    .sum::<f32>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracks_a_steady_sine() {
+        let track = track_pitch(&tone(44100.0, 220.0, 0.5, 1), 44100.0, None).expect("voiced");
+        assert!((track.base_freq - 220.0).abs() < 1.0, "base {}", track.base_freq);
+        assert!(track.contour.iter().all(|&f| (f - 220.0).abs() < 2.0));
+    }
+
+    #[test]
+    fn rich_tone_tracks_fundamental_not_an_octave_up() {
+        // Strong upper partials must not pull the estimate to 2·f0.
+        let track = track_pitch(&tone(44100.0, 110.0, 0.5, 8), 44100.0, None).expect("voiced");
+        assert!((track.base_freq - 110.0).abs() < 1.0, "base {}", track.base_freq);
+    }
+
+    #[test]
+    fn contour_follows_vibrato() {
+        let sr = 44100.0;
+        let (base, depth, rate) = (330.0f32, 0.03f32, 4.0f32);
+        let mut phase = 0.0f32;
+        let samples: Vec<f32> = (0..(sr * 1.0) as usize)
+            .map(|i| {
+                let t = i as f32 / sr;
+                phase += 2.0 * PI * base * (1.0 + depth * (2.0 * PI * rate * t).sin()) / sr;
+                phase.sin()
+            })
+            .collect();
+        let track = track_pitch(&samples, sr, None).expect("voiced");
+        let hi = track.contour.iter().cloned().fold(f32::MIN, f32::max);
+        let lo = track.contour.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(hi / base > 1.02 && lo / base < 0.98, "contour [{lo}, {hi}]");
+    }
+
+    #[test]
+    fn silence_has_no_pitch_but_a_given_base_still_resolves() {
+        let silence = vec![0.0f32; 44100];
+        assert!(track_pitch(&silence, 44100.0, None).is_none());
+        assert!(resolve_pitch(&silence, 44100.0, 0.0, &[]).is_none());
+
+        let flat = resolve_pitch(&silence, 44100.0, 440.0, &[]).expect("base given");
+        assert_eq!(flat.base_freq, 440.0);
+        assert!(flat.contour.is_empty() && !flat.detected);
+    }
+
+    #[test]
+    fn resolve_keeps_host_pitch_and_fills_missing_base() {
+        let samples = tone(44100.0, 220.0, 0.3, 1);
+        let given = resolve_pitch(&samples, 44100.0, 221.0, &[219.0, 221.0]).unwrap();
+        assert_eq!((given.base_freq, given.detected), (221.0, false));
+
+        let from_contour = resolve_pitch(&samples, 44100.0, 0.0, &[210.0, 220.0, 230.0]).unwrap();
+        assert_eq!(from_contour.base_freq, 220.0);
+
+        let detected = resolve_pitch(&samples, 44100.0, 0.0, &[]).unwrap();
+        assert!(detected.detected);
+        assert!((detected.base_freq - 220.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn bridge_gaps_interpolates_and_holds_edges() {
+        let filled = bridge_gaps(&[None, Some(100.0), None, Some(200.0), None]);
+        assert_eq!(filled, vec![100.0, 100.0, 150.0, 200.0, 200.0]);
+    }
+}
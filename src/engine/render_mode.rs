@@ -0,0 +1,61 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// How a key's buffer is rendered from the amp/phase grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Original behaviour: every bucket is one independent period starting
+    /// at t = 0, so the grid steps from bucket to bucket.
+    Periodic,
+    /// Per-harmonic phase accumulators run across the whole note, with
+    /// amplitude, phase and pitch ratio interpolated between bucket centres:
+    /// no zipper steps at bucket edges, and vibrato glides instead of jumping.
+    Continuous,
+}
+
+impl Default for RenderMode {
+    fn default() -> Self {
+        RenderMode::Periodic
+    }
+}
+
+impl RenderMode {
+    pub fn as_u8(self) -> u8 {
+        match self {
+            RenderMode::Periodic => 0,
+            RenderMode::Continuous => 1,
+        }
+    }
+
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            1 => RenderMode::Continuous,
+            _ => RenderMode::Periodic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_mode_round_trips_through_u8() {
+        for mode in [RenderMode::Periodic, RenderMode::Continuous] {
+            assert_eq!(RenderMode::from_u8(mode.as_u8()), mode);
+        }
+        assert_eq!(RenderMode::from_u8(7), RenderMode::Periodic);
+        assert_eq!(RenderMode::default(), RenderMode::Periodic);
+    }
+}
@@ -13,10 +13,13 @@
 // limitations under the License.
 
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
-use crate::constants::NUM_KEYS;
-use crate::engine::ExecutionMode;
-use crate::voice::Voice;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use crate::constants::{key_frequency, max_harmonic_for_freq, DEFAULT_REFERENCE_PITCH, NUM_KEYS};
+use crate::engine::oscillator::StreamGrid;
+use crate::engine::{
+    pack_range, unpack_range, ArcSlot, BufferSlot, ExecutionMode, FormantSettings, KeyZone, LoadedGrid, RenderMode,
+    Tuning, VelocityRange, MAX_KEY_ZONES, MAX_VELOCITY_LAYERS,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BufferState {
@@ -25,6 +28,42 @@ pub enum BufferState {
     Computing, // Buffer is currently being computed
 }
 
+/// A note the editor asks the audio thread to start or release (see
+/// [`SharedParams::request_note`]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteRequest {
+    On,
+    Off,
+}
+
+impl NoteRequest {
+    fn as_u8(self) -> u8 {
+        match self {
+            NoteRequest::On => 1,
+            NoteRequest::Off => 2,
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(NoteRequest::On),
+            2 => Some(NoteRequest::Off),
+            _ => None,
+        }
+    }
+}
+
+/// Packed form of "no release tail" in the release-tail atomics.
+const NO_RELEASE_TAIL: u32 = u32::MAX;
+
+fn pack_tail(tail: Option<f32>) -> u32 {
+    tail.map_or(NO_RELEASE_TAIL, f32::to_bits)
+}
+
+fn unpack_tail(bits: u32) -> Option<f32> {
+    (bits != NO_RELEASE_TAIL).then(|| f32::from_bits(bits))
+}
+
 #[derive(Clone)]
 pub struct SharedParams {
     pub amplitude_data: Arc<Mutex<Vec<Vec<f32>>>>,
@@ -49,9 +88,26 @@ pub struct SharedParams {
     /// absolute per-bucket pitch, so the GUI can report the original tone's
     /// min/max pitch. `0.0` means "no analysis loaded".
     pub analysis_base_freq: Arc<Mutex<f32>>,
-    pub voices: Arc<Mutex<Vec<Option<Voice>>>>,
+    /// Per-key "a voice is sounding" flags, published by the audio thread
+    /// (which owns the voices) after every block, for the editor to read.
+    pub active_voices: Arc<Vec<AtomicBool>>,
+    /// Per-key note on/off requests from the editor's keyboard, picked up by
+    /// the audio thread at the start of its next block.
+    pub note_requests: Arc<Vec<AtomicU8>>,
     pub assembled_sound_plotted: Arc<Mutex<Vec<f32>>>,
-    pub piano_periods: Arc<Mutex<Vec<u32>>>,
+    /// Exact period (samples, as `f64` bits) of each key at the playback
+    /// sample rate. Keys render whole-sample periods (see
+    /// [`Self::piano_period`]); voices make up the difference with their
+    /// playback rate (see [`Self::period_rate`]). Atomics, as the audio
+    /// thread reads them at NoteOn.
+    pub piano_periods: Arc<Vec<AtomicU64>>,
+    /// Fundamental (Hz, as `f64` bits) of each key under the current tuning;
+    /// `piano_periods` are derived from it (see [`Self::key_frequency`]).
+    pub key_frequencies: Arc<Vec<AtomicU64>>,
+    /// Frequency (Hz) of A4 (`REFERENCE_KEY`) the keys are tuned to.
+    pub reference_pitch: Arc<Mutex<f32>>,
+    /// Scala tuning in use; `None` = equal temperament.
+    pub tuning: Arc<Mutex<Option<Tuning>>>,
     pub normalization_needed: Arc<Mutex<bool>>,
     pub harmonic_ampl_enabled: Arc<Mutex<Vec<bool>>>,
     pub harmonic_phase_enabled: Arc<Mutex<Vec<bool>>>,
@@ -69,11 +125,71 @@ pub struct SharedParams {
     pub analysis_amplitude_data: Arc<Mutex<Vec<Vec<f32>>>>,
     pub analysis_phase_data: Arc<Mutex<Vec<Vec<f32>>>>,
     pub fade_duration: usize,
+
+    // Velocity layers (see `engine::velocity_layers`)
+    /// Velocity range of each layer slot, packed by [`pack_range`] (empty
+    /// slots included). Slot 0 (the live grid) is always in use. Atomics kept
+    /// apart from the grids, so the audio thread can pick layers at NoteOn
+    /// without locking (see [`Self::velocity_ranges`]).
+    pub velocity_ranges: Arc<Vec<AtomicU64>>,
+    /// Grids of layers `1..MAX_VELOCITY_LAYERS` (entry `i` is layer `i + 1`).
+    pub velocity_layers: Arc<Mutex<Vec<Option<LoadedGrid>>>>,
+    /// Rendered buffer per key of layers above 0, at [`Self::layer_slot`].
+    pub layer_key_buffers: Arc<Vec<BufferSlot>>,
+    pub layer_buffer_states: Arc<Mutex<Vec<BufferState>>>,
+
+    // Key zones (see `engine::key_zones`)
+    /// Key zone of each zone slot in use; `None` for an empty slot. Slot 0
+    /// (the live grid) is always in use.
+    pub key_zones: Arc<Mutex<[Option<KeyZone>; MAX_KEY_ZONES]>>,
+    /// Grids of zones `1..MAX_KEY_ZONES` (entry `i` is zone `i + 1`).
+    pub zone_grids: Arc<Mutex<Vec<Option<LoadedGrid>>>>,
+    /// Whether keys between two zone roots blend the zones' amplitudes.
+    pub zone_interpolation: Arc<AtomicBool>,
+
+    // Release tails (Analysis mode)
+    /// Where the release tail of each velocity layer's grid starts (slot 0
+    /// is the live grid), as `SynthComputeEngine::update_release_tails`
+    /// measured it; read at NoteOn (see [`Self::layer_release_tail`]).
+    pub layer_release_tails: Arc<Vec<AtomicU32>>,
+    /// Same for each key's live-layer sound, from the key zone it renders
+    /// from (see [`Self::key_release_tail`]).
+    pub key_release_tails: Arc<Vec<AtomicU32>>,
+    /// Set by grid, layer and zone changes; the background thread then
+    /// measures the release tails again.
+    pub release_tails_dirty: Arc<AtomicBool>,
+
+    /// Formant preservation of analysed grids (see `engine::formant`),
+    /// mirrored from the plugin params by the background thread.
+    pub formant: Arc<Mutex<FormantSettings>>,
     
     // Async buffer computation
-    pub key_buffers: Arc<Mutex<Vec<Option<Vec<f32>>>>>,
+    /// Rendered buffer per key, swapped in whole so the audio thread can
+    /// pick one up without locking or copying.
+    pub key_buffers: Arc<Vec<BufferSlot>>,
+    /// Buffers swapped out of `key_buffers` that a voice may still be
+    /// playing; freed off the audio thread once nothing else holds them.
+    pub retired_buffers: Arc<Mutex<Vec<Arc<[f32]>>>>,
     pub buffer_states: Arc<Mutex<Vec<BufferState>>>,
     pub computation_cancel: Arc<AtomicBool>,
+
+    // Background audio analysis (see `SynthComputeEngine::analyze_and_load`)
+    /// Raised to abort the analysis in flight; cleared when the next one starts.
+    pub analysis_cancel: Arc<AtomicBool>,
+    /// True while an analysis is running.
+    pub analysis_running: Arc<AtomicBool>,
+    /// Buckets analysed so far / in total, for the progress display.
+    pub analysis_buckets_done: Arc<AtomicUsize>,
+    pub analysis_buckets_total: Arc<AtomicUsize>,
+    /// Whether the loaded analysis ran on the built-in pitch tracker's contour
+    /// rather than one supplied by the host (see `engine::pitch`).
+    pub analysis_pitch_detected: Arc<AtomicBool>,
+    /// Sample rate (Hz) of the audio the loaded grid came from; informational.
+    pub analysis_sample_rate: Arc<Mutex<f32>>,
+    /// Job submitted from this instance's own editor (e.g. a loaded WAV file),
+    /// run by its analysis worker ahead of host-pushed jobs. A newer
+    /// submission replaces one that has not started yet.
+    pub pending_analysis: Arc<Mutex<Option<crate::AnalysisJob>>>,
     
     // Chart view control
     pub should_reset_chart_view: Arc<AtomicBool>,
@@ -83,6 +199,32 @@ pub struct SharedParams {
 
     /// When true a held note loops its buffer; when false it plays once.
     pub repeat_playback: Arc<AtomicBool>,
+
+    /// Periodic (per-bucket) vs Continuous (phase-accumulator) rendering.
+    pub render_mode: Arc<AtomicU8>,
+
+    /// When true, voices are synthesised on the fly in `process()` from the
+    /// live grid instead of playing precomputed `key_buffers`.
+    pub streaming: Arc<AtomicBool>,
+
+    /// Snapshot of the live grid streaming voices play from; the audio
+    /// thread only loads it. Republished off the audio thread whenever
+    /// `stream_grid_dirty` is set.
+    pub stream_grid: Arc<ArcSlot<StreamGrid>>,
+    /// Set by every grid change (see [`Self::mark_all_buffers_dirty`]).
+    pub stream_grid_dirty: Arc<AtomicBool>,
+    /// Replaced snapshots the audio thread may still hold, freed on the
+    /// background side like `retired_buffers`.
+    pub retired_stream_grids: Arc<Mutex<Vec<Arc<StreamGrid>>>>,
+
+    /// Token the host tagged this instance with (see
+    /// `lesynth_fourier_prepare_instance`); `None` for a plain DAW instance.
+    /// The analysis worker claims jobs addressed to it.
+    pub instance_token: Arc<Mutex<Option<u64>>>,
+
+    /// Set when the owning plugin instance is dropped; the engine's background
+    /// threads exit on their next iteration.
+    pub shutdown: Arc<AtomicBool>,
 }
 
 impl SharedParams {
@@ -97,9 +239,17 @@ impl SharedParams {
             sample_rate: Arc::new(Mutex::new(44100.0)),
             analysis_duration_secs: Arc::new(Mutex::new(0.0)),
             analysis_base_freq: Arc::new(Mutex::new(0.0)),
-            voices: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
+            active_voices: Arc::new((0..NUM_KEYS).map(|_| AtomicBool::new(false)).collect()),
+            note_requests: Arc::new((0..NUM_KEYS).map(|_| AtomicU8::new(0)).collect()),
             assembled_sound_plotted: Arc::new(Mutex::new(Vec::new())),
-            piano_periods: Arc::new(Mutex::new(Self::populate_piano_periods())),
+            piano_periods: Arc::new(
+                Self::populate_piano_periods().into_iter().map(|p| AtomicU64::new(p.to_bits())).collect(),
+            ),
+            key_frequencies: Arc::new(
+                Self::equal_temperament(DEFAULT_REFERENCE_PITCH).into_iter().map(|f| AtomicU64::new(f.to_bits())).collect(),
+            ),
+            reference_pitch: Arc::new(Mutex::new(DEFAULT_REFERENCE_PITCH)),
+            tuning: Arc::new(Mutex::new(None)),
             normalization_needed: Arc::new(Mutex::new(false)),
             harmonic_ampl_enabled: Arc::new(Mutex::new(vec![true; num_harmonics])),
             harmonic_phase_enabled: Arc::new(Mutex::new(vec![true; num_harmonics])),
@@ -108,11 +258,39 @@ impl SharedParams {
             analysis_amplitude_data: Arc::new(Mutex::new(vec![vec![0.0; buckets]; num_harmonics])),
             analysis_phase_data: Arc::new(Mutex::new(vec![vec![0.0; buckets]; num_harmonics])),
             fade_duration: 128,
+
+            velocity_ranges: Arc::new(
+                Self::initial_velocity_ranges().into_iter().map(|r| AtomicU64::new(pack_range(r))).collect(),
+            ),
+            velocity_layers: Arc::new(Mutex::new(vec![None; MAX_VELOCITY_LAYERS - 1])),
+            layer_key_buffers: Arc::new(
+                (0..(MAX_VELOCITY_LAYERS - 1) * NUM_KEYS).map(|_| BufferSlot::new()).collect(),
+            ),
+            layer_buffer_states: Arc::new(Mutex::new(vec![BufferState::Clean; (MAX_VELOCITY_LAYERS - 1) * NUM_KEYS])),
+
+            key_zones: Arc::new(Mutex::new(Self::initial_key_zones())),
+            zone_grids: Arc::new(Mutex::new(vec![None; MAX_KEY_ZONES - 1])),
+            zone_interpolation: Arc::new(AtomicBool::new(false)),
+
+            layer_release_tails: Arc::new((0..MAX_VELOCITY_LAYERS).map(|_| AtomicU32::new(NO_RELEASE_TAIL)).collect()),
+            key_release_tails: Arc::new((0..NUM_KEYS).map(|_| AtomicU32::new(NO_RELEASE_TAIL)).collect()),
+            release_tails_dirty: Arc::new(AtomicBool::new(true)),
+
+            formant: Arc::new(Mutex::new(FormantSettings::default())),
             
             // Async buffer computation - initialize all buffers as dirty
-            key_buffers: Arc::new(Mutex::new(vec![None; NUM_KEYS])),
+            key_buffers: Arc::new((0..NUM_KEYS).map(|_| BufferSlot::new()).collect()),
+            retired_buffers: Arc::new(Mutex::new(Vec::new())),
             buffer_states: Arc::new(Mutex::new(vec![BufferState::Dirty; NUM_KEYS])),
             computation_cancel: Arc::new(AtomicBool::new(false)),
+
+            analysis_cancel: Arc::new(AtomicBool::new(false)),
+            analysis_running: Arc::new(AtomicBool::new(false)),
+            analysis_buckets_done: Arc::new(AtomicUsize::new(0)),
+            analysis_buckets_total: Arc::new(AtomicUsize::new(0)),
+            analysis_pitch_detected: Arc::new(AtomicBool::new(false)),
+            analysis_sample_rate: Arc::new(Mutex::new(0.0)),
+            pending_analysis: Arc::new(Mutex::new(None)),
             
             // Chart view control
             should_reset_chart_view: Arc::new(AtomicBool::new(false)),
@@ -121,6 +299,15 @@ impl SharedParams {
 
             // Default to looping a held note, matching prior behaviour.
             repeat_playback: Arc::new(AtomicBool::new(true)),
+
+            render_mode: Arc::new(AtomicU8::new(RenderMode::Periodic.as_u8())),
+            streaming: Arc::new(AtomicBool::new(false)),
+            stream_grid: Arc::new(ArcSlot::new()),
+            stream_grid_dirty: Arc::new(AtomicBool::new(true)),
+            retired_stream_grids: Arc::new(Mutex::new(Vec::new())),
+
+            instance_token: Arc::new(Mutex::new(None)),
+            shutdown: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -142,34 +329,220 @@ impl SharedParams {
     /// Switch execution mode.
     pub fn set_execution_mode(&self, mode: ExecutionMode) {
         self.execution_mode.store(mode.as_u8(), Ordering::Relaxed);
+        // Pitch ratios apply to streaming voices only in Analysis mode.
+        self.stream_grid_dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// How key buffers are rendered from the grid.
+    pub fn render_mode(&self) -> RenderMode {
+        RenderMode::from_u8(self.render_mode.load(Ordering::Relaxed))
+    }
+
+    /// Switch the render mode. Key buffers are not invalidated here; see
+    /// `SynthComputeEngine::set_render_mode`.
+    pub fn set_render_mode(&self, mode: RenderMode) {
+        self.render_mode.store(mode.as_u8(), Ordering::Relaxed);
+    }
+
+    /// Whether voices are synthesised on the fly (see `voice::Voice::streaming`).
+    pub fn streaming(&self) -> bool {
+        self.streaming.load(Ordering::Relaxed)
+    }
+
+    /// Whether a voice is sounding on `key`, as of the last audio block.
+    pub fn voice_active(&self, key: usize) -> bool {
+        self.active_voices.get(key).is_some_and(|a| a.load(Ordering::Relaxed))
+    }
+
+    pub fn any_voice_active(&self) -> bool {
+        self.active_voices.iter().any(|a| a.load(Ordering::Relaxed))
+    }
+
+    /// Publish whether a voice is sounding on `key` (audio thread only).
+    pub fn set_voice_active(&self, key: usize, active: bool) {
+        if let Some(a) = self.active_voices.get(key) {
+            a.store(active, Ordering::Relaxed);
+        }
+    }
+
+    /// Ask the audio thread to start or release a note on `key`. A request
+    /// not yet picked up is replaced by a newer one.
+    pub fn request_note(&self, key: usize, request: NoteRequest) {
+        if let Some(r) = self.note_requests.get(key) {
+            r.store(request.as_u8(), Ordering::Release);
+        }
+    }
+
+    /// Take the pending request for `key`, if any (audio thread only).
+    pub fn take_note_request(&self, key: usize) -> Option<NoteRequest> {
+        NoteRequest::from_u8(self.note_requests.get(key)?.swap(0, Ordering::Acquire))
+    }
+
+    /// Whether a note on `key` has been requested but not yet started.
+    pub fn note_on_pending(&self, key: usize) -> bool {
+        self.note_requests.get(key).is_some_and(|r| r.load(Ordering::Relaxed) == NoteRequest::On.as_u8())
+    }
+
+    /// `(buckets done, buckets total)` of the running analysis, or `None` when
+    /// idle. The total is `0` until the bucket layout is known.
+    pub fn analysis_progress(&self) -> Option<(usize, usize)> {
+        if !self.analysis_running.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some((
+            self.analysis_buckets_done.load(Ordering::Relaxed),
+            self.analysis_buckets_total.load(Ordering::Relaxed),
+        ))
+    }
+
+    /// Ask the running or queued analysis (if any) to stop; the grid is left
+    /// untouched. Stays raised until the next job is submitted.
+    pub fn cancel_analysis(&self) {
+        self.analysis_cancel.store(true, Ordering::Relaxed);
+    }
+
+    fn initial_velocity_ranges() -> [Option<VelocityRange>; MAX_VELOCITY_LAYERS] {
+        let mut ranges = [None; MAX_VELOCITY_LAYERS];
+        ranges[0] = Some(VelocityRange::FULL);
+        ranges
+    }
+
+    fn initial_key_zones() -> [Option<KeyZone>; MAX_KEY_ZONES] {
+        let mut zones = [None; MAX_KEY_ZONES];
+        zones[0] = Some(KeyZone::default());
+        zones
+    }
+
+    /// Key zones of the zone slots in use, indexed by zone.
+    pub fn key_zones(&self) -> [Option<KeyZone>; MAX_KEY_ZONES] {
+        *self.key_zones.lock().unwrap()
+    }
+
+    pub fn formant(&self) -> FormantSettings {
+        *self.formant.lock().unwrap()
+    }
+
+    /// Velocity ranges of the layer slots in use, indexed by layer.
+    pub fn velocity_ranges(&self) -> [Option<VelocityRange>; MAX_VELOCITY_LAYERS] {
+        std::array::from_fn(|layer| unpack_range(self.velocity_ranges[layer].load(Ordering::Relaxed)))
+    }
+
+    /// Set the velocity range of layer slot `layer`; `None` empties it.
+    pub fn store_velocity_range(&self, layer: usize, range: Option<VelocityRange>) {
+        self.velocity_ranges[layer].store(pack_range(range), Ordering::Relaxed);
+    }
+
+    /// Index into `layer_key_buffers` / `layer_buffer_states` of `key` in
+    /// `layer` (1..MAX_VELOCITY_LAYERS).
+    pub fn layer_slot(layer: usize, key: usize) -> usize {
+        (layer - 1) * NUM_KEYS + key
     }
 
-    fn populate_piano_periods() -> Vec<u32> {
-        Self::compute_piano_periods(44100.0)
+    fn populate_piano_periods() -> Vec<f64> {
+        Self::compute_piano_periods(&Self::equal_temperament(DEFAULT_REFERENCE_PITCH), 44100.0)
     }
 
     pub fn update_sample_rate(&self, sample_rate: f32) {
-        let new_periods = Self::compute_piano_periods(sample_rate as f64);
-        let mut piano_periods = self.piano_periods.lock().unwrap();
-        *piano_periods = new_periods;
+        self.store_piano_periods(Self::compute_piano_periods(&self.key_frequencies(), sample_rate as f64));
         *self.sample_rate.lock().unwrap() = sample_rate;
+        // Streaming voices' timeline length depends on it.
+        self.stream_grid_dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Retune every key to A4 = `reference_pitch` Hz. Returns whether the
+    /// tuning changed; the caller re-renders the keys.
+    pub fn set_reference_pitch(&self, reference_pitch: f32) -> bool {
+        {
+            let mut current = self.reference_pitch.lock().unwrap();
+            if *current == reference_pitch {
+                return false;
+            }
+            *current = reference_pitch;
+        }
+        self.retune();
+        true
+    }
+
+    /// Switch to a Scala tuning (`None` = equal temperament); the caller
+    /// re-renders the keys.
+    pub fn set_tuning(&self, tuning: Option<Tuning>) {
+        *self.tuning.lock().unwrap() = tuning;
+        self.retune();
+    }
+
+    /// Recompute every key's frequency and period from the tuning and
+    /// reference pitch.
+    fn retune(&self) {
+        let reference_pitch = *self.reference_pitch.lock().unwrap();
+        let frequencies = match self.tuning.lock().unwrap().as_ref() {
+            Some(tuning) => tuning.key_frequencies(reference_pitch as f64),
+            None => Self::equal_temperament(reference_pitch),
+        };
+        let sample_rate = *self.sample_rate.lock().unwrap();
+        self.store_piano_periods(Self::compute_piano_periods(&frequencies, sample_rate as f64));
+        for (slot, f) in self.key_frequencies.iter().zip(frequencies) {
+            slot.store(f.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    fn store_piano_periods(&self, periods: Vec<f64>) {
+        for (slot, period) in self.piano_periods.iter().zip(periods) {
+            slot.store(period.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    fn equal_temperament(reference_pitch: f32) -> Vec<f64> {
+        (0..NUM_KEYS).map(|key| key_frequency(key, reference_pitch as f64)).collect()
+    }
+
+    fn compute_piano_periods(key_frequencies: &[f64], sample_rate: f64) -> Vec<f64> {
+        // Keys a tuning leaves unmapped (frequency 0) are silent; one sample.
+        key_frequencies.iter().map(|&f| if f > 0.0 { sample_rate / f } else { 1.0 }).collect()
+    }
+
+    /// Exact period (samples) of `key` at the playback sample rate; `None`
+    /// off the keyboard.
+    pub fn exact_period(&self, key: usize) -> Option<f64> {
+        self.piano_periods.get(key).map(|p| f64::from_bits(p.load(Ordering::Relaxed)))
+    }
+
+    /// Fundamental (Hz) of `key` under the current tuning; 0 off the keyboard
+    /// or unmapped by the tuning.
+    pub fn key_frequency(&self, key: usize) -> f64 {
+        self.key_frequencies.get(key).map_or(0.0, |f| f64::from_bits(f.load(Ordering::Relaxed)))
+    }
+
+    /// Fundamental (Hz) of every key under the current tuning.
+    pub fn key_frequencies(&self) -> Vec<f64> {
+        (0..NUM_KEYS).map(|key| self.key_frequency(key)).collect()
+    }
+
+    /// Whole-sample period `key` renders at: its exact period rounded, at
+    /// least one sample (the top keys at low sample rates); `None` off the
+    /// keyboard.
+    pub fn piano_period(&self, key: usize) -> Option<usize> {
+        self.exact_period(key).map(|p| p.round().max(1.0) as usize)
     }
 
-    fn compute_piano_periods(sample_rate: f64) -> Vec<u32> {
-        let mut piano_periods = Vec::with_capacity(NUM_KEYS);
-        for key in 0..NUM_KEYS {
-            // Calculate the frequency for the given key.
-            // A0 (key 0) is 27.5 Hz and each key increases by the factor 2^(1/12).
-            let frequency = 27.5 * 2f64.powf(key as f64 / 12.0);
-            let period = (sample_rate / frequency).round() as u32;
-            piano_periods.push(period);
+    /// Playback rate that brings `key`'s whole-sample period
+    /// ([`Self::piano_period`]) to its exact pitch; 1 off the keyboard.
+    pub fn period_rate(&self, key: usize) -> f64 {
+        match (self.piano_period(key), self.exact_period(key)) {
+            (Some(whole), Some(exact)) => whole as f64 / exact,
+            _ => 1.0,
         }
-        piano_periods
+    }
+
+    /// Highest harmonic `key` can play without aliasing.
+    pub fn max_harmonic(&self, key: usize) -> usize {
+        max_harmonic_for_freq(self.key_frequency(key))
     }
     
     /// Mark all buffers as dirty and cancel any ongoing computations
     pub fn mark_all_buffers_dirty(&self) {
         self.computation_cancel.store(true, Ordering::Relaxed);
+        self.stream_grid_dirty.store(true, Ordering::Relaxed);
+        self.release_tails_dirty.store(true, Ordering::Relaxed);
         
         let mut buffer_states = self.buffer_states.lock().unwrap();
         for state in buffer_states.iter_mut() {
@@ -177,6 +550,42 @@ impl SharedParams {
                 *state = BufferState::Dirty;
             }
         }
+        drop(buffer_states);
+
+        self.mark_layer_buffers_dirty();
+    }
+
+    /// Where the release tail of velocity layer `layer`'s grid starts, as a
+    /// fraction of the note; `None` without one.
+    pub fn layer_release_tail(&self, layer: usize) -> Option<f32> {
+        self.layer_release_tails.get(layer).and_then(|t| unpack_tail(t.load(Ordering::Relaxed)))
+    }
+
+    /// Where the release tail of `key`'s live-layer sound starts, from its
+    /// key zone; `None` without one.
+    pub fn key_release_tail(&self, key: usize) -> Option<f32> {
+        self.key_release_tails.get(key).and_then(|t| unpack_tail(t.load(Ordering::Relaxed)))
+    }
+
+    pub fn store_layer_release_tail(&self, layer: usize, tail: Option<f32>) {
+        self.layer_release_tails[layer].store(pack_tail(tail), Ordering::Relaxed);
+    }
+
+    pub fn store_key_release_tail(&self, key: usize, tail: Option<f32>) {
+        self.key_release_tails[key].store(pack_tail(tail), Ordering::Relaxed);
+    }
+
+    /// Mark the key buffers of every loaded layer above 0 as dirty.
+    pub fn mark_layer_buffers_dirty(&self) {
+        let ranges = self.velocity_ranges();
+        let mut states = self.layer_buffer_states.lock().unwrap();
+        for layer in 1..MAX_VELOCITY_LAYERS {
+            if ranges[layer].is_some() {
+                for key in 0..NUM_KEYS {
+                    states[Self::layer_slot(layer, key)] = BufferState::Dirty;
+                }
+            }
+        }
     }
     
     /// Mark a specific buffer as dirty
@@ -208,10 +617,11 @@ mod tests {
         assert_eq!(phase_data.len(), 8);
         assert_eq!(phase_data[0].len(), 50);
         
-        // Test voices initialization
-        let voices = params.voices.lock().unwrap();
-        assert_eq!(voices.len(), NUM_KEYS);
-        assert!(voices.iter().all(|v| v.is_none()));
+        // Test voice snapshot and key buffer initialization
+        assert_eq!(params.active_voices.len(), NUM_KEYS);
+        assert!(!params.any_voice_active());
+        assert_eq!(params.key_buffers.len(), NUM_KEYS);
+        assert!(params.key_buffers.iter().all(BufferSlot::is_empty));
         
         // Test harmonic enabled flags
         let amp_enabled = params.harmonic_ampl_enabled.lock().unwrap();
@@ -231,22 +641,22 @@ mod tests {
         
         assert_eq!(periods.len(), NUM_KEYS);
         
-        // Test first key (A0 = 27.5 Hz)
+        // Test A0 (key 21 = 27.5 Hz)
         // Period should be around 44100 / 27.5 ≈ 1603 samples
-        let first_period = periods[0];
-        assert!(first_period > 1600 && first_period < 1610);
+        let a0_period = periods[21];
+        assert!(a0_period > 1600.0 && a0_period < 1610.0);
         
         // Test that periods decrease as we go up in pitch
         // (higher frequency = smaller period)
         assert!(periods[0] > periods[12]); // One octave higher should have half the period
         assert!(periods[12] > periods[24]); // Another octave higher
         
-        // Test middle A (A4, key 48) should be around 220Hz
+        // Test middle A (A4, key 69)
         // Period should be around 44100 / 440 ≈ 100 samples (for A4)
-        let middle_a_idx = 48; // A4
+        let middle_a_idx = 69; // A4
         if middle_a_idx < NUM_KEYS {
             let middle_a_period = periods[middle_a_idx];
-            assert!(middle_a_period > 90 && middle_a_period < 110);
+            assert!(middle_a_period > 90.0 && middle_a_period < 110.0);
         }
     }
 
@@ -255,13 +665,37 @@ mod tests {
         let periods = SharedParams::populate_piano_periods();
         
         // Test that each octave (12 keys) doubles the period (halves frequency)
-        for i in 0..NUM_KEYS-12 {
-            let ratio = periods[i] as f64 / periods[i + 12] as f64;
-            // Should be approximately 2.0 (since frequency doubles each octave)
-            assert!((ratio - 2.0).abs() < 0.1, "Period ratio should be ~2.0, got {}", ratio);
+        for i in 0..NUM_KEYS - 12 {
+            let ratio = periods[i] / periods[i + 12];
+            assert!((ratio - 2.0).abs() < 1e-9, "Period ratio should be 2.0, got {}", ratio);
         }
     }
 
+    #[test]
+    fn test_reference_pitch_retunes_every_key() {
+        let params = SharedParams::new(4, 10);
+        assert!(!params.set_reference_pitch(DEFAULT_REFERENCE_PITCH));
+        assert!(params.set_reference_pitch(415.0));
+        assert!((params.key_frequency(69) - 415.0).abs() < 1e-9);
+        assert!((params.key_frequency(57) - 207.5).abs() < 1e-9);
+        assert_eq!(params.piano_period(69), Some(106)); // 44100 / 415
+        assert_eq!(params.piano_period(NUM_KEYS), None);
+        assert!((params.exact_period(69).unwrap() - 44100.0 / 415.0).abs() < 1e-9);
+        assert_eq!(params.key_frequency(NUM_KEYS), 0.0);
+        assert_eq!(params.max_harmonic(NUM_KEYS), 0);
+    }
+
+    #[test]
+    fn test_period_rate_corrects_whole_sample_periods() {
+        let params = SharedParams::new(4, 10);
+        // Key 127 (~12544 Hz) is ~3.52 samples long at 44.1 kHz: it renders
+        // 4 samples per cycle and plays them back faster.
+        assert_eq!(params.piano_period(127), Some(4));
+        let played = 44100.0 / 4.0 * params.period_rate(127);
+        assert!((played - params.key_frequency(127)).abs() < 1e-6, "{}", played);
+        assert_eq!(params.period_rate(NUM_KEYS), 1.0);
+    }
+
     #[test]
     fn test_shared_params_thread_safety() {
         let params = SharedParams::new(4, 10);
@@ -287,4 +721,22 @@ mod tests {
         assert_eq!(params.phase_data.lock().unwrap()[0][0], 2.0);
         assert_eq!(*params.normalization_needed.lock().unwrap(), true);
     }
+
+    #[test]
+    fn test_note_requests_are_taken_once() {
+        let params = SharedParams::new(4, 10);
+        assert_eq!(params.take_note_request(3), None);
+        params.request_note(3, NoteRequest::On);
+        assert!(params.note_on_pending(3));
+        params.request_note(3, NoteRequest::Off);
+        assert!(!params.note_on_pending(3));
+        assert_eq!(params.take_note_request(3), Some(NoteRequest::Off));
+        assert_eq!(params.take_note_request(3), None);
+        params.request_note(NUM_KEYS, NoteRequest::On); // out of range: ignored
+        assert_eq!(params.take_note_request(NUM_KEYS), None);
+
+        params.set_voice_active(5, true);
+        assert!(params.voice_active(5) && params.any_voice_active());
+        assert!(!params.voice_active(NUM_KEYS));
+    }
 }
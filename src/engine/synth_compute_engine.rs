@@ -12,17 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::Ordering;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use realfft::num_complex::Complex;
 use realfft::{ComplexToReal, RealFftPlanner};
-use crate::constants::{NUM_HARMONICS, NUM_OF_BUCKETS_DEFAULT, TWO_PI, NUM_KEYS, max_harmonic_for_key};
-use crate::params::{CurveType, LeSynthParams};
-use super::{ChartType, ExecutionMode, SharedParams};
+use crate::constants::{
+    NUM_HARMONICS, NUM_OF_BUCKETS_DEFAULT, TWO_PI, NUM_KEYS, PREVIEW_KEY, key_name, MIN_OFFSET_AMP,
+    MAX_OFFSET_AMP, MIN_OFFSET_PHASE, MAX_OFFSET_PHASE,
+};
+use nih_plug::prelude::ParamSetter;
+use crate::params::analysis_state::{pack_rows, unpack_rows};
+use crate::params::lesynth_file::HarmonicCurveState;
+use crate::params::{
+    AnalysisState, CurveType, HarmonicParam, KeyZoneState, LeSynthParams, LesynthFile, NestedFourierSeries,
+    VelocityLayerState, ANALYSIS_STATE_VERSION,
+};
+use super::{
+    nearest_key, resample_formants, select_layers, select_zones, BufferSlot, ChartType, ExecutionMode, FormantSettings,
+    GridSlot, KeyZone, LayerBlend, LoadedGrid, RenderMode, SharedParams, Tuning, VelocityRange, MAX_KEY_ZONES,
+    MAX_VELOCITY_LAYERS,
+};
+use super::oscillator::{GridView, OscillatorBank, StreamGrid};
 use super::shared_params::BufferState;
+use crate::voice::{mix_gains, velocity_darkness, velocity_gain, Adsr, NoteStart, Voice};
 
 /// Snapshot the per-bucket pitch ratios for playback — but only in Analysis
 /// mode. In Synth mode playback is always flat, so this returns empty and every
@@ -65,6 +83,26 @@ fn resample_row(src: &[f32], new_len: usize) -> Vec<f32> {
         .collect()
 }
 
+/// `buffer` replayed `rate` times as fast, as a voice reads it: linearly
+/// interpolated into `len / rate` samples (rounded), over exactly the whole
+/// buffer, so a looped replay stays seamless.
+fn replay_at_rate(buffer: &[f32], rate: f64) -> Vec<f32> {
+    let len = buffer.len();
+    let new_len = (len as f64 / rate).round() as usize;
+    if len == 0 || new_len == 0 {
+        return Vec::new();
+    }
+    let step = len as f64 / new_len as f64;
+    (0..new_len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let lo = (pos as usize).min(len - 1);
+            let hi = (lo + 1) % len;
+            buffer[lo] + (buffer[hi] - buffer[lo]) * pos.fract() as f32
+        })
+        .collect()
+}
+
 /// Rendered period length (samples) for `bucket`: the key's base period scaled
 /// by the bucket's pitch ratio (clamped ≥ 2). A missing/empty ratio means flat.
 fn bucket_period(base_period: usize, ratios: &[f32], bucket: usize) -> usize {
@@ -177,6 +215,10 @@ fn render_bucket_ifft(
 ///
 /// When `cancel` is supplied (background thread) the render bails out early on
 /// request and periodically yields so the GUI stays responsive.
+///
+/// `mode` picks the renderer: [`RenderMode::Periodic`] is described above;
+/// [`RenderMode::Continuous`] hands off to [`render_key_buffer_continuous`],
+/// which keeps the same timeline and length.
 fn render_key_buffer(
     num_harmonics: usize,
     ampl: &[Vec<f32>],
@@ -187,32 +229,34 @@ fn render_key_buffer(
     max_harmonic: usize,
     ratios: &[f32],
     target_samples: usize,
+    mode: RenderMode,
     cancel: Option<&std::sync::atomic::AtomicBool>,
 ) -> Vec<f32> {
     let num_buckets = ampl.first().map(|r| r.len()).unwrap_or(0);
     if num_buckets == 0 {
         return Vec::new();
     }
-    let drive_by_time = target_samples > 0;
+    if mode == RenderMode::Continuous {
+        return render_key_buffer_continuous(
+            num_harmonics,
+            ampl,
+            phase,
+            ampl_enabled,
+            phase_enabled,
+            base_period,
+            max_harmonic,
+            ratios,
+            target_samples,
+            cancel,
+        );
+    }
 
     let mut sound: Vec<f32> = Vec::new();
     let mut produced = 0usize;
     let mut chunk = 0usize;
     let mut last_yield = 0usize;
     let mut ifft_bank = IfftBank::new();
-    loop {
-        let bucket = if drive_by_time {
-            if produced >= target_samples {
-                break;
-            }
-            (((produced as f32 / target_samples as f32) * num_buckets as f32) as usize)
-                .min(num_buckets - 1)
-        } else {
-            if chunk >= num_buckets {
-                break;
-            }
-            chunk
-        };
+    while let Some(bucket) = timeline_bucket(produced, chunk, num_buckets, target_samples) {
 
         if let Some(c) = cancel {
             if c.load(Ordering::Relaxed) {
@@ -266,6 +310,107 @@ fn render_key_buffer(
     sound
 }
 
+/// Bucket of the next period on [`render_key_buffer`]'s timeline, given the
+/// samples `produced` and periods (`chunk`) rendered so far, or `None` once
+/// the note is complete: one period per bucket in order (Synth mode,
+/// `target_samples == 0`), or the bucket at this point in time (Analysis).
+fn timeline_bucket(produced: usize, chunk: usize, num_buckets: usize, target_samples: usize) -> Option<usize> {
+    if target_samples > 0 {
+        if produced >= target_samples {
+            return None;
+        }
+        Some((((produced as f32 / target_samples as f32) * num_buckets as f32) as usize).min(num_buckets - 1))
+    } else if chunk < num_buckets {
+        Some(chunk)
+    } else {
+        None
+    }
+}
+
+/// Continuous-phase counterpart of [`render_key_buffer`]
+/// ([`RenderMode::Continuous`]). It produces the same number of samples over
+/// the same bucket timeline, but each harmonic is one oscillator for the
+/// whole note ([`OscillatorBank`]): its phase accumulates sample by sample at
+/// `(n + 1) · ratio / base_period` cycles, while its amplitude, its phase
+/// offset (along the shorter arc) and the pitch ratio are interpolated
+/// linearly between bucket centres. Bucket edges therefore no longer step,
+/// and vibrato glides instead of switching period length. For a flat, steady
+/// grid the result matches the periodic render sample for sample.
+fn render_key_buffer_continuous(
+    num_harmonics: usize,
+    ampl: &[Vec<f32>],
+    phase: &[Vec<f32>],
+    ampl_enabled: &[bool],
+    phase_enabled: &[bool],
+    base_period: usize,
+    max_harmonic: usize,
+    ratios: &[f32],
+    target_samples: usize,
+    cancel: Option<&std::sync::atomic::AtomicBool>,
+) -> Vec<f32> {
+    let num_buckets = ampl.first().map(|r| r.len()).unwrap_or(0);
+    if num_buckets == 0 || base_period == 0 {
+        return Vec::new();
+    }
+    let grid = GridView { ampl, phase, ampl_enabled, phase_enabled, ratios };
+    let len = timeline_len(num_buckets, base_period, ratios, target_samples);
+    let mut bank = OscillatorBank::new(
+        &grid,
+        base_period,
+        max_harmonic.min(num_harmonics),
+        len,
+        timeline_span(len, target_samples),
+    );
+
+    let mut sound = Vec::with_capacity(len);
+    for i in 0..len {
+        if let Some(c) = cancel {
+            if i % 8192 == 0 && i > 0 {
+                if c.load(Ordering::Relaxed) {
+                    return Vec::new();
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        sound.push(bank.next_sample(&grid, false));
+    }
+    sound
+}
+
+/// Length in samples of [`render_key_buffer`]'s output: whole periods along
+/// the bucket timeline. Continuous rendering and streamed voices use the same
+/// length, so looping and note length agree across modes.
+fn timeline_len(num_buckets: usize, base_period: usize, ratios: &[f32], target_samples: usize) -> usize {
+    let mut len = 0usize;
+    let mut chunk = 0usize;
+    while let Some(bucket) = timeline_bucket(len, chunk, num_buckets, target_samples) {
+        len += bucket_period(base_period, ratios, bucket);
+        chunk += 1;
+    }
+    len
+}
+
+/// Samples the bucket centres are spread over: the source duration in
+/// Analysis mode, the whole note in Synth mode (one equal period per bucket).
+fn timeline_span(len: usize, target_samples: usize) -> f64 {
+    (if target_samples > 0 { target_samples } else { len }) as f64
+}
+
+/// Where the decaying tail of an analysed sound starts, as a fraction of
+/// its timeline: after the last bucket within 6 dB of the loudest one.
+/// `None` for a silent grid or one still that loud in its last bucket.
+fn release_tail_start(ampl: &[Vec<f32>]) -> Option<f32> {
+    let num_buckets = ampl.first().map_or(0, Vec::len);
+    let energy: Vec<f32> =
+        (0..num_buckets).map(|b| ampl.iter().map(|row| row.get(b).map_or(0.0, |a| a * a)).sum()).collect();
+    let peak = energy.iter().copied().fold(0.0, f32::max);
+    if peak <= 0.0 {
+        return None;
+    }
+    let tail = energy.iter().rposition(|&e| e >= 0.25 * peak)? + 1;
+    (tail < num_buckets).then(|| tail as f32 / num_buckets as f32)
+}
+
 /// Playback length in samples for `key`: `0` in Synth mode (caller renders one
 /// period per bucket), or the source's wall-clock duration at the playback
 /// sample rate in Analysis mode ("preserve seconds").
@@ -282,26 +427,246 @@ fn target_samples_for(shared_params: &SharedParams) -> usize {
     }
 }
 
+/// Source length in seconds of `len` samples at `sample_rate`.
+fn source_duration(len: usize, sample_rate: f32) -> f32 {
+    if sample_rate > 0.0 {
+        len as f32 / sample_rate
+    } else {
+        0.0
+    }
+}
+
+/// Scale each bucket of `rows` (`[harmonic][bucket]`) whose amplitudes sum
+/// above 1 down to a sum of 1, as playback normalises the grid.
+fn normalize_per_bucket(rows: &mut [Vec<f32>]) {
+    let num_buckets = rows.first().map(|r| r.len()).unwrap_or(0);
+    for b in 0..num_buckets {
+        let sum: f32 = rows.iter().map(|harmonic| harmonic[b]).sum();
+        if sum > 1.0 {
+            rows.iter_mut().for_each(|harmonic| harmonic[b] /= sum);
+        }
+    }
+}
+
+/// What a key renders from when the key zones pick something other than the
+/// live grid alone.
+struct ZonedGrid {
+    /// Normalised amplitudes.
+    ampl: Vec<Vec<f32>>,
+    phase: Vec<Vec<f32>>,
+    ratios: Vec<f32>,
+    target_samples: usize,
+    /// Fundamental (Hz) the primary zone was analysed at.
+    base_freq: f32,
+}
+
+/// The grid `key` renders from under the key zones (see
+/// [`super::select_zones`]), or `None` when that is the live grid as is: in
+/// Synth mode, or when zone 0 plays unblended. `live_ampl` / `live_phase` are
+/// the live grid's normalised amplitudes and phases, which zone 0 stands for.
+fn zoned_grid_for_key(
+    shared_params: &SharedParams,
+    key: usize,
+    live_ampl: &[Vec<f32>],
+    live_phase: &[Vec<f32>],
+) -> Option<ZonedGrid> {
+    if shared_params.execution_mode() != ExecutionMode::Analysis {
+        return None;
+    }
+    let interpolate = shared_params.zone_interpolation.load(Ordering::Relaxed);
+    let pick = select_zones(&shared_params.key_zones(), key, interpolate);
+    if pick.primary == 0 && pick.mix == 0.0 {
+        return None;
+    }
+    let (live_ratios, live_target) = (bucket_pitch_ratios(shared_params), target_samples_for(shared_params));
+    let live_base = *shared_params.analysis_base_freq.lock().unwrap();
+    let sr = *shared_params.sample_rate.lock().unwrap();
+
+    let grids = shared_params.zone_grids.lock().unwrap();
+    let zone_ampl = |zone: usize| -> Option<Vec<Vec<f32>>> {
+        if zone == 0 {
+            return Some(live_ampl.to_vec());
+        }
+        let mut ampl = grids.get(zone - 1)?.as_ref()?.amplitude.clone();
+        normalize_per_bucket(&mut ampl);
+        Some(ampl)
+    };
+    let mut zoned = match pick.primary {
+        0 => ZonedGrid {
+            ampl: zone_ampl(0)?,
+            phase: live_phase.to_vec(),
+            ratios: live_ratios,
+            target_samples: live_target,
+            base_freq: live_base,
+        },
+        zone => {
+            let grid = grids.get(zone - 1)?.as_ref()?;
+            ZonedGrid {
+                ampl: zone_ampl(zone)?,
+                phase: grid.phase.clone(),
+                ratios: grid.pitch_ratio.clone(),
+                target_samples: (grid.duration_secs * sr).round().max(0.0) as usize,
+                base_freq: grid.base_freq,
+            }
+        }
+    };
+    if pick.mix > 0.0 {
+        if let Some(other) = zone_ampl(pick.secondary) {
+            let buckets = zoned.ampl.first().map(|r| r.len()).unwrap_or(0);
+            for (row, other) in zoned.ampl.iter_mut().zip(&other) {
+                let other = resample_row(other, buckets);
+                for (a, b) in row.iter_mut().zip(other) {
+                    *a += pick.mix * (b - *a);
+                }
+            }
+        }
+    }
+    Some(zoned)
+}
+
+/// `ampl` (normalised, analysed at `source_freq`) re-sampled so its formants
+/// stay put on a key of `base_period` samples (see
+/// [`super::preserve_formants`]), or `None` when that is off or does not
+/// apply (see [`formant_period`]).
+fn formant_corrected(
+    shared_params: &SharedParams,
+    ampl: &[Vec<f32>],
+    source_freq: f32,
+    base_period: usize,
+) -> Option<Vec<Vec<f32>>> {
+    let period = formant_period(shared_params, source_freq)?;
+    (base_period > 0).then(|| resample_formants(ampl, period / base_period as f32))
+}
+
+/// Period (samples) of a grid analysed at `source_freq`, scaled by the
+/// formant shift: a key of `base_period` samples re-samples the grid's
+/// formants by `period / base_period`. `None` when formant preservation is
+/// off or does not apply: in Synth mode, or without a source pitch.
+fn formant_period(shared_params: &SharedParams, source_freq: f32) -> Option<f32> {
+    let formant = shared_params.formant();
+    if !formant.preserve || shared_params.execution_mode() != ExecutionMode::Analysis || source_freq <= 0.0 {
+        return None;
+    }
+    let sr = *shared_params.sample_rate.lock().unwrap();
+    Some(sr / (source_freq * formant.shift_factor()))
+}
+
+/// How often the background thread checks the harmonic params for changes
+/// made outside the editor (host automation, state load).
+const PARAM_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the formant params must hold still before every key is
+/// re-rendered with them (see `SynthComputeEngine::sync_formant_from_params`).
+const FORMANT_SETTLE: Duration = Duration::from_millis(150);
+
+/// How soon a grid edit reaches streaming voices: the background thread
+/// republishes their grid snapshot at this interval while it is stale.
+const STREAM_GRID_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Longest the analysis worker blocks waiting for a job before re-checking
+/// for shutdown (a push wakes it immediately).
+const ANALYSIS_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Frame length assumed for a wavetable WAV without a `clm ` chunk (the
+/// common 2048-sample single-cycle size).
+const DEFAULT_WAVETABLE_FRAME_LEN: usize = 2048;
+
+/// How long one note takes to sweep an imported wavetable when no duration
+/// is given.
+const DEFAULT_WAVETABLE_SECS: f32 = 2.0;
+
+/// Hash of everything [`SynthComputeEngine::write_curve_row`] draws one
+/// harmonic's amplitude or phase row from: curve type, offset and wobble of
+/// `curves`, and `harmonic`'s nested-Fourier series. Two equal fingerprints
+/// produce the same row.
+fn curve_fingerprint(curves: &HarmonicCurveState, harmonic: &HarmonicParam, chart_type: ChartType) -> u64 {
+    let (curve, offset, wobble_amp, wobble_freq) = curves.chart(chart_type);
+    let mut hasher = DefaultHasher::new();
+    matches!(curve, CurveType::NestedFourier).hash(&mut hasher);
+    for v in [offset, wobble_amp, wobble_freq] {
+        v.to_bits().hash(&mut hasher);
+    }
+    {
+        let state = harmonic.nested_fourier.read().unwrap();
+        let series = state.series(chart_type);
+        for v in series.amps.iter().chain(series.phases.iter()) {
+            v.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn chart_slot(chart_type: ChartType) -> usize {
+    match chart_type {
+        ChartType::Amp => 0,
+        ChartType::Phase => 1,
+    }
+}
+
 #[derive(Clone)]
 pub struct SynthComputeEngine {
     synth_params: Arc<LeSynthParams>,
     pub shared_params: Arc<SharedParams>,
+    /// Per-harmonic `[amp, phase]` [`curve_fingerprint`] of the params each
+    /// grid row was last written from. Seeded with the param defaults: the grid
+    /// starts silent even though untouched harmonics carry non-zero default
+    /// offsets, and only an actual change should (re)write a row.
+    curve_fingerprints: Arc<Mutex<Vec<[u64; 2]>>>,
+    /// The seed of [`Self::curve_fingerprints`], restored when a `.lesynth`
+    /// file replaces the grid.
+    default_curve_fingerprints: Arc<Vec<[u64; 2]>>,
+    /// Curve params of a loaded `.lesynth` file that haven't reached the host
+    /// params yet (see [`Self::apply_pending_curves`]).
+    pending_curves: Arc<Mutex<Option<PendingCurves>>>,
+    /// Formant params that differ from [`SharedParams::formant`], with when
+    /// they last changed (see [`Self::sync_formant_from_params`]).
+    formant_change: Arc<Mutex<Option<(FormantSettings, Instant)>>>,
+}
+
+struct PendingCurves {
+    curves: Vec<HarmonicCurveState>,
+    /// Whether the editor has handed them to the host.
+    sent: bool,
+    /// Per harmonic, `[amp, phase]` [`curve_fingerprint`] of the params the
+    /// file replaced, while its rows follow the file's values; `None` once
+    /// the params hold those values or have moved on.
+    superseded: Vec<[Option<u64>; 2]>,
+}
+
+/// `[amp, phase]` [`curve_fingerprint`] of `harmonic`'s current params.
+fn param_fingerprints(harmonic: &HarmonicParam) -> [u64; 2] {
+    let curves = HarmonicCurveState::capture(harmonic);
+    [ChartType::Amp, ChartType::Phase].map(|chart_type| curve_fingerprint(&curves, harmonic, chart_type))
 }
 
 impl SynthComputeEngine {
     pub fn new(synth_params_p: Arc<LeSynthParams>) -> Self {
         let buckets = NUM_OF_BUCKETS_DEFAULT;
+        let curve_fingerprints: Vec<[u64; 2]> = synth_params_p.harmonics.iter().map(param_fingerprints).collect();
         let engine = Self {
             synth_params: synth_params_p,
             shared_params: Arc::new(SharedParams::new(NUM_HARMONICS, buckets)),
+            default_curve_fingerprints: Arc::new(curve_fingerprints.clone()),
+            curve_fingerprints: Arc::new(Mutex::new(curve_fingerprints)),
+            pending_curves: Arc::new(Mutex::new(None)),
+            formant_change: Arc::new(Mutex::new(None)),
         };
         
         // Start background computation thread
         engine.start_async_computation_thread();
+        // Start the worker that runs host-pushed analysis jobs
+        engine.start_analysis_worker_thread();
         
         engine
     }
 
+    /// Stop the background threads (computation and analysis worker). Called
+    /// when the owning plugin instance is dropped.
+    pub fn shutdown(&self) {
+        self.shared_params.shutdown.store(true, Ordering::Relaxed);
+        self.shared_params.computation_cancel.store(true, Ordering::Relaxed);
+    }
+
     pub fn fill_constant_curve(&self, n: usize, value: f32, chart_type: ChartType) {
         let wobble_amp = match chart_type {
             ChartType::Amp => self.synth_params.harmonics[n].wobble_amp_amp.value(),
@@ -327,22 +692,15 @@ impl SynthComputeEngine {
         self.write_constant_row(n, value, chart_type);
         self.set_normalization_needed(true);
         self.shared_params.mark_all_buffers_dirty();
-        // Update assembled chart with key 24 for immediate preview
-        self.update_assembled_chart_with_key24();
+        // Update assembled chart with the preview key for immediate preview
+        self.update_assembled_chart_preview();
     }
 
     /// Write harmonic `n`'s constant-curve amplitude/phase row, without the
     /// normalize/dirty/chart side effects. Used both by the public fill (which
     /// adds those) and by bulk operations that batch the side effects once.
     fn write_constant_row(&self, n: usize, value: f32, chart_type: ChartType) {
-        let wobble_amp = match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].wobble_amp_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].wobble_amp_phase.value(),
-        };
-        let wobble_freq = match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].wobble_freq_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].wobble_freq_phase.value(),
-        };
+        let (_, _, wobble_amp, wobble_freq) = self.curves_of(n, chart_type).chart(chart_type);
 
         let mut data = match chart_type {
             ChartType::Amp => self.shared_params.amplitude_data.lock().unwrap(),
@@ -361,6 +719,8 @@ impl SynthComputeEngine {
             };
             data[n][bucket] = final_value;
         }
+        drop(data);
+        self.record_curve_fingerprint(n, chart_type);
     }
 
     pub fn fill_sin_curve(&self, n: usize, chart_type: ChartType) {
@@ -406,8 +766,8 @@ impl SynthComputeEngine {
         // Mark all buffers as dirty since harmonic parameters changed
         drop(data); // Release the lock before calling mark_all_buffers_dirty
         self.shared_params.mark_all_buffers_dirty();
-        // Update assembled chart with key 24 for immediate preview
-        self.update_assembled_chart_with_key24();
+        // Update assembled chart with the preview key for immediate preview
+        self.update_assembled_chart_preview();
     }
 
     /// Fill harmonic n's amplitude or phase data using a Fourier series of sub-harmonics.
@@ -418,22 +778,15 @@ impl SynthComputeEngine {
         self.write_nested_fourier_row(n, chart_type);
         self.set_normalization_needed(true);
         self.shared_params.mark_all_buffers_dirty();
-        self.update_assembled_chart_with_key24();
+        self.update_assembled_chart_preview();
     }
 
     /// Write harmonic `n`'s nested-Fourier amplitude/phase row, without the
     /// normalize/dirty/chart side effects (see [`Self::write_constant_row`]).
     fn write_nested_fourier_row(&self, n: usize, chart_type: ChartType) {
         let harmonic = &self.synth_params.harmonics[n];
-        let offset = match chart_type {
-            ChartType::Amp => harmonic.curve_offset_amp.value() as f64,
-            ChartType::Phase => harmonic.curve_offset_phase.value() as f64,
-        };
-        let (sub_amps, sub_phases) = {
-            let state = harmonic.nested_fourier.read().unwrap();
-            let series = state.series(chart_type);
-            (series.amps, series.phases)
-        };
+        let offset = self.curve_offset_of(n, chart_type) as f64;
+        let series = harmonic.nested_fourier.read().unwrap().series(chart_type).clone();
 
         let mut data = match chart_type {
             ChartType::Amp => self.shared_params.amplitude_data.lock().unwrap(),
@@ -442,17 +795,14 @@ impl SynthComputeEngine {
         let num_buckets = data[n].len();
 
         for bucket in 0..num_buckets {
-            let t = bucket as f64 / num_buckets as f64;
-            let mut value = offset;
-            for (k, (&amp, &phase)) in sub_amps.iter().zip(sub_phases.iter()).enumerate() {
-                value += amp as f64
-                    * (2.0 * std::f64::consts::PI * (k + 1) as f64 * t + phase as f64).sin();
-            }
+            let value = series.value_at(offset, bucket as f64 / num_buckets as f64);
             data[n][bucket] = match chart_type {
                 ChartType::Amp => value.clamp(0.0, 1.0) as f32,
                 ChartType::Phase => value as f32,
             };
         }
+        drop(data);
+        self.record_curve_fingerprint(n, chart_type);
     }
 
     /// Refill harmonic `n`'s amplitude or phase row from its current Synth-mode
@@ -467,18 +817,206 @@ impl SynthComputeEngine {
         }
     }
 
-    fn curve_type_of(&self, n: usize, chart_type: ChartType) -> CurveType {
-        match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].curve_type_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].curve_type_phase.value(),
+    /// Write harmonic `n`'s row from its Synth-mode curve without any side
+    /// effects; the batch counterpart of [`Self::refill_harmonic_curve`].
+    fn write_curve_row(&self, n: usize, chart_type: ChartType) {
+        match self.curve_type_of(n, chart_type) {
+            CurveType::Constant => {
+                self.write_constant_row(n, self.curve_offset_of(n, chart_type), chart_type);
+            }
+            CurveType::NestedFourier => self.write_nested_fourier_row(n, chart_type),
         }
     }
 
-    fn curve_offset_of(&self, n: usize, chart_type: ChartType) -> f32 {
-        match chart_type {
-            ChartType::Amp => self.synth_params.harmonics[n].curve_offset_amp.value(),
-            ChartType::Phase => self.synth_params.harmonics[n].curve_offset_phase.value(),
+    /// Remember that harmonic `n`'s row now reflects its current params.
+    fn record_curve_fingerprint(&self, n: usize, chart_type: ChartType) {
+        if let Some(harmonic) = self.synth_params.harmonics.get(n) {
+            let fingerprint = curve_fingerprint(&self.curves_of(n, chart_type), harmonic, chart_type);
+            if let Some(slots) = self.curve_fingerprints.lock().unwrap().get_mut(n) {
+                slots[chart_slot(chart_type)] = fingerprint;
+            }
+        }
+    }
+
+    /// Refill every grid row whose curve params changed since the row was last
+    /// written, so host automation and restored state reach the sound without
+    /// the editor. In Analysis mode only rows with a custom override follow
+    /// their curve; a change on any other row is acknowledged without touching
+    /// the analysed data (and so doesn't resurface on a later mode switch).
+    /// Rows a loaded `.lesynth` file drew keep its values until their params
+    /// take them on or move (see [`Self::curves_of`]). Returns whether any
+    /// row was rewritten.
+    pub fn sync_curves_from_params(&self) -> bool {
+        self.settle_pending_curves();
+        let analysis = self.shared_params.execution_mode() == ExecutionMode::Analysis;
+        let applied = self.curve_fingerprints.lock().unwrap().clone();
+        let ampl_custom = self.shared_params.harmonic_ampl_custom.lock().unwrap().clone();
+        let phase_custom = self.shared_params.harmonic_phase_custom.lock().unwrap().clone();
+
+        let mut refilled = false;
+        for (n, harmonic) in self.synth_params.harmonics.iter().enumerate().take(applied.len()) {
+            for chart_type in [ChartType::Amp, ChartType::Phase] {
+                let slot = chart_slot(chart_type);
+                let fingerprint = curve_fingerprint(&self.curves_of(n, chart_type), harmonic, chart_type);
+                if applied[n][slot] == fingerprint {
+                    continue;
+                }
+                let custom = match chart_type {
+                    ChartType::Amp => ampl_custom.get(n).copied().unwrap_or(false),
+                    ChartType::Phase => phase_custom.get(n).copied().unwrap_or(false),
+                };
+                if analysis && !custom {
+                    self.curve_fingerprints.lock().unwrap()[n][slot] = fingerprint;
+                    continue;
+                }
+                self.write_curve_row(n, chart_type);
+                refilled = true;
+            }
+        }
+
+        if refilled {
+            self.set_normalization_needed(true);
+            self.shared_params.mark_all_buffers_dirty();
+            self.update_assembled_chart_preview();
+            log::debug!("Refilled grid rows from changed harmonic params");
+        }
+        refilled
+    }
+
+    /// Mirror the formant params into [`SharedParams::formant`], re-rendering
+    /// every key, once they have held still for [`FORMANT_SETTLE`]: a drag of
+    /// the shift slider re-renders the keys once, not on every step. Returns
+    /// whether they changed.
+    pub fn sync_formant_from_params(&self) -> bool {
+        let formant = FormantSettings {
+            preserve: self.synth_params.formant_preserve.value(),
+            shift_semitones: self.synth_params.formant_shift.value(),
+        };
+        {
+            let mut change = self.formant_change.lock().unwrap();
+            let mut current = self.shared_params.formant.lock().unwrap();
+            if *current == formant {
+                *change = None;
+                return false;
+            }
+            match *change {
+                Some((pending, since)) if pending == formant => {
+                    if since.elapsed() < FORMANT_SETTLE {
+                        return false;
+                    }
+                }
+                _ => {
+                    *change = Some((formant, Instant::now()));
+                    return false;
+                }
+            }
+            *change = None;
+            *current = formant;
+        }
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+        true
+    }
+
+    /// Retune every key to the reference pitch param, re-rendering them when
+    /// it changed. Returns whether it did.
+    pub fn sync_tuning_from_params(&self) -> bool {
+        if !self.shared_params.set_reference_pitch(self.synth_params.reference_pitch.value()) {
+            return false;
+        }
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+        true
+    }
+
+    /// Switch to a Scala tuning (`None` = back to equal temperament), persist
+    /// it in the plugin state and re-render every key.
+    pub fn set_tuning(&self, tuning: Option<Tuning>) {
+        *self.synth_params.tuning.write().unwrap() = tuning.as_ref().map(Tuning::to_state);
+        self.shared_params.set_tuning(tuning);
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
+
+    /// Load a Scala scale and optional keyboard mapping from disk and play in
+    /// that tuning. Returns the tuning's name.
+    pub fn load_tuning_files(&self, scl_path: &Path, kbm_path: Option<&Path>) -> Result<String, String> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+        };
+        let scl = read(scl_path)?;
+        let kbm = kbm_path.map(read).transpose()?;
+        let file_name = scl_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let tuning = Tuning::parse(&file_name, &scl, kbm.as_deref())?;
+        let name = tuning.name.clone();
+        self.set_tuning(Some(tuning));
+        log::info!("Tuned to {} ({})", name, scl_path.display());
+        Ok(name)
+    }
+
+    /// Name of the Scala tuning in use, `None` under equal temperament.
+    pub fn tuning_name(&self) -> Option<String> {
+        self.shared_params.tuning.lock().unwrap().as_ref().map(|t| t.name.clone())
+    }
+
+    /// Apply the tuning persisted in the plugin state, if it differs from the
+    /// one in use. Called from `Plugin::initialize`; a tuning that no longer
+    /// parses falls back to equal temperament.
+    pub fn restore_tuning(&self) {
+        let state = self.synth_params.tuning.read().unwrap().clone();
+        let tuning = state.and_then(|state| {
+            Tuning::from_state(&state)
+                .map_err(|e| log::warn!("Ignoring persisted tuning '{}': {}", state.name, e))
+                .ok()
+        });
+        if *self.shared_params.tuning.lock().unwrap() != tuning {
+            self.shared_params.set_tuning(tuning);
+            self.shared_params.mark_all_buffers_dirty();
+        }
+    }
+
+    /// Apply the render mode and streaming switch persisted in the plugin
+    /// state. Called from `Plugin::initialize`.
+    pub fn restore_render_settings(&self) {
+        let mode = *self.synth_params.render_mode.read().unwrap();
+        self.set_render_mode(RenderMode::from_u8(mode));
+        let streaming = *self.synth_params.streaming.read().unwrap();
+        self.set_streaming(streaming);
+    }
+
+    /// Bring the grid in line with freshly loaded params: apply the restored
+    /// bucket count (Synth grid only; an analysed grid keeps the source's) and
+    /// refill every row whose curve differs. Called from `Plugin::initialize`,
+    /// so a project reloaded with the editor closed plays back as saved.
+    pub fn rebuild_grid_from_params(&self) {
+        let has_analysis = *self.shared_params.analysis_duration_secs.lock().unwrap() > 0.0;
+        if !has_analysis {
+            let buckets = self.synth_params.num_buckets.value().max(1) as usize;
+            if self.num_buckets() != buckets {
+                self.set_num_buckets(buckets);
+            }
         }
+        self.sync_curves_from_params();
+    }
+
+    fn curve_type_of(&self, n: usize, chart_type: ChartType) -> CurveType {
+        self.curves_of(n, chart_type).chart(chart_type).0
+    }
+
+    fn curve_offset_of(&self, n: usize, chart_type: ChartType) -> f32 {
+        self.curves_of(n, chart_type).chart(chart_type).1
+    }
+
+    /// The curve params harmonic `n`'s `chart_type` row is drawn from: its
+    /// host params or, while a loaded `.lesynth` file supersedes them, the
+    /// file's.
+    fn curves_of(&self, n: usize, chart_type: ChartType) -> HarmonicCurveState {
+        let pending = self.pending_curves.lock().unwrap();
+        let from_file = pending.as_ref().and_then(|p| {
+            let superseded = p.superseded.get(n).and_then(|s| s[chart_slot(chart_type)]);
+            superseded.and(p.curves.get(n).copied())
+        });
+        from_file.unwrap_or_else(|| HarmonicCurveState::capture(&self.synth_params.harmonics[n]))
     }
 
     /// Current number of buckets (time-resolution of the synthesised envelope).
@@ -529,7 +1067,7 @@ impl SynthComputeEngine {
 
         self.set_normalization_needed(true);
         self.shared_params.mark_all_buffers_dirty();
-        self.update_assembled_chart_with_key24();
+        self.update_assembled_chart_preview();
     }
 
     pub fn normalize_amplitude_data(&self) {
@@ -570,8 +1108,7 @@ impl SynthComputeEngine {
         let num_harmonics = self.shared_params.amplitude_data.lock().unwrap().len();
         let ampl_data_normalized = self.shared_params.amplitude_data_normalized.lock().unwrap();
         let phase_data = self.shared_params.phase_data.lock().unwrap();
-        let piano_periods = self.shared_params.piano_periods.lock().unwrap();
-        let base_period = piano_periods[key] as usize;
+        let base_period = self.shared_params.piano_period(key).unwrap_or(1);
         // Per-bucket vibrato ratios apply only in Analysis mode; flat otherwise.
         let pitch_ratio = bucket_pitch_ratios(&self.shared_params);
         // Hoist the per-harmonic enable flags out of the hot loops — locking
@@ -581,20 +1118,33 @@ impl SynthComputeEngine {
         let harmonic_phase_enabled = self.shared_params.harmonic_phase_enabled.lock().unwrap();
 
         // Calculate maximum usable harmonic for this key to prevent aliasing
-        let max_harmonic = max_harmonic_for_key(key);
+        let max_harmonic = self.shared_params.max_harmonic(key);
         // Synth mode: one period per bucket. Analysis mode: the source duration.
         let target_samples = target_samples_for(&self.shared_params);
+        // Key zones (Analysis mode) may swap in another zone's grid.
+        let zoned = zoned_grid_for_key(&self.shared_params, key, &ampl_data_normalized, &phase_data);
+        let (ampl, phase, pitch_ratio, target_samples) = match &zoned {
+            Some(z) => (&z.ampl[..], &z.phase[..], &z.ratios[..], z.target_samples),
+            None => (&ampl_data_normalized[..], &phase_data[..], &pitch_ratio[..], target_samples),
+        };
+        // Formant preservation (Analysis mode) re-samples the amplitudes for this key.
+        let source_freq = zoned
+            .as_ref()
+            .map_or_else(|| *self.shared_params.analysis_base_freq.lock().unwrap(), |z| z.base_freq);
+        let corrected = formant_corrected(&self.shared_params, ampl, source_freq, base_period);
+        let ampl = corrected.as_deref().unwrap_or(ampl);
 
         let sound = render_key_buffer(
             num_harmonics,
-            &ampl_data_normalized,
-            &phase_data,
+            ampl,
+            phase,
             &harmonic_ampl_enabled,
             &harmonic_phase_enabled,
             base_period,
             max_harmonic,
-            &pitch_ratio,
+            pitch_ratio,
             target_samples,
+            self.shared_params.render_mode(),
             None,
         );
 
@@ -607,18 +1157,19 @@ impl SynthComputeEngine {
 
     // Quick mixdown of active voices for plotting
     pub fn update_plotted_mix(&self) {
-        let voices = self.shared_params.voices.lock().unwrap();
+        let sp = &self.shared_params;
+        // Keys sounding on the audio thread, plus ones just requested from
+        // the keyboard that it hasn't picked up yet.
+        let buffers: Vec<Arc<[f32]>> = (0..NUM_KEYS)
+            .filter(|&key| sp.voice_active(key) || sp.note_on_pending(key))
+            .filter_map(|key| self.key_buffer(key))
+            .collect();
         // choose a reasonable window length to visualize
-        let target_len = voices
-            .iter()
-            .filter_map(|v| v.as_ref().map(|vv| vv.buffer.len()))
-            .max()
-            .unwrap_or(0);
+        let target_len = buffers.iter().map(|b| b.len()).max().unwrap_or(0);
         
         if target_len == 0 {
-            // No active voices - generate a sample waveform using middle C (key 48) for visualization
-            drop(voices); // Release the lock before calling get_buffer_for_key
-            let sample_buffer = self.get_buffer_for_key(48); // Middle C
+            // No active voices - generate a sample waveform using middle C (key 60) for visualization
+            let sample_buffer = self.get_buffer_for_key(60); // Middle C
             if !sample_buffer.is_empty() {
                 // Clamp the sample buffer for display
                 let clamped_buffer: Vec<f32> = sample_buffer.iter().map(|&s| s.clamp(-1.0, 1.0)).collect();
@@ -634,10 +1185,10 @@ impl SynthComputeEngine {
             return;
         }
         let mut mix = vec![0.0f32; target_len];
-        for v in voices.iter().filter_map(|o| o.as_ref()) {
+        for buffer in &buffers {
             // add unclipped (plotting only); clamp for display later
-            for i in 0..v.buffer.len() {
-                mix[i] += v.buffer[i];
+            for (m, s) in mix.iter_mut().zip(buffer.iter()) {
+                *m += s;
             }
         }
         for s in &mut mix {
@@ -650,18 +1201,58 @@ impl SynthComputeEngine {
             .unwrap() = mix;
     }
 
+    /// Switch between periodic and continuous-phase rendering, persisting
+    /// the choice in the plugin state; every key buffer is re-rendered in the
+    /// new mode.
+    pub fn set_render_mode(&self, mode: RenderMode) {
+        *self.synth_params.render_mode.write().unwrap() = mode.as_u8();
+        if self.shared_params.render_mode() == mode {
+            return;
+        }
+        self.shared_params.set_render_mode(mode);
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+    }
+
+    /// Switch streaming playback on or off, persisting the choice in the
+    /// plugin state. Streaming voices are synthesised in `process()` from the
+    /// live grid, so the precomputed key buffers are dropped to free their
+    /// memory and the background thread stops rendering them; switching back
+    /// marks every key for re-rendering.
+    pub fn set_streaming(&self, streaming: bool) {
+        *self.synth_params.streaming.write().unwrap() = streaming;
+        if self.shared_params.streaming() == streaming {
+            return;
+        }
+        if streaming {
+            // Voices started from here on read it straight away.
+            self.publish_stream_grid();
+        }
+        self.shared_params.streaming.store(streaming, Ordering::Relaxed);
+        self.shared_params.mark_all_buffers_dirty();
+        if streaming {
+            (0..NUM_KEYS).for_each(|key| self.publish_key_buffer(key, None));
+            for slot in self.shared_params.layer_key_buffers.iter() {
+                self.publish_buffer(slot, None);
+            }
+        }
+    }
+
     pub fn set_normalization_needed(&self, normalization_needed: bool) {
         *self
             .shared_params
             .normalization_needed
             .lock()
             .unwrap() = normalization_needed;
+        if normalization_needed {
+            self.shared_params.stream_grid_dirty.store(true, Ordering::Relaxed);
+        }
     }
     
-    /// Update the assembled chart with key 24's waveform for immediate preview
-    pub fn update_assembled_chart_with_key24(&self) {
+    /// Update the assembled chart with [`PREVIEW_KEY`]'s waveform for immediate preview
+    pub fn update_assembled_chart_preview(&self) {
         // Force synchronous recomputation instead of using cached buffer
-        let sample_buffer = self.assemble_buffer_for_key(24); // Key 24 (one octave up from key 0)
+        let sample_buffer = self.assemble_buffer_for_key(PREVIEW_KEY);
         if !sample_buffer.is_empty() {
             // Clamp the sample buffer for display
             let clamped_buffer: Vec<f32> = sample_buffer.iter().map(|&s| s.clamp(-1.0, 1.0)).collect();
@@ -671,7 +1262,7 @@ impl SynthComputeEngine {
             // Signal that the chart view should be reset to default range (0-2000)
             self.shared_params.should_reset_chart_view.store(true, std::sync::atomic::Ordering::Relaxed);
             
-            log::debug!("Updated assembled chart with key 24 preview (samples: {})", sample_buffer.len());
+            log::debug!("Updated assembled chart with key {} preview (samples: {})", PREVIEW_KEY, sample_buffer.len());
         } else {
             // If no buffer available, clear the display
             self.shared_params
@@ -679,16 +1270,44 @@ impl SynthComputeEngine {
                 .lock()
                 .unwrap()
                 .clear();
-            log::debug!("Cleared assembled chart (no key 24 buffer available yet)");
+            log::debug!("Cleared assembled chart (no preview buffer available yet)");
         }
     }
     
     /// Start the background thread that continuously computes dirty buffers
     fn start_async_computation_thread(&self) {
+        let engine = self.clone();
         let shared_params = self.shared_params.clone();
         
         thread::spawn(move || {
-            loop {
+            let mut last_param_poll = Instant::now();
+            while !shared_params.shutdown.load(Ordering::Relaxed) {
+                // Pick up harmonic param changes made outside the editor (host
+                // automation); refilled rows mark the buffers dirty themselves.
+                if last_param_poll.elapsed() >= PARAM_POLL_INTERVAL {
+                    last_param_poll = Instant::now();
+                    if engine.sync_curves_from_params()
+                        | engine.sync_formant_from_params()
+                        | engine.sync_tuning_from_params()
+                    {
+                        crate::wake_editor();
+                    }
+                }
+
+                if shared_params.release_tails_dirty.load(Ordering::Relaxed) {
+                    engine.update_release_tails();
+                }
+
+                // Streaming voices play from a grid snapshot: keep it current,
+                // there is nothing to render.
+                if shared_params.streaming() {
+                    if shared_params.stream_grid_dirty.load(Ordering::Relaxed) {
+                        engine.publish_stream_grid();
+                    }
+                    thread::sleep(STREAM_GRID_POLL_INTERVAL);
+                    continue;
+                }
+
                 // Check if we need to cancel and reset
                 if shared_params.computation_cancel.load(Ordering::Relaxed) {
                     shared_params.computation_cancel.store(false, Ordering::Relaxed);
@@ -696,18 +1315,23 @@ impl SynthComputeEngine {
                     continue;
                 }
                 
-                // Find the next dirty buffer to compute, prioritizing key 24 first, then lower keys
+                // Find the next dirty buffer to compute, prioritizing the preview key first, then lower keys
                 let mut next_key = None;
                 {
                     let buffer_states = shared_params.buffer_states.lock().unwrap();
                     
-                    // First priority: key 24 (for preview)
-                    if buffer_states[24] == BufferState::Dirty {
-                        next_key = Some(24);
+                    // First priority: keys being played, so edits reach them
+                    // soonest; then the preview key
+                    if let Some(key) = (0..NUM_KEYS)
+                        .find(|&k| buffer_states[k] == BufferState::Dirty && shared_params.voice_active(k))
+                    {
+                        next_key = Some(key);
+                    } else if buffer_states[PREVIEW_KEY] == BufferState::Dirty {
+                        next_key = Some(PREVIEW_KEY);
                     } else {
                         // Second priority: lower keys (which take longer)
                         for key in 0..NUM_KEYS {
-                            if key != 24 && buffer_states[key] == BufferState::Dirty {
+                            if key != PREVIEW_KEY && buffer_states[key] == BufferState::Dirty {
                                 next_key = Some(key);
                                 break;
                             }
@@ -736,10 +1360,8 @@ impl SynthComputeEngine {
                     if !shared_params.computation_cancel.load(Ordering::Relaxed) {
                         // Store the computed buffer and mark as clean
                         {
-                            let mut key_buffers = shared_params.key_buffers.lock().unwrap();
                             let mut buffer_states = shared_params.buffer_states.lock().unwrap();
-                            
-                            key_buffers[key] = Some(computed_buffer);
+                            engine.publish_key_buffer(key, Some(computed_buffer.into()));
                             buffer_states[key] = BufferState::Clean;
                         }
                         log::trace!("Completed async computation for key {}", key);
@@ -749,14 +1371,73 @@ impl SynthComputeEngine {
                         buffer_states[key] = BufferState::Dirty;
                         log::trace!("Cancelled async computation for key {}", key);
                     }
+                } else if let Some((layer, key)) = engine.claim_dirty_layer_key() {
+                    // Velocity layers above the live grid come after its keys.
+                    engine.render_layer_key(layer, key);
                 } else {
-                    // No dirty buffers, sleep a bit
+                    // No dirty buffers: free what voices have let go of, sleep a bit
+                    engine.free_retired_buffers();
                     thread::sleep(Duration::from_millis(50));
                 }
             }
         });
     }
     
+    /// Start the background worker that runs this instance's own submitted
+    /// job (see [`submit_analysis`](Self::submit_analysis)) and claims
+    /// host-pushed analysis jobs addressed to this instance (or to any
+    /// instance) and loads them, so the host can drive analysis with the
+    /// editor closed.
+    fn start_analysis_worker_thread(&self) {
+        let engine = self.clone();
+
+        thread::spawn(move || {
+            while !engine.shared_params.shutdown.load(Ordering::Relaxed) {
+                let Some(job) = engine.claim_analysis_job() else {
+                    continue;
+                };
+                engine.run_analysis_job(&job);
+                // Repaint the idle editor (if any) so the result shows.
+                crate::wake_editor();
+            }
+        });
+    }
+
+    /// The next job for the analysis worker: this instance's own submitted
+    /// job, else a host-pushed one (waiting up to [`ANALYSIS_POLL_INTERVAL`]).
+    /// A host-pushed job is submitted to this instance only as it is claimed,
+    /// so that is when an earlier cancel is cleared.
+    fn claim_analysis_job(&self) -> Option<crate::AnalysisJob> {
+        let sp = &self.shared_params;
+        if let Some(job) = sp.pending_analysis.lock().unwrap().take() {
+            return Some(job);
+        }
+        let token = *sp.instance_token.lock().unwrap();
+        let job = crate::wait_for_analysis_job(token, ANALYSIS_POLL_INTERVAL)?;
+        sp.analysis_cancel.store(false, Ordering::Relaxed);
+        Some(job)
+    }
+
+    /// Run a claimed job as [`analyze_and_load`](Self::analyze_and_load) or
+    /// [`analyze_and_load_into`](Self::analyze_and_load_into) would, unless
+    /// it was cancelled while queued. Returns whether a new grid was loaded.
+    fn run_analysis_job(&self, job: &crate::AnalysisJob) -> bool {
+        if self.shared_params.analysis_cancel.load(Ordering::Relaxed) {
+            log::info!("Analysis cancelled before it started; keeping the current grid");
+            return false;
+        }
+        log::info!(
+            "Analysing job ({} samples @ {} Hz, target {:?})",
+            job.samples.len(),
+            job.sample_rate,
+            job.target
+        );
+        match job.slot {
+            GridSlot::Live => self.analyze_live(&job.samples, job.sample_rate, job.base_freq, &job.contour, 0),
+            slot => self.analyze_into(slot, &job.samples, job.sample_rate, job.base_freq, &job.contour),
+        }
+    }
+
     /// Static version of assemble_buffer_for_key for use in background thread
     fn compute_buffer_for_key_static(shared_params: &Arc<SharedParams>, key: usize) -> Vec<f32> {
         let start_time = std::time::Instant::now();
@@ -767,18 +1448,17 @@ impl SynthComputeEngine {
         }
         
         // Calculate maximum usable harmonic for this key to prevent aliasing
-        let max_harmonic = max_harmonic_for_key(key);
+        let max_harmonic = shared_params.max_harmonic(key);
 
         // Copy all required data once and release locks immediately to avoid blocking GUI
         let (num_harmonics, ampl_data_copy, phase_data_copy, harmonic_ampl_enabled_copy, harmonic_phase_enabled_copy, base_period, pitch_ratio, target_samples) = {
             let ampl_data_normalized = shared_params.amplitude_data_normalized.lock().unwrap();
             let phase_data = shared_params.phase_data.lock().unwrap();
-            let piano_periods = shared_params.piano_periods.lock().unwrap();
             let harmonic_ampl_enabled = shared_params.harmonic_ampl_enabled.lock().unwrap();
             let harmonic_phase_enabled = shared_params.harmonic_phase_enabled.lock().unwrap();
 
             let num_harmonics = ampl_data_normalized.len();
-            let base_period = piano_periods[key] as usize;
+            let base_period = shared_params.piano_period(key).unwrap_or(1);
 
             // Deep copy the data we need
             let ampl_data_copy: Vec<Vec<f32>> = ampl_data_normalized.clone();
@@ -793,6 +1473,19 @@ impl SynthComputeEngine {
             (num_harmonics, ampl_data_copy, phase_data_copy, harmonic_ampl_enabled_copy, harmonic_phase_enabled_copy, base_period, pitch_ratio, target_samples)
         }; // All locks are released here
 
+        // Key zones (Analysis mode) may swap in another zone's grid.
+        let (ampl_data_copy, phase_data_copy, pitch_ratio, target_samples, source_freq) =
+            match zoned_grid_for_key(shared_params, key, &ampl_data_copy, &phase_data_copy) {
+                Some(zoned) => (zoned.ampl, zoned.phase, zoned.ratios, zoned.target_samples, zoned.base_freq),
+                None => {
+                    let base_freq = *shared_params.analysis_base_freq.lock().unwrap();
+                    (ampl_data_copy, phase_data_copy, pitch_ratio, target_samples, base_freq)
+                }
+            };
+        // Formant preservation (Analysis mode) re-samples the amplitudes for this key.
+        let ampl_data_copy = formant_corrected(shared_params, &ampl_data_copy, source_freq, base_period)
+            .unwrap_or(ampl_data_copy);
+
         let sound = render_key_buffer(
             num_harmonics,
             &ampl_data_copy,
@@ -803,6 +1496,7 @@ impl SynthComputeEngine {
             max_harmonic,
             &pitch_ratio,
             target_samples,
+            shared_params.render_mode(),
             Some(&shared_params.computation_cancel),
         );
 
@@ -833,115 +1527,405 @@ impl SynthComputeEngine {
             }
         }
         
-        for b in 0..ampl_data_normalized[0].len() {
-            let sum: f32 = ampl_data_normalized
-                .iter()
-                .map(|harmonic| harmonic[b])
-                .sum();
-            if sum > 1.0 {
-                for a in 0..ampl_data_normalized.len() {
-                    let val = ampl_data_normalized[a][b];
-                    ampl_data_normalized[a][b] = val / sum;
-                }
-            }
+        normalize_per_bucket(&mut ampl_data_normalized);
+    }
+
+    /// Render `key` of velocity layer `layer` (1..) from that layer's grid,
+    /// like [`compute_buffer_for_key_static`](Self::compute_buffer_for_key_static)
+    /// renders the live grid in Analysis mode. `None` once the layer is empty.
+    fn compute_layer_buffer_static(shared_params: &Arc<SharedParams>, layer: usize, key: usize) -> Option<Vec<f32>> {
+        let (mut ampl, phase, ratios, duration_secs, base_freq) = {
+            let layers = shared_params.velocity_layers.lock().unwrap();
+            let grid = layers.get(layer.checked_sub(1)?)?.as_ref()?;
+            (grid.amplitude.clone(), grid.phase.clone(), grid.pitch_ratio.clone(), grid.duration_secs, grid.base_freq)
+        };
+        normalize_per_bucket(&mut ampl);
+
+        let base_period = shared_params.piano_period(key)?;
+        if let Some(corrected) = formant_corrected(shared_params, &ampl, base_freq, base_period) {
+            ampl = corrected;
+        }
+        let sr = *shared_params.sample_rate.lock().unwrap();
+        let ampl_enabled = shared_params.harmonic_ampl_enabled.lock().unwrap().clone();
+        let phase_enabled = shared_params.harmonic_phase_enabled.lock().unwrap().clone();
+        let target_samples = (duration_secs * sr).round().max(0.0) as usize;
+
+        Some(render_key_buffer(
+            ampl.len(),
+            &ampl,
+            &phase,
+            &ampl_enabled,
+            &phase_enabled,
+            base_period,
+            shared_params.max_harmonic(key),
+            &ratios,
+            target_samples,
+            shared_params.render_mode(),
+            Some(&shared_params.computation_cancel),
+        ))
+    }
+
+    /// Claim the next dirty key buffer of a velocity layer above 0, marking
+    /// it as computing. Keys being played go first.
+    fn claim_dirty_layer_key(&self) -> Option<(usize, usize)> {
+        let sp = &self.shared_params;
+        let mut states = sp.layer_buffer_states.lock().unwrap();
+        let dirty = |i: usize| states[i] == BufferState::Dirty;
+        let idx = (0..states.len())
+            .find(|&i| dirty(i) && sp.voice_active(i % NUM_KEYS))
+            .or_else(|| (0..states.len()).find(|&i| dirty(i)))?;
+        states[idx] = BufferState::Computing;
+        Some((idx / NUM_KEYS + 1, idx % NUM_KEYS))
+    }
+
+    /// Render a claimed layer key buffer and publish it, unless it was marked
+    /// dirty again meanwhile (the layer changed or was cleared).
+    fn render_layer_key(&self, layer: usize, key: usize) {
+        let sp = &self.shared_params;
+        let buffer = Self::compute_layer_buffer_static(sp, layer, key);
+        let slot = SharedParams::layer_slot(layer, key);
+        let mut states = sp.layer_buffer_states.lock().unwrap();
+        if states[slot] != BufferState::Computing || sp.computation_cancel.load(Ordering::Relaxed) {
+            states[slot] = BufferState::Dirty;
+            return;
         }
+        self.publish_buffer(&sp.layer_key_buffers[slot], buffer.map(Into::into));
+        states[slot] = BufferState::Clean;
     }
     
-    /// Get a buffer for a key, using pre-computed version if available
-    pub fn get_buffer_for_key(&self, key: usize) -> Vec<f32> {
+    /// Get a buffer for a key, using pre-computed version if available.
+    /// Falls back to rendering it synchronously, so never call this from the
+    /// audio thread; see [`key_buffer`](Self::key_buffer).
+    pub fn get_buffer_for_key(&self, key: usize) -> Arc<[f32]> {
         if key >= NUM_KEYS {
-            return Vec::new();
+            return Arc::from([]);
         }
-        
-        let buffer_states = self.shared_params.buffer_states.lock().unwrap();
-        let key_buffers = self.shared_params.key_buffers.lock().unwrap();
-        
-        match buffer_states[key] {
-            BufferState::Clean => {
-                if let Some(ref buffer) = key_buffers[key] {
-                    log::debug!("Using pre-computed buffer for key {}", key);
-                    return buffer.clone();
-                }
-            }
-            BufferState::Computing => {
-                // Check if we have an old buffer we can use while waiting
-                if let Some(ref buffer) = key_buffers[key] {
-                    log::debug!("Using old buffer for key {} while computing new one", key);
-                    return buffer.clone();
-                }
+        if let Some(buffer) = self.key_buffer(key) {
+            return buffer;
+        }
+        log::warn!("Fallback to synchronous computation for key {}", key);
+        self.assemble_buffer_for_key(key).into()
+    }
+
+    /// The key's most recently rendered buffer, possibly stale while a
+    /// re-render is pending. Lock-free and allocation-free.
+    pub fn key_buffer(&self, key: usize) -> Option<Arc<[f32]>> {
+        self.shared_params.key_buffers.get(key)?.load()
+    }
+
+    /// Like [`key_buffer`](Self::key_buffer), for velocity layer `layer`
+    /// (0 is the live grid's buffer).
+    pub fn layer_buffer(&self, layer: usize, key: usize) -> Option<Arc<[f32]>> {
+        if layer == 0 {
+            return self.key_buffer(key);
+        }
+        if layer >= MAX_VELOCITY_LAYERS || key >= NUM_KEYS {
+            return None;
+        }
+        self.shared_params.layer_key_buffers[SharedParams::layer_slot(layer, key)].load()
+    }
+
+    /// Swap in `key`'s buffer (see [`publish_buffer`](Self::publish_buffer)).
+    fn publish_key_buffer(&self, key: usize, buffer: Option<Arc<[f32]>>) {
+        if let Some(slot) = self.shared_params.key_buffers.get(key) {
+            self.publish_buffer(slot, buffer);
+        }
+    }
+
+    /// Swap `buffer` into `slot`. The one it replaces may still be playing,
+    /// so it is retired rather than dropped: only this side frees buffers,
+    /// never the audio thread.
+    fn publish_buffer(&self, slot: &BufferSlot, buffer: Option<Arc<[f32]>>) {
+        if let Some(old) = slot.swap(buffer) {
+            self.shared_params.retired_buffers.lock().unwrap().push(old);
+        }
+        self.free_retired_buffers();
+    }
+
+    /// Free retired buffers no voice holds any more.
+    fn free_retired_buffers(&self) {
+        self.shared_params.retired_buffers.lock().unwrap().retain(|b| Arc::strong_count(b) > 1);
+    }
+
+    /// Point a restarted `voice` at a new note of `key` without allocating
+    /// or rendering (it runs on the audio thread): the key's current buffer,
+    /// or silence until the background thread delivers one (see
+    /// [`refresh_voice_buffer`](Self::refresh_voice_buffer)); when streaming,
+    /// its oscillators reset in place over the live grid. `velocity` (0..1)
+    /// sets its level and tilt through the velocity params and, in Analysis
+    /// mode, which velocity layers the note plays (see [`select_layers`]),
+    /// and with the release-tail param, where its release tail starts (as
+    /// [`update_release_tails`](Self::update_release_tails) measured it).
+    pub fn start_voice(&self, voice: &mut Voice, key: usize, velocity: f32) {
+        let Some(base_period) = self.shared_params.piano_period(key) else {
+            return;
+        };
+        voice.set_velocity(
+            velocity_gain(velocity, self.synth_params.velocity_curve.value()),
+            velocity_darkness(velocity, self.synth_params.velocity_tilt.value()),
+            base_period,
+        );
+        voice.streamed = self.shared_params.streaming();
+        let blend = if !voice.streamed && self.shared_params.execution_mode() == ExecutionMode::Analysis {
+            select_layers(&self.shared_params.velocity_ranges(), velocity)
+        } else {
+            LayerBlend::single(0)
+        };
+        voice.release_tail = self.release_tail(key, blend.primary, voice.streamed);
+        voice.layer = blend.primary;
+        voice.blend_layer = blend.secondary;
+        voice.layer_mix = blend.mix;
+        voice.blend_buffer = None;
+        if !voice.streamed {
+            voice.buffer = self.layer_buffer(blend.primary, key);
+            if blend.mix > 0.0 {
+                voice.blend_buffer = self.layer_buffer(blend.secondary, key);
             }
-            BufferState::Dirty => {
-                // Check if we have an old buffer we can use
-                if let Some(ref buffer) = key_buffers[key] {
-                    log::debug!("Using old buffer for key {} (marked dirty)", key);
-                    return buffer.clone();
-                }
+        } else {
+            voice.buffer = None;
+            let max_harmonic = self.shared_params.max_harmonic(key);
+            let bank = &mut voice.bank;
+            self.with_stream_snapshot(|snapshot| {
+                let (grid, target_samples) = (snapshot.view(), snapshot.target_samples);
+                let len = timeline_len(grid.num_buckets(), base_period, grid.ratios, target_samples);
+                bank.reset(&grid, base_period, max_harmonic, len, timeline_span(len, target_samples));
+                bank.set_formant(snapshot.formant_period.map(|p| p / base_period as f32));
+            });
+        }
+        // After the bank reset, which clears its pitch.
+        voice.set_period_rate(self.shared_params.period_rate(key));
+    }
+
+    /// Where the release tail of a note of `key` on velocity layer `layer`
+    /// starts, with the release-tail param in Analysis mode: a buffer voice
+    /// of the live layer plays its key's zone.
+    fn release_tail(&self, key: usize, layer: usize, streamed: bool) -> Option<f32> {
+        if !self.synth_params.release_tail.value() || self.shared_params.execution_mode() != ExecutionMode::Analysis {
+            None
+        } else if layer == 0 && !streamed {
+            self.shared_params.key_release_tail(key)
+        } else {
+            self.shared_params.layer_release_tail(layer)
+        }
+    }
+
+    /// The amplitude envelope of the envelope params, at `sample_rate`.
+    pub fn envelope(&self, sample_rate: f32) -> Adsr {
+        let params = &self.synth_params;
+        Adsr::from_millis(
+            params.attack_time.value(),
+            params.decay_time.value(),
+            params.sustain_level.value(),
+            params.release_time.value(),
+            params.envelope_curve.value(),
+            sample_rate,
+        )
+    }
+
+    /// Measure where the release tail of every grid starts (see
+    /// [`release_tail_start`]), for [`start_voice`](Self::start_voice) to
+    /// read: each velocity layer's and, for each key, that of the key zone
+    /// its live-layer buffer renders from (the primary one when zones
+    /// blend). Runs off the audio thread after grid changes.
+    pub fn update_release_tails(&self) {
+        let sp = &self.shared_params;
+        sp.release_tails_dirty.store(false, Ordering::Relaxed);
+        let live = release_tail_start(&sp.amplitude_data.lock().unwrap());
+        sp.store_layer_release_tail(0, live);
+        let grid_tail = |g: &Option<LoadedGrid>| g.as_ref().and_then(|g| release_tail_start(&g.amplitude));
+        for (i, grid) in sp.velocity_layers.lock().unwrap().iter().enumerate() {
+            sp.store_layer_release_tail(i + 1, grid_tail(grid));
+        }
+
+        let zone_tails: Vec<Option<f32>> =
+            std::iter::once(live).chain(sp.zone_grids.lock().unwrap().iter().map(grid_tail)).collect();
+        let (zones, interpolate) = (sp.key_zones(), sp.zone_interpolation.load(Ordering::Relaxed));
+        for key in 0..NUM_KEYS {
+            let zone = select_zones(&zones, key, interpolate).primary;
+            sp.store_key_release_tail(key, zone_tails.get(zone).copied().flatten());
+        }
+    }
+
+    /// Point the voice of a pool note-on (or of a mono note-off returning
+    /// to a held key) at its note: restarted by
+    /// [`start_voice`](Self::start_voice), or carried on by
+    /// [`retarget_voice`](Self::retarget_voice) when legato. A mono note
+    /// glides from the previous key's pitch over `glide_samples`.
+    pub fn start_note(&self, note: NoteStart, glide_samples: f32) {
+        let NoteStart { voice, key, velocity, from_key, legato } = note;
+        if legato {
+            self.retarget_voice(voice, key);
+        } else {
+            self.start_voice(voice, key, velocity);
+        }
+        if let Some(from_key) = from_key {
+            voice.glide_from(self.key_interval(from_key, key), glide_samples);
+        }
+    }
+
+    /// Move a sounding `voice` over to `key` without restarting it (a mono
+    /// legato note change), on the audio thread like
+    /// [`start_voice`](Self::start_voice). A buffer voice goes on from its
+    /// position in `key`'s buffers of the same velocity layers; a streaming
+    /// voice's oscillators retune in place. Level and fades carry on.
+    pub fn retarget_voice(&self, voice: &mut Voice, key: usize) {
+        let Some(base_period) = self.shared_params.piano_period(key) else {
+            return;
+        };
+        voice.set_velocity(voice.velocity_gain, voice.darkness, base_period);
+        voice.set_period_rate(self.shared_params.period_rate(key));
+        if !voice.streamed {
+            let blend = if voice.layer_mix > 0.0 { self.layer_buffer(voice.blend_layer, key) } else { None };
+            voice.set_buffers(self.layer_buffer(voice.layer, key), blend);
+            return;
+        }
+        let max_harmonic = self.shared_params.max_harmonic(key);
+        let bank = &mut voice.bank;
+        self.with_stream_snapshot(|snapshot| {
+            bank.retarget(&snapshot.view(), base_period, max_harmonic);
+            bank.set_formant(snapshot.formant_period.map(|p| p / base_period as f32));
+        });
+    }
+
+    /// Semitones from `to`'s pitch up to `from`'s under the current tuning;
+    /// 0 if either key is silent.
+    fn key_interval(&self, from: usize, to: usize) -> f32 {
+        let (from, to) = (self.shared_params.key_frequency(from), self.shared_params.key_frequency(to));
+        if from > 0.0 && to > 0.0 {
+            (12.0 * (from / to).log2()) as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Hand a playing buffer voice its key's latest buffers, keeping its
+    /// place in the note, so a re-render after an edit is heard mid-note.
+    pub fn refresh_voice_buffer(&self, voice: &mut Voice, key: usize) {
+        let buffer = self.layer_buffer(voice.layer, key).or_else(|| voice.buffer.take());
+        let blend_buffer = if voice.layer_mix > 0.0 {
+            self.layer_buffer(voice.blend_layer, key).or_else(|| voice.blend_buffer.take())
+        } else {
+            voice.blend_buffer.take()
+        };
+        voice.set_buffers(buffer, blend_buffer);
+    }
+
+    /// Run `f` over the grid streaming voices play from: the snapshot last
+    /// published by [`publish_stream_grid`](Self::publish_stream_grid), or an
+    /// empty grid before the first. Lock-free and allocation-free, for the
+    /// audio thread.
+    pub fn with_stream_grid<R>(&self, f: impl FnOnce(&GridView) -> R) -> R {
+        self.with_stream_snapshot(|grid| f(&grid.view()))
+    }
+
+    fn with_stream_snapshot<R>(&self, f: impl FnOnce(&StreamGrid) -> R) -> R {
+        let grid = self.shared_params.stream_grid.load();
+        let empty = StreamGrid::default();
+        f(grid.as_deref().unwrap_or(&empty))
+    }
+
+    /// Snapshot the live playback grid (normalised amplitudes, phases, enable
+    /// flags and, in Analysis mode, the pitch ratios) for streaming voices,
+    /// applying pending normalisation first. Runs off the audio thread; the
+    /// snapshot it replaces is retired rather than dropped, as key buffers are.
+    pub fn publish_stream_grid(&self) {
+        let sp = &self.shared_params;
+        sp.stream_grid_dirty.store(false, Ordering::Relaxed);
+        if *sp.normalization_needed.lock().unwrap() {
+            self.normalize_amplitude_data();
+            *sp.normalization_needed.lock().unwrap() = false;
+        }
+        let grid = {
+            // Held together so the rows and flags match.
+            let ampl = sp.amplitude_data_normalized.lock().unwrap();
+            let phase = sp.phase_data.lock().unwrap();
+            let ampl_enabled = sp.harmonic_ampl_enabled.lock().unwrap();
+            let phase_enabled = sp.harmonic_phase_enabled.lock().unwrap();
+            let ratios = sp.bucket_pitch_ratio.lock().unwrap();
+            StreamGrid {
+                ampl: ampl.clone(),
+                phase: phase.clone(),
+                ampl_enabled: ampl_enabled.clone(),
+                phase_enabled: phase_enabled.clone(),
+                // Pitch ratios apply only in Analysis mode; flat otherwise.
+                ratios: if sp.execution_mode() == ExecutionMode::Analysis { ratios.clone() } else { Vec::new() },
+                target_samples: target_samples_for(sp),
+                formant_period: formant_period(sp, *sp.analysis_base_freq.lock().unwrap()),
             }
+        };
+        let mut retired = sp.retired_stream_grids.lock().unwrap();
+        if let Some(old) = sp.stream_grid.swap(Some(Arc::new(grid))) {
+            retired.push(old);
         }
-        
-        // Fallback to synchronous computation if no buffer available
-        drop(buffer_states);
-        drop(key_buffers);
-        log::warn!("Fallback to synchronous computation for key {}", key);
-        self.assemble_buffer_for_key(key)
+        retired.retain(|g| Arc::strong_count(g) > 1);
     }
 
-    /// Replace the amplitude/phase grid with the result of an audio analysis.
-    /// Used by the Analysis execution mode. The grid is resized to the
-    /// analysis bucket count; harmonics beyond the engine's `NUM_HARMONICS`
-    /// are dropped and missing ones are zero-filled.
-    pub fn load_analysis(&self, result: &super::AnalysisResult) {
+    /// Replace the amplitude/phase grid with the result of an audio analysis
+    /// and switch to Analysis mode. The grid is resized to the analysis bucket
+    /// count; harmonics beyond the engine's `NUM_HARMONICS` are dropped and
+    /// missing ones are zero-filled. Everything playback reads (grid, pitch
+    /// ratios, duration, base frequency, mode) is swapped in while holding all
+    /// of its locks, so a key buffer is never rendered from half a result.
+    pub fn load_analysis(&self, result: &super::AnalysisResult, base_freq: f32, duration_secs: f32) {
         let buckets = result.num_buckets().max(1);
+        let sp = &self.shared_params;
+        let n = NUM_HARMONICS;
+
+        // Build the new rows before taking any lock.
+        let row_of = |src: &[Vec<f32>], h: usize| -> Vec<f32> {
+            let src = src.get(h);
+            (0..buckets)
+                .map(|b| src.and_then(|r| r.get(b)).copied().unwrap_or(0.0))
+                .collect()
+        };
+        let new_amp: Vec<Vec<f32>> = (0..n).map(|h| row_of(&result.amplitude, h)).collect();
+        let new_phase: Vec<Vec<f32>> = (0..n).map(|h| row_of(&result.phase, h)).collect();
+        // Per-bucket pitch ratio drives the playback vibrato. Missing/short
+        // → 1.0 (flat) so playback degrades gracefully.
+        let new_ratio: Vec<f32> = (0..buckets)
+            .map(|b| result.pitch_ratio.get(b).copied().unwrap_or(1.0))
+            .collect();
 
         {
-            let mut amp = self.shared_params.amplitude_data.lock().unwrap();
-            let mut phase = self.shared_params.phase_data.lock().unwrap();
-            let mut norm = self.shared_params.amplitude_data_normalized.lock().unwrap();
-            let n = amp.len();
-            for h in 0..n {
-                let src_amp = result.amplitude.get(h);
-                let src_phase = result.phase.get(h);
-                amp[h] = (0..buckets)
-                    .map(|b| src_amp.and_then(|r| r.get(b)).copied().unwrap_or(0.0))
-                    .collect();
-                phase[h] = (0..buckets)
-                    .map(|b| src_phase.and_then(|r| r.get(b)).copied().unwrap_or(0.0))
-                    .collect();
-            }
-            // Keep the normalized grid the same shape as the new data.
-            *norm = vec![vec![0.0; buckets]; n];
+            // Same order the render paths lock in (amp → normalized → phase →
+            // … → ratio → duration), so the swap can't deadlock against them.
+            let mut amp = sp.amplitude_data.lock().unwrap();
+            let mut norm = sp.amplitude_data_normalized.lock().unwrap();
+            let mut phase = sp.phase_data.lock().unwrap();
+            let mut analysis_amp = sp.analysis_amplitude_data.lock().unwrap();
+            let mut analysis_phase = sp.analysis_phase_data.lock().unwrap();
+            let mut ampl_custom = sp.harmonic_ampl_custom.lock().unwrap();
+            let mut phase_custom = sp.harmonic_phase_custom.lock().unwrap();
+            let mut ratio = sp.bucket_pitch_ratio.lock().unwrap();
+            let mut duration = sp.analysis_duration_secs.lock().unwrap();
+            let mut base = sp.analysis_base_freq.lock().unwrap();
 
             // Snapshot the pristine analysis grid so a per-harmonic "custom"
             // override can be undone (restoring the analysed row), and clear any
             // existing overrides — freshly loaded data starts fully analysed.
-            *self.shared_params.analysis_amplitude_data.lock().unwrap() = amp.clone();
-            *self.shared_params.analysis_phase_data.lock().unwrap() = phase.clone();
-            self.shared_params
-                .harmonic_ampl_custom
-                .lock()
-                .unwrap()
-                .iter_mut()
-                .for_each(|c| *c = false);
-            self.shared_params
-                .harmonic_phase_custom
-                .lock()
-                .unwrap()
-                .iter_mut()
-                .for_each(|c| *c = false);
+            *analysis_amp = new_amp.clone();
+            *analysis_phase = new_phase.clone();
+            *amp = new_amp;
+            *phase = new_phase;
+            // Keep the normalized grid the same shape as the new data.
+            *norm = vec![vec![0.0; buckets]; n];
+            ampl_custom.iter_mut().for_each(|c| *c = false);
+            phase_custom.iter_mut().for_each(|c| *c = false);
+            *ratio = new_ratio;
+            *duration = duration_secs.max(0.0);
+            *base = base_freq.max(0.0);
+            sp.set_execution_mode(super::ExecutionMode::Analysis);
         }
-
-        {
-            // Per-bucket pitch ratio drives the playback vibrato. Missing/short
-            // → 1.0 (flat) so playback degrades gracefully.
-            let mut ratio = self.shared_params.bucket_pitch_ratio.lock().unwrap();
-            *ratio = (0..buckets)
-                .map(|b| result.pitch_ratio.get(b).copied().unwrap_or(1.0))
-                .collect();
+        // Zone 0 is rooted at the new grid's pitch; its key range stays.
+        let root_key = nearest_key(base_freq, &sp.key_frequencies());
+        if let Some(zone) = sp.key_zones.lock().unwrap()[0].as_mut() {
+            zone.root_key = root_key;
         }
 
         self.set_normalization_needed(true);
-        self.shared_params.mark_all_buffers_dirty();
-        self.update_assembled_chart_with_key24();
+        sp.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+        self.persist_analysis_state();
         log::info!(
             "Loaded analysis grid: {} harmonics x {} buckets",
             result.num_harmonics(),
@@ -990,14 +1974,472 @@ impl SynthComputeEngine {
             }
             self.set_normalization_needed(true);
             self.shared_params.mark_all_buffers_dirty();
-            self.update_assembled_chart_with_key24();
+            self.update_assembled_chart_preview();
+        }
+        self.persist_analysis_state();
+    }
+
+    /// Fit harmonic `n`'s Nested Fourier series for `chart_type` to its
+    /// analysed row, so the analysed curve becomes an editable starting point
+    /// for the "cust" override instead of being replaced by a blank one. The
+    /// sub-harmonics are stored in the harmonic's [`NestedFourierState`]; the
+    /// fitted offset is returned for the caller to set on `curve_offset_*`
+    /// (host params go through the editor's `ParamSetter`). Phase rows are
+    /// unwrapped first, so a curve crossing 2π fits as one smooth sweep, and
+    /// shifted by whole turns to keep the offset in the phase param's range.
+    /// `None` when no analysed row exists for `n`.
+    pub fn fit_harmonic_curve(&self, n: usize, chart_type: ChartType) -> Option<f32> {
+        let mut row = match chart_type {
+            ChartType::Amp => self.shared_params.analysis_amplitude_data.lock().unwrap().get(n)?.clone(),
+            ChartType::Phase => self.shared_params.analysis_phase_data.lock().unwrap().get(n)?.clone(),
+        };
+        if row.is_empty() || n >= self.synth_params.harmonics.len() {
+            return None;
+        }
+        if chart_type == ChartType::Phase {
+            for b in 1..row.len() {
+                let step = row[b] - row[b - 1];
+                row[b] -= TWO_PI * (step / TWO_PI).round();
+            }
+            let mean = row.iter().sum::<f32>() / row.len() as f32;
+            let turns = (mean / TWO_PI).floor();
+            row.iter_mut().for_each(|v| *v -= turns * TWO_PI);
+        }
+
+        let (min, max) = match chart_type {
+            ChartType::Amp => (MIN_OFFSET_AMP, MAX_OFFSET_AMP),
+            ChartType::Phase => (MIN_OFFSET_PHASE, MAX_OFFSET_PHASE),
+        };
+        // Sub-harmonics may swing as far as the chart's range reaches.
+        let (offset, series) = NestedFourierSeries::fit(&row, max as f32);
+        *self.synth_params.harmonics[n].nested_fourier.write().unwrap().series_mut(chart_type) = series;
+        Some(offset.clamp(min as f32, max as f32))
+    }
+
+    /// Capture the live Analysis-mode state — pristine grid, pitch ratios,
+    /// duration/base frequency, per-harmonic enable/custom flags and the
+    /// execution mode — into the persisted [`AnalysisState`], so the host's
+    /// next project save carries it. Call after every change to that state.
+    pub fn persist_analysis_state(&self) {
+        let sp = &self.shared_params;
+        let (amplitude, phase, num_buckets) = {
+            let amp = sp.analysis_amplitude_data.lock().unwrap();
+            let phase = sp.analysis_phase_data.lock().unwrap();
+            (pack_rows(&amp), pack_rows(&phase), amp.first().map(|r| r.len()).unwrap_or(0))
+        };
+        // One lock per statement: guards taken inside the struct literal would
+        // all be held together, in an order the render paths don't share.
+        let duration_secs = *sp.analysis_duration_secs.lock().unwrap();
+        let base_freq = *sp.analysis_base_freq.lock().unwrap();
+        let source_sample_rate = *sp.analysis_sample_rate.lock().unwrap();
+        let pitch_ratio = sp.bucket_pitch_ratio.lock().unwrap().clone();
+        let harmonic_ampl_enabled = sp.harmonic_ampl_enabled.lock().unwrap().clone();
+        let harmonic_phase_enabled = sp.harmonic_phase_enabled.lock().unwrap().clone();
+        let harmonic_ampl_custom = sp.harmonic_ampl_custom.lock().unwrap().clone();
+        let harmonic_phase_custom = sp.harmonic_phase_custom.lock().unwrap().clone();
+        let ranges = sp.velocity_ranges();
+        let velocity_layers = sp
+            .velocity_layers
+            .lock()
+            .unwrap()
+            .iter()
+            .zip(&ranges[1..])
+            .map(|(grid, range)| Some(VelocityLayerState { range: (*range)?, grid: grid.as_ref()?.to_state() }))
+            .collect();
+        let zones = sp.key_zones();
+        let key_zones = sp
+            .zone_grids
+            .lock()
+            .unwrap()
+            .iter()
+            .zip(&zones[1..])
+            .map(|(grid, zone)| Some(KeyZoneState { zone: (*zone)?, grid: grid.as_ref()?.to_state() }))
+            .collect();
+        let snapshot = AnalysisState {
+            version: ANALYSIS_STATE_VERSION,
+            execution_mode: sp.execution_mode().as_u8(),
+            duration_secs,
+            base_freq,
+            source_sample_rate,
+            num_buckets,
+            amplitude,
+            phase,
+            pitch_ratio,
+            harmonic_ampl_enabled,
+            harmonic_phase_enabled,
+            harmonic_ampl_custom,
+            harmonic_phase_custom,
+            velocity_range: ranges[0].unwrap_or_default(),
+            velocity_layers,
+            key_zone: zones[0],
+            key_zones,
+            zone_interpolation: sp.zone_interpolation.load(Ordering::Relaxed),
+            applied: true,
+        };
+        *self.synth_params.analysis_state.write().unwrap() = snapshot;
+    }
+
+    /// Restore a freshly deserialized [`AnalysisState`] into the engine. Called
+    /// from `Plugin::initialize`, which nih-plug re-runs after loading state; a
+    /// snapshot that already matches the engine (`applied`) is skipped, so
+    /// sample-rate re-initialisations don't redo the work. Snapshots of an
+    /// unknown version are ignored with a warning.
+    pub fn restore_analysis_state(&self) {
+        let state = {
+            let mut guard = self.synth_params.analysis_state.write().unwrap();
+            if guard.applied {
+                return;
+            }
+            guard.applied = true;
+            if guard.version != ANALYSIS_STATE_VERSION {
+                if guard.version != 0 {
+                    log::warn!(
+                        "Ignoring persisted analysis state with unsupported version {}",
+                        guard.version
+                    );
+                }
+                return;
+            }
+            guard.clone()
+        };
+        self.apply_analysis_state(&state, false);
+        log::info!(
+            "Restored persisted analysis state ({} buckets, {:.2} s)",
+            state.num_buckets,
+            state.duration_secs
+        );
+    }
+
+    /// Load an [`AnalysisState`] snapshot into the engine: the grid (if the
+    /// snapshot has one), the per-harmonic flags with their custom rows, the
+    /// velocity layers, the key zones and the execution mode. A `.lesynth`
+    /// file (`from_file`) always carries a grid but no layers or zones, so its
+    /// grid is loaded unconditionally and the current layers and zones are
+    /// kept.
+    fn apply_analysis_state(&self, state: &AnalysisState, from_file: bool) {
+        let sp = &self.shared_params;
+        if state.num_buckets > 0 && (from_file || state.duration_secs > 0.0) {
+            *sp.analysis_sample_rate.lock().unwrap() = state.source_sample_rate;
+            // Reloads the pristine grid (and clears the custom flags, which are
+            // re-applied below).
+            self.load_grid(
+                unpack_rows(&state.amplitude, state.num_buckets),
+                unpack_rows(&state.phase, state.num_buckets),
+                state.pitch_ratio.clone(),
+                state.base_freq,
+                state.duration_secs,
+            );
+        }
+
+        let copy_flags = |src: &[bool], dst: &mut Vec<bool>| {
+            for (d, &s) in dst.iter_mut().zip(src) {
+                *d = s;
+            }
+        };
+        copy_flags(&state.harmonic_ampl_enabled, &mut sp.harmonic_ampl_enabled.lock().unwrap());
+        copy_flags(&state.harmonic_phase_enabled, &mut sp.harmonic_phase_enabled.lock().unwrap());
+        copy_flags(&state.harmonic_ampl_custom, &mut sp.harmonic_ampl_custom.lock().unwrap());
+        copy_flags(&state.harmonic_phase_custom, &mut sp.harmonic_phase_custom.lock().unwrap());
+
+        // Custom-overridden rows replay the user's Synth-mode curve, exactly as
+        // `set_harmonic_custom` wrote them before the save.
+        let ampl_custom = sp.harmonic_ampl_custom.lock().unwrap().clone();
+        let phase_custom = sp.harmonic_phase_custom.lock().unwrap().clone();
+        for (n, &custom) in ampl_custom.iter().enumerate() {
+            if custom {
+                self.write_curve_row(n, ChartType::Amp);
+            }
+        }
+        for (n, &custom) in phase_custom.iter().enumerate() {
+            if custom {
+                self.write_curve_row(n, ChartType::Phase);
+            }
+        }
+
+        if !from_file {
+            sp.store_velocity_range(0, Some(state.velocity_range));
+            for layer in 1..MAX_VELOCITY_LAYERS {
+                match state.velocity_layers.get(layer - 1).and_then(Option::as_ref) {
+                    Some(saved) => self.store_velocity_layer(layer, saved.range, LoadedGrid::from_state(&saved.grid)),
+                    None => self.store_cleared_velocity_layer(layer),
+                }
+            }
+        }
+
+        if !from_file {
+            if let Some(zone) = state.key_zone {
+                sp.key_zones.lock().unwrap()[0] = Some(zone);
+            }
+            for zone in 1..MAX_KEY_ZONES {
+                let saved = state.key_zones.get(zone - 1).and_then(Option::as_ref);
+                sp.zone_grids.lock().unwrap()[zone - 1] = saved.map(|z| LoadedGrid::from_state(&z.grid));
+                sp.key_zones.lock().unwrap()[zone] = saved.map(|z| z.zone);
+            }
+            sp.zone_interpolation.store(state.zone_interpolation, Ordering::Relaxed);
+        }
+
+        sp.set_execution_mode(ExecutionMode::from_u8(state.execution_mode));
+        self.set_normalization_needed(true);
+        sp.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+        self.persist_analysis_state();
+    }
+
+    /// Load `grid` into `slot`: the live grid (as [`load_grid`](Self::load_grid)),
+    /// a velocity layer or a key zone.
+    pub fn load_into(&self, slot: GridSlot, grid: LoadedGrid) -> Result<(), String> {
+        match slot {
+            GridSlot::Live => {
+                self.load_live_grid(grid);
+                Ok(())
+            }
+            GridSlot::VelocityLayer(layer, range) => self.set_velocity_layer(layer, range, grid),
+            GridSlot::KeyZone(zone, key_zone) => self.set_key_zone(zone, key_zone, grid),
+        }
+    }
+
+    fn load_live_grid(&self, grid: LoadedGrid) {
+        *self.shared_params.analysis_sample_rate.lock().unwrap() = grid.source_sample_rate;
+        self.load_grid(grid.amplitude, grid.phase, grid.pitch_ratio, grid.base_freq, grid.duration_secs);
+    }
+
+    /// Load `grid` as velocity layer `layer`, played for `range`. Layer 0
+    /// replaces the live grid (as [`load_grid`](Self::load_grid) does);
+    /// layers above it render into their own key buffers.
+    pub fn set_velocity_layer(&self, layer: usize, range: VelocityRange, grid: LoadedGrid) -> Result<(), String> {
+        if layer >= MAX_VELOCITY_LAYERS {
+            return Err(format!("Velocity layer {} out of range (0..{})", layer, MAX_VELOCITY_LAYERS));
+        }
+        if layer == 0 {
+            self.shared_params.store_velocity_range(0, Some(range));
+            self.load_live_grid(grid);
+            return Ok(());
+        }
+        let buckets = grid.num_buckets();
+        self.store_velocity_layer(layer, range, grid);
+        self.persist_analysis_state();
+        log::info!(
+            "Loaded velocity layer {} ({:.2}..{:.2}): {} buckets",
+            layer,
+            range.lo,
+            range.hi,
+            buckets
+        );
+        Ok(())
+    }
+
+    /// Empty velocity layer `layer` (1..); its notes fall back to the
+    /// remaining layers. Layer 0, the live grid, cannot be cleared.
+    pub fn clear_velocity_layer(&self, layer: usize) -> Result<(), String> {
+        if layer == 0 || layer >= MAX_VELOCITY_LAYERS {
+            return Err(format!("Velocity layer {} cannot be cleared (1..{})", layer, MAX_VELOCITY_LAYERS));
+        }
+        self.store_cleared_velocity_layer(layer);
+        self.persist_analysis_state();
+        Ok(())
+    }
+
+    /// Change the velocity range of a loaded layer; nothing is re-rendered.
+    pub fn set_velocity_range(&self, layer: usize, range: VelocityRange) {
+        if !matches!(self.shared_params.velocity_ranges().get(layer), Some(Some(_))) {
+            return;
+        }
+        self.shared_params.store_velocity_range(layer, Some(range));
+        self.persist_analysis_state();
+    }
+
+    fn store_velocity_layer(&self, layer: usize, range: VelocityRange, grid: LoadedGrid) {
+        let sp = &self.shared_params;
+        sp.velocity_layers.lock().unwrap()[layer - 1] = Some(grid);
+        sp.store_velocity_range(layer, Some(range));
+        self.mark_layer_dirty(layer);
+    }
+
+    fn store_cleared_velocity_layer(&self, layer: usize) {
+        let sp = &self.shared_params;
+        sp.velocity_layers.lock().unwrap()[layer - 1] = None;
+        sp.store_velocity_range(layer, None);
+        // Re-rendering an empty layer publishes `None`, freeing its buffers.
+        self.mark_layer_dirty(layer);
+    }
+
+    fn mark_layer_dirty(&self, layer: usize) {
+        self.shared_params.release_tails_dirty.store(true, Ordering::Relaxed);
+        let mut states = self.shared_params.layer_buffer_states.lock().unwrap();
+        for key in 0..NUM_KEYS {
+            states[SharedParams::layer_slot(layer, key)] = BufferState::Dirty;
+        }
+    }
+
+    /// Load `grid` as key zone `zone`, played for `key_zone`'s keys. Zone 0
+    /// replaces the live grid (as [`load_grid`](Self::load_grid) does); every
+    /// key is re-rendered from its zone.
+    pub fn set_key_zone(&self, zone: usize, key_zone: KeyZone, grid: LoadedGrid) -> Result<(), String> {
+        if zone >= MAX_KEY_ZONES {
+            return Err(format!("Key zone {} out of range (0..{})", zone, MAX_KEY_ZONES));
+        }
+        if zone == 0 {
+            self.load_live_grid(grid);
+            self.set_key_zone_keys(0, key_zone);
+            return Ok(());
+        }
+        let buckets = grid.num_buckets();
+        self.shared_params.zone_grids.lock().unwrap()[zone - 1] = Some(grid);
+        self.shared_params.key_zones.lock().unwrap()[zone] = Some(key_zone);
+        self.zones_changed();
+        log::info!(
+            "Loaded key zone {} (root {}, keys {}..={}): {} buckets",
+            zone,
+            key_name(key_zone.root_key),
+            key_name(key_zone.lo_key),
+            key_name(key_zone.hi_key),
+            buckets
+        );
+        Ok(())
+    }
+
+    /// Empty key zone `zone` (1..); its keys fall back to the remaining
+    /// zones. Zone 0, the live grid, cannot be cleared.
+    pub fn clear_key_zone(&self, zone: usize) -> Result<(), String> {
+        if zone == 0 || zone >= MAX_KEY_ZONES {
+            return Err(format!("Key zone {} cannot be cleared (1..{})", zone, MAX_KEY_ZONES));
+        }
+        self.shared_params.zone_grids.lock().unwrap()[zone - 1] = None;
+        self.shared_params.key_zones.lock().unwrap()[zone] = None;
+        self.zones_changed();
+        Ok(())
+    }
+
+    /// Change the root key and key range of a zone in use.
+    pub fn set_key_zone_keys(&self, zone: usize, key_zone: KeyZone) {
+        {
+            let mut zones = self.shared_params.key_zones.lock().unwrap();
+            match zones.get_mut(zone) {
+                Some(Some(z)) if *z != key_zone => *z = key_zone,
+                _ => return,
+            }
+        }
+        self.zones_changed();
+    }
+
+    /// Switch blending of the amplitudes of neighbouring zones.
+    pub fn set_zone_interpolation(&self, interpolate: bool) {
+        if self.shared_params.zone_interpolation.swap(interpolate, Ordering::Relaxed) != interpolate {
+            self.zones_changed();
+        }
+    }
+
+    /// Re-render every key from its (possibly new) zone.
+    fn zones_changed(&self) {
+        self.shared_params.mark_all_buffers_dirty();
+        self.update_assembled_chart_preview();
+        self.persist_analysis_state();
+    }
+
+    /// Snapshot the whole instrument as a `.lesynth` file: the analysis state
+    /// as persisted plus every harmonic's nested-Fourier series and curve
+    /// params.
+    pub fn capture_lesynth_file(&self) -> LesynthFile {
+        self.persist_analysis_state();
+        let harmonics = &self.synth_params.harmonics;
+        LesynthFile {
+            state: self.synth_params.analysis_state.read().unwrap().clone(),
+            nested_fourier: harmonics.iter().map(|h| h.nested_fourier.read().unwrap().clone()).collect(),
+            curves: harmonics.iter().map(HarmonicCurveState::capture).collect(),
+        }
+    }
+
+    /// Replace the instrument with the contents of a `.lesynth` file. The
+    /// file's grid always replaces the current one (a Synth-mode file carries
+    /// an empty analysis grid). Rows are then redrawn as after a project
+    /// reload: every row whose curve params differ from their defaults, or in
+    /// Analysis mode every custom row. They are drawn from the file's curve
+    /// params at once, also without an editor; the host params only take
+    /// those on through [`Self::apply_pending_curves`], and until then (or
+    /// until automation moves them) they don't redraw the rows.
+    pub fn apply_lesynth_file(&self, file: &LesynthFile) {
+        for (harmonic, nested) in self.synth_params.harmonics.iter().zip(&file.nested_fourier) {
+            *harmonic.nested_fourier.write().unwrap() = nested.clone();
+        }
+        self.apply_analysis_state(&file.state, true);
+        *self.curve_fingerprints.lock().unwrap() = self.default_curve_fingerprints.as_ref().clone();
+
+        let harmonics = &self.synth_params.harmonics;
+        let already_set = harmonics.iter().zip(&file.curves).all(|(h, c)| c.is_set_on(h));
+        *self.pending_curves.lock().unwrap() = (!already_set).then(|| PendingCurves {
+            curves: file.curves.clone(),
+            sent: false,
+            superseded: harmonics.iter().zip(&file.curves).map(|(h, _)| param_fingerprints(h).map(Some)).collect(),
+        });
+        self.sync_curves_from_params();
+    }
+
+    /// Hand the curve params of a loaded `.lesynth` file to the host, so it
+    /// shows and saves them. Host params can only be set from the editor, so
+    /// it calls this every frame; a file loaded while the editor is closed
+    /// already plays, and reaches the host params once it opens. Harmonics
+    /// whose params automation has moved since keep them.
+    pub fn apply_pending_curves(&self, setter: &ParamSetter) {
+        let mut pending = self.pending_curves.lock().unwrap();
+        let Some(pending) = pending.as_mut().filter(|p| !p.sent) else {
+            return;
+        };
+        let harmonics = self.synth_params.harmonics.iter().zip(&pending.curves).zip(&pending.superseded);
+        for ((harmonic, curves), superseded) in harmonics {
+            if superseded.iter().any(Option::is_some) {
+                curves.apply(harmonic, setter);
+            }
+        }
+        pending.sent = true;
+    }
+
+    /// Let each row a loaded `.lesynth` file drew follow its params again
+    /// once they hold the file's values or have moved (host automation), and
+    /// forget the file when no row waits for its params any longer.
+    fn settle_pending_curves(&self) {
+        let mut pending = self.pending_curves.lock().unwrap();
+        let Some(p) = pending.as_mut() else {
+            return;
+        };
+        for ((harmonic, curves), superseded) in self.synth_params.harmonics.iter().zip(&p.curves).zip(&mut p.superseded) {
+            let arrived = curves.is_set_on(harmonic);
+            for (slot, fingerprint) in superseded.iter_mut().zip(param_fingerprints(harmonic)) {
+                if arrived || *slot != Some(fingerprint) {
+                    *slot = None;
+                }
+            }
+        }
+        if p.superseded.iter().flatten().all(Option::is_none) {
+            *pending = None;
         }
     }
 
+    /// Write the instrument to a `.lesynth` file.
+    pub fn save_lesynth_file(&self, path: &Path) -> Result<(), String> {
+        self.capture_lesynth_file().write_to(path)?;
+        log::info!("Saved {}", path.display());
+        Ok(())
+    }
+
+    /// Read a `.lesynth` file and load it. On error the instrument is untouched.
+    pub fn load_lesynth_file(&self, path: &Path) -> Result<(), String> {
+        let file = LesynthFile::read_from(path)?;
+        self.apply_lesynth_file(&file);
+        log::info!("Loaded {}", path.display());
+        Ok(())
+    }
+
     /// Analyse a subtrack and load the resulting grid, switching to Analysis
     /// mode. `num_buckets == 0` lets the analyser pick period-synchronous
     /// buckets. `contour` is the host's per-position fundamental (absolute Hz,
-    /// uniformly resampled across the subtrack); empty → flat at `base_freq`.
+    /// uniformly resampled across the subtrack). Either may be left out —
+    /// `base_freq <= 0` or an empty contour — and is then estimated by the
+    /// built-in tracker ([`super::resolve_pitch`]).
+    ///
+    /// Slow on long sources, so it runs on the analysis worker: progress is
+    /// published through [`SharedParams::analysis_progress`], and
+    /// [`SharedParams::cancel_analysis`] aborts it, leaving the current grid
+    /// untouched. Returns whether a new grid was loaded.
     pub fn analyze_and_load(
         &self,
         samples: &[f32],
@@ -1005,7 +2447,115 @@ impl SynthComputeEngine {
         base_freq: f32,
         contour: &[f32],
         num_buckets: usize,
-    ) {
+    ) -> bool {
+        self.shared_params.analysis_cancel.store(false, Ordering::Relaxed);
+        self.analyze_live(samples, sample_rate, base_freq, contour, num_buckets)
+    }
+
+    /// [`analyze_and_load`](Self::analyze_and_load) for a job whose cancel
+    /// flag was cleared when it was submitted.
+    fn analyze_live(
+        &self,
+        samples: &[f32],
+        sample_rate: f32,
+        base_freq: f32,
+        contour: &[f32],
+        num_buckets: usize,
+    ) -> bool {
+        let Some((result, base_freq, detected)) =
+            self.run_analysis(samples, sample_rate, base_freq, contour, num_buckets)
+        else {
+            return false;
+        };
+        // Record the source duration so playback lasts the same wall-clock time
+        // at every key (pitch-independent), regardless of the played period.
+        // The source fundamental lets the GUI report the original tone's
+        // absolute min/max pitch (base_freq * per-bucket pitch ratio).
+        let sp = &self.shared_params;
+        *sp.analysis_sample_rate.lock().unwrap() = sample_rate;
+        self.load_analysis(&result, base_freq, source_duration(samples.len(), sample_rate));
+        sp.analysis_pitch_detected.store(detected, Ordering::Relaxed);
+        sp.analysis_running.store(false, Ordering::Relaxed);
+        true
+    }
+
+    /// Analyse a subtrack as by [`analyze_and_load`](Self::analyze_and_load)
+    /// (period-synchronous buckets) and load it into `slot` (see
+    /// [`load_into`](Self::load_into)).
+    pub fn analyze_and_load_into(
+        &self,
+        slot: GridSlot,
+        samples: &[f32],
+        sample_rate: f32,
+        base_freq: f32,
+        contour: &[f32],
+    ) -> bool {
+        self.shared_params.analysis_cancel.store(false, Ordering::Relaxed);
+        self.analyze_into(slot, samples, sample_rate, base_freq, contour)
+    }
+
+    /// [`analyze_and_load_into`](Self::analyze_and_load_into) for a job whose
+    /// cancel flag was cleared when it was submitted.
+    fn analyze_into(
+        &self,
+        slot: GridSlot,
+        samples: &[f32],
+        sample_rate: f32,
+        base_freq: f32,
+        contour: &[f32],
+    ) -> bool {
+        let Some((result, base_freq, detected)) = self.run_analysis(samples, sample_rate, base_freq, contour, 0)
+        else {
+            return false;
+        };
+        let grid = LoadedGrid::new(
+            &result.amplitude,
+            &result.phase,
+            &result.pitch_ratio,
+            base_freq,
+            source_duration(samples.len(), sample_rate),
+            sample_rate,
+        );
+        let sp = &self.shared_params;
+        let loaded = match self.load_into(slot, grid) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Analysis not loaded: {}", e);
+                false
+            }
+        };
+        if matches!(slot, GridSlot::Live | GridSlot::VelocityLayer(0, _) | GridSlot::KeyZone(0, _)) {
+            sp.analysis_pitch_detected.store(detected, Ordering::Relaxed);
+        }
+        sp.analysis_running.store(false, Ordering::Relaxed);
+        loaded
+    }
+
+    /// The analysis half of [`analyze_and_load`](Self::analyze_and_load):
+    /// the display-normalised result, its base frequency and whether the
+    /// pitch was detected. Leaves `analysis_running` raised on success, for
+    /// the caller to lower once the result is loaded.
+    fn run_analysis(
+        &self,
+        samples: &[f32],
+        sample_rate: f32,
+        base_freq: f32,
+        contour: &[f32],
+        num_buckets: usize,
+    ) -> Option<(super::AnalysisResult, f32, bool)> {
+        let sp = &self.shared_params;
+        sp.analysis_buckets_done.store(0, Ordering::Relaxed);
+        sp.analysis_buckets_total.store(0, Ordering::Relaxed);
+        sp.analysis_running.store(true, Ordering::Relaxed);
+
+        // Without a usable base frequency or contour from the caller, estimate
+        // them from the audio; a DFT at the wrong fundamental is meaningless.
+        let Some(pitch) = super::resolve_pitch(samples, sample_rate, base_freq, contour) else {
+            sp.analysis_running.store(false, Ordering::Relaxed);
+            log::warn!("Analysis skipped: no base frequency given and no pitch detected");
+            return None;
+        };
+
         // The bucket grid is period-synchronous (num_buckets == 0): its size
         // tracks the source length and is no longer clamped to a small playback
         // cap. Playback length is now decoupled from the bucket count — every
@@ -1014,38 +2564,69 @@ impl SynthComputeEngine {
         // buffers. Only a generous safety bound remains, to keep the charts and
         // the per-bucket DFT sane on very long inputs.
         let max_buckets = (crate::constants::NUM_OF_BUCKETS_MAX as usize).max(num_buckets);
-        let mut result = super::analyze_subtrack(
+        let result = super::analyze_subtrack_with_progress(
             samples,
             sample_rate,
-            base_freq,
-            contour,
+            pitch.base_freq,
+            &pitch.contour,
             num_buckets,
             NUM_HARMONICS,
             max_buckets,
+            &mut |done, total| {
+                sp.analysis_buckets_done.store(done, Ordering::Relaxed);
+                sp.analysis_buckets_total.store(total, Ordering::Relaxed);
+            },
+            Some(&sp.analysis_cancel),
         );
+        let Some(mut result) = result else {
+            sp.analysis_running.store(false, Ordering::Relaxed);
+            log::info!("Analysis cancelled; keeping the current grid");
+            return None;
+        };
         // Scale the (often very quiet) analysed grid up so the charts are
         // legible; resynthesis re-normalises separately.
         super::normalize_for_display(&mut result, 0.9);
-        // Record the source duration so playback lasts the same wall-clock time
-        // at every key (pitch-independent), regardless of the played period.
-        let duration_secs = if sample_rate > 0.0 {
-            samples.len() as f32 / sample_rate
-        } else {
-            0.0
+        Some((result, pitch.base_freq, pitch.detected))
+    }
+
+    /// Queue an analysis of `samples` on this instance's analysis worker and
+    /// return immediately; the result is loaded as by
+    /// [`analyze_and_load`](Self::analyze_and_load). For the editor, which
+    /// must not block on a long analysis. A `slot` other than the live grid
+    /// takes the result instead (see
+    /// [`analyze_and_load_into`](Self::analyze_and_load_into)).
+    ///
+    /// Clears an earlier cancel, so a [`SharedParams::cancel_analysis`] from
+    /// here on stops this job even before the worker picks it up. Only the
+    /// latest submission is kept: returns whether it replaced a queued job
+    /// that hadn't started.
+    pub fn submit_analysis(&self, samples: Vec<f32>, sample_rate: f32, base_freq: f32, slot: GridSlot) -> bool {
+        let sp = &self.shared_params;
+        let replaced = {
+            let mut pending = sp.pending_analysis.lock().unwrap();
+            sp.analysis_cancel.store(false, Ordering::Relaxed);
+            pending
+                .replace(crate::AnalysisJob {
+                    samples,
+                    sample_rate,
+                    base_freq,
+                    contour: Vec::new(),
+                    target: None,
+                    slot,
+                })
+                .is_some()
         };
-        *self.shared_params.analysis_duration_secs.lock().unwrap() = duration_secs;
-        // Remember the source fundamental so the GUI can report the original
-        // tone's absolute min/max pitch (base_freq * per-bucket pitch ratio).
-        *self.shared_params.analysis_base_freq.lock().unwrap() = base_freq.max(0.0);
-        self.shared_params
-            .set_execution_mode(super::ExecutionMode::Analysis);
-        self.load_analysis(&result);
+        if replaced {
+            log::warn!("Replaced a queued analysis that hadn't started");
+        }
+        crate::wake_analysis_workers();
+        replaced
     }
 
     /// Load a precomputed harmonic grid directly (from a saved LeSynth track),
-    /// bypassing DFT analysis. Mirrors the tail of [`analyze_and_load`]: records
-    /// the source duration and fundamental, switches to Analysis mode, and hands
-    /// the grid to [`load_analysis`]. `amplitude`/`phase` are `[harmonic][bucket]`;
+    /// bypassing DFT analysis. Mirrors the tail of [`analyze_and_load`]: hands
+    /// the grid, source duration and fundamental to [`load_analysis`], which
+    /// switches to Analysis mode. `amplitude`/`phase` are `[harmonic][bucket]`;
     /// `pitch_ratio` is one entry per bucket (`f_local / base_freq`).
     ///
     /// The instance's playback sample rate is left untouched (it must stay at the
@@ -1072,18 +2653,220 @@ impl SynthComputeEngine {
             bucket_periods,
             pitch_ratio,
         };
-        *self.shared_params.analysis_duration_secs.lock().unwrap() = duration_secs.max(0.0);
-        *self.shared_params.analysis_base_freq.lock().unwrap() = base_freq.max(0.0);
-        self.shared_params
-            .set_execution_mode(super::ExecutionMode::Analysis);
-        self.load_analysis(&result);
+        self.load_analysis(&result, base_freq, duration_secs);
+        self.shared_params.analysis_pitch_detected.store(false, Ordering::Relaxed);
+    }
+
+    /// Render one note of `key` offline, sample-for-sample as `LeSynth::process`
+    /// plays a lone full-velocity voice: one pass over the key's buffer from
+    /// the current grid at the key's exact pitch, shaped by the envelope
+    /// params and released at its end (also in repeat mode) or, with a
+    /// release tail, where the tail starts; single-voice gain staging. Mono,
+    /// at the engine's sample rate.
+    pub fn render_key_offline(&self, key: usize) -> Vec<f32> {
+        let buffer = self.get_buffer_for_key(key);
+        let sp = &self.shared_params;
+        let Some(base_period) = sp.piano_period(key).filter(|_| !buffer.is_empty()) else {
+            return Vec::new();
+        };
+        if sp.release_tails_dirty.load(Ordering::Relaxed) {
+            self.update_release_tails();
+        }
+        let repeat_playback = sp.repeat_playback();
+        let (voice_gain, master_gain) = mix_gains(1);
+        let len = buffer.len();
+        let mut voice = Voice::new(buffer);
+        voice.envelope = self.envelope(*sp.sample_rate.lock().unwrap());
+        voice.set_velocity(
+            velocity_gain(1.0, self.synth_params.velocity_curve.value()),
+            velocity_darkness(1.0, self.synth_params.velocity_tilt.value()),
+            base_period,
+        );
+        voice.release_tail = self.release_tail(key, 0, false);
+        voice.set_period_rate(sp.period_rate(key));
+        let release_at = voice.release_tail.map_or(len, |tail| (tail.clamp(0.0, 1.0) * len as f32) as usize);
+        let mut out = Vec::with_capacity(len + voice.envelope.release.max(sp.fade_duration));
+        while let Some(s) = voice.next_sample(voice_gain, sp.fade_duration, repeat_playback) {
+            out.push((s * master_gain).clamp(-1.0, 1.0));
+            if voice.idx >= release_at && !voice.is_released() {
+                voice.release();
+            }
+        }
+        out
+    }
+
+    /// Render `key` (see [`render_key_offline`](Self::render_key_offline)) to a
+    /// 32-bit float WAV file.
+    pub fn render_key_to_wav(&self, key: usize, path: &Path) -> Result<(), String> {
+        if key >= NUM_KEYS {
+            return Err(format!("Key {} out of range (0..{})", key, NUM_KEYS));
+        }
+        let sample_rate = *self.shared_params.sample_rate.lock().unwrap();
+        crate::wav::write_wav_f32(path, &self.render_key_offline(key), sample_rate.round() as u32)
+    }
+
+    /// Render every key into `dir` (created if missing) as
+    /// `key_<index>_<name>.wav`, e.g. `key_48_A4.wav`. Returns the file count.
+    pub fn render_all_keys_to_wav(&self, dir: &Path) -> Result<usize, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        for key in 0..NUM_KEYS {
+            let name = format!("key_{:02}_{}.wav", key, key_name(key));
+            self.render_key_to_wav(key, &dir.join(name))?;
+        }
+        log::info!("Rendered {} keys to {}", NUM_KEYS, dir.display());
+        Ok(NUM_KEYS)
+    }
+
+    /// Export the current sound as an SFZ multisample: one 32-bit float WAV
+    /// per `step`-th key (1 = every key) and a `<name>.sfz` mapping them, all
+    /// in `dir` (created if missing). Samples carry the single-voice gain
+    /// staging and play at the key's exact pitch (the key buffer replayed as
+    /// a voice does, see `SharedParams::period_rate`), so `pitch_keycenter`
+    /// holds; the note fades become the SFZ amp envelope. With repeat
+    /// playback each sample is the key buffer looped end to end, otherwise it
+    /// plays once and fades out like a one-shot voice. Returns the `.sfz` path.
+    pub fn export_sfz(&self, dir: &Path, name: &str, step: usize) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        let sp = &self.shared_params;
+        let sample_rate = *sp.sample_rate.lock().unwrap();
+        let repeat_playback = sp.repeat_playback();
+        let fade = sp.fade_duration;
+        let (voice_gain, master_gain) = mix_gains(1);
+
+        let mut regions = Vec::new();
+        for (key, lo_key, hi_key) in crate::sfz::sampled_key_ranges(step) {
+            let mut samples: Vec<f32> = replay_at_rate(&self.get_buffer_for_key(key), sp.period_rate(key))
+                .iter()
+                .map(|&s| (s * voice_gain * master_gain).clamp(-1.0, 1.0))
+                .collect();
+            let loop_end = if repeat_playback {
+                samples.len().checked_sub(1)
+            } else {
+                // Hold the last sample through the fade-out, as a one-shot voice does.
+                let last = samples.last().copied().unwrap_or(0.0);
+                samples.extend((0..fade).map(|i| last * (1.0 - i as f32 / fade as f32)));
+                None
+            };
+            let sample = format!("{}_key_{:02}_{}.wav", name, key, key_name(key));
+            crate::wav::write_wav_f32(&dir.join(&sample), &samples, sample_rate.round() as u32)?;
+            regions.push(crate::sfz::SfzRegion { sample, key, lo_key, hi_key, loop_end });
+        }
+
+        let sfz_path = dir.join(format!("{}.sfz", name));
+        let text = crate::sfz::build_sfz(&regions, fade as f32 / sample_rate);
+        std::fs::write(&sfz_path, text).map_err(|e| format!("Cannot write {}: {}", sfz_path.display(), e))?;
+        log::info!("Exported {} SFZ regions to {}", regions.len(), sfz_path.display());
+        Ok(sfz_path)
+    }
+
+    /// Render the grid as a wavetable: one `frame_len`-sample single cycle per
+    /// bucket, through the same inverse-FFT path as playback
+    /// ([`render_bucket_ifft`]). `num_frames == 0` exports every bucket;
+    /// otherwise that many frames are picked evenly from first to last
+    /// bucket. Harmonics are limited only by the frame's own Nyquist.
+    pub fn render_wavetable(&self, frame_len: usize, num_frames: usize) -> Vec<f32> {
+        if *self.shared_params.normalization_needed.lock().unwrap() {
+            self.normalize_amplitude_data();
+            *self.shared_params.normalization_needed.lock().unwrap() = false;
+        }
+        let sp = &self.shared_params;
+        let ampl = sp.amplitude_data_normalized.lock().unwrap();
+        let phase = sp.phase_data.lock().unwrap();
+        let ampl_enabled = sp.harmonic_ampl_enabled.lock().unwrap();
+        let phase_enabled = sp.harmonic_phase_enabled.lock().unwrap();
+
+        let num_buckets = ampl.first().map(|r| r.len()).unwrap_or(0);
+        if num_buckets == 0 || frame_len < 4 {
+            return Vec::new();
+        }
+        let buckets: Vec<usize> = if num_frames == 0 || num_frames >= num_buckets {
+            (0..num_buckets).collect()
+        } else if num_frames == 1 {
+            vec![0]
+        } else {
+            (0..num_frames)
+                .map(|i| (i as f32 * (num_buckets - 1) as f32 / (num_frames - 1) as f32).round() as usize)
+                .collect()
+        };
+
+        let max_h = ampl.len().min(frame_len / 2);
+        let mut bank = IfftBank::new();
+        let mut table = Vec::with_capacity(buckets.len() * frame_len);
+        for bucket in buckets {
+            render_bucket_ifft(
+                &mut bank,
+                &mut table,
+                &ampl,
+                &phase,
+                &ampl_enabled,
+                &phase_enabled,
+                bucket,
+                frame_len,
+                max_h,
+            );
+        }
+        table
+    }
+
+    /// Write [`render_wavetable`](Self::render_wavetable) to a 32-bit float
+    /// wavetable WAV with a `clm ` cycle-length chunk. Returns the frame count.
+    pub fn export_wavetable(&self, path: &Path, frame_len: usize, num_frames: usize) -> Result<usize, String> {
+        let table = self.render_wavetable(frame_len, num_frames);
+        if table.is_empty() {
+            return Err(format!("Nothing to export at a frame length of {}", frame_len));
+        }
+        let sample_rate = *self.shared_params.sample_rate.lock().unwrap();
+        let bytes = crate::wav::encode_wavetable_f32(&table, frame_len, sample_rate.round() as u32);
+        std::fs::write(path, bytes).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        let frames = table.len() / frame_len;
+        log::info!("Exported {} wavetable frames to {}", frames, path.display());
+        Ok(frames)
+    }
+
+    /// Load a wavetable WAV as the bucket grid: one bucket per frame, each
+    /// frame's harmonic amplitude and phase read by [`super::analyze_wavetable`].
+    /// The frame length comes from `frame_len` when non-zero, else from the
+    /// file's `clm ` chunk, else [`DEFAULT_WAVETABLE_FRAME_LEN`]. A note sweeps
+    /// the table over `duration_secs` (`<= 0` → [`DEFAULT_WAVETABLE_SECS`]).
+    /// Returns the number of frames loaded.
+    pub fn import_wavetable(&self, path: &Path, frame_len: usize, duration_secs: f32) -> Result<usize, String> {
+        let wav = crate::wav::read_wav(path)?;
+        let frame_len = match frame_len {
+            0 => wav.cycle_len.unwrap_or(DEFAULT_WAVETABLE_FRAME_LEN),
+            n => n,
+        };
+        // The frame analysis needs a few samples per cycle.
+        if frame_len < 4 {
+            return Err(format!("Invalid wavetable frame length {}", frame_len));
+        }
+        let mut result = super::analyze_wavetable(&wav.samples, frame_len, NUM_HARMONICS).ok_or_else(|| {
+            format!(
+                "{} holds {} samples, less than one {}-sample frame",
+                path.display(),
+                wav.samples.len(),
+                frame_len
+            )
+        })?;
+        super::normalize_for_display(&mut result, 0.9);
+        let frames = result.pitch_ratio.len();
+        let duration_secs = if duration_secs > 0.0 { duration_secs } else { DEFAULT_WAVETABLE_SECS };
+        // Each frame is one cycle, so the table's own fundamental is one frame
+        // per cycle at the file's rate.
+        let base_freq = wav.sample_rate / frame_len as f32;
+        self.load_grid(result.amplitude, result.phase, result.pitch_ratio, base_freq, duration_secs);
+        *self.shared_params.analysis_sample_rate.lock().unwrap() = wav.sample_rate;
+        log::info!("Imported {} wavetable frames of {} samples from {}", frames, frame_len, path.display());
+        Ok(frames)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::BufferSlot;
     use crate::params::LeSynthParams;
+    use crate::voice::{VoicePool, MAX_VOICES};
+    use nih_plug::prelude::{FloatParam, FloatRange, IntParam, IntRange};
     use std::sync::Arc;
 
     fn create_test_engine() -> SynthComputeEngine {
@@ -1091,6 +2874,26 @@ mod tests {
         SynthComputeEngine::new(params)
     }
 
+    /// A float param holding `value`, standing in for one the host moved.
+    fn moved_float(value: f32) -> FloatParam {
+        FloatParam::new("Moved", value, FloatRange::Linear { min: -1000.0, max: 1000.0 })
+    }
+
+    /// An engine whose params `move_params` changed after its rows were drawn
+    /// from the defaults, as host automation or a project restore does. Its
+    /// background threads are stopped, so only the test syncs the rows.
+    fn engine_with_moved_params(move_params: impl FnOnce(&mut LeSynthParams)) -> SynthComputeEngine {
+        let mut params = LeSynthParams::default();
+        move_params(&mut params);
+        let mut engine = SynthComputeEngine::new(Arc::new(params));
+        engine.shutdown();
+        let defaults = create_test_engine();
+        defaults.shutdown();
+        *engine.curve_fingerprints.lock().unwrap() = defaults.default_curve_fingerprints.to_vec();
+        engine.default_curve_fingerprints = defaults.default_curve_fingerprints.clone();
+        engine
+    }
+
     #[test]
     fn test_engine_creation() {
         let engine = create_test_engine();
@@ -1262,7 +3065,7 @@ mod tests {
         assert_ne!(buckets, NUM_OF_BUCKETS_DEFAULT, "test should exercise a resize");
 
         // Must not panic and must produce audio.
-        let buf = engine.assemble_buffer_for_key(24);
+        let buf = engine.assemble_buffer_for_key(45);
         assert!(!buf.is_empty());
     }
 
@@ -1317,9 +3120,9 @@ mod tests {
         assert!(buckets > 128, "grid should no longer be capped at 128, got {}", buckets);
 
         let target = (secs * sr) as i64;
-        for key in [0usize, 24, 48, 72] {
+        for key in [21usize, 45, 69, 93] {
             let len = engine.assemble_buffer_for_key(key).len() as i64;
-            let period = engine.shared_params.piano_periods.lock().unwrap()[key] as i64;
+            let period = engine.shared_params.piano_period(key).unwrap() as i64;
             // The render overshoots the target by at most one final period.
             assert!(
                 len >= target && len - target <= period,
@@ -1367,13 +3170,818 @@ mod tests {
         assert_eq!(snapshot, engine.shared_params.amplitude_data.lock().unwrap()[h]);
     }
 
+    #[test]
+    fn persisted_analysis_state_restores_into_fresh_instance() {
+        // Simulates a project save/reload in a plain host: the persisted
+        // snapshot of one instance, deserialized (so `applied == false`) into a
+        // fresh instance, must bring back the grid, flags and mode.
+        let engine = create_test_engine();
+        engine.analyze_and_load(&tone(44100.0, 440.0, 1.0), 44100.0, 440.0, &[], 0);
+        engine.shared_params.harmonic_ampl_enabled.lock().unwrap()[2] = false;
+        engine.set_harmonic_custom(1, ChartType::Amp, true);
+        let mut saved = engine.synth_params.analysis_state.read().unwrap().clone();
+        assert_eq!(saved.version, ANALYSIS_STATE_VERSION);
+        saved.applied = false;
+
+        let restored = create_test_engine();
+        *restored.synth_params.analysis_state.write().unwrap() = saved;
+        restored.restore_analysis_state();
+
+        let (a, b) = (&engine.shared_params, &restored.shared_params);
+        assert_eq!(b.execution_mode(), ExecutionMode::Analysis);
+        assert_eq!(*b.analysis_base_freq.lock().unwrap(), 440.0);
+        assert_eq!(*b.analysis_duration_secs.lock().unwrap(), *a.analysis_duration_secs.lock().unwrap());
+        assert_eq!(*b.analysis_amplitude_data.lock().unwrap(), *a.analysis_amplitude_data.lock().unwrap());
+        assert_eq!(*b.analysis_phase_data.lock().unwrap(), *a.analysis_phase_data.lock().unwrap());
+        assert_eq!(*b.amplitude_data.lock().unwrap(), *a.amplitude_data.lock().unwrap());
+        assert_eq!(*b.bucket_pitch_ratio.lock().unwrap(), *a.bucket_pitch_ratio.lock().unwrap());
+        assert!(!b.harmonic_ampl_enabled.lock().unwrap()[2]);
+        assert!(b.harmonic_ampl_custom.lock().unwrap()[1]);
+
+        // A second initialize (e.g. a sample-rate change) must not redo it.
+        assert!(restored.synth_params.analysis_state.read().unwrap().applied);
+    }
+
+    #[test]
+    fn restore_ignores_unknown_state_version() {
+        let engine = create_test_engine();
+        {
+            let mut state = engine.synth_params.analysis_state.write().unwrap();
+            state.version = ANALYSIS_STATE_VERSION + 1;
+            state.duration_secs = 1.0;
+            state.num_buckets = 4;
+        }
+        engine.restore_analysis_state();
+        assert_eq!(engine.shared_params.execution_mode(), ExecutionMode::Synth);
+        assert_eq!(*engine.shared_params.analysis_duration_secs.lock().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn automated_curve_params_refill_their_rows() {
+        let engine = engine_with_moved_params(|params| {
+            params.harmonics[0].curve_offset_amp = moved_float(0.4);
+            params.harmonics[1].curve_offset_phase = moved_float(0.5);
+        });
+        assert!(engine.sync_curves_from_params());
+
+        let amp = engine.shared_params.amplitude_data.lock().unwrap().clone();
+        let phase = engine.shared_params.phase_data.lock().unwrap().clone();
+        assert!(amp[0].iter().all(|&v| (v - 0.4).abs() < 1e-6));
+        assert!(phase[1].iter().all(|&v| (v - 0.5).abs() < 1e-6));
+        // Untouched harmonics keep their silent row despite non-zero defaults.
+        assert!(amp[20].iter().all(|&v| v == 0.0));
+        // Nothing left to do once the rows match the params.
+        assert!(!engine.sync_curves_from_params());
+    }
+
+    #[test]
+    fn analysis_mode_only_refills_custom_rows_from_params() {
+        let engine = engine_with_moved_params(|params| params.harmonics[0].curve_offset_amp = moved_float(0.6));
+        engine.analyze_and_load(&tone(44100.0, 440.0, 1.0), 44100.0, 440.0, &[], 0);
+        let analysed = engine.shared_params.amplitude_data.lock().unwrap()[0].clone();
+
+        assert!(!engine.sync_curves_from_params());
+        assert_eq!(engine.shared_params.amplitude_data.lock().unwrap()[0], analysed);
+
+        // A custom row is drawn from its params, and redrawn when they move.
+        engine.set_harmonic_custom(0, ChartType::Amp, true);
+        let row = engine.shared_params.amplitude_data.lock().unwrap()[0].clone();
+        assert!(row.iter().all(|&v| (v - 0.6).abs() < 1e-6));
+        engine.curve_fingerprints.lock().unwrap()[0] = engine.default_curve_fingerprints[0];
+        assert!(engine.sync_curves_from_params());
+    }
+
+    #[test]
+    fn synth_mode_lesynth_file_round_trips_its_curves() {
+        let src = engine_with_moved_params(|params| {
+            params.harmonics[0].curve_offset_amp = moved_float(0.4);
+            params.harmonics[0].wobble_amp_amp = moved_float(0.1);
+        });
+        assert!(src.sync_curves_from_params());
+        let drawn = src.shared_params.amplitude_data.lock().unwrap()[0].clone();
+        let path = std::env::temp_dir().join(format!("lesynth-curves-{}.lesynth", std::process::id()));
+        src.save_lesynth_file(&path).unwrap();
+
+        // Loaded without an editor (as over FFI): the host params never
+        // receive the file's curves, yet the rows play them.
+        let dst = create_test_engine();
+        dst.shutdown();
+        dst.analyze_and_load(&tone(44100.0, 440.0, 1.0), 44100.0, 440.0, &[], 0);
+        dst.load_lesynth_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let sp = &dst.shared_params;
+        // The file's (empty) analysis grid replaces the analysed one.
+        assert_eq!(sp.execution_mode(), ExecutionMode::Synth);
+        assert_eq!(*sp.analysis_duration_secs.lock().unwrap(), 0.0);
+        assert_eq!(sp.amplitude_data.lock().unwrap()[0], drawn);
+        assert!(sp.amplitude_data.lock().unwrap()[20].iter().all(|&v| v == 0.0));
+        // The params the file replaced don't draw over its rows.
+        assert!(!dst.sync_curves_from_params());
+        assert_eq!(sp.amplitude_data.lock().unwrap()[0], drawn);
+        assert!(dst.pending_curves.lock().unwrap().as_ref().is_some_and(|p| !p.sent));
+    }
+
+    #[test]
+    fn rebuild_applies_restored_bucket_count() {
+        let mut params = LeSynthParams::default();
+        params.num_buckets = IntParam::new("Moved", 40, IntRange::Linear { min: 1, max: 10_000 });
+        let engine = SynthComputeEngine::new(Arc::new(params));
+        engine.rebuild_grid_from_params();
+        assert_eq!(engine.num_buckets(), 40);
+    }
+
+    #[test]
+    fn submitted_analysis_runs_on_the_worker() {
+        let engine = create_test_engine();
+        engine.submit_analysis(tone(44100.0, 330.0, 0.5), 44100.0, 0.0, GridSlot::Live);
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while *engine.shared_params.analysis_duration_secs.lock().unwrap() == 0.0 {
+            assert!(Instant::now() < deadline, "worker never loaded the submitted job");
+            thread::sleep(Duration::from_millis(10));
+        }
+        let base = *engine.shared_params.analysis_base_freq.lock().unwrap();
+        assert!((base - 330.0).abs() < 1.0, "base {}", base);
+        assert!(engine.shared_params.pending_analysis.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn analysis_cancelled_before_the_worker_starts_never_runs() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        // Let the worker see the shutdown so the job below is ours to claim.
+        thread::sleep(ANALYSIS_POLL_INTERVAL * 3);
+        let sp = &engine.shared_params;
+        sp.cancel_analysis();
+        assert!(!engine.submit_analysis(tone(44100.0, 330.0, 0.5), 44100.0, 0.0, GridSlot::Live));
+        assert!(engine.submit_analysis(tone(44100.0, 220.0, 0.5), 44100.0, 0.0, GridSlot::Live));
+        assert!(!sp.analysis_cancel.load(Ordering::Relaxed), "submitting clears an earlier cancel");
+        sp.cancel_analysis();
+
+        let job = engine.claim_analysis_job().expect("the submitted job is queued");
+        assert!(job.samples == tone(44100.0, 220.0, 0.5), "the later submission replaced the first");
+        assert!(!engine.run_analysis_job(&job));
+        assert_eq!(*sp.analysis_duration_secs.lock().unwrap(), 0.0);
+        assert!(sp.analysis_progress().is_none());
+    }
+
+    #[test]
+    fn cancelled_analysis_keeps_current_grid() {
+        let engine = Arc::new(create_test_engine());
+        let worker = {
+            let engine = engine.clone();
+            std::thread::spawn(move || {
+                engine.analyze_and_load(&tone(44100.0, 110.0, 20.0), 44100.0, 110.0, &[], 0)
+            })
+        };
+        let sp = &engine.shared_params;
+        while !matches!(sp.analysis_progress(), Some((done, _)) if done > 0) {
+            std::thread::yield_now();
+        }
+        sp.cancel_analysis();
+
+        assert!(!worker.join().unwrap(), "cancelled analysis reports no load");
+        assert!(sp.analysis_progress().is_none());
+        assert_eq!(sp.execution_mode(), ExecutionMode::Synth);
+        assert_eq!(*sp.analysis_duration_secs.lock().unwrap(), 0.0);
+        assert_eq!(engine.num_buckets(), NUM_OF_BUCKETS_DEFAULT);
+    }
+
+    #[test]
+    fn finished_analysis_reports_full_progress() {
+        let engine = create_test_engine();
+        assert!(engine.analyze_and_load(&tone(44100.0, 440.0, 1.0), 44100.0, 440.0, &[], 0));
+        let sp = &engine.shared_params;
+        assert!(sp.analysis_progress().is_none(), "idle once loaded");
+        assert_eq!(
+            sp.analysis_buckets_done.load(Ordering::Relaxed),
+            engine.num_buckets()
+        );
+    }
+
+    #[test]
+    fn analysis_without_base_freq_detects_the_pitch() {
+        let engine = create_test_engine();
+        let sp = &engine.shared_params;
+        assert!(engine.analyze_and_load(&tone(44100.0, 220.0, 1.0), 44100.0, 0.0, &[], 0));
+        let base = *sp.analysis_base_freq.lock().unwrap();
+        assert!((base - 220.0).abs() < 1.0, "detected base {}", base);
+        assert!(sp.analysis_pitch_detected.load(Ordering::Relaxed));
+
+        // Nothing to detect and nothing given: the current grid stays.
+        assert!(!engine.analyze_and_load(&vec![0.0; 44100], 44100.0, 0.0, &[], 0));
+        assert_eq!(*sp.analysis_base_freq.lock().unwrap(), base);
+    }
+
+    #[test]
+    fn offline_render_matches_a_lone_voice() {
+        let engine = create_test_engine();
+        let key = 45;
+        let buffer = engine.get_buffer_for_key(key);
+        let fade = engine.shared_params.fade_duration;
+        let rate = engine.shared_params.period_rate(key);
+        let out = engine.render_key_offline(key);
+        let pass = (buffer.len() as f64 / rate).ceil() as usize;
+        assert!(out.len().abs_diff(pass + fade) <= 1, "one pass plus the fade-out");
+        assert_eq!(out[0], 0.0, "starts at the bottom of the fade-in");
+        // Read at the key's exact pitch, as a voice does.
+        let pos = (fade + 10) as f64 * rate;
+        let (i, t) = (pos as usize, pos.fract() as f32);
+        let played = buffer[i] + (buffer[i + 1] - buffer[i]) * t;
+        assert!((out[fade + 10] - 0.8 * played).abs() < 1e-5, "single-voice gain");
+        assert!(out.last().unwrap().abs() <= 0.8 * max_abs(&buffer) / fade as f32 + 1e-6);
+        assert!(engine.render_key_offline(NUM_KEYS).is_empty());
+
+        let path = std::env::temp_dir().join(format!("lesynth-render-{}.wav", std::process::id()));
+        engine.render_key_to_wav(key, &path).unwrap();
+        let wav = crate::wav::read_wav(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(wav.samples, out);
+        assert!(engine.render_key_to_wav(NUM_KEYS, &path).is_err());
+
+        // A shaped envelope, looping, and released at the end of the pass:
+        // the same samples as a pool voice note-off'd there.
+        let time = |name: &str, ms: f32| FloatParam::new(name, ms, FloatRange::Linear { min: 0.0, max: 10_000.0 });
+        let shaped = SynthComputeEngine::new(Arc::new(LeSynthParams {
+            attack_time: time("Attack Time", 20.0),
+            decay_time: time("Decay Time", 50.0),
+            sustain_level: FloatParam::new("Sustain Level", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            release_time: time("Release Time", 100.0),
+            ..LeSynthParams::default()
+        }));
+        shaped.shutdown();
+        let sp = &shaped.shared_params;
+        sp.set_repeat_playback(true);
+        let buffer: Arc<[f32]> = shaped.assemble_buffer_for_key(key).into();
+        shaped.publish_key_buffer(key, Some(buffer.clone()));
+        let envelope = shaped.envelope(*sp.sample_rate.lock().unwrap());
+        assert!(envelope.release > fade && envelope.sustain == 0.5);
+        let out = shaped.render_key_offline(key);
+        let pass = (buffer.len() as f64 / sp.period_rate(key)).ceil() as usize;
+        assert!(out.len().abs_diff(pass + envelope.release) <= 1, "one pass plus the release");
+
+        let mut pool = VoicePool::new(MAX_VOICES, NUM_KEYS, NUM_HARMONICS);
+        pool.set_envelope(envelope);
+        shaped.start_note(pool.note_on(key, 0, None, 1.0).unwrap(), 0.0);
+        let mut played = Vec::new();
+        while pool.is_playing(key) {
+            played.push(pool.next_mix_sample(None, fade, true));
+            if pool.buffered_mut().any(|(_, voice)| voice.idx >= buffer.len()) {
+                let _ = pool.note_off(key, 0, None);
+            }
+        }
+        assert_eq!(played.pop(), Some(0.0), "the mix of the finished voice");
+        assert_eq!(played, out);
+    }
+
+    #[test]
+    fn sfz_export_writes_mapped_samples() {
+        let engine = create_test_engine();
+        let sp = &engine.shared_params;
+        let len = replay_at_rate(&engine.get_buffer_for_key(60), sp.period_rate(60)).len();
+        let fade = sp.fade_duration;
+        for repeat in [true, false] {
+            sp.set_repeat_playback(repeat);
+            let dir = std::env::temp_dir().join(format!("lesynth-sfz-{}-{}", std::process::id(), repeat));
+            let sfz = engine.export_sfz(&dir, "patch", 12).unwrap();
+            let text = std::fs::read_to_string(&sfz).unwrap();
+            let wav = crate::wav::read_wav(&dir.join("patch_key_60_C4.wav")).unwrap();
+            let _ = std::fs::remove_dir_all(&dir);
+
+            let regions = text.lines().filter(|l| l.starts_with("<region>")).count();
+            assert_eq!(regions, crate::sfz::sampled_key_ranges(12).len());
+            let region = "sample=patch_key_60_C4.wav lokey=55 hikey=66 pitch_keycenter=60";
+            if repeat {
+                // The key buffer looped end to end.
+                assert_eq!(wav.samples.len(), len);
+                let looped = format!("{} loop_mode=loop_continuous loop_start=0 loop_end={}", region, len - 1);
+                assert!(text.contains(&looped), "{}", text);
+            } else {
+                // Played once, then held through the fade-out.
+                assert_eq!(wav.samples.len(), len + fade);
+                assert!(text.contains(&format!("{} loop_mode=no_loop", region)), "{}", text);
+                assert!(!text.contains("loop_continuous"));
+            }
+        }
+    }
+
+    /// Fundamental (Hz) of a pure tone, from its rising zero crossings.
+    fn crossing_frequency(samples: &[f32], sample_rate: f64) -> f64 {
+        let rising = samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count();
+        rising as f64 * sample_rate / samples.len() as f64
+    }
+
+    #[test]
+    fn exports_play_high_keys_at_their_exact_pitch() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        assert!(engine.analyze_and_load(&tone(44100.0, 220.0, 1.0), 44100.0, 220.0, &[], 0));
+        // Key 127 renders whole periods of 4 samples for an exact 3.52.
+        let key = 127;
+        assert_eq!(engine.shared_params.piano_period(key), Some(4));
+        for k in [0, key] {
+            engine.publish_key_buffer(k, Some(engine.assemble_buffer_for_key(k).into()));
+        }
+        let expected = engine.shared_params.key_frequency(key);
+        let fade = engine.shared_params.fade_duration;
+
+        let out = engine.render_key_offline(key);
+        let f = crossing_frequency(&out[fade..out.len() - fade], 44100.0);
+        assert!((f / expected - 1.0).abs() < 0.005, "offline {} Hz vs {} Hz", f, expected);
+
+        let dir = std::env::temp_dir().join(format!("lesynth-sfz-pitch-{}", std::process::id()));
+        engine.export_sfz(&dir, "patch", key).unwrap();
+        let wav = crate::wav::read_wav(&dir.join(format!("patch_key_{}_{}.wav", key, key_name(key)))).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let held = wav.samples.len() - fade;
+        let f = crossing_frequency(&wav.samples[..held], 44100.0);
+        assert!((f / expected - 1.0).abs() < 0.005, "SFZ {} Hz vs {} Hz", f, expected);
+    }
+
+    #[test]
+    fn wavetable_frames_are_single_cycles_of_each_bucket() {
+        let engine = create_test_engine();
+        assert!(engine.analyze_and_load(&tone(44100.0, 220.0, 0.5), 44100.0, 220.0, &[], 0));
+        let nb = engine.num_buckets();
+        let all = engine.render_wavetable(2048, 0);
+        assert_eq!(all.len(), nb * 2048);
+        assert!(max_abs(&all) > 0.0);
+
+        let subset = engine.render_wavetable(256, 4);
+        assert_eq!(subset.len(), 4 * 256);
+        // Each frame is one full cycle: it wraps cleanly onto its own start.
+        let frame = &subset[..256];
+        let wrap = (frame[255] - frame[0]).abs();
+        let step = frame.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0f32, f32::max);
+        assert!(wrap <= step + 1e-6, "wrap {} vs largest step {}", wrap, step);
+        assert!(engine.render_wavetable(2, 0).is_empty());
+    }
+
+    /// A flat, steady grid: every bucket holds the same harmonics.
+    fn steady_grid(num_harmonics: usize, num_buckets: usize) -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+        let ampl = (0..num_harmonics)
+            .map(|n| vec![0.6 / (n + 1) as f32; num_buckets])
+            .collect();
+        let phase = (0..num_harmonics)
+            .map(|n| vec![(0.7 * n as f32).rem_euclid(TWO_PI); num_buckets])
+            .collect();
+        (ampl, phase)
+    }
+
+    #[test]
+    fn continuous_render_matches_periodic_for_a_steady_grid() {
+        // Few harmonics (direct sum) and many (inverse FFT), per-bucket
+        // (Synth) and time-driven (Analysis) timelines.
+        for (num_harmonics, target_samples) in [(6, 0), (6, 5000), (40, 0), (40, 5000)] {
+            let (ampl, phase) = steady_grid(num_harmonics, 12);
+            let enabled = vec![true; num_harmonics];
+            let render = |mode| {
+                render_key_buffer(
+                    num_harmonics, &ampl, &phase, &enabled, &enabled, 200, 64, &[], target_samples, mode, None,
+                )
+            };
+            let periodic = render(RenderMode::Periodic);
+            let continuous = render(RenderMode::Continuous);
+            assert_eq!(periodic.len(), continuous.len());
+            assert!(max_abs(&periodic) > 0.1);
+            let worst = periodic
+                .iter()
+                .zip(&continuous)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0f32, f32::max);
+            assert!(worst < 1e-4, "{} harmonics, target {}: max diff {}", num_harmonics, target_samples, worst);
+        }
+    }
+
+    #[test]
+    fn continuous_render_has_no_steps_at_bucket_edges() {
+        // Two buckets with different amplitude and phase: the periodic render
+        // jumps at the edge, the continuous one moves in small steps.
+        let ampl = vec![vec![0.2, 0.8]];
+        let phase = vec![vec![0.0, 2.5]];
+        let on = [true];
+        let periodic = render_key_buffer(1, &ampl, &phase, &on, &on, 100, 1, &[], 0, RenderMode::Periodic, None);
+        let continuous = render_key_buffer(1, &ampl, &phase, &on, &on, 100, 1, &[], 0, RenderMode::Continuous, None);
+        let largest_step = |s: &[f32]| s.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0f32, f32::max);
+        // A 0.8-amplitude sine at 100 samples per cycle moves ~0.05 per sample;
+        // the gliding phase adds a little on top.
+        assert!(largest_step(&periodic) > 0.3);
+        assert!(largest_step(&continuous) < 0.1, "step {}", largest_step(&continuous));
+    }
+
+    #[test]
+    fn streamed_voice_matches_the_continuous_render_and_hears_edits() {
+        let engine = create_test_engine();
+        assert!(engine.analyze_and_load(&tone(44100.0, 330.0, 0.3), 44100.0, 330.0, &[], 0));
+        engine.set_render_mode(RenderMode::Continuous);
+        let key = 61;
+        let rendered = engine.assemble_buffer_for_key(key);
+
+        engine.publish_key_buffer(key, Some(rendered.clone().into()));
+        engine.set_streaming(true);
+        assert!(engine.shared_params.key_buffers.iter().all(BufferSlot::is_empty));
+        let mut voice = Voice::new(Arc::from([]));
+        engine.start_voice(&mut voice, key, 1.0);
+        assert!(voice.is_streamed() && voice.buffer.is_none());
+        let bank = &mut voice.bank;
+        assert_eq!(bank.len(), rendered.len());
+        // The render is at the key's whole-sample period; so is the bank
+        // without the voice's correction to the exact pitch.
+        bank.set_pitch(1.0);
+        let streamed: Vec<f32> = engine.with_stream_grid(|grid| (0..1000).map(|_| bank.next_sample(grid, true)).collect());
+        assert_eq!(&streamed[..], &rendered[..1000]);
+
+        // Silencing the grid is heard on the next sample once the snapshot
+        // is republished.
+        engine.shared_params.amplitude_data.lock().unwrap().iter_mut().for_each(|r| r.fill(0.0));
+        engine.set_normalization_needed(true);
+        assert!(engine.shared_params.stream_grid_dirty.load(Ordering::Relaxed));
+        engine.publish_stream_grid();
+        assert_eq!(engine.with_stream_grid(|grid| bank.next_sample(grid, true)), 0.0);
+        assert!(engine.shared_params.retired_stream_grids.lock().unwrap().is_empty(), "no voice held the old one");
+
+        engine.set_streaming(false);
+        engine.start_voice(&mut voice, key, 1.0);
+        assert!(!voice.is_streamed());
+    }
+
+    #[test]
+    fn note_on_never_renders_and_picks_up_the_buffer_when_it_lands() {
+        let engine = create_test_engine();
+        // Keep the background thread from publishing behind the test's back.
+        engine.shutdown();
+        let key = 51;
+        let mut pool = VoicePool::new(MAX_VOICES, NUM_KEYS, NUM_HARMONICS);
+        engine.publish_key_buffer(key, None);
+        let voice = pool.start(key, 0, None).unwrap();
+        engine.start_voice(voice, key, 1.0);
+        assert!(voice.buffer.is_none(), "no synchronous render on note-on");
+
+        let first: Arc<[f32]> = engine.assemble_buffer_for_key(key).into();
+        engine.publish_key_buffer(key, Some(first.clone()));
+        for (k, voice) in pool.buffered_mut() {
+            engine.refresh_voice_buffer(voice, k);
+        }
+        assert!(pool.next_mix_sample(None, 4, true).abs() <= 1.0);
+
+        // A re-render retires the old buffer while the voice still plays it,
+        // and frees it once the voice has moved on.
+        let second: Arc<[f32]> = engine.assemble_buffer_for_key(key).into();
+        engine.publish_key_buffer(key, Some(second.clone()));
+        assert_eq!(engine.shared_params.retired_buffers.lock().unwrap().len(), 1);
+        drop(first);
+        for (k, voice) in pool.buffered_mut() {
+            engine.refresh_voice_buffer(voice, k);
+            assert!(Arc::ptr_eq(voice.buffer.as_ref().unwrap(), &second));
+        }
+        engine.free_retired_buffers();
+        assert!(engine.shared_params.retired_buffers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn release_tail_starts_after_the_last_loud_bucket() {
+        let decay = vec![vec![1.0, 0.9, 0.6, 0.3, 0.1], vec![0.0, 0.2, 0.1, 0.0, 0.0]];
+        assert_eq!(release_tail_start(&decay), Some(0.6));
+        assert_eq!(release_tail_start(&[vec![0.5; 4]]), None, "no tail");
+        assert_eq!(release_tail_start(&[vec![0.0; 4]]), None, "silent");
+        assert_eq!(release_tail_start(&[]), None);
+    }
+
+    #[test]
+    fn release_tails_are_measured_per_layer_and_zone() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let (amp, phase) = steady_grid(2, 5);
+        engine.load_grid(amp.clone(), phase.clone(), vec![1.0; 2], 220.0, 0.1);
+        let decay = vec![vec![1.0, 0.9, 0.6, 0.3, 0.1]; 2];
+        let decaying = || LoadedGrid::new(&decay, &phase, &[1.0; 2], 880.0, 0.1, 44_100.0);
+        engine.set_velocity_layer(1, VelocityRange::new(0.4, 1.0), decaying()).unwrap();
+        engine.set_key_zone(1, KeyZone::new(81, 71, 108), decaying()).unwrap();
+        assert!(engine.shared_params.release_tails_dirty.load(Ordering::Relaxed));
+
+        engine.update_release_tails();
+        let sp = &engine.shared_params;
+        assert!(!sp.release_tails_dirty.load(Ordering::Relaxed));
+        assert_eq!(sp.layer_release_tail(0), None, "the steady live grid has no tail");
+        assert_eq!(sp.layer_release_tail(1), Some(0.6));
+        assert_eq!(sp.key_release_tail(61), None, "outside zone 1's keys");
+        assert_eq!(sp.key_release_tail(91), Some(0.6));
+
+        engine.clear_key_zone(1).unwrap();
+        engine.update_release_tails();
+        assert_eq!(engine.shared_params.key_release_tail(91), None);
+    }
+
+    #[test]
+    fn mono_legato_carries_the_voice_over_to_the_new_key() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let (low, high) = (60, 67);
+        let low_buffer: Arc<[f32]> = engine.assemble_buffer_for_key(low).into();
+        let high_buffer: Arc<[f32]> = engine.assemble_buffer_for_key(high).into();
+        engine.publish_key_buffer(low, Some(low_buffer.clone()));
+        engine.publish_key_buffer(high, Some(high_buffer.clone()));
+        let mut pool = VoicePool::new(MAX_VOICES, NUM_KEYS, NUM_HARMONICS);
+        pool.set_mono(true, true);
+        engine.start_note(pool.note_on(low, 0, None, 1.0).unwrap(), 100.0);
+        (0..500).for_each(|_| { pool.next_mix_sample(None, 4, true); });
+        let (_, voice) = pool.buffered_mut().next().unwrap();
+        let progress = voice.idx as f64 / low_buffer.len() as f64;
+
+        let note = pool.note_on(high, 0, None, 1.0).unwrap();
+        assert!(note.legato && note.from_key == Some(low));
+        engine.start_note(note, 100.0);
+        let (key, voice) = pool.buffered_mut().next().unwrap();
+        assert_eq!(key, high);
+        assert!(Arc::ptr_eq(voice.buffer.as_ref().unwrap(), &high_buffer));
+        assert!(!voice.fade_in_active, "carried on, not restarted");
+        // The shorter buffer of the higher key goes on from the same point
+        // of the note, not from the same sample.
+        assert!(high_buffer.len() < low_buffer.len());
+        let carried = voice.idx as f64 / high_buffer.len() as f64;
+        assert!((carried - progress).abs() <= 1.0 / high_buffer.len() as f64, "{} vs {}", carried, progress);
+        assert!((engine.key_interval(low, high) + 7.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn note_on_picks_and_crossfades_velocity_layers() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let key = 61;
+        let (amp, phase) = steady_grid(4, 3);
+        engine.load_grid(amp.clone(), phase.clone(), vec![1.0; 3], 220.0, 0.1);
+        engine.set_velocity_range(0, VelocityRange::new(0.0, 0.6));
+        let hard = LoadedGrid::new(&amp, &phase, &[1.0; 3], 220.0, 0.2, 44_100.0);
+        engine.set_velocity_layer(1, VelocityRange::new(0.4, 1.0), hard).unwrap();
+        assert!(engine.set_velocity_layer(MAX_VELOCITY_LAYERS, VelocityRange::FULL, LoadedGrid::default()).is_err());
+
+        let soft_buf: Arc<[f32]> = engine.assemble_buffer_for_key(key).into();
+        engine.publish_key_buffer(key, Some(soft_buf.clone()));
+        let sp = &engine.shared_params;
+        let hard_buf: Arc<[f32]> = SynthComputeEngine::compute_layer_buffer_static(sp, 1, key).unwrap().into();
+        assert!(hard_buf.len() > soft_buf.len(), "each layer keeps its own duration");
+        engine.publish_buffer(&sp.layer_key_buffers[SharedParams::layer_slot(1, key)], Some(hard_buf.clone()));
+
+        let mut voice = Voice::new(Arc::from([]));
+        engine.start_voice(&mut voice, key, 0.2);
+        assert!(Arc::ptr_eq(voice.buffer.as_ref().unwrap(), &soft_buf) && voice.blend_buffer.is_none());
+        engine.start_voice(&mut voice, key, 0.9);
+        assert_eq!(voice.layer, 1);
+        assert!(Arc::ptr_eq(voice.buffer.as_ref().unwrap(), &hard_buf));
+        engine.start_voice(&mut voice, key, 0.5);
+        assert_eq!((voice.layer, voice.blend_layer), (0, 1));
+        assert!((voice.layer_mix - 0.5).abs() < 1e-6);
+        assert!(Arc::ptr_eq(voice.blend_buffer.as_ref().unwrap(), &hard_buf));
+
+        // The layer outlives a state round trip; clearing it silences it.
+        let mut saved = engine.synth_params.analysis_state.read().unwrap().clone();
+        saved.applied = false;
+        let restored = create_test_engine();
+        *restored.synth_params.analysis_state.write().unwrap() = saved;
+        restored.restore_analysis_state();
+        let ranges = restored.shared_params.velocity_ranges();
+        assert_eq!((ranges[0], ranges[1]), (Some(VelocityRange::new(0.0, 0.6)), Some(VelocityRange::new(0.4, 1.0))));
+
+        // A `.lesynth` file carries no layers: loading one keeps them.
+        restored.apply_lesynth_file(&create_test_engine().capture_lesynth_file());
+        assert_eq!(restored.shared_params.velocity_ranges(), ranges);
+        assert!(SynthComputeEngine::compute_layer_buffer_static(&restored.shared_params, 1, key).is_some());
+        restored.clear_velocity_layer(1).unwrap();
+        assert!(SynthComputeEngine::compute_layer_buffer_static(&restored.shared_params, 1, key).is_none());
+        restored.shutdown();
+    }
+
+    #[test]
+    fn keys_render_from_their_zone() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let (amp, phase) = steady_grid(4, 3);
+        engine.load_grid(amp.clone(), phase.clone(), vec![1.0; 3], 220.0, 0.1);
+        assert_eq!(engine.shared_params.key_zones()[0], Some(KeyZone::rooted_at(57)));
+        let live_len = engine.assemble_buffer_for_key(91).len();
+
+        let high = LoadedGrid::new(&amp, &phase, &[1.0; 3], 880.0, 0.2, 44_100.0);
+        engine.set_key_zone(1, KeyZone::new(81, 71, 108), high).unwrap();
+        assert!(engine.set_key_zone(MAX_KEY_ZONES, KeyZone::default(), LoadedGrid::default()).is_err());
+        assert_eq!(engine.assemble_buffer_for_key(61).len(), live_len, "outside zone 1's keys");
+        assert!(engine.assemble_buffer_for_key(91).len() > live_len, "zone 1 keeps its own duration");
+
+        // Between the roots the nearer zone plays, blended towards the other.
+        engine.set_zone_interpolation(true);
+        assert_eq!(engine.assemble_buffer_for_key(76).len(), engine.assemble_buffer_for_key(91).len());
+
+        let mut saved = engine.synth_params.analysis_state.read().unwrap().clone();
+        saved.applied = false;
+        let restored = create_test_engine();
+        *restored.synth_params.analysis_state.write().unwrap() = saved;
+        restored.restore_analysis_state();
+        assert_eq!(restored.shared_params.key_zones(), engine.shared_params.key_zones());
+        assert!(restored.shared_params.zone_interpolation.load(Ordering::Relaxed));
+        restored.shutdown();
+
+        // A `.lesynth` file carries no zones: loading one keeps them all.
+        let zones = engine.shared_params.key_zones();
+        engine.apply_lesynth_file(&engine.capture_lesynth_file());
+        assert_eq!(engine.shared_params.key_zones(), zones);
+        assert!(engine.shared_params.zone_interpolation.load(Ordering::Relaxed));
+
+        assert!(engine.clear_key_zone(0).is_err());
+        engine.clear_key_zone(1).unwrap();
+        assert_eq!(engine.assemble_buffer_for_key(91).len(), live_len);
+    }
+
+    #[test]
+    fn streamed_voice_keeps_the_formants_of_the_render() {
+        let engine = create_test_engine();
+        assert!(engine.analyze_and_load(&tone(44100.0, 330.0, 0.3), 44100.0, 330.0, &[], 0));
+        engine.set_render_mode(RenderMode::Continuous);
+        // Key 81 (880 Hz) plays well above the analysed pitch.
+        let key = 81;
+        let plain = engine.assemble_buffer_for_key(key);
+        *engine.shared_params.formant.lock().unwrap() = FormantSettings { preserve: true, shift_semitones: 3.0 };
+        let rendered = engine.assemble_buffer_for_key(key);
+        assert_ne!(rendered, plain);
+
+        engine.set_streaming(true);
+        let mut voice = Voice::new(Arc::from([]));
+        engine.start_voice(&mut voice, key, 1.0);
+        let bank = &mut voice.bank;
+        bank.set_pitch(1.0);
+        let streamed: Vec<f32> = engine.with_stream_grid(|grid| (0..1000).map(|_| bank.next_sample(grid, true)).collect());
+        assert!(streamed.iter().zip(&rendered).all(|(s, r)| (s - r).abs() < 1e-5));
+    }
+
+    #[test]
+    fn formant_preservation_rerenders_transposed_keys() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let (amp, phase) = steady_grid(8, 3);
+        engine.load_grid(amp, phase, vec![1.0; 3], 220.0, 0.05);
+        assert!(!engine.sync_formant_from_params());
+
+        // Key 81 (880 Hz) plays two octaves above the analysed pitch.
+        let plain = engine.assemble_buffer_for_key(81);
+        *engine.shared_params.formant.lock().unwrap() = FormantSettings { preserve: true, shift_semitones: 0.0 };
+        let preserved = engine.assemble_buffer_for_key(81);
+        assert_eq!(plain.len(), preserved.len());
+        assert!(plain.iter().zip(&preserved).any(|(a, b)| (a - b).abs() > 1e-4));
+
+        // Synced back to the (untouched) params once they have settled,
+        // preservation is off again.
+        assert!(!engine.sync_formant_from_params());
+        assert!(engine.shared_params.formant().preserve, "not before they settle");
+        thread::sleep(FORMANT_SETTLE);
+        assert!(engine.sync_formant_from_params());
+        assert_eq!(engine.assemble_buffer_for_key(81), plain);
+    }
+
+    #[test]
+    fn scala_tuning_retunes_keys_and_persists() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let dir = std::env::temp_dir().join(format!("lesynth-tuning-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let scl = dir.join("just.scl");
+        std::fs::write(&scl, "! just.scl\nJust major\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n").unwrap();
+        assert!(engine.load_tuning_files(&dir.join("missing.scl"), None).is_err());
+        assert_eq!(engine.load_tuning_files(&scl, None).unwrap(), "Just major");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let sp = &engine.shared_params;
+        assert!((sp.key_frequency(69) - 440.0).abs() < 1e-9);
+        assert!((sp.key_frequency(64) / sp.key_frequency(60) - 1.5).abs() < 1e-9);
+        let c4_period = 44_100.0 / sp.key_frequency(60);
+        assert_eq!(sp.piano_period(60), Some(c4_period.round() as usize));
+        assert_eq!(sp.buffer_states.lock().unwrap()[60], BufferState::Dirty);
+        // Whole-sample periods played at the voice's rate sound the scale.
+        for key in 0..NUM_KEYS {
+            let played = 44_100.0 / sp.piano_period(key).unwrap() as f64 * sp.period_rate(key);
+            assert!((played / sp.key_frequency(key) - 1.0).abs() < 1e-9, "key {} plays {} Hz", key, played);
+        }
+
+        let restored = create_test_engine();
+        *restored.synth_params.tuning.write().unwrap() = engine.synth_params.tuning.read().unwrap().clone();
+        restored.restore_tuning();
+        assert_eq!(restored.tuning_name().as_deref(), Some("Just major"));
+        assert_eq!(restored.shared_params.key_frequency(64), sp.key_frequency(64));
+        restored.shutdown();
+
+        engine.set_tuning(None);
+        assert!(engine.synth_params.tuning.read().unwrap().is_none());
+        assert!((sp.key_frequency(64) - 329.627_556_9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn keys_play_at_their_exact_pitch() {
+        let engine = create_test_engine();
+        engine.shutdown();
+        let sp = &engine.shared_params;
+        assert!(sp.set_reference_pitch(442.0));
+        let key = 69;
+        assert_eq!(sp.piano_period(key), Some(100), "renders 441 Hz cycles");
+        engine.publish_key_buffer(key, Some(engine.assemble_buffer_for_key(key).into()));
+        let mut voice = Voice::new(Arc::from([]));
+        engine.start_voice(&mut voice, key, 1.0);
+        (0..44_100).for_each(|_| { voice.next_sample(1.0, 4, true); });
+        // Rendered cycles played in one second; dropping the fractional
+        // sample costs under 0.01 Hz.
+        let played = voice.idx as f64 / 100.0;
+        assert!((played - 442.0).abs() < 0.01, "A4 plays at {} Hz", played);
+    }
+
+    #[test]
+    fn render_settings_switch_rerender_keys_and_persist() {
+        let engine = create_test_engine();
+        assert_eq!(engine.shared_params.render_mode(), RenderMode::Periodic);
+        engine.shared_params.buffer_states.lock().unwrap()[10] = BufferState::Clean;
+        engine.set_render_mode(RenderMode::Continuous);
+        assert_eq!(engine.shared_params.render_mode(), RenderMode::Continuous);
+        assert_eq!(engine.shared_params.buffer_states.lock().unwrap()[10], BufferState::Dirty);
+
+        // The choice is saved with the project and comes back on load.
+        let restored = create_test_engine();
+        let saved = *engine.synth_params.render_mode.read().unwrap();
+        *restored.synth_params.render_mode.write().unwrap() = saved;
+        restored.restore_render_settings();
+        assert_eq!(restored.shared_params.render_mode(), RenderMode::Continuous);
+        assert!(!restored.shared_params.streaming());
+
+        engine.set_streaming(true);
+        let saved = *engine.synth_params.streaming.read().unwrap();
+        *restored.synth_params.streaming.write().unwrap() = saved;
+        restored.restore_render_settings();
+        assert!(restored.shared_params.streaming());
+    }
+
+    #[test]
+    fn fitted_curve_follows_the_analysed_row_across_the_phase_wrap() {
+        let engine = create_test_engine();
+        let nb = 48;
+        let amp_row: Vec<f32> = (0..nb).map(|b| 0.5 + 0.3 * (TWO_PI * b as f32 / nb as f32).sin()).collect();
+        // A drifting phase that wraps past 2π halfway through.
+        let phase_row: Vec<f32> = (0..nb).map(|b| (5.0 + 2.5 * b as f32 / nb as f32).rem_euclid(TWO_PI)).collect();
+        let mut amplitude = vec![vec![0.0; nb]; NUM_HARMONICS];
+        let mut phase = vec![vec![0.0; nb]; NUM_HARMONICS];
+        amplitude[2] = amp_row.clone();
+        phase[2] = phase_row.clone();
+        engine.load_grid(amplitude, phase, vec![1.0; nb], 110.0, 1.0);
+
+        let offset = engine.fit_harmonic_curve(2, ChartType::Amp).unwrap();
+        assert!((offset - 0.5).abs() < 1e-4);
+        let series = engine.synth_params.harmonics[2].nested_fourier.read().unwrap().amp_chart.clone();
+        assert!((series.amps[0] - 0.3).abs() < 1e-4);
+        for (b, &v) in amp_row.iter().enumerate() {
+            assert!((series.value_at(offset as f64, b as f64 / nb as f64) as f32 - v).abs() < 1e-3);
+        }
+
+        let offset = engine.fit_harmonic_curve(2, ChartType::Phase).unwrap();
+        assert!((0.0..=MAX_OFFSET_PHASE as f32).contains(&offset));
+        let series = engine.synth_params.harmonics[2].nested_fourier.read().unwrap().phase_chart.clone();
+        // The unwrapped ramp leaves one jump (end → start), so compare the
+        // middle of the row, away from the Gibbs ringing at the edges.
+        for b in nb / 4..3 * nb / 4 {
+            let fitted = series.value_at(offset as f64, b as f64 / nb as f64) as f32;
+            let diff = (fitted - phase_row[b]).rem_euclid(TWO_PI);
+            assert!(diff.min(TWO_PI - diff) < 0.1, "bucket {}: {} vs {}", b, fitted, phase_row[b]);
+        }
+        assert!(engine.fit_harmonic_curve(NUM_HARMONICS, ChartType::Amp).is_none());
+    }
+
+    #[test]
+    fn exported_wavetable_imports_back_frame_for_frame() {
+        let engine = create_test_engine();
+        assert!(engine.analyze_and_load(&tone(44100.0, 220.0, 0.5), 44100.0, 220.0, &[], 0));
+        let path = std::env::temp_dir().join(format!("lesynth_wt_import_{}.wav", std::process::id()));
+        assert_eq!(engine.export_wavetable(&path, 512, 6), Ok(6));
+
+        let other = create_test_engine();
+        // Frame length comes from the clm chunk.
+        assert_eq!(other.import_wavetable(&path, 0, 0.0), Ok(6));
+        assert_eq!(other.num_buckets(), 6);
+        assert_eq!(*other.shared_params.analysis_duration_secs.lock().unwrap(), DEFAULT_WAVETABLE_SECS);
+        let base = *other.shared_params.analysis_base_freq.lock().unwrap();
+        assert!((base - 44100.0 / 512.0).abs() < 1e-3, "base {}", base);
+        // The fundamental dominates every imported frame, as it did on export.
+        let amp = other.shared_params.amplitude_data.lock().unwrap();
+        for b in 0..6 {
+            assert!(amp[0][b] > amp[1][b] && amp[0][b] > 0.0, "bucket {}", b);
+        }
+        drop(amp);
+
+        // An explicit frame length overrides the chunk.
+        assert_eq!(other.import_wavetable(&path, 1024, 1.0), Ok(3));
+        assert!(other.import_wavetable(&path, 1 << 20, 1.0).is_err());
+        let short = other.import_wavetable(&path, 3, 1.0).unwrap_err();
+        assert!(short.starts_with("Invalid wavetable frame length"), "{}", short);
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn analysis_playback_buffers_are_audible() {
         let engine = create_test_engine();
         engine.analyze_and_load(&tone(44100.0, 440.0, 1.0), 44100.0, 440.0, &[], 0);
         // Both the synchronous (GUI fallback) and static (async thread) render
         // paths must yield non-empty, non-silent audio for a range of keys.
-        for key in [0usize, 24, 48, 60] {
+        for key in [21usize, 45, 69, 81] {
             let inst = engine.assemble_buffer_for_key(key);
             assert!(!inst.is_empty(), "instance buffer empty for key {}", key);
             assert!(max_abs(&inst) > 0.01, "instance buffer silent for key {}", key);
@@ -1417,7 +4025,7 @@ mod tests {
         assert!(hi - lo > 0.02, "vibrato not reflected in playback ratios: [{lo}, {hi}]");
 
         // Playback still produces audible audio.
-        let buf = engine.assemble_buffer_for_key(48);
+        let buf = engine.assemble_buffer_for_key(69);
         assert!(max_abs(&buf) > 0.01, "vibrato playback is silent");
     }
 
@@ -1431,8 +4039,8 @@ mod tests {
             *r = vec![1.5; buckets];
         }
         engine.shared_params.set_execution_mode(ExecutionMode::Synth);
-        let base_period = engine.shared_params.piano_periods.lock().unwrap()[36] as usize;
-        let len = engine.assemble_buffer_for_key(36).len();
+        let base_period = engine.shared_params.piano_period(57).unwrap();
+        let len = engine.assemble_buffer_for_key(57).len();
         assert_eq!(len, buckets * base_period, "synth playback must ignore ratios");
     }
 
@@ -1521,9 +4129,9 @@ mod tests {
     #[test]
     fn analysis_pitch_ratio_transposes_playback_period() {
         let engine = create_test_engine();
-        let key = 40;
+        let key = 61;
         let buckets = engine.shared_params.amplitude_data.lock().unwrap()[0].len();
-        let base_period = engine.shared_params.piano_periods.lock().unwrap()[key] as usize;
+        let base_period = engine.shared_params.piano_period(key).unwrap();
         *engine.shared_params.normalization_needed.lock().unwrap() = false;
 
         // A ratio grid is present, but Synth mode must ignore it (flat playback).
@@ -0,0 +1,279 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Microtuning from Scala files.
+//!
+//! A scale (`.scl`) lists the pitches of its degrees above degree 0, the last
+//! one being the period it repeats at (usually the octave). A keyboard
+//! mapping (`.kbm`) says which key plays which degree and which key is tuned
+//! to which frequency. Without a mapping, degree 0 sits on middle C (key 60),
+//! every key is the next degree and A4 (key 69) is tuned to the reference
+//! pitch, as Scala itself does.
+
+use crate::constants::{NUM_KEYS, REFERENCE_KEY};
+use crate::params::TuningState;
+
+/// A parsed `.scl` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    /// Cents of degrees `1..=n` above degree 0; the last is the period.
+    pub cents: Vec<f64>,
+}
+
+impl Scale {
+    /// Parse `.scl` text: `!` comment lines, a description line, the number
+    /// of notes, then one pitch per line, in cents (with a `.`) or as a ratio
+    /// (`3/2`, or a whole number). Anything after a pitch is ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|l| !l.starts_with('!'));
+        let description = lines.next().ok_or("Scale file is empty")?.trim().to_string();
+        let count_line = lines.next().ok_or("Scale file has no note count")?;
+        let count: usize = first_token(count_line)
+            .parse()
+            .map_err(|_| format!("Bad note count '{}'", count_line.trim()))?;
+        if count == 0 {
+            return Err("Scale has no notes".into());
+        }
+        let cents = lines
+            .filter(|l| !l.trim().is_empty())
+            .take(count)
+            .map(parse_pitch)
+            .collect::<Result<Vec<f64>, String>>()?;
+        if cents.len() < count {
+            return Err(format!("Scale lists {} of {} notes", cents.len(), count));
+        }
+        Ok(Self { description, cents })
+    }
+
+    /// Number of degrees per period.
+    pub fn num_degrees(&self) -> usize {
+        self.cents.len()
+    }
+
+    /// Cents of `degree` above degree 0, repeating the scale every period.
+    pub fn degree_cents(&self, degree: i64) -> f64 {
+        let n = self.num_degrees() as i64;
+        let (periods, step) = (degree.div_euclid(n), degree.rem_euclid(n));
+        let period = self.cents[self.num_degrees() - 1];
+        let within = if step == 0 { 0.0 } else { self.cents[step as usize - 1] };
+        periods as f64 * period + within
+    }
+}
+
+/// A parsed `.kbm` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// Keys outside `first_key..=last_key` are not mapped.
+    pub first_key: usize,
+    pub last_key: usize,
+    /// Key playing degree 0.
+    pub middle_key: usize,
+    /// Key tuned to `reference_freq` (Hz).
+    pub reference_key: usize,
+    pub reference_freq: f64,
+    /// Degree the mapping repeats at (`0` = the scale's period).
+    pub octave_degree: usize,
+    /// Degree of each key in one repetition of the mapping from `middle_key`
+    /// (`None` = unmapped); empty maps every key to the next degree.
+    pub degrees: Vec<Option<usize>>,
+}
+
+impl KeyboardMapping {
+    /// Parse `.kbm` text: `!` comment lines, then map size, first key, last
+    /// key, middle key, reference key, reference frequency, octave degree and
+    /// one degree (or `x` for an unmapped key) per map entry.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut fields = text.lines().filter(|l| !l.starts_with('!') && !l.trim().is_empty()).map(first_token);
+        let mut next = |what: &str| fields.next().ok_or_else(|| format!("Keyboard mapping has no {}", what));
+        let number = |s: &str, what: &str| s.parse::<usize>().map_err(|_| format!("Bad {} '{}'", what, s));
+        let map_size = number(next("map size")?, "map size")?;
+        let first_key = number(next("first key")?, "first key")?;
+        let last_key = number(next("last key")?, "last key")?;
+        let middle_key = number(next("middle key")?, "middle key")?;
+        let reference_key = number(next("reference key")?, "reference key")?;
+        let freq = next("reference frequency")?;
+        let reference_freq: f64 = freq.parse().map_err(|_| format!("Bad reference frequency '{}'", freq))?;
+        if !(reference_freq.is_finite() && reference_freq > 0.0) {
+            return Err(format!("Bad reference frequency '{}'", freq));
+        }
+        let octave_degree = number(next("octave degree")?, "octave degree")?;
+        let mut degrees = Vec::with_capacity(map_size);
+        for _ in 0..map_size {
+            // Entries left out at the end are unmapped.
+            match fields.next() {
+                None | Some("x") | Some("X") => degrees.push(None),
+                Some(s) => degrees.push(Some(number(s, "degree")?)),
+            }
+        }
+        Ok(Self { first_key, last_key, middle_key, reference_key, reference_freq, octave_degree, degrees })
+    }
+
+    /// Scala's default mapping: every key the next degree from middle C, A4
+    /// at `reference_pitch`.
+    pub fn linear(reference_pitch: f64) -> Self {
+        Self {
+            first_key: 0,
+            last_key: NUM_KEYS - 1,
+            middle_key: 60,
+            reference_key: REFERENCE_KEY,
+            reference_freq: reference_pitch,
+            octave_degree: 0,
+            degrees: Vec::new(),
+        }
+    }
+
+    /// Cents of `key` above degree 0 of `scale`, `None` if it is unmapped.
+    fn key_cents(&self, scale: &Scale, key: usize) -> Option<f64> {
+        if !(self.first_key..=self.last_key).contains(&key) {
+            return None;
+        }
+        let offset = key as i64 - self.middle_key as i64;
+        if self.degrees.is_empty() {
+            return Some(scale.degree_cents(offset));
+        }
+        let size = self.degrees.len() as i64;
+        let (repeats, entry) = (offset.div_euclid(size), offset.rem_euclid(size));
+        let degree = self.degrees[entry as usize]?;
+        let octave = if self.octave_degree == 0 { scale.num_degrees() } else { self.octave_degree };
+        Some(repeats as f64 * scale.degree_cents(octave as i64) + scale.degree_cents(degree as i64))
+    }
+}
+
+/// A scale with an optional keyboard mapping, as loaded from Scala files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    /// Shown in the editor: the scale's description, or the file name.
+    pub name: String,
+    pub scale: Scale,
+    pub mapping: Option<KeyboardMapping>,
+    /// The files' text, kept to persist the tuning.
+    scl: String,
+    kbm: Option<String>,
+}
+
+impl Tuning {
+    /// Parse a scale and optional keyboard mapping. `name` is used when the
+    /// scale has no description.
+    pub fn parse(name: &str, scl: &str, kbm: Option<&str>) -> Result<Self, String> {
+        let scale = Scale::parse(scl)?;
+        let mapping = kbm.map(KeyboardMapping::parse).transpose()?;
+        if let Some(m) = &mapping {
+            if m.key_cents(&scale, m.reference_key).is_none() {
+                return Err(format!("Reference key {} is not mapped", m.reference_key));
+            }
+        }
+        let name = if scale.description.is_empty() { name.to_string() } else { scale.description.clone() };
+        Ok(Self { name, scale, mapping, scl: scl.to_string(), kbm: kbm.map(str::to_string) })
+    }
+
+    /// Fundamental (Hz) of every key; `0.0` for unmapped keys, which play
+    /// silence. A keyboard mapping brings its own reference frequency;
+    /// without one A4 is tuned to `reference_pitch`.
+    pub fn key_frequencies(&self, reference_pitch: f64) -> Vec<f64> {
+        let linear = KeyboardMapping::linear(reference_pitch);
+        let mapping = self.mapping.as_ref().unwrap_or(&linear);
+        let reference_cents = mapping.key_cents(&self.scale, mapping.reference_key).unwrap_or(0.0);
+        (0..NUM_KEYS)
+            .map(|key| match mapping.key_cents(&self.scale, key) {
+                Some(cents) => mapping.reference_freq * 2f64.powf((cents - reference_cents) / 1200.0),
+                None => 0.0,
+            })
+            .collect()
+    }
+
+    /// The tuning as persisted.
+    pub fn to_state(&self) -> TuningState {
+        TuningState { name: self.name.clone(), scl: self.scl.clone(), kbm: self.kbm.clone() }
+    }
+
+    pub fn from_state(state: &TuningState) -> Result<Self, String> {
+        Self::parse(&state.name, &state.scl, state.kbm.as_deref())
+    }
+}
+
+fn first_token(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// Cents of one `.scl` pitch line.
+fn parse_pitch(line: &str) -> Result<f64, String> {
+    let token = first_token(line);
+    let bad = || format!("Bad pitch '{}'", line.trim());
+    if token.contains('.') {
+        return token.parse::<f64>().map_err(|_| bad());
+    }
+    let (num, den) = token.split_once('/').unwrap_or((token, "1"));
+    let num: f64 = num.parse::<u64>().map_err(|_| bad())? as f64;
+    let den: f64 = den.parse::<u64>().map_err(|_| bad())? as f64;
+    if num <= 0.0 || den <= 0.0 {
+        return Err(bad());
+    }
+    Ok(1200.0 * (num / den).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::key_frequency;
+
+    const JUST_MAJOR: &str = "! just.scl\n!\nJust major\n 7\n!\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n";
+
+    #[test]
+    fn scala_files_parse() {
+        let scale = Scale::parse(JUST_MAJOR).unwrap();
+        assert_eq!(scale.description, "Just major");
+        assert_eq!(scale.num_degrees(), 7);
+        assert!((scale.cents[4] - 701.955).abs() < 1e-3);
+        assert!((scale.degree_cents(-1) - (1088.269 - 1200.0)).abs() < 1e-3);
+        assert_eq!(Scale::parse("Cents\n2\n100.0 semitone\n1200.\n").unwrap().cents, vec![100.0, 1200.0]);
+        assert!(Scale::parse("Short\n3\n100.0\n").is_err());
+        assert!(Scale::parse("Bad\n1\n-3/2\n").is_err());
+
+        let kbm = "! white keys\n12\n0\n127\n60\n69\n432.0\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        assert_eq!(mapping.degrees.len(), 12);
+        assert_eq!((mapping.reference_freq, mapping.octave_degree), (432.0, 7));
+        assert_eq!((mapping.degrees[0], mapping.degrees[1]), (Some(0), None));
+        assert!(KeyboardMapping::parse("0\n0\n127\n60\n69\n").is_err());
+    }
+
+    #[test]
+    fn equal_temperament_matches_the_default_tuning() {
+        let scale: String = "12-TET\n12\n".to_string() + &(1..=12).map(|i| format!("{}.0\n", i * 100)).collect::<String>();
+        let tuning = Tuning::parse("12-tet", &scale, None).unwrap();
+        for (key, f) in tuning.key_frequencies(415.0).into_iter().enumerate() {
+            assert!((f - key_frequency(key, 415.0)).abs() < 1e-6 * f, "key {}", key);
+        }
+    }
+
+    #[test]
+    fn mapped_keys_follow_the_scale() {
+        let tuning = Tuning::parse("just", JUST_MAJOR, None).unwrap();
+        let freqs = tuning.key_frequencies(440.0);
+        // Degree 0 on middle C, A4 (degree 9 % 7 = 2, one period up) at 440 Hz.
+        assert!((freqs[69] - 440.0).abs() < 1e-9);
+        assert!((freqs[67] / freqs[60] - 2.0).abs() < 1e-9);
+        assert!((freqs[64] / freqs[60] - 1.5).abs() < 1e-9);
+
+        let kbm = "7\n0\n127\n60\n69\n440.0\n0\n0\n1\n2\n3\n4\nx\n6\n";
+        let mapped = Tuning::parse("just", JUST_MAJOR, Some(kbm)).unwrap();
+        let freqs = mapped.key_frequencies(415.0);
+        assert!((freqs[69] - 440.0).abs() < 1e-9, "the mapping's reference wins");
+        assert_eq!(freqs[65], 0.0);
+        assert!((freqs[74] / freqs[67] - 2.0).abs() < 1e-9);
+        assert_eq!(Tuning::from_state(&mapped.to_state()).unwrap(), mapped);
+        assert!(Tuning::parse("just", JUST_MAJOR, Some("7\n0\n127\n60\n65\n440.0\n0\n0\n1\n2\n3\n4\nx\n6\n")).is_err());
+    }
+}
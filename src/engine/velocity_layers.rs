@@ -0,0 +1,174 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Velocity layers: several analysed grids per instance, each played for a
+//! range of note-on velocities, so an acoustic instrument can sound different
+//! when struck softly or hard.
+//!
+//! Layer 0 is the live grid (`SharedParams::amplitude_data` and friends),
+//! with all its editing. Layers `1..MAX_VELOCITY_LAYERS` hold further
+//! analysed grids as loaded ([`super::LoadedGrid`]); they share the live
+//! grid's per-harmonic enable flags and render into their own key buffers. On NoteOn, [`select_layers`]
+//! picks the layer(s) for the velocity and how to crossfade them.
+
+use serde::{Deserialize, Serialize};
+
+/// Layer slots per instance, counting layer 0 (the live grid).
+pub const MAX_VELOCITY_LAYERS: usize = 4;
+
+/// Inclusive range of note-on velocities (0..1, as nih-plug reports them).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VelocityRange {
+    pub lo: f32,
+    pub hi: f32,
+}
+
+impl VelocityRange {
+    /// Every velocity.
+    pub const FULL: Self = Self { lo: 0.0, hi: 1.0 };
+
+    /// A range between `a` and `b` (in either order), clamped to 0..1.
+    pub fn new(a: f32, b: f32) -> Self {
+        let (a, b) = (a.clamp(0.0, 1.0), b.clamp(0.0, 1.0));
+        Self { lo: a.min(b), hi: a.max(b) }
+    }
+
+    pub fn contains(&self, velocity: f32) -> bool {
+        (self.lo..=self.hi).contains(&velocity)
+    }
+
+    /// How far `velocity` lies outside the range (0 inside it).
+    fn distance(&self, velocity: f32) -> f32 {
+        (self.lo - velocity).max(velocity - self.hi).max(0.0)
+    }
+}
+
+/// Packed form of an empty layer slot's range (see [`pack_range`]).
+const NO_RANGE: u64 = u64::MAX;
+
+/// Pack a layer slot's range into one word (`lo` in the high half), so the
+/// audio thread can read it from an atomic.
+pub fn pack_range(range: Option<VelocityRange>) -> u64 {
+    range.map_or(NO_RANGE, |r| (u64::from(r.lo.to_bits()) << 32) | u64::from(r.hi.to_bits()))
+}
+
+/// Inverse of [`pack_range`].
+pub fn unpack_range(bits: u64) -> Option<VelocityRange> {
+    (bits != NO_RANGE).then(|| VelocityRange {
+        lo: f32::from_bits((bits >> 32) as u32),
+        hi: f32::from_bits(bits as u32),
+    })
+}
+
+impl Default for VelocityRange {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Which layers a note plays: `primary` at `1 - mix` and `secondary` at
+/// `mix`. A note inside a single layer has `mix == 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerBlend {
+    pub primary: usize,
+    pub secondary: usize,
+    pub mix: f32,
+}
+
+impl LayerBlend {
+    pub fn single(layer: usize) -> Self {
+        Self { primary: layer, secondary: layer, mix: 0.0 }
+    }
+}
+
+/// Choose the layers for `velocity` among `ranges` (one per layer slot,
+/// `None` for an empty slot). Inside one layer's range that layer plays.
+/// Where two ranges overlap the note crossfades across the overlap, from the
+/// layer that starts lower to the one that starts higher; with more than two
+/// the two starting highest are used. Outside every range the nearest layer
+/// plays. Falls back to layer 0 when no slot is in use.
+pub fn select_layers(ranges: &[Option<VelocityRange>], velocity: f32) -> LayerBlend {
+    let velocity = velocity.clamp(0.0, 1.0);
+    let mut lower: Option<(usize, VelocityRange)> = None;
+    let mut upper: Option<(usize, VelocityRange)> = None;
+    let mut nearest: Option<(usize, f32)> = None;
+    for (layer, range) in ranges.iter().enumerate() {
+        let Some(range) = *range else {
+            continue;
+        };
+        if range.contains(velocity) {
+            if upper.is_none_or(|(_, u)| range.lo > u.lo) {
+                lower = upper;
+                upper = Some((layer, range));
+            } else if lower.is_none_or(|(_, l)| range.lo > l.lo) {
+                lower = Some((layer, range));
+            }
+        }
+        let distance = range.distance(velocity);
+        if nearest.is_none_or(|(_, d)| distance < d) {
+            nearest = Some((layer, distance));
+        }
+    }
+
+    match (lower, upper) {
+        (Some((a, ra)), Some((b, rb))) => {
+            let width = ra.hi.min(rb.hi) - rb.lo;
+            let mix = if width > 0.0 { (velocity - rb.lo) / width } else { 0.5 };
+            LayerBlend { primary: a, secondary: b, mix: mix.clamp(0.0, 1.0) }
+        }
+        (None, Some((layer, _))) => LayerBlend::single(layer),
+        _ => LayerBlend::single(nearest.map_or(0, |(layer, _)| layer)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn velocity_range_orders_and_clamps() {
+        assert_eq!(VelocityRange::new(0.8, -0.5), VelocityRange { lo: 0.0, hi: 0.8 });
+        assert!(VelocityRange::FULL.contains(0.0) && VelocityRange::FULL.contains(1.0));
+        assert_eq!(VelocityRange::default(), VelocityRange::FULL);
+    }
+
+    #[test]
+    fn velocity_range_packs_into_a_word() {
+        for range in [None, Some(VelocityRange::FULL), Some(VelocityRange::new(0.25, 0.7))] {
+            assert_eq!(unpack_range(pack_range(range)), range);
+        }
+    }
+
+    #[test]
+    fn select_layers_picks_and_crossfades() {
+        let soft = Some(VelocityRange::new(0.0, 0.6));
+        let hard = Some(VelocityRange::new(0.4, 1.0));
+        let ranges = [soft, None, hard, None];
+
+        assert_eq!(select_layers(&ranges, 0.2), LayerBlend::single(0));
+        assert_eq!(select_layers(&ranges, 0.9), LayerBlend::single(2));
+        let blend = select_layers(&ranges, 0.5);
+        assert_eq!((blend.primary, blend.secondary), (0, 2));
+        assert!((blend.mix - 0.5).abs() < 1e-6);
+        assert!(select_layers(&ranges, 0.4).mix.abs() < 1e-6);
+    }
+
+    #[test]
+    fn select_layers_falls_back_to_the_nearest_layer() {
+        let ranges = [Some(VelocityRange::new(0.0, 0.2)), Some(VelocityRange::new(0.7, 1.0))];
+        assert_eq!(select_layers(&ranges, 0.3), LayerBlend::single(0));
+        assert_eq!(select_layers(&ranges, 0.6), LayerBlend::single(1));
+        assert_eq!(select_layers(&[None, None], 0.5), LayerBlend::single(0));
+    }
+}
@@ -16,15 +16,30 @@
 //! amplitude and phase contributions. In Analysis mode the amp/phase grid is
 //! produced by analysing input audio, so the user can no longer "draw" it;
 //! instead they sculpt the resynthesis by switching individual harmonics on
-//! and off (the requested per-harmonic disable feature).
+//! and off (the requested per-harmonic disable feature). A harmonic's analysed
+//! curve can also be fitted into its Nested Fourier series ("fit"), turning it
+//! into an editable custom override. While an analysis is
+//! running in the background its progress is shown here, with a Cancel button,
+//! and a loader row analyses a WAV file from disk, so no custom host is needed.
+//! The loader can also fill a velocity layer, listed (with its velocity
+//! range) under a collapsible "Velocity layers" header, or a key zone, listed
+//! (with its root key and key range) under "Key zones". A formant row keeps
+//! an analysed voice's formants in place across the keyboard.
 
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use nih_plug::prelude::ParamSetter;
 use nih_plug_egui::egui::{self, Color32, RichText};
-use crate::engine::{ChartType, SynthComputeEngine};
+use nih_plug_egui::widgets::ParamSlider;
+use crate::constants::{key_name, NUM_KEYS};
+use crate::engine::{ChartType, GridSlot, KeyZone, SynthComputeEngine, VelocityRange, MAX_KEY_ZONES, MAX_VELOCITY_LAYERS};
+use crate::params::{CurveType, LeSynthParams};
 
 pub fn draw_analysis_controls(
     ui: &mut egui::Ui,
     engine: &Arc<SynthComputeEngine>,
+    synth_params: &LeSynthParams,
+    setter: &ParamSetter,
     window_width: f32,
     window_height: f32,
 ) {
@@ -47,7 +62,8 @@ pub fn draw_analysis_controls(
         RichText::new(
             "The amplitude / phase grid below was extracted from the input audio. \
              Enable or disable harmonics to shape the resynthesis. Tick \"cust\" to \
-             override a harmonic's analysed curve with your Synth-mode curve.",
+             override a harmonic's analysed curve with your Synth-mode curve, or \
+             \"fit\" to start that curve from the analysed one.",
         )
         .size(11.0)
         .color(Color32::from_gray(190)),
@@ -63,10 +79,16 @@ pub fn draw_analysis_controls(
             let max_ratio = ratios.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
             let min_pitch = base_freq * min_ratio;
             let max_pitch = base_freq * max_ratio;
+            // Say where the contour came from: the built-in tracker or the host.
+            let source = if shared.analysis_pitch_detected.load(Ordering::Relaxed) {
+                "detected"
+            } else {
+                "supplied"
+            };
             ui.label(
                 RichText::new(format!(
-                    "Original tone pitch: min {:.1} Hz  ·  max {:.1} Hz",
-                    min_pitch, max_pitch
+                    "Original tone pitch ({}): min {:.1} Hz  ·  max {:.1} Hz",
+                    source, min_pitch, max_pitch
                 ))
                 .size(12.0)
                 .strong()
@@ -75,6 +97,40 @@ pub fn draw_analysis_controls(
         }
     }
 
+    // Formant preservation (host-automatable), applied when keys are rendered
+    // and by streaming voices.
+    ui.horizontal(|ui| {
+        ui.label("Formants:")
+            .on_hover_text("Keep the analysed formants at fixed frequencies on every key");
+        ui.add(ParamSlider::for_param(&synth_params.formant_preserve, setter));
+        ui.label("shift")
+            .on_hover_text("Move the preserved formants up or down (semitones)");
+        ui.add(ParamSlider::for_param(&synth_params.formant_shift, setter));
+    });
+
+    // Progress of a running (background) analysis, with a way to abort it.
+    if let Some((done, total)) = shared.analysis_progress() {
+        ui.horizontal(|ui| {
+            let fraction = if total > 0 { done as f32 / total as f32 } else { 0.0 };
+            ui.add(
+                egui::ProgressBar::new(fraction)
+                    .desired_width((window_width * 0.5).max(120.0))
+                    .text(format!("Analysing… {} / {} buckets", done, total)),
+            );
+            if ui
+                .button("Cancel")
+                .on_hover_text("Stop the analysis and keep the current grid")
+                .clicked()
+            {
+                shared.cancel_analysis();
+            }
+        });
+    }
+
+    draw_wav_loader(ui, engine);
+    draw_velocity_layers(ui, engine);
+    draw_key_zones(ui, engine);
+
     ui.add_space(4.0);
 
     let mut changed = false;
@@ -89,19 +145,21 @@ pub fn draw_analysis_controls(
 
     // Size the toggle grid so the whole control box fills the same
     // window_height * 0.40 region the Synth-mode control box occupies. The
-    // header/description labels and the Enable/Disable buttons take a roughly
-    // fixed amount of chrome above and below the grid; reserve for it so the
-    // analysis box matches the Synth box height (and keyboard/charts align).
-    const CHROME: f32 = 115.0;
+    // header/description labels, the formant and WAV loader rows, the
+    // (collapsed) velocity layer and key zone headers and the Enable/Disable
+    // buttons take a roughly fixed amount of chrome above and below the grid;
+    // reserve for it so the analysis box matches the Synth box height (and
+    // keyboard/charts align).
+    const CHROME: f32 = 214.0;
     let grid_height = (window_height * 0.40 - CHROME).max(140.0);
     egui::ScrollArea::both()
         .id_salt("analysis_harmonic_toggles")
         .auto_shrink([false; 2])
         .max_height(grid_height)
         .show(ui, |ui| {
-            // Lay the harmonics out in columns. Each entry now carries four
-            // toggles (amp / amp-custom / phase / phase-custom), so widen them.
-            let cols = (window_width / 240.0).floor().max(1.0) as usize;
+            // Lay the harmonics out in columns. Each entry carries four toggles
+            // (amp / amp-custom / phase / phase-custom) and two fit buttons.
+            let cols = (window_width / 300.0).floor().max(1.0) as usize;
             let per_col = num_harmonics.div_ceil(cols);
             ui.horizontal_top(|ui| {
                 for c in 0..cols {
@@ -158,6 +216,10 @@ pub fn draw_analysis_controls(
                                 {
                                     engine.set_harmonic_custom(n, ChartType::Amp, amp_custom[n]);
                                 }
+                                if fit_button(ui, has_analysis).clicked() {
+                                    amp_custom[n] |=
+                                        fit_analysed_curve(engine, synth_params, setter, n, ChartType::Amp);
+                                }
                                 if ui
                                     .checkbox(&mut phase_enabled[n], "phase")
                                     .on_hover_text("Apply this harmonic's analysed phase")
@@ -184,6 +246,10 @@ pub fn draw_analysis_controls(
                                         phase_custom[n],
                                     );
                                 }
+                                if fit_button(ui, has_analysis).clicked() {
+                                    phase_custom[n] |=
+                                        fit_analysed_curve(engine, synth_params, setter, n, ChartType::Phase);
+                                }
                             });
                         }
                     });
@@ -213,6 +279,373 @@ pub fn draw_analysis_controls(
         *shared.harmonic_phase_enabled.lock().unwrap() = phase_enabled;
         engine.set_normalization_needed(true);
         shared.mark_all_buffers_dirty();
-        engine.update_assembled_chart_with_key24();
+        engine.update_assembled_chart_preview();
+        engine.persist_analysis_state();
+    }
+}
+
+fn fit_button(ui: &mut egui::Ui, has_analysis: bool) -> egui::Response {
+    ui.add_enabled(has_analysis, egui::Button::new(RichText::new("fit").size(10.0)).small())
+        .on_hover_text(
+            "Fit this harmonic's Nested Fourier curve to the analysed one and use it \
+             as the custom override, so the analysed curve can be edited",
+        )
+}
+
+/// Fit harmonic `n`'s Nested Fourier series to its analysed row, point the
+/// harmonic's curve params at it (Nested Fourier, fitted offset) and switch
+/// its custom override on. Returns whether the override is now active.
+fn fit_analysed_curve(
+    engine: &SynthComputeEngine,
+    synth_params: &LeSynthParams,
+    setter: &ParamSetter,
+    n: usize,
+    chart_type: ChartType,
+) -> bool {
+    let Some(offset) = engine.fit_harmonic_curve(n, chart_type) else {
+        return false;
+    };
+    let harmonic = &synth_params.harmonics[n];
+    let (offset_param, curve_param) = match chart_type {
+        ChartType::Amp => (&harmonic.curve_offset_amp, &harmonic.curve_type_amp),
+        ChartType::Phase => (&harmonic.curve_offset_phase, &harmonic.curve_type_phase),
+    };
+    setter.begin_set_parameter(curve_param);
+    setter.set_parameter(curve_param, CurveType::NestedFourier);
+    setter.end_set_parameter(curve_param);
+    setter.begin_set_parameter(offset_param);
+    setter.set_parameter(offset_param, offset);
+    setter.end_set_parameter(offset_param);
+    engine.set_harmonic_custom(n, chart_type, true);
+    true
+}
+
+/// GUI-only state of the WAV loader row (kept in egui memory).
+#[derive(Clone)]
+struct WavLoaderForm {
+    path: String,
+    start_secs: f32,
+    /// `0` → to the end of the file.
+    end_secs: f32,
+    /// `0` → detect with the built-in pitch tracker.
+    base_freq: f32,
+    /// Wavetable frame length; `0` → from the file's `clm ` chunk.
+    frame_len: usize,
+    /// Where the analysis goes.
+    target: LoaderTarget,
+    /// Velocity layer (1..) of a `LoaderTarget::Layer` analysis.
+    layer: usize,
+    /// MIDI velocity range (0..=127) of that layer.
+    vel_lo: u8,
+    vel_hi: u8,
+    /// Key zone (1..) of a `LoaderTarget::Zone` analysis, with its root key
+    /// and key range.
+    zone: usize,
+    root_key: usize,
+    lo_key: usize,
+    hi_key: usize,
+    /// Outcome of the last load, shown next to the button.
+    status: Option<Result<String, String>>,
+}
+
+impl Default for WavLoaderForm {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            start_secs: 0.0,
+            end_secs: 0.0,
+            base_freq: 0.0,
+            frame_len: 0,
+            target: LoaderTarget::Live,
+            layer: 1,
+            vel_lo: 0,
+            vel_hi: 127,
+            zone: 1,
+            root_key: KeyZone::default().root_key,
+            lo_key: 0,
+            hi_key: NUM_KEYS - 1,
+            status: None,
+        }
+    }
+}
+
+/// What the WAV loader's analysis replaces or adds.
+#[derive(Clone, Copy, PartialEq)]
+enum LoaderTarget {
+    Live,
+    Layer,
+    Zone,
+}
+
+impl LoaderTarget {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Live => "live grid",
+            Self::Layer => "velocity layer",
+            Self::Zone => "key zone",
+        }
     }
 }
+
+impl WavLoaderForm {
+    fn slot(&self) -> GridSlot {
+        match self.target {
+            LoaderTarget::Live => GridSlot::Live,
+            LoaderTarget::Layer => GridSlot::VelocityLayer(
+                self.layer,
+                VelocityRange::new(velocity_from_midi(self.vel_lo), velocity_from_midi(self.vel_hi)),
+            ),
+            LoaderTarget::Zone => GridSlot::KeyZone(self.zone, KeyZone::new(self.root_key, self.lo_key, self.hi_key)),
+        }
+    }
+}
+
+/// A key picker showing note names (keys are MIDI notes).
+fn key_drag(key: &mut usize) -> egui::DragValue<'_> {
+    egui::DragValue::new(key)
+        .range(0..=NUM_KEYS - 1)
+        .custom_formatter(|k, _| key_name(k as usize))
+}
+
+/// MIDI velocity (0..=127) → the 0..1 velocity layers are keyed by.
+fn velocity_from_midi(v: u8) -> f32 {
+    v.min(127) as f32 / 127.0
+}
+
+fn velocity_to_midi(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+/// One row for analysing a WAV file from disk: path, time range, optional
+/// base pitch and an Analyse button. The file is decoded here (quick) and the
+/// analysis itself runs on the engine's worker thread. The same path can
+/// instead be loaded as a wavetable, one bucket per frame, which is quick
+/// enough to do inline.
+fn draw_wav_loader(ui: &mut egui::Ui, engine: &Arc<SynthComputeEngine>) {
+    let form_id = ui.id().with("wav_loader_form");
+    let mut form: WavLoaderForm = ui.data_mut(|d| d.get_temp(form_id)).unwrap_or_default();
+    let busy = engine.shared_params.analysis_progress().is_some();
+
+    ui.horizontal(|ui| {
+        ui.label("WAV file:");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.path)
+                .hint_text("/path/to/sample.wav")
+                .desired_width(260.0),
+        );
+        ui.label("from");
+        ui.add(egui::DragValue::new(&mut form.start_secs).speed(0.01).range(0.0..=3600.0).suffix(" s"))
+            .on_hover_text("Start of the range to analyse");
+        ui.label("to");
+        ui.add(egui::DragValue::new(&mut form.end_secs).speed(0.01).range(0.0..=3600.0).suffix(" s"))
+            .on_hover_text("End of the range to analyse (0 = end of file)");
+        ui.label("pitch");
+        ui.add(egui::DragValue::new(&mut form.base_freq).speed(0.5).range(0.0..=4000.0).suffix(" Hz"))
+            .on_hover_text("Fundamental of the recording (0 = detect automatically)");
+        ui.label("into");
+        egui::ComboBox::from_id_salt("wav_loader_target")
+            .selected_text(form.target.label())
+            .show_ui(ui, |ui| {
+                for target in [LoaderTarget::Live, LoaderTarget::Layer, LoaderTarget::Zone] {
+                    ui.selectable_value(&mut form.target, target, target.label());
+                }
+            });
+        match form.target {
+            LoaderTarget::Live => {}
+            LoaderTarget::Layer => {
+                ui.add(egui::DragValue::new(&mut form.layer).range(1..=MAX_VELOCITY_LAYERS - 1))
+                    .on_hover_text("Velocity layer to analyse into");
+                ui.label("vel");
+                ui.add(egui::DragValue::new(&mut form.vel_lo).range(0..=127))
+                    .on_hover_text("Lowest note-on velocity the layer plays for");
+                ui.label("–");
+                ui.add(egui::DragValue::new(&mut form.vel_hi).range(0..=127))
+                    .on_hover_text("Highest note-on velocity the layer plays for");
+            }
+            LoaderTarget::Zone => {
+                ui.add(egui::DragValue::new(&mut form.zone).range(1..=MAX_KEY_ZONES - 1))
+                    .on_hover_text("Key zone to analyse into");
+                ui.label("root");
+                ui.add(key_drag(&mut form.root_key)).on_hover_text("Key the recording was made at");
+                ui.label("keys");
+                ui.add(key_drag(&mut form.lo_key)).on_hover_text("Lowest key the zone plays for");
+                ui.label("–");
+                ui.add(key_drag(&mut form.hi_key)).on_hover_text("Highest key the zone plays for");
+            }
+        }
+
+        if ui
+            .add_enabled(!busy && !form.path.trim().is_empty(), egui::Button::new("Analyse"))
+            .on_hover_text("Load the file and analyse the selected range")
+            .clicked()
+        {
+            form.status = Some(
+                crate::wav::read_wav(std::path::Path::new(form.path.trim())).and_then(|wav| {
+                    let range = wav.range(form.start_secs, form.end_secs);
+                    if range.is_empty() {
+                        return Err("The selected range is empty".to_string());
+                    }
+                    let mut summary = format!(
+                        "{:.2} s of {:.2} s, {} ch @ {} Hz",
+                        range.len() as f32 / wav.sample_rate,
+                        wav.duration_secs(),
+                        wav.channels,
+                        wav.sample_rate
+                    );
+                    if engine.submit_analysis(range.to_vec(), wav.sample_rate, form.base_freq, form.slot()) {
+                        summary.push_str(" (replaced the queued analysis)");
+                    }
+                    Ok(summary)
+                }),
+            );
+        }
+
+        ui.label("frame");
+        ui.add(egui::DragValue::new(&mut form.frame_len).speed(8.0).range(0..=65536))
+            .on_hover_text("Wavetable frame length in samples (0 = read it from the file)");
+        if ui
+            .add_enabled(!busy && !form.path.trim().is_empty(), egui::Button::new("Import wavetable"))
+            .on_hover_text("Load the file's single-cycle frames as the bucket grid")
+            .clicked()
+        {
+            form.status = Some(
+                engine
+                    .import_wavetable(std::path::Path::new(form.path.trim()), form.frame_len, 0.0)
+                    .map(|frames| format!("{} wavetable frames", frames)),
+            );
+        }
+
+        match &form.status {
+            Some(Ok(summary)) => {
+                ui.label(RichText::new(summary).size(11.0).color(Color32::from_gray(190)));
+            }
+            Some(Err(message)) => {
+                ui.label(RichText::new(message).size(11.0).color(Color32::from_rgb(255, 130, 130)));
+            }
+            None => {}
+        }
+    });
+
+    ui.data_mut(|d| d.insert_temp(form_id, form));
+}
+
+/// The velocity layers in use: each one's MIDI velocity range (editable), its
+/// source and, above layer 0, a Clear button. Layers are filled through the
+/// WAV loader's "layer" field or the host.
+fn draw_velocity_layers(ui: &mut egui::Ui, engine: &Arc<SynthComputeEngine>) {
+    let shared = &engine.shared_params;
+    egui::CollapsingHeader::new("Velocity layers")
+        .id_salt("velocity_layers")
+        .show(ui, |ui| {
+            let ranges = shared.velocity_ranges();
+            egui::Grid::new("velocity_layer_grid").num_columns(4).show(ui, |ui| {
+                for (layer, range) in ranges.iter().enumerate() {
+                    ui.label(format!("Layer {}", layer));
+                    let Some(range) = *range else {
+                        ui.label(RichText::new("empty").size(11.0).color(Color32::from_gray(140)));
+                        ui.end_row();
+                        continue;
+                    };
+
+                    let (mut lo, mut hi) = (velocity_to_midi(range.lo), velocity_to_midi(range.hi));
+                    let edited = ui
+                        .horizontal(|ui| {
+                            let lo_changed = ui.add(egui::DragValue::new(&mut lo).range(0..=127)).changed();
+                            ui.label("–");
+                            lo_changed | ui.add(egui::DragValue::new(&mut hi).range(0..=127)).changed()
+                        })
+                        .inner;
+                    if edited {
+                        engine.set_velocity_range(layer, VelocityRange::new(velocity_from_midi(lo), velocity_from_midi(hi)));
+                    }
+
+                    let source = if layer == 0 {
+                        "live grid".to_string()
+                    } else {
+                        let layers = shared.velocity_layers.lock().unwrap();
+                        layers[layer - 1]
+                            .as_ref()
+                            .map(|g| format!("{:.1} Hz, {:.2} s, {} buckets", g.base_freq, g.duration_secs, g.num_buckets()))
+                            .unwrap_or_default()
+                    };
+                    ui.label(RichText::new(source).size(11.0).color(Color32::from_gray(190)));
+
+                    if layer > 0
+                        && ui
+                            .small_button("Clear")
+                            .on_hover_text("Remove this layer; its velocities fall back to the others")
+                            .clicked()
+                    {
+                        let _ = engine.clear_velocity_layer(layer);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+/// The key zones in use: each one's root key and key range (editable), its
+/// source and, above zone 0, a Clear button, plus the switch for blending
+/// neighbouring zones. Zones are filled through the WAV loader or the host.
+fn draw_key_zones(ui: &mut egui::Ui, engine: &Arc<SynthComputeEngine>) {
+    let shared = &engine.shared_params;
+    egui::CollapsingHeader::new("Key zones")
+        .id_salt("key_zones")
+        .show(ui, |ui| {
+            let mut interpolate = shared.zone_interpolation.load(Ordering::Relaxed);
+            if ui
+                .checkbox(&mut interpolate, "Interpolate between zones")
+                .on_hover_text("Blend the amplitudes of the zones rooted either side of each key")
+                .changed()
+            {
+                engine.set_zone_interpolation(interpolate);
+            }
+
+            let zones = shared.key_zones();
+            egui::Grid::new("key_zone_grid").num_columns(4).show(ui, |ui| {
+                for (index, zone) in zones.iter().enumerate() {
+                    ui.label(format!("Zone {}", index));
+                    let Some(zone) = *zone else {
+                        ui.label(RichText::new("empty").size(11.0).color(Color32::from_gray(140)));
+                        ui.end_row();
+                        continue;
+                    };
+
+                    let (mut root, mut lo, mut hi) = (zone.root_key, zone.lo_key, zone.hi_key);
+                    let edited = ui
+                        .horizontal(|ui| {
+                            let mut changed = ui.add(key_drag(&mut root)).on_hover_text("Root key").changed();
+                            ui.label("keys");
+                            changed |= ui.add(key_drag(&mut lo)).changed();
+                            ui.label("–");
+                            changed | ui.add(key_drag(&mut hi)).changed()
+                        })
+                        .inner;
+                    if edited {
+                        engine.set_key_zone_keys(index, KeyZone::new(root, lo, hi));
+                    }
+
+                    let source = if index == 0 {
+                        "live grid".to_string()
+                    } else {
+                        let grids = shared.zone_grids.lock().unwrap();
+                        grids[index - 1]
+                            .as_ref()
+                            .map(|g| format!("{:.1} Hz, {:.2} s, {} buckets", g.base_freq, g.duration_secs, g.num_buckets()))
+                            .unwrap_or_default()
+                    };
+                    ui.label(RichText::new(source).size(11.0).color(Color32::from_gray(190)));
+
+                    if index > 0
+                        && ui
+                            .small_button("Clear")
+                            .on_hover_text("Remove this zone; its keys fall back to the others")
+                            .clicked()
+                    {
+                        let _ = engine.clear_key_zone(index);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
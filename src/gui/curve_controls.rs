@@ -190,7 +190,8 @@ pub fn draw_curve_controls(
 
         if changed {
             synth_compute_engine.shared_params.mark_all_buffers_dirty();
-            synth_compute_engine.update_assembled_chart_with_key24();
+            synth_compute_engine.update_assembled_chart_preview();
+            synth_compute_engine.persist_analysis_state();
             params_changed_action();
         }
     }
@@ -0,0 +1,80 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Save / Load of the native `.lesynth` grid file, shown next to the mode
+//! switch. The path is typed in (there is no native file dialog) and kept in
+//! egui memory along with the outcome of the last action.
+
+use std::path::Path;
+use std::sync::Arc;
+use nih_plug_egui::egui::{self, Color32, RichText};
+use crate::engine::SynthComputeEngine;
+use crate::params::lesynth_file::LESYNTH_FILE_EXTENSION;
+
+#[derive(Clone, Default)]
+struct FileForm {
+    path: String,
+    status: Option<Result<String, String>>,
+}
+
+pub fn draw_file_controls(ui: &mut egui::Ui, engine: &Arc<SynthComputeEngine>) {
+    let form_id = ui.id().with("lesynth_file_form");
+    let mut form: FileForm = ui.data_mut(|d| d.get_temp(form_id)).unwrap_or_default();
+    // Loading while an analysis runs would be overwritten when it finishes.
+    let busy = engine.shared_params.analysis_progress().is_some();
+    let has_path = !form.path.trim().is_empty();
+
+    ui.label("File:");
+    ui.add(
+        egui::TextEdit::singleline(&mut form.path)
+            .hint_text(format!("/path/to/instrument.{}", LESYNTH_FILE_EXTENSION))
+            .desired_width(240.0),
+    );
+    if ui
+        .add_enabled(has_path, egui::Button::new("Save"))
+        .on_hover_text("Save the grid, harmonic flags, nested-Fourier curves and curve params")
+        .clicked()
+    {
+        let path = with_extension(form.path.trim());
+        form.status = Some(engine.save_lesynth_file(Path::new(&path)).map(|()| format!("Saved {}", path)));
+    }
+    if ui
+        .add_enabled(has_path && !busy, egui::Button::new("Load"))
+        .on_hover_text("Replace the instrument with a saved .lesynth file")
+        .clicked()
+    {
+        let path = form.path.trim().to_string();
+        form.status = Some(engine.load_lesynth_file(Path::new(&path)).map(|()| format!("Loaded {}", path)));
+    }
+    match &form.status {
+        Some(Ok(message)) => {
+            ui.label(RichText::new(message).size(11.0).color(Color32::from_gray(190)));
+        }
+        Some(Err(message)) => {
+            ui.label(RichText::new(message).size(11.0).color(Color32::from_rgb(255, 130, 130)));
+        }
+        None => {}
+    }
+
+    ui.data_mut(|d| d.insert_temp(form_id, form));
+}
+
+/// Append `.lesynth` to a path typed without an extension.
+fn with_extension(path: &str) -> String {
+    if Path::new(path).extension().is_some() {
+        path.to_string()
+    } else {
+        format!("{}.{}", path, LESYNTH_FILE_EXTENSION)
+    }
+}
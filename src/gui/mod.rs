@@ -17,16 +17,20 @@ pub mod piano_keyboard;
 pub mod harmonic_plot;
 pub mod assembled_chart;
 pub mod curve_controls;
+pub mod file_controls;
 pub mod metallic_background;
 pub mod nested_fourier_controls;
+pub mod tuning_controls;
 
 pub use analysis_controls::draw_analysis_controls;
 pub use piano_keyboard::draw_piano_keyboard;
 pub use harmonic_plot::draw_harmonic_plot;
 pub use assembled_chart::draw_assembled_chart;
 pub use curve_controls::draw_curve_controls;
+pub use file_controls::draw_file_controls;
 pub use metallic_background::draw_metallic_background;
 pub use nested_fourier_controls::draw_nested_fourier_controls;
+pub use tuning_controls::draw_tuning_controls;
 
 use nih_plug_egui::egui;
 
@@ -115,8 +115,10 @@ pub fn draw_nested_fourier_controls(
                 // push changes to the parameter on change. This avoids the inverted /
                 // jumpy behaviour that `Slider::from_get_set` exhibits for vertical
                 // sliders.
+                // A fitted amplitude may lie above the granularity cap; widen
+                // the range rather than let the slider clamp it down.
                 let mut amp_val = cur_amp as f64;
-                let amp_slider = egui::Slider::new(&mut amp_val, 0.0..=gran_max)
+                let amp_slider = egui::Slider::new(&mut amp_val, 0.0..=gran_max.max(amp_val))
                     .vertical()
                     .show_value(false);
                 let amp_resp = ui.add_sized([col_w - 4.0, amp_slider_h], amp_slider);
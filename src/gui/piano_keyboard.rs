@@ -16,28 +16,30 @@ use std::sync::Arc;
 use nih_plug_egui::egui::{Color32, CornerRadius, StrokeKind, Stroke, Vec2, Rect, pos2};
 use crate::constants::NUM_KEYS;
 use crate::engine::SynthComputeEngine;
-use crate::engine::shared_params::BufferState;
-use crate::voice::Voice;
+use crate::engine::shared_params::{BufferState, NoteRequest};
+
+/// Narrowest a white key gets: below it the keyboard scrolls instead.
+const MIN_WHITE_KEY_WIDTH: f32 = 16.0;
+
+/// Key the keyboard is first scrolled to (A0, the bottom of a piano).
+const FIRST_VISIBLE_KEY: usize = 21;
 
 fn is_black_key(key_index: usize) -> bool {
-    // Piano starts at A0. Offset by 9 to align with C-based chromatic scale
-    // where black keys are C#(1), D#(3), F#(6), G#(8), A#(10)
-    let octave_pos = (key_index + 9) % 12;
+    // Keys are MIDI notes, so key 0 is a C: black keys are C#(1), D#(3),
+    // F#(6), G#(8), A#(10)
+    let octave_pos = key_index % 12;
     matches!(octave_pos, 1 | 3 | 6 | 8 | 10)
 }
 
 fn get_white_key_index(key_index: usize) -> usize {
-    // Count white keys from A0 up to (but not including) key_index
+    // Count white keys from C-1 up to (but not including) key_index
     (0..key_index).filter(|&i| !is_black_key(i)).count()
 }
 
 fn get_black_key_x_pos(key_index: usize) -> f32 {
     // Returns absolute position in white-key-width units from the left edge.
-    // Use the C-based adjusted octave so the standard offsets apply.
-    let adjusted = key_index + 9;
-    let c_octave = adjusted / 12;
-    let c_octave_pos = adjusted % 12;
-    let within_octave = match c_octave_pos {
+    let c_octave = key_index / 12;
+    let within_octave = match key_index % 12 {
         1 => 0.7,   // C#
         3 => 1.7,   // D#
         6 => 3.7,   // F#
@@ -45,8 +47,7 @@ fn get_black_key_x_pos(key_index: usize) -> f32 {
         10 => 5.7,  // A#
         _ => 0.0,
     };
-    // Subtract 5.0 because A0 is the 6th white key (index 5) in the C0-relative system
-    c_octave as f32 * 7.0 + within_octave - 5.0
+    c_octave as f32 * 7.0 + within_octave
 }
 
 pub fn draw_piano_keyboard(
@@ -64,24 +65,25 @@ pub fn draw_piano_keyboard(
         .memory(|mem| mem.data.get_temp::<Option<usize>>(last_key_id).unwrap_or(None));
 
     let mut last_pressed_key_persist = egui_ctx
-        .memory(|mem| mem.data.get_temp::<Option<usize>>(last_key_id_persist).unwrap_or(Some(15)));
+        .memory(|mem| mem.data.get_temp::<Option<usize>>(last_key_id_persist).unwrap_or(Some(36)));
 
     let keyboard_height = window_height * 0.055;
     let white_key_height = keyboard_height;
     let black_key_height = keyboard_height * 0.6;
 
-    // Calculate number of white keys for proper spacing
+    // Calculate number of white keys for proper spacing; a narrow window
+    // scrolls the keyboard rather than squeezing all 128 keys in.
     let actual_white_keys = (0..NUM_KEYS).filter(|&i| !is_black_key(i)).count();
-    let white_key_width = window_width / actual_white_keys as f32;
+    let white_key_width = (window_width / actual_white_keys as f32).max(MIN_WHITE_KEY_WIDTH);
     let black_key_width = white_key_width * 0.6;
 
     let mut pressed_this_frame: Option<usize> = None;
 
-    // Check if any voice is currently active for visual feedback
+    // Check if any voice is currently active for visual feedback (the audio
+    // thread's lock-free snapshot)
     let active_voices = {
         let shared = &synth_compute_engine.shared_params;
-        let voices = shared.voices.lock().unwrap();
-        (0..NUM_KEYS).filter(|&i| voices[i].is_some()).collect::<Vec<_>>()
+        (0..NUM_KEYS).filter(|&i| shared.voice_active(i)).collect::<Vec<_>>()
     };
 
     // Get buffer states for visual feedback
@@ -100,7 +102,10 @@ pub fn draw_piano_keyboard(
         }
     });
 
-    let status_text = if computing_count > 0 {
+    let streaming = synth_compute_engine.shared_params.streaming();
+    let status_text = if streaming {
+        "Streaming synthesis (edits play live)".to_string()
+    } else if computing_count > 0 {
         format!("Recomputing the final sound ({} keys remaining)", computing_count + dirty_count)
     } else if dirty_count > 0 {
         format!("Recomputing the final sound ({} keys pending)", dirty_count)
@@ -108,7 +113,7 @@ pub fn draw_piano_keyboard(
         "Synthesis finished".to_string()
     };
 
-    let status_color = if computing_count > 0 || dirty_count > 0 {
+    let status_color = if !streaming && (computing_count > 0 || dirty_count > 0) {
         Color32::from_rgb(200, 100, 50) // Orange for computing/pending
     } else {
         Color32::from_rgb(50, 150, 50) // Green for finished
@@ -125,119 +130,138 @@ pub fn draw_piano_keyboard(
         if ui.checkbox(&mut repeat, "Repeat").changed() {
             synth_compute_engine.shared_params.set_repeat_playback(repeat);
         }
+        // Synthesise notes on the fly instead of from pre-rendered buffers.
+        let mut stream = streaming;
+        if ui
+            .checkbox(&mut stream, "Stream")
+            .on_hover_text("Synthesise notes live from the grid: edits are heard at once and no per-key buffers are kept")
+            .changed()
+        {
+            synth_compute_engine.set_streaming(stream);
+        }
     });
     ui.add_space(5.0);
 
-    let (kb_rect, _kb_resp) = ui.allocate_exact_size(
-        Vec2::new(window_width, keyboard_height),
-        nih_plug_egui::egui::Sense::hover(),
-    );
-
-    // Draw white keys first
-    for key_idx in 0..NUM_KEYS {
-        if is_black_key(key_idx) {
-            continue;
-        }
-
-        let white_key_idx = get_white_key_index(key_idx);
-        let x = kb_rect.left() + white_key_idx as f32 * white_key_width;
-        let key_rect = Rect::from_min_size(
-            pos2(x, kb_rect.top()),
-            Vec2::new(white_key_width - 1.0, white_key_height),
-        );
-
-        let resp = ui.interact(
-            key_rect,
-            nih_plug_egui::egui::Id::new(format!("white_key_{}", key_idx)),
-            nih_plug_egui::egui::Sense::click(),
+    // Start scrolled to the piano's range; the user's scrolling sticks.
+    let mut scroll_area = nih_plug_egui::egui::ScrollArea::horizontal().id_salt("piano_keyboard");
+    let scrolled_id = nih_plug_egui::egui::Id::new("piano_keyboard_scrolled");
+    if !egui_ctx.memory(|mem| mem.data.get_temp::<bool>(scrolled_id).unwrap_or(false)) {
+        let offset = get_white_key_index(FIRST_VISIBLE_KEY) as f32 * white_key_width;
+        scroll_area = scroll_area.horizontal_scroll_offset(offset);
+        egui_ctx.memory_mut(|mem| mem.data.insert_temp(scrolled_id, true));
+    }
+    scroll_area.show(ui, |ui| {
+        let (kb_rect, _kb_resp) = ui.allocate_exact_size(
+            Vec2::new(actual_white_keys as f32 * white_key_width, keyboard_height),
+            nih_plug_egui::egui::Sense::hover(),
         );
 
-        // Determine key color based on state
-        let key_color = if active_voices.contains(&key_idx) {
-            Color32::from_rgb(200, 220, 255) // Light blue for active
-        } else if resp.hovered() {
-            Color32::from_rgb(245, 245, 245) // Light gray for hover
-        } else {
-            match buffer_states[key_idx] {
-                BufferState::Clean => Color32::WHITE, // Normal - buffer ready
-                BufferState::Dirty => Color32::from_rgb(230, 230, 230), // Light shadow - needs recomputation
-                BufferState::Computing => Color32::from_rgb(255, 255, 200), // Light yellow - currently computing
+        // Draw white keys first
+        for key_idx in 0..NUM_KEYS {
+            if is_black_key(key_idx) {
+                continue;
             }
-        };
-
-        // Draw white key with rounded corners
-        ui.painter().rect_filled(
-            key_rect,
-            CornerRadius::same(3),
-            key_color,
-        );
-        
-        // Add subtle shadow/border
-        ui.painter().rect_stroke(
-            key_rect,
-            CornerRadius::same(3),
-            Stroke::new(1.0, Color32::from_rgb(180, 180, 180)),
-            StrokeKind::Outside,
-        );
 
-        if resp.is_pointer_button_down_on() && input.pointer.any_pressed() {
-            pressed_this_frame = Some(key_idx);
-        }
-    }
+            let white_key_idx = get_white_key_index(key_idx);
+            let x = kb_rect.left() + white_key_idx as f32 * white_key_width;
+            let key_rect = Rect::from_min_size(
+                pos2(x, kb_rect.top()),
+                Vec2::new(white_key_width - 1.0, white_key_height),
+            );
+
+            let resp = ui.interact(
+                key_rect,
+                nih_plug_egui::egui::Id::new(format!("white_key_{}", key_idx)),
+                nih_plug_egui::egui::Sense::click(),
+            );
+
+            // Determine key color based on state
+            let key_color = if active_voices.contains(&key_idx) {
+                Color32::from_rgb(200, 220, 255) // Light blue for active
+            } else if resp.hovered() {
+                Color32::from_rgb(245, 245, 245) // Light gray for hover
+            } else {
+                match buffer_states[key_idx] {
+                    BufferState::Clean => Color32::WHITE, // Normal - buffer ready
+                    BufferState::Dirty => Color32::from_rgb(230, 230, 230), // Light shadow - needs recomputation
+                    BufferState::Computing => Color32::from_rgb(255, 255, 200), // Light yellow - currently computing
+                }
+            };
 
-    // Draw black keys on top
-    for key_idx in 0..NUM_KEYS {
-        if !is_black_key(key_idx) {
-            continue;
+            // Draw white key with rounded corners
+            ui.painter().rect_filled(
+                key_rect,
+                CornerRadius::same(3),
+                key_color,
+            );
+        
+            // Add subtle shadow/border
+            ui.painter().rect_stroke(
+                key_rect,
+                CornerRadius::same(3),
+                Stroke::new(1.0, Color32::from_rgb(180, 180, 180)),
+                StrokeKind::Outside,
+            );
+
+            if resp.is_pointer_button_down_on() && input.pointer.any_pressed() {
+                pressed_this_frame = Some(key_idx);
+            }
         }
 
-        let x = kb_rect.left() + get_black_key_x_pos(key_idx) * white_key_width - black_key_width / 2.0;
-        let key_rect = Rect::from_min_size(
-            pos2(x, kb_rect.top()),
-            Vec2::new(black_key_width, black_key_height),
-        );
-
-        let resp = ui.interact(
-            key_rect,
-            nih_plug_egui::egui::Id::new(format!("black_key_{}", key_idx)),
-            nih_plug_egui::egui::Sense::click(),
-        );
-
-        // Determine key color based on state
-        let key_color = if active_voices.contains(&key_idx) {
-            Color32::from_rgb(100, 120, 180) // Darker blue for active black key
-        } else if resp.hovered() {
-            Color32::from_rgb(60, 60, 60) // Lighter black for hover
-        } else {
-            match buffer_states[key_idx] {
-                BufferState::Clean => Color32::from_rgb(30, 30, 30), // Normal - buffer ready
-                BufferState::Dirty => Color32::from_rgb(60, 60, 60), // Lighter shadow - needs recomputation
-                BufferState::Computing => Color32::from_rgb(80, 80, 40), // Darker yellow - currently computing
+        // Draw black keys on top
+        for key_idx in 0..NUM_KEYS {
+            if !is_black_key(key_idx) {
+                continue;
             }
-        };
 
-        // Draw black key with rounded corners
-        ui.painter().rect_filled(
-            key_rect,
-            CornerRadius::same(2),
-            key_color,
-        );
-        
-        // Add subtle highlight on top edge
-        let highlight_rect = Rect::from_min_size(
-            pos2(x + 2.0, kb_rect.top() + 2.0),
-            Vec2::new(black_key_width - 4.0, 3.0),
-        );
-        ui.painter().rect_filled(
-            highlight_rect,
-            CornerRadius::same(1),
-            Color32::from_rgb(80, 80, 80),
-        );
+            let x = kb_rect.left() + get_black_key_x_pos(key_idx) * white_key_width - black_key_width / 2.0;
+            let key_rect = Rect::from_min_size(
+                pos2(x, kb_rect.top()),
+                Vec2::new(black_key_width, black_key_height),
+            );
+
+            let resp = ui.interact(
+                key_rect,
+                nih_plug_egui::egui::Id::new(format!("black_key_{}", key_idx)),
+                nih_plug_egui::egui::Sense::click(),
+            );
+
+            // Determine key color based on state
+            let key_color = if active_voices.contains(&key_idx) {
+                Color32::from_rgb(100, 120, 180) // Darker blue for active black key
+            } else if resp.hovered() {
+                Color32::from_rgb(60, 60, 60) // Lighter black for hover
+            } else {
+                match buffer_states[key_idx] {
+                    BufferState::Clean => Color32::from_rgb(30, 30, 30), // Normal - buffer ready
+                    BufferState::Dirty => Color32::from_rgb(60, 60, 60), // Lighter shadow - needs recomputation
+                    BufferState::Computing => Color32::from_rgb(80, 80, 40), // Darker yellow - currently computing
+                }
+            };
 
-        if resp.is_pointer_button_down_on() && input.pointer.any_pressed() {
-            pressed_this_frame = Some(key_idx);
+            // Draw black key with rounded corners
+            ui.painter().rect_filled(
+                key_rect,
+                CornerRadius::same(2),
+                key_color,
+            );
+        
+            // Add subtle highlight on top edge
+            let highlight_rect = Rect::from_min_size(
+                pos2(x + 2.0, kb_rect.top() + 2.0),
+                Vec2::new(black_key_width - 4.0, 3.0),
+            );
+            ui.painter().rect_filled(
+                highlight_rect,
+                CornerRadius::same(1),
+                Color32::from_rgb(80, 80, 80),
+            );
+
+            if resp.is_pointer_button_down_on() && input.pointer.any_pressed() {
+                pressed_this_frame = Some(key_idx);
+            }
         }
-    }
+    });
 
     let released = input.pointer.any_released();
     
@@ -245,9 +269,8 @@ pub fn draw_piano_keyboard(
     let mut keyboard_pressed_key: Option<usize> = None;
     let mut keyboard_released_key: Option<usize> = None;
     
-    // Map computer keyboard keys to piano keys (starting from C4 = key 39)
-    // key 0 = A0 (27.5 Hz), so C4 = A0 + 39 semitones = key 39
-    let base_key = 39; // C4
+    // Map computer keyboard keys to piano keys (starting from C4 = MIDI note 60)
+    let base_key = 60; // C4
     for event in &input.events {
         if let nih_plug_egui::egui::Event::Key { key, pressed, .. } = event {
             let piano_key = match key {
@@ -287,19 +310,8 @@ pub fn draw_piano_keyboard(
     if let Some(key_idx) = pressed_this_frame.or(keyboard_pressed_key) {
         if Some(key_idx) != last_pressed_key {
             log::debug!("Key {} clicked", key_idx);
-            {
-                let shared = &synth_compute_engine.shared_params;
-                let buf = synth_compute_engine.get_buffer_for_key(key_idx);
-                let mut voices = shared.voices.lock().unwrap();
-                voices[key_idx] = Some(Voice {
-                    buffer: buf,
-                    idx: 0,
-                    fade_in_active: true,
-                    fade_in_pos: 0,
-                    fade_out_active: false,
-                    fade_out_pos: 0,
-                });
-            }
+            // The audio thread owns the voices: ask it to start the note.
+            synth_compute_engine.shared_params.request_note(key_idx, NoteRequest::On);
             synth_compute_engine.update_plotted_mix();
             last_pressed_key = Some(key_idx);
             last_pressed_key_persist = Some(key_idx);
@@ -313,14 +325,7 @@ pub fn draw_piano_keyboard(
         
         if let Some(prev_key) = release_key {
             log::debug!("Key {} released", prev_key);
-            {
-                let shared = &synth_compute_engine.shared_params;
-                let mut voices = shared.voices.lock().unwrap();
-                if let Some(v) = voices[prev_key].as_mut() {
-                    v.fade_out_active = true;
-                    v.fade_out_pos = 0;
-                }
-            }
+            synth_compute_engine.shared_params.request_note(prev_key, NoteRequest::Off);
 
             synth_compute_engine.update_plotted_mix();
             last_pressed_key = None;
@@ -0,0 +1,83 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Load / Reset of a Scala tuning, shown under the keyboard's velocity row.
+//! Like the `.lesynth` file row, paths are typed in and kept in egui memory
+//! along with the outcome of the last action.
+
+use std::path::Path;
+use std::sync::Arc;
+use nih_plug_egui::egui::{self, Color32, RichText};
+use crate::engine::SynthComputeEngine;
+
+#[derive(Clone, Default)]
+struct TuningForm {
+    scl_path: String,
+    kbm_path: String,
+    status: Option<Result<String, String>>,
+}
+
+pub fn draw_tuning_controls(ui: &mut egui::Ui, engine: &Arc<SynthComputeEngine>) {
+    let form_id = ui.id().with("tuning_form");
+    let mut form: TuningForm = ui.data_mut(|d| d.get_temp(form_id)).unwrap_or_default();
+
+    let current = engine.tuning_name();
+    ui.label(RichText::new("Tuning:").color(Color32::WHITE));
+    ui.label(
+        RichText::new(current.as_deref().unwrap_or("Equal temperament"))
+            .color(Color32::from_rgb(170, 200, 255)),
+    );
+    ui.add(
+        egui::TextEdit::singleline(&mut form.scl_path)
+            .hint_text("/path/to/scale.scl")
+            .desired_width(180.0),
+    );
+    ui.add(
+        egui::TextEdit::singleline(&mut form.kbm_path)
+            .hint_text("mapping.kbm (optional)")
+            .desired_width(150.0),
+    );
+    if ui
+        .add_enabled(!form.scl_path.trim().is_empty(), egui::Button::new("Load"))
+        .on_hover_text("Tune the keys to a Scala scale, mapped by the keyboard mapping if given")
+        .clicked()
+    {
+        let kbm = form.kbm_path.trim();
+        let kbm = (!kbm.is_empty()).then(|| Path::new(kbm));
+        form.status = Some(
+            engine
+                .load_tuning_files(Path::new(form.scl_path.trim()), kbm)
+                .map(|name| format!("Tuned to {}", name)),
+        );
+    }
+    if ui
+        .add_enabled(current.is_some(), egui::Button::new("Reset"))
+        .on_hover_text("Back to equal temperament")
+        .clicked()
+    {
+        engine.set_tuning(None);
+        form.status = None;
+    }
+    match &form.status {
+        Some(Ok(message)) => {
+            ui.label(RichText::new(message).size(11.0).color(Color32::from_gray(190)));
+        }
+        Some(Err(message)) => {
+            ui.label(RichText::new(message).size(11.0).color(Color32::from_rgb(255, 130, 130)));
+        }
+        None => {}
+    }
+
+    ui.data_mut(|d| d.insert_temp(form_id, form));
+}
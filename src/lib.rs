@@ -17,7 +17,9 @@ mod engine;
 mod gui;
 mod params;
 mod plugin;
+mod sfz;
 mod voice;
+mod wav;
 
 pub use plugin::LeSynth;
 
@@ -28,14 +30,20 @@ pub use plugin::LeSynth;
 // plain cdylib). These exported functions let the host feed recorded audio
 // "subtracks" to the plugin for Fourier analysis. Because the host's VST3
 // component instances live in *this* shared object's address space, a global
-// inbox here is shared with them: the host pushes a job, the running editor
-// claims it and runs the analysis on its own engine.
+// inbox here is shared with them: the host pushes a job, and the analysis
+// worker of each instance's engine claims the jobs addressed to it (or to any
+// instance) and runs the analysis — whether or not an editor is open.
 // ───────────────────────────────────────────────────────────────────────────
 
 use std::collections::VecDeque;
-use std::sync::{Arc, Mutex, Weak};
+use std::ffi::{c_char, CStr};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
 
-use crate::engine::SynthComputeEngine;
+use crate::engine::{
+    GridSlot, KeyZone, LoadedGrid, RenderMode, SynthComputeEngine, VelocityRange, MAX_KEY_ZONES, MAX_VELOCITY_LAYERS,
+};
 
 /// A pending analysis request handed from the host to a plugin instance.
 pub struct AnalysisJob {
@@ -46,9 +54,17 @@ pub struct AnalysisJob {
     /// subtrack. Empty → flat at `base_freq` (legacy). Drives period-synchronous
     /// bucketing and the per-bucket DFT frequency.
     pub contour: Vec<f32>,
+    /// Instance token (see [`lesynth_fourier_prepare_instance`]) the job is
+    /// addressed to; `None` → whichever instance's worker claims it first.
+    pub target: Option<u64>,
+    /// Where the result is loaded (see `SynthComputeEngine::load_into`);
+    /// `GridSlot::Live` → the live grid, keeping its range and key zone.
+    pub slot: GridSlot,
 }
 
 static ANALYSIS_INBOX: Mutex<VecDeque<AnalysisJob>> = Mutex::new(VecDeque::new());
+/// Signalled on every push so idle analysis workers wake immediately.
+static ANALYSIS_READY: Condvar = Condvar::new();
 
 /// Editor egui context, registered so background threads can wake the idle
 /// editor (it blocks its event loop when idle) via [`wake_editor`].
@@ -70,9 +86,75 @@ pub(crate) fn wake_editor() {
     }
 }
 
-/// Claim the oldest pending analysis job (called by a plugin editor).
-pub(crate) fn claim_analysis_job() -> Option<AnalysisJob> {
-    ANALYSIS_INBOX.lock().ok().and_then(|mut q| q.pop_front())
+/// Remove the oldest job an instance tagged `token` may run: one addressed
+/// to it, or an untargeted one.
+fn take_job_for(queue: &mut VecDeque<AnalysisJob>, token: Option<u64>) -> Option<AnalysisJob> {
+    let pos = queue
+        .iter()
+        .position(|job| job.target.is_none() || job.target == token)?;
+    queue.remove(pos)
+}
+
+/// Claim the oldest analysis job for the instance tagged `token`, waiting up
+/// to `timeout` for one to be pushed. Called by each engine's analysis worker.
+pub(crate) fn wait_for_analysis_job(token: Option<u64>, timeout: Duration) -> Option<AnalysisJob> {
+    let mut queue = ANALYSIS_INBOX.lock().ok()?;
+    if let Some(job) = take_job_for(&mut queue, token) {
+        return Some(job);
+    }
+    let (mut queue, _) = ANALYSIS_READY.wait_timeout(queue, timeout).ok()?;
+    take_job_for(&mut queue, token)
+}
+
+/// Wake every idle analysis worker, e.g. after an engine queued a job of its
+/// own (see `SynthComputeEngine::submit_analysis`).
+pub(crate) fn wake_analysis_workers() {
+    ANALYSIS_READY.notify_all();
+}
+
+/// Queue a job and wake the analysis workers. Returns the new queue depth.
+fn enqueue_analysis_job(job: AnalysisJob) -> u64 {
+    let depth = match ANALYSIS_INBOX.lock() {
+        Ok(mut q) => {
+            q.push_back(job);
+            q.len() as u64
+        }
+        Err(_) => 0,
+    };
+    ANALYSIS_READY.notify_all();
+    depth
+}
+
+/// Copy a host subtrack (and optional contour) into an [`AnalysisJob`].
+/// `None` on invalid input.
+///
+/// # Safety
+/// As for [`lesynth_fourier_push_analysis`].
+unsafe fn job_from_raw(
+    samples: *const f32,
+    len: usize,
+    sample_rate: f32,
+    base_freq: f32,
+    contour: *const f32,
+    contour_len: usize,
+    target: Option<u64>,
+) -> Option<AnalysisJob> {
+    if samples.is_null() || len == 0 {
+        return None;
+    }
+    let contour = if contour.is_null() || contour_len == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(contour, contour_len).to_vec()
+    };
+    Some(AnalysisJob {
+        samples: std::slice::from_raw_parts(samples, len).to_vec(),
+        sample_rate,
+        base_freq,
+        contour,
+        target,
+        slot: GridSlot::Live,
+    })
 }
 
 // ───────────────────────────────────────────────────────────────────────────
@@ -109,6 +191,8 @@ fn take_pending_token() -> Option<u64> {
 /// normal VST3 in a DAW.
 pub(crate) fn register_new_instance(engine: &Arc<SynthComputeEngine>) {
     if let Some(token) = take_pending_token() {
+        // Lets the engine's analysis worker pick up jobs addressed to it.
+        *engine.shared_params.instance_token.lock().unwrap() = Some(token);
         if let Ok(mut reg) = INSTANCE_REGISTRY.lock() {
             reg.retain(|(_, w)| w.strong_count() > 0);
             reg.push((token, Arc::downgrade(engine)));
@@ -220,10 +304,29 @@ pub unsafe extern "C" fn lesynth_fourier_export_grid(
     nb as i64
 }
 
+/// The grid a host passes to the `_import_grid` calls: amplitude and phase
+/// rows from row-major `[h*nb + b]` arrays, and the pitch ratios.
+///
+/// # Safety
+/// `amp`/`phase` must point to `nh * nb` valid `f32`s and `pitch_ratio` to `nb`.
+unsafe fn grid_rows_from_raw<'a>(
+    nh: usize,
+    nb: usize,
+    amp: *const f32,
+    phase: *const f32,
+    pitch_ratio: *const f32,
+) -> (Vec<Vec<f32>>, Vec<Vec<f32>>, &'a [f32]) {
+    let rows = |data: *const f32| -> Vec<Vec<f32>> {
+        std::slice::from_raw_parts(data, nh * nb).chunks_exact(nb).map(<[f32]>::to_vec).collect()
+    };
+    (rows(amp), rows(phase), std::slice::from_raw_parts(pitch_ratio, nb))
+}
+
 /// Load a saved grid into a tagged instance (Analysis mode), bypassing DFT
 /// analysis. `amp`/`phase` are row-major `[h*nb + b]`; `pitch_ratio` is `nb`
-/// long. `sample_rate` is accepted for format completeness but not applied — the
-/// instance keeps the host device rate so playback duration stays correct.
+/// long. `sample_rate` is recorded as the source rate (informational) but not
+/// applied — the instance keeps the host device rate so playback duration stays
+/// correct.
 /// Returns 0 on success, negative on error.
 ///
 /// # Safety
@@ -235,7 +338,7 @@ pub unsafe extern "C" fn lesynth_fourier_import_grid(
     nb: u32,
     base_freq: f32,
     duration_secs: f32,
-    _sample_rate: f32,
+    sample_rate: f32,
     amp: *const f32,
     phase: *const f32,
     pitch_ratio: *const f32,
@@ -250,24 +353,377 @@ pub unsafe extern "C" fn lesynth_fourier_import_grid(
     if nh == 0 || nb == 0 {
         return -3;
     }
-    let amp = std::slice::from_raw_parts(amp, nh * nb);
-    let phase = std::slice::from_raw_parts(phase, nh * nb);
-    let ratio = std::slice::from_raw_parts(pitch_ratio, nb);
-
-    let amplitude: Vec<Vec<f32>> = (0..nh).map(|h| amp[h * nb..(h + 1) * nb].to_vec()).collect();
-    let phase_v: Vec<Vec<f32>> = (0..nh).map(|h| phase[h * nb..(h + 1) * nb].to_vec()).collect();
+    let (amplitude, phase, ratio) = grid_rows_from_raw(nh, nb, amp, phase, pitch_ratio);
 
-    engine.load_grid(amplitude, phase_v, ratio.to_vec(), base_freq, duration_secs);
+    *engine.shared_params.analysis_sample_rate.lock().unwrap() = sample_rate;
+    engine.load_grid(amplitude, phase, ratio.to_vec(), base_freq, duration_secs);
     // Repaint the idle editor so the loaded grid appears immediately.
     wake_editor();
     0
 }
 
+/// Like [`lesynth_fourier_import_grid`], but into velocity layer `layer`
+/// (0 = the live grid, up to `MAX_VELOCITY_LAYERS - 1`), played for note-on
+/// velocities `vel_lo..=vel_hi` (0..1). Returns 0 on success, or a negative
+/// value on null pointers (-1), an unknown/dead token (-2), empty dimensions
+/// (-3) or a layer out of range (-4).
+///
+/// # Safety
+/// As for [`lesynth_fourier_import_grid`].
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_import_grid_layer(
+    token: u64,
+    layer: u32,
+    vel_lo: f32,
+    vel_hi: f32,
+    nh: u32,
+    nb: u32,
+    base_freq: f32,
+    duration_secs: f32,
+    sample_rate: f32,
+    amp: *const f32,
+    phase: *const f32,
+    pitch_ratio: *const f32,
+) -> i64 {
+    if amp.is_null() || phase.is_null() || pitch_ratio.is_null() {
+        return -1;
+    }
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    let (nh, nb) = (nh as usize, nb as usize);
+    if nh == 0 || nb == 0 {
+        return -3;
+    }
+    if layer as usize >= MAX_VELOCITY_LAYERS {
+        return -4;
+    }
+    let (amplitude, phase, ratio) = grid_rows_from_raw(nh, nb, amp, phase, pitch_ratio);
+    let grid = LoadedGrid::new(&amplitude, &phase, ratio, base_freq, duration_secs, sample_rate);
+    match engine.set_velocity_layer(layer as usize, VelocityRange::new(vel_lo, vel_hi), grid) {
+        Ok(()) => {
+            wake_editor();
+            0
+        }
+        Err(_) => -4,
+    }
+}
+
+/// The slot of key zone `zone`, rooted at `root_key` and played for
+/// `lo_key..=hi_key` (keys 0..NUM_KEYS). `None` if any is out of range.
+fn zone_slot_from_raw(zone: u32, root_key: u32, lo_key: u32, hi_key: u32) -> Option<GridSlot> {
+    let (zone, keys) = (zone as usize, [root_key, lo_key, hi_key].map(|k| k as usize));
+    if zone >= MAX_KEY_ZONES || keys.iter().any(|&k| k >= constants::NUM_KEYS) {
+        return None;
+    }
+    Some(GridSlot::KeyZone(zone, KeyZone::new(keys[0], keys[1], keys[2])))
+}
+
+/// Like [`lesynth_fourier_import_grid`], but into key zone `zone` (0 = the
+/// live grid, up to `MAX_KEY_ZONES - 1`), recorded at `root_key` and played
+/// for keys `lo_key..=hi_key` (MIDI notes). Returns 0 on success, or a negative
+/// value on null pointers (-1), an unknown/dead token (-2), empty dimensions
+/// (-3) or a zone or key out of range (-4).
+///
+/// # Safety
+/// As for [`lesynth_fourier_import_grid`].
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_import_grid_zone(
+    token: u64,
+    zone: u32,
+    root_key: u32,
+    lo_key: u32,
+    hi_key: u32,
+    nh: u32,
+    nb: u32,
+    base_freq: f32,
+    duration_secs: f32,
+    sample_rate: f32,
+    amp: *const f32,
+    phase: *const f32,
+    pitch_ratio: *const f32,
+) -> i64 {
+    if amp.is_null() || phase.is_null() || pitch_ratio.is_null() {
+        return -1;
+    }
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    let (nh, nb) = (nh as usize, nb as usize);
+    if nh == 0 || nb == 0 {
+        return -3;
+    }
+    let Some(slot) = zone_slot_from_raw(zone, root_key, lo_key, hi_key) else {
+        return -4;
+    };
+    let (amplitude, phase, ratio) = grid_rows_from_raw(nh, nb, amp, phase, pitch_ratio);
+    let grid = LoadedGrid::new(&amplitude, &phase, ratio, base_freq, duration_secs, sample_rate);
+    match engine.load_into(slot, grid) {
+        Ok(()) => {
+            wake_editor();
+            0
+        }
+        Err(_) => -4,
+    }
+}
+
+/// Empty key zone `zone` (1..) of a tagged instance; its keys fall back to
+/// the remaining zones. Returns 0 on success, or a negative value on an
+/// unknown/dead token (-2) or a zone that cannot be cleared (-4; zone 0 is
+/// the live grid).
+#[no_mangle]
+pub extern "C" fn lesynth_fourier_clear_key_zone(token: u64, zone: u32) -> i64 {
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    match engine.clear_key_zone(zone as usize) {
+        Ok(()) => {
+            wake_editor();
+            0
+        }
+        Err(_) => -4,
+    }
+}
+
+/// Empty velocity layer `layer` (1..) of a tagged instance. Returns 0 on
+/// success, or a negative value on an unknown/dead token (-2) or a layer that
+/// cannot be cleared (-4; layer 0 is the live grid).
+#[no_mangle]
+pub extern "C" fn lesynth_fourier_clear_velocity_layer(token: u64, layer: u32) -> i64 {
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    match engine.clear_velocity_layer(layer as usize) {
+        Ok(()) => {
+            wake_editor();
+            0
+        }
+        Err(_) => -4,
+    }
+}
+
+/// Convert a NUL-terminated UTF-8 path from the host. `None` if null/invalid.
+///
+/// # Safety
+/// `path`, if non-null, must point to a NUL-terminated string.
+unsafe fn path_from_raw(path: *const c_char) -> Option<PathBuf> {
+    if path.is_null() {
+        return None;
+    }
+    CStr::from_ptr(path).to_str().ok().map(PathBuf::from)
+}
+
+/// Save a tagged instance to a `.lesynth` file at `path` (grid, flags,
+/// nested-Fourier state and curve params). Returns 0 on success, or a negative value on a bad
+/// path (-1), an unknown/dead token (-2) or a write error (-3).
+///
+/// # Safety
+/// `path` must point to a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_save_file(token: u64, path: *const c_char) -> i64 {
+    let Some(path) = path_from_raw(path) else {
+        return -1;
+    };
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    match engine.save_lesynth_file(&path) {
+        Ok(()) => 0,
+        Err(e) => {
+            log::warn!("{}", e);
+            -3
+        }
+    }
+}
+
+/// Load a `.lesynth` file at `path` into a tagged instance, replacing its
+/// grid, flags, nested-Fourier state and curve params. The sound follows the
+/// file at once; its curve params reach the host params once the instance's
+/// editor is open. Returns 0 on success, or a negative
+/// value on a bad path (-1), an unknown/dead token (-2) or an unreadable or
+/// invalid file (-3, the instance is left untouched).
+///
+/// # Safety
+/// `path` must point to a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_load_file(token: u64, path: *const c_char) -> i64 {
+    let Some(path) = path_from_raw(path) else {
+        return -1;
+    };
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    match engine.load_lesynth_file(&path) {
+        Ok(()) => {
+            wake_editor();
+            0
+        }
+        Err(e) => {
+            log::warn!("{}", e);
+            -3
+        }
+    }
+}
+
+/// Bounce a tagged instance's current sound to 32-bit float WAV, as a lone
+/// note played through the plugin's own voice/fade/gain path. With `key` in
+/// `0..128` (a MIDI note) the note is written to the file `path`; with `key < 0` every key
+/// is written into the directory `path` (created if missing) as
+/// `key_<index>_<name>.wav`. Returns the number of files written, or a
+/// negative value on a bad path (-1), an unknown/dead token (-2), a key out of
+/// range (-3) or a write error (-4).
+///
+/// # Safety
+/// `path` must point to a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_render_to_wav(token: u64, key: i32, path: *const c_char) -> i64 {
+    let Some(path) = path_from_raw(path) else {
+        return -1;
+    };
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    let result = if key < 0 {
+        engine.render_all_keys_to_wav(&path)
+    } else if (key as usize) < constants::NUM_KEYS {
+        engine.render_key_to_wav(key as usize, &path).map(|()| 1)
+    } else {
+        return -3;
+    };
+    match result {
+        Ok(files) => files as i64,
+        Err(e) => {
+            log::warn!("{}", e);
+            -4
+        }
+    }
+}
+
+/// Export a tagged instance's current sound as an SFZ multisample into the
+/// directory `dir`: one WAV per `step`-th key (`1` = all 128) plus `<name>.sfz`
+/// mapping them (see `SynthComputeEngine::export_sfz`). Returns the number of
+/// regions written, or a negative value on a bad path or name (-1), an
+/// unknown/dead token (-2) or a write error (-3).
+///
+/// # Safety
+/// `dir` and `name` must point to NUL-terminated UTF-8 strings.
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_export_sfz(
+    token: u64,
+    dir: *const c_char,
+    name: *const c_char,
+    step: u32,
+) -> i64 {
+    let (Some(dir), Some(name)) = (path_from_raw(dir), path_from_raw(name)) else {
+        return -1;
+    };
+    let Some(name) = name.to_str().filter(|n| !n.is_empty()) else {
+        return -1;
+    };
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    match engine.export_sfz(&dir, name, step as usize) {
+        Ok(_) => sfz::sampled_key_ranges(step as usize).len() as i64,
+        Err(e) => {
+            log::warn!("{}", e);
+            -3
+        }
+    }
+}
+
+/// Export a tagged instance's grid as a wavetable WAV at `path`: one
+/// `frame_len`-sample cycle per bucket, or `num_frames` evenly picked buckets
+/// when non-zero, with a `clm ` cycle-length chunk. Returns the number of
+/// frames written, or a negative value on a bad path (-1), an unknown/dead
+/// token (-2), a frame length below 4 (-3) or a write error (-4).
+///
+/// # Safety
+/// `path` must point to a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_export_wavetable(
+    token: u64,
+    path: *const c_char,
+    frame_len: u32,
+    num_frames: u32,
+) -> i64 {
+    let Some(path) = path_from_raw(path) else {
+        return -1;
+    };
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    if frame_len < 4 {
+        return -3;
+    }
+    match engine.export_wavetable(&path, frame_len as usize, num_frames as usize) {
+        Ok(frames) => frames as i64,
+        Err(e) => {
+            log::warn!("{}", e);
+            -4
+        }
+    }
+}
+
+/// Load a wavetable WAV at `path` into a tagged instance's grid, one bucket
+/// per frame, switching it to Analysis mode. `frame_len == 0` takes the frame
+/// length from the file's `clm ` chunk (2048 without one); a note sweeps the
+/// table over `duration_secs` (`<= 0` → 2 s). Returns the number of frames
+/// loaded, or a negative value on a bad path (-1), an unknown/dead token (-2)
+/// or a read/format error (-3).
+///
+/// # Safety
+/// `path` must point to a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_import_wavetable(
+    token: u64,
+    path: *const c_char,
+    frame_len: u32,
+    duration_secs: f32,
+) -> i64 {
+    let Some(path) = path_from_raw(path) else {
+        return -1;
+    };
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    match engine.import_wavetable(&path, frame_len as usize, duration_secs) {
+        Ok(frames) => {
+            wake_editor();
+            frames as i64
+        }
+        Err(e) => {
+            log::warn!("{}", e);
+            -3
+        }
+    }
+}
+
+/// Select how a tagged instance renders its keys: `0` periodic (one
+/// independent period per bucket), `1` continuous phase (one oscillator per
+/// harmonic across the note). Returns `0`, or a negative value for an
+/// unknown/dead token (-2) or an unknown mode (-3).
+#[no_mangle]
+pub extern "C" fn lesynth_fourier_set_render_mode(token: u64, mode: u32) -> i64 {
+    let Some(engine) = lookup_instance(token) else {
+        return -2;
+    };
+    let mode = match mode {
+        0 => RenderMode::Periodic,
+        1 => RenderMode::Continuous,
+        _ => return -3,
+    };
+    engine.set_render_mode(mode);
+    0
+}
+
 /// Push a subtrack to be analysed by the next available plugin instance.
 /// Returns the new queue depth (0 on invalid input).
 ///
 /// `contour`/`contour_len` are the host's per-position fundamental (absolute Hz,
-/// uniformly resampled across the subtrack); pass `null`/`0` for flat (legacy).
+/// uniformly resampled across the subtrack). Pass `null`/`0` to have the
+/// instance track the pitch itself, and `base_freq <= 0` if the fundamental is
+/// unknown too (see `engine::resolve_pitch`).
 ///
 /// # Safety
 /// `samples` must point to `len` valid `f32`s; `contour`, if non-null, to
@@ -281,41 +737,121 @@ pub unsafe extern "C" fn lesynth_fourier_push_analysis(
     contour: *const f32,
     contour_len: usize,
 ) -> u64 {
-    if samples.is_null() || len == 0 {
-        return 0;
+    match job_from_raw(samples, len, sample_rate, base_freq, contour, contour_len, None) {
+        Some(job) => enqueue_analysis_job(job),
+        None => 0,
     }
-    let slice = std::slice::from_raw_parts(samples, len);
-    let contour = if contour.is_null() || contour_len == 0 {
-        Vec::new()
-    } else {
-        std::slice::from_raw_parts(contour, contour_len).to_vec()
-    };
-    let job = AnalysisJob {
-        samples: slice.to_vec(),
-        sample_rate,
-        base_freq,
-        contour,
-    };
-    let depth = match ANALYSIS_INBOX.lock() {
-        Ok(mut q) => {
-            q.push_back(job);
-            q.len() as u64
+}
+
+/// Like [`lesynth_fourier_push_analysis`], but addressed to the tagged
+/// instance `token`: only that instance's worker runs it, editor open or not.
+/// Returns the new queue depth, or a negative value on invalid input (-1) or
+/// an unknown/dead token (-2).
+///
+/// # Safety
+/// As for [`lesynth_fourier_push_analysis`].
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_push_analysis_to(
+    token: u64,
+    samples: *const f32,
+    len: usize,
+    sample_rate: f32,
+    base_freq: f32,
+    contour: *const f32,
+    contour_len: usize,
+) -> i64 {
+    if lookup_instance(token).is_none() {
+        return -2;
+    }
+    match job_from_raw(samples, len, sample_rate, base_freq, contour, contour_len, Some(token)) {
+        Some(job) => enqueue_analysis_job(job) as i64,
+        None => -1,
+    }
+}
+
+/// Like [`lesynth_fourier_push_analysis_to`], but the result is loaded into
+/// velocity layer `layer` (0 = the live grid), played for note-on velocities
+/// `vel_lo..=vel_hi` (0..1). Returns the new queue depth, or a negative value
+/// on invalid input (-1), an unknown/dead token (-2) or a layer out of range
+/// (-4).
+///
+/// # Safety
+/// As for [`lesynth_fourier_push_analysis`].
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_push_analysis_layer_to(
+    token: u64,
+    layer: u32,
+    vel_lo: f32,
+    vel_hi: f32,
+    samples: *const f32,
+    len: usize,
+    sample_rate: f32,
+    base_freq: f32,
+    contour: *const f32,
+    contour_len: usize,
+) -> i64 {
+    if lookup_instance(token).is_none() {
+        return -2;
+    }
+    if layer as usize >= MAX_VELOCITY_LAYERS {
+        return -4;
+    }
+    match job_from_raw(samples, len, sample_rate, base_freq, contour, contour_len, Some(token)) {
+        Some(mut job) => {
+            job.slot = GridSlot::VelocityLayer(layer as usize, VelocityRange::new(vel_lo, vel_hi));
+            enqueue_analysis_job(job) as i64
         }
-        Err(_) => 0,
+        None => -1,
+    }
+}
+
+/// Like [`lesynth_fourier_push_analysis_to`], but the result is loaded into
+/// key zone `zone` (0 = the live grid), recorded at `root_key` and played for
+/// keys `lo_key..=hi_key` (MIDI notes). Returns the new queue depth, or a
+/// negative value on invalid input (-1), an unknown/dead token (-2) or a zone
+/// or key out of range (-4).
+///
+/// # Safety
+/// As for [`lesynth_fourier_push_analysis`].
+#[no_mangle]
+pub unsafe extern "C" fn lesynth_fourier_push_analysis_zone_to(
+    token: u64,
+    zone: u32,
+    root_key: u32,
+    lo_key: u32,
+    hi_key: u32,
+    samples: *const f32,
+    len: usize,
+    sample_rate: f32,
+    base_freq: f32,
+    contour: *const f32,
+    contour_len: usize,
+) -> i64 {
+    if lookup_instance(token).is_none() {
+        return -2;
+    }
+    let Some(slot) = zone_slot_from_raw(zone, root_key, lo_key, hi_key) else {
+        return -4;
     };
-    // Wake the idle editor to claim and render the job.
-    wake_editor();
-    depth
+    match job_from_raw(samples, len, sample_rate, base_freq, contour, contour_len, Some(token)) {
+        Some(mut job) => {
+            job.slot = slot;
+            enqueue_analysis_job(job) as i64
+        }
+        None => -1,
+    }
 }
 
 /// Stateless harmonic analysis, for the host's own preview plotting.
 ///
 /// Writes `num_harmonics * num_buckets` floats (row-major, `[h*num_buckets+b]`)
 /// into `out_amp` and `out_phase`. Returns the number of buckets written, or a
-/// negative value on bad arguments.
+/// negative value on bad arguments (-1) or when `base_freq <= 0` and no pitch
+/// can be detected in the samples (-2).
 ///
 /// `contour`/`contour_len` are the host's per-position fundamental (absolute Hz,
-/// uniformly resampled across the subtrack); pass `null`/`0` for flat (legacy).
+/// uniformly resampled across the subtrack); pass `null`/`0` to track it from
+/// the samples, and `base_freq <= 0` to detect the fundamental as well.
 /// `num_buckets` is the fixed grid the caller allocated for (must be > 0 here,
 /// since the output buffers are sized to it).
 ///
@@ -345,11 +881,14 @@ pub unsafe extern "C" fn lesynth_fourier_analyze(
     } else {
         std::slice::from_raw_parts(contour, contour_len)
     };
+    let Some(pitch) = engine::resolve_pitch(slice, sample_rate, base_freq, contour) else {
+        return -2;
+    };
     let mut result = engine::analyze_subtrack(
         slice,
         sample_rate,
-        base_freq,
-        contour,
+        pitch.base_freq,
+        &pitch.contour,
         num_buckets,
         num_harmonics,
         num_buckets,
@@ -372,15 +911,26 @@ pub unsafe extern "C" fn lesynth_fourier_analyze(
 #[cfg(test)]
 mod ffi_tests {
     use super::*;
+    use crate::params::LeSynthParams;
+
+    /// Register `engine` under `token` without telling the engine, so its own
+    /// worker leaves jobs addressed to `token` for the test to claim.
+    fn register_silently(token: u64, engine: &Arc<SynthComputeEngine>) {
+        INSTANCE_REGISTRY.lock().unwrap().push((token, Arc::downgrade(engine)));
+    }
 
     #[test]
     fn push_analysis_round_trips_contour() {
+        let engine = Arc::new(SynthComputeEngine::new(Arc::new(LeSynthParams::default())));
+        let token = 31337;
+        register_silently(token, &engine);
         let samples = vec![0.1f32, 0.2, 0.3, 0.4];
         let contour = vec![440.0f32, 441.0, 439.0];
 
         // With a contour pointer.
         let depth = unsafe {
-            lesynth_fourier_push_analysis(
+            lesynth_fourier_push_analysis_to(
+                token,
                 samples.as_ptr(),
                 samples.len(),
                 44_100.0,
@@ -390,14 +940,16 @@ mod ffi_tests {
             )
         };
         assert!(depth >= 1);
-        let job = claim_analysis_job().expect("queued job");
+        let job = wait_for_analysis_job(Some(token), Duration::ZERO).expect("queued job");
         assert_eq!(job.samples, samples);
         assert_eq!(job.base_freq, 440.0);
+        assert_eq!(job.target, Some(token));
         assert_eq!(job.contour, contour, "contour must survive the FFI boundary");
 
         // Null contour → flat (legacy), no crash.
         let depth2 = unsafe {
-            lesynth_fourier_push_analysis(
+            lesynth_fourier_push_analysis_to(
+                token,
                 samples.as_ptr(),
                 samples.len(),
                 44_100.0,
@@ -407,9 +959,45 @@ mod ffi_tests {
             )
         };
         assert!(depth2 >= 1);
-        let job2 = claim_analysis_job().expect("queued job");
+        let job2 = wait_for_analysis_job(Some(token), Duration::ZERO).expect("queued job");
         assert!(job2.contour.is_empty(), "null contour → empty");
     }
+
+    #[test]
+    fn push_to_unknown_token_errors() {
+        let samples = [0.0f32; 4];
+        let rc = unsafe {
+            lesynth_fourier_push_analysis_to(
+                424_242,
+                samples.as_ptr(),
+                samples.len(),
+                44_100.0,
+                440.0,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(rc, -2);
+    }
+
+    #[test]
+    fn targeted_jobs_are_only_claimed_by_their_instance() {
+        let mut queue = VecDeque::new();
+        let job = |target| AnalysisJob {
+            samples: vec![0.0],
+            sample_rate: 44_100.0,
+            base_freq: 440.0,
+            contour: Vec::new(),
+            target,
+            slot: GridSlot::Live,
+        };
+        queue.push_back(job(Some(1)));
+        queue.push_back(job(None));
+
+        assert_eq!(take_job_for(&mut queue, Some(2)).unwrap().target, None);
+        assert!(take_job_for(&mut queue, Some(2)).is_none());
+        assert_eq!(take_job_for(&mut queue, Some(1)).unwrap().target, Some(1));
+    }
 }
 
 #[cfg(test)]
@@ -432,7 +1020,8 @@ mod state_registry_tests {
         assert!(lookup_instance(9999).is_none(), "unknown token → none");
 
         // Dropping the last strong ref makes the weak entry resolve to none and
-        // get pruned (the detached compute thread only holds SharedParams).
+        // get pruned (the detached background threads hold their own engine
+        // handle, not this Arc).
         drop(engine);
         assert!(lookup_instance(4242).is_none(), "dead instance pruned");
     }
@@ -505,6 +1094,186 @@ mod state_registry_tests {
         drop(engine);
     }
 
+    #[test]
+    fn worker_runs_job_pushed_to_its_token_without_editor() {
+        let engine = new_engine();
+        let token = 9001;
+        // Tag directly rather than via the shared pending-token slot, which
+        // the other tests here race on.
+        *engine.shared_params.instance_token.lock().unwrap() = Some(token);
+        INSTANCE_REGISTRY.lock().unwrap().push((token, Arc::downgrade(&engine)));
+
+        let sr = 44_100.0f32;
+        let tone: Vec<f32> = (0..(sr * 0.5) as usize)
+            .map(|i| (std::f32::consts::TAU * 220.0 * i as f32 / sr).sin())
+            .collect();
+        let rc = unsafe {
+            lesynth_fourier_push_analysis_to(
+                token,
+                tone.as_ptr(),
+                tone.len(),
+                sr,
+                220.0,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert!(rc >= 1);
+
+        let sp = &engine.shared_params;
+        let deadline = std::time::Instant::now() + Duration::from_secs(20);
+        while *sp.analysis_base_freq.lock().unwrap() != 220.0 {
+            assert!(std::time::Instant::now() < deadline, "worker never ran the job");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(sp.execution_mode(), crate::engine::ExecutionMode::Analysis);
+        engine.shutdown();
+    }
+
+    #[test]
+    fn save_and_load_file_round_trip_between_instances() {
+        let (src_params, dst_params) = (Arc::new(LeSynthParams::default()), Arc::new(LeSynthParams::default()));
+        src_params.harmonics[3].nested_fourier.write().unwrap().amp_chart.amps[2] = 0.6;
+        let src = Arc::new(SynthComputeEngine::new(src_params));
+        let dst = Arc::new(SynthComputeEngine::new(dst_params.clone()));
+        INSTANCE_REGISTRY.lock().unwrap().push((4242, Arc::downgrade(&src)));
+        INSTANCE_REGISTRY.lock().unwrap().push((4243, Arc::downgrade(&dst)));
+        let amp = vec![vec![0.8f32, 0.4], vec![0.2, 0.1]];
+        let phase = vec![vec![0.0f32; 2]; 2];
+        src.load_grid(amp, phase, vec![1.0, 1.02], 196.0, 0.75);
+        src.shared_params.harmonic_phase_enabled.lock().unwrap()[1] = false;
+
+        let path = std::env::temp_dir().join(format!("lesynth-ffi-{}.lesynth", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { lesynth_fourier_save_file(4242, c_path.as_ptr()) }, 0);
+        assert_eq!(unsafe { lesynth_fourier_load_file(4243, c_path.as_ptr()) }, 0);
+        let _ = std::fs::remove_file(&path);
+
+        let sp = &dst.shared_params;
+        assert_eq!(*sp.analysis_base_freq.lock().unwrap(), 196.0);
+        assert_eq!(*sp.bucket_pitch_ratio.lock().unwrap(), vec![1.0, 1.02]);
+        assert_eq!(sp.analysis_amplitude_data.lock().unwrap()[1], vec![0.2, 0.1]);
+        assert!(!sp.harmonic_phase_enabled.lock().unwrap()[1]);
+        assert_eq!(sp.execution_mode(), crate::engine::ExecutionMode::Analysis);
+        assert_eq!(dst_params.harmonics[3].nested_fourier.read().unwrap().amp_chart.amps[2], 0.6);
+
+        assert_eq!(unsafe { lesynth_fourier_save_file(4242, std::ptr::null()) }, -1);
+        assert_eq!(unsafe { lesynth_fourier_load_file(999_999, c_path.as_ptr()) }, -2);
+        // The file was removed above: loading it again fails and keeps the grid.
+        assert_eq!(unsafe { lesynth_fourier_load_file(4243, c_path.as_ptr()) }, -3);
+        assert_eq!(*sp.analysis_base_freq.lock().unwrap(), 196.0);
+    }
+
+    #[test]
+    fn render_to_wav_writes_one_key_and_rejects_bad_args() {
+        let engine = new_engine();
+        INSTANCE_REGISTRY.lock().unwrap().push((5150, Arc::downgrade(&engine)));
+        let path = std::env::temp_dir().join(format!("lesynth-ffi-render-{}.wav", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(unsafe { lesynth_fourier_render_to_wav(5150, 40, c_path.as_ptr()) }, 1);
+        let wav = wav::read_wav(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(wav.samples, engine.render_key_offline(40));
+
+        assert_eq!(unsafe { lesynth_fourier_render_to_wav(5150, 128, c_path.as_ptr()) }, -3);
+        assert_eq!(unsafe { lesynth_fourier_render_to_wav(999_998, 0, c_path.as_ptr()) }, -2);
+        assert_eq!(unsafe { lesynth_fourier_render_to_wav(5150, 0, std::ptr::null()) }, -1);
+    }
+
+    #[test]
+    fn set_render_mode_switches_the_tagged_instance() {
+        let engine = new_engine();
+        INSTANCE_REGISTRY.lock().unwrap().push((5151, Arc::downgrade(&engine)));
+        assert_eq!(lesynth_fourier_set_render_mode(5151, 1), 0);
+        assert_eq!(engine.shared_params.render_mode(), RenderMode::Continuous);
+        assert_eq!(lesynth_fourier_set_render_mode(5151, 2), -3);
+        assert_eq!(lesynth_fourier_set_render_mode(999_997, 0), -2);
+        assert_eq!(lesynth_fourier_set_render_mode(5151, 0), 0);
+        assert_eq!(engine.shared_params.render_mode(), RenderMode::Periodic);
+    }
+
+    #[test]
+    fn import_grid_layer_loads_and_clears_a_velocity_layer() {
+        let engine = new_engine();
+        INSTANCE_REGISTRY.lock().unwrap().push((5152, Arc::downgrade(&engine)));
+        let (nh, nb) = (2usize, 3usize);
+        let amp = vec![0.5f32, 0.5, 0.5, 0.1, 0.1, 0.1];
+        let phase = vec![0.0f32; nh * nb];
+        let ratio = vec![1.0f32; nb];
+        let import = |layer: u32| unsafe {
+            lesynth_fourier_import_grid_layer(
+                5152,
+                layer,
+                0.9,
+                0.5,
+                nh as u32,
+                nb as u32,
+                330.0,
+                0.5,
+                48_000.0,
+                amp.as_ptr(),
+                phase.as_ptr(),
+                ratio.as_ptr(),
+            )
+        };
+
+        assert_eq!(import(2), 0);
+        let sp = &engine.shared_params;
+        assert_eq!(sp.velocity_ranges()[2], Some(VelocityRange::new(0.5, 0.9)));
+        {
+            let layers = sp.velocity_layers.lock().unwrap();
+            let grid = layers[1].as_ref().unwrap();
+            assert_eq!((grid.num_buckets(), grid.base_freq), (nb, 330.0));
+            assert_eq!(grid.amplitude[1], vec![0.1; nb]);
+        }
+        assert_eq!(import(MAX_VELOCITY_LAYERS as u32), -4);
+
+        assert_eq!(lesynth_fourier_clear_velocity_layer(5152, 0), -4);
+        assert_eq!(lesynth_fourier_clear_velocity_layer(5152, 2), 0);
+        assert_eq!(sp.velocity_ranges()[2], None);
+        assert_eq!(lesynth_fourier_clear_velocity_layer(999_996, 2), -2);
+    }
+
+    #[test]
+    fn import_grid_zone_loads_and_clears_a_key_zone() {
+        let engine = new_engine();
+        INSTANCE_REGISTRY.lock().unwrap().push((5153, Arc::downgrade(&engine)));
+        let (nh, nb) = (2usize, 3usize);
+        let amp = vec![0.5f32, 0.5, 0.5, 0.1, 0.1, 0.1];
+        let phase = vec![0.0f32; nh * nb];
+        let ratio = vec![1.0f32; nb];
+        let import = |zone: u32, root_key: u32| unsafe {
+            lesynth_fourier_import_grid_zone(
+                5153,
+                zone,
+                root_key,
+                60,
+                40,
+                nh as u32,
+                nb as u32,
+                330.0,
+                0.5,
+                48_000.0,
+                amp.as_ptr(),
+                phase.as_ptr(),
+                ratio.as_ptr(),
+            )
+        };
+
+        assert_eq!(import(3, 50), 0);
+        let sp = &engine.shared_params;
+        assert_eq!(sp.key_zones()[3], Some(KeyZone::new(50, 40, 60)));
+        assert_eq!(sp.zone_grids.lock().unwrap()[2].as_ref().unwrap().num_buckets(), nb);
+        assert_eq!(import(MAX_KEY_ZONES as u32, 50), -4);
+        assert_eq!(import(3, constants::NUM_KEYS as u32), -4);
+
+        assert_eq!(lesynth_fourier_clear_key_zone(5153, 0), -4);
+        assert_eq!(lesynth_fourier_clear_key_zone(5153, 3), 0);
+        assert_eq!(sp.key_zones()[3], None);
+        assert_eq!(lesynth_fourier_clear_key_zone(999_995, 3), -2);
+    }
+
     #[test]
     fn export_unknown_token_errors() {
         let (mut nh, mut nb) = (0u32, 0u32);
@@ -0,0 +1,158 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persisted Analysis-mode state.
+//!
+//! Everything Analysis mode produces (the analysed grid, its per-bucket pitch
+//! ratios, duration and base frequency) plus the per-harmonic enable/custom
+//! flags lives in the engine's `SharedParams`, which the host never sees. This
+//! serde struct mirrors it into the plugin's own state blob via `#[persist]`,
+//! so a plain VST3/CLAP host round-trips an analysed instrument with the
+//! project. The engine keeps it in sync after every change and restores it in
+//! `Plugin::initialize`, which nih-plug re-runs after loading state.
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{KeyZone, VelocityRange};
+
+/// Layout version written into every snapshot. Bump on incompatible changes;
+/// a snapshot with any other version is ignored on load. `0` is the default of
+/// a fresh instance that never captured anything.
+pub const ANALYSIS_STATE_VERSION: u32 = 1;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AnalysisState {
+    pub version: u32,
+    /// `ExecutionMode::as_u8` of the instance when captured.
+    pub execution_mode: u8,
+    /// Source duration (seconds); `0.0` means no analysis was loaded.
+    pub duration_secs: f32,
+    /// Median fundamental (Hz) of the analysed source.
+    pub base_freq: f32,
+    /// Sample rate (Hz) of the analysed source; informational. Absent from
+    /// snapshots written before it was recorded.
+    #[serde(default)]
+    pub source_sample_rate: f32,
+    /// Bucket count of the grid rows below (needed to expand empty rows).
+    pub num_buckets: usize,
+    /// Pristine analysed grid, `[harmonic][bucket]`. Harmonics above the
+    /// source's Nyquist are all zero, so such rows are stored empty (see
+    /// [`pack_rows`]) to keep the JSON state blob small.
+    pub amplitude: Vec<Vec<f32>>,
+    pub phase: Vec<Vec<f32>>,
+    pub pitch_ratio: Vec<f32>,
+    pub harmonic_ampl_enabled: Vec<bool>,
+    pub harmonic_phase_enabled: Vec<bool>,
+    pub harmonic_ampl_custom: Vec<bool>,
+    pub harmonic_phase_custom: Vec<bool>,
+    /// Velocity range of the grid above (velocity layer 0). Absent from
+    /// snapshots written before velocity layers.
+    #[serde(default)]
+    pub velocity_range: VelocityRange,
+    /// Velocity layers `1..` (entry `i` is layer `i + 1`), `None` for an
+    /// empty slot.
+    #[serde(default)]
+    pub velocity_layers: Vec<Option<VelocityLayerState>>,
+    /// Key zone of the grid above (zone 0); `None` → rooted at its base
+    /// frequency over every key, as for a freshly loaded grid.
+    #[serde(default)]
+    pub key_zone: Option<KeyZone>,
+    /// Key zones `1..` (entry `i` is zone `i + 1`), `None` for an empty slot.
+    #[serde(default)]
+    pub key_zones: Vec<Option<KeyZoneState>>,
+    /// Whether keys between two zone roots blend the zones' amplitudes.
+    #[serde(default)]
+    pub zone_interpolation: bool,
+    /// Whether this snapshot already matches the engine. Never serialized, so
+    /// it is `false` right after nih-plug deserializes a saved state, which is
+    /// what tells `initialize` there is something to restore.
+    #[serde(skip)]
+    pub applied: bool,
+}
+
+/// A persisted grid beside the live one (see `engine::LoadedGrid`).
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GridState {
+    pub duration_secs: f32,
+    pub base_freq: f32,
+    pub source_sample_rate: f32,
+    pub num_buckets: usize,
+    /// Packed like [`AnalysisState::amplitude`].
+    pub amplitude: Vec<Vec<f32>>,
+    pub phase: Vec<Vec<f32>>,
+    pub pitch_ratio: Vec<f32>,
+}
+
+/// One persisted velocity layer above 0 (see `engine::velocity_layers`).
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VelocityLayerState {
+    pub range: VelocityRange,
+    #[serde(flatten)]
+    pub grid: GridState,
+}
+
+/// One persisted key zone above 0 (see `engine::key_zones`).
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct KeyZoneState {
+    pub zone: KeyZone,
+    #[serde(flatten)]
+    pub grid: GridState,
+}
+
+/// Copy grid rows for persisting, storing all-zero rows as empty.
+pub fn pack_rows(rows: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    rows.iter()
+        .map(|row| {
+            if row.iter().all(|&v| v == 0.0) {
+                Vec::new()
+            } else {
+                row.clone()
+            }
+        })
+        .collect()
+}
+
+/// Inverse of [`pack_rows`]: expand every row to exactly `num_buckets`
+/// entries (empty rows become zeros, short rows are zero-padded).
+pub fn unpack_rows(rows: &[Vec<f32>], num_buckets: usize) -> Vec<Vec<f32>> {
+    rows.iter()
+        .map(|row| {
+            let mut out = row.clone();
+            out.resize(num_buckets, 0.0);
+            out
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_unpack_round_trips_and_drops_silent_rows() {
+        let rows = vec![vec![0.5, 0.25, 0.0], vec![0.0; 3], vec![0.0, 0.0, 0.1]];
+        let packed = pack_rows(&rows);
+        assert_eq!(packed[0], rows[0]);
+        assert!(packed[1].is_empty(), "silent row is stored empty");
+        assert_eq!(packed[2], rows[2]);
+        assert_eq!(unpack_rows(&packed, 3), rows);
+    }
+
+    #[test]
+    fn default_snapshot_is_unversioned_and_unapplied() {
+        let state = AnalysisState::default();
+        assert_eq!(state.version, 0);
+        assert!(!state.applied);
+    }
+}
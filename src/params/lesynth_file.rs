@@ -0,0 +1,498 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The native `.lesynth` grid file.
+//!
+//! A self-contained snapshot of an instrument: the analysed grid with its
+//! per-bucket pitch ratios and source metadata, the per-harmonic enable/custom
+//! flags, and every harmonic's nested-Fourier state (which the custom rows and
+//! Synth mode are drawn from) together with its curve params. Unlike the
+//! `#[persist]` state blob it is a compact binary file that can move between
+//! projects and hosts. Velocity layers and key zones above the live grid are
+//! not part of it; loading a file keeps them.
+//!
+//! Layout (all little-endian), version 1:
+//!
+//! ```text
+//! magic "LSYNGRID" · u32 version · u32 harmonics · u32 buckets
+//! f32 base_freq · f32 duration_secs · f32 source_sample_rate · u8 mode
+//! f32 pitch_ratio[buckets]
+//! f32 amplitude[harmonics][buckets] · f32 phase[harmonics][buckets]
+//! u8 flags[harmonics]   (bit 0 amp enabled, 1 phase enabled,
+//!                        2 amp custom, 3 phase custom)
+//! u32 sub_harmonics · per harmonic: amp chart amps, phases,
+//!                     phase chart amps, phases (f32 × sub_harmonics each)
+//! per harmonic:         u8 curve type amp, phase · u8 granularity amp, phase
+//!                       f32 offset amp, phase · f32 sine amp/freq for amp,
+//!                       then for phase · f32 wobble amp/freq for amp, then
+//!                       for phase
+//! ```
+
+use std::path::Path;
+
+use nih_plug::prelude::{FloatParam, Param, ParamSetter};
+
+use super::analysis_state::{pack_rows, unpack_rows};
+use super::nested_fourier::{NestedFourierSeries, NUM_NESTED_FOURIER_HARMONICS};
+use crate::engine::ChartType;
+
+use super::{AnalysisState, CurveType, GranularityLevel, HarmonicParam, NestedFourierState, ANALYSIS_STATE_VERSION};
+
+pub const LESYNTH_FILE_MAGIC: &[u8; 8] = b"LSYNGRID";
+/// Bump on incompatible layout changes; readers reject other versions.
+pub const LESYNTH_FILE_VERSION: u32 = 1;
+/// Conventional file extension (without the dot).
+pub const LESYNTH_FILE_EXTENSION: &str = "lesynth";
+
+/// Upper bound on either grid dimension accepted from a file, so a corrupt
+/// header can't request an absurd allocation.
+const MAX_DIMENSION: usize = 1 << 20;
+
+/// Contents of a `.lesynth` file.
+#[derive(Clone, Default)]
+pub struct LesynthFile {
+    /// Grid, metadata and flags, in the same shape the plugin persists them.
+    pub state: AnalysisState,
+    /// Nested-Fourier state of every harmonic, in harmonic order.
+    pub nested_fourier: Vec<NestedFourierState>,
+    /// Curve params of every harmonic, in harmonic order.
+    pub curves: Vec<HarmonicCurveState>,
+}
+
+/// One harmonic's curve params as stored in the file. They are host params,
+/// which only the editor's [`ParamSetter`] can set; the engine draws rows
+/// from the file's values until they arrive.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HarmonicCurveState {
+    pub curve_type_amp: CurveType,
+    pub curve_type_phase: CurveType,
+    pub granularity_amp: GranularityLevel,
+    pub granularity_phase: GranularityLevel,
+    pub curve_offset_amp: f32,
+    pub curve_offset_phase: f32,
+    pub sine_curve_amp_amp: f32,
+    pub sine_curve_freq_amp: f32,
+    pub sine_curve_amp_phase: f32,
+    pub sine_curve_freq_phase: f32,
+    pub wobble_amp_amp: f32,
+    pub wobble_freq_amp: f32,
+    pub wobble_amp_phase: f32,
+    pub wobble_freq_phase: f32,
+}
+
+impl HarmonicCurveState {
+    pub fn capture(harmonic: &HarmonicParam) -> Self {
+        Self {
+            curve_type_amp: harmonic.curve_type_amp.value(),
+            curve_type_phase: harmonic.curve_type_phase.value(),
+            granularity_amp: harmonic.granularity_amp.value(),
+            granularity_phase: harmonic.granularity_phase.value(),
+            curve_offset_amp: harmonic.curve_offset_amp.value(),
+            curve_offset_phase: harmonic.curve_offset_phase.value(),
+            sine_curve_amp_amp: harmonic.sine_curve_amp_amp.value(),
+            sine_curve_freq_amp: harmonic.sine_curve_freq_amp.value(),
+            sine_curve_amp_phase: harmonic.sine_curve_amp_phase.value(),
+            sine_curve_freq_phase: harmonic.sine_curve_freq_phase.value(),
+            wobble_amp_amp: harmonic.wobble_amp_amp.value(),
+            wobble_freq_amp: harmonic.wobble_freq_amp.value(),
+            wobble_amp_phase: harmonic.wobble_amp_phase.value(),
+            wobble_freq_phase: harmonic.wobble_freq_phase.value(),
+        }
+    }
+
+    /// Curve type, offset, wobble amount and wobble rate of the amplitude or
+    /// phase chart: what its Synth-mode row is drawn from.
+    pub fn chart(&self, chart_type: ChartType) -> (CurveType, f32, f32, f32) {
+        match chart_type {
+            ChartType::Amp => (self.curve_type_amp, self.curve_offset_amp, self.wobble_amp_amp, self.wobble_freq_amp),
+            ChartType::Phase => {
+                (self.curve_type_phase, self.curve_offset_phase, self.wobble_amp_phase, self.wobble_freq_phase)
+            }
+        }
+    }
+
+    /// Set every curve param of `harmonic` to the stored value.
+    pub fn apply(&self, harmonic: &HarmonicParam, setter: &ParamSetter) {
+        fn set<P: Param>(setter: &ParamSetter, param: &P, value: P::Plain) {
+            setter.begin_set_parameter(param);
+            setter.set_parameter(param, value);
+            setter.end_set_parameter(param);
+        }
+        set(setter, &harmonic.curve_type_amp, self.curve_type_amp);
+        set(setter, &harmonic.curve_type_phase, self.curve_type_phase);
+        set(setter, &harmonic.granularity_amp, self.granularity_amp);
+        set(setter, &harmonic.granularity_phase, self.granularity_phase);
+        for (param, value) in Self::float_params(harmonic).into_iter().zip(self.float_values()) {
+            set(setter, param, value);
+        }
+    }
+
+    /// Whether `harmonic`'s params hold these values. Floats are compared
+    /// loosely: a value set through the host comes back via its normalized
+    /// form and may not round-trip bit for bit.
+    pub fn is_set_on(&self, harmonic: &HarmonicParam) -> bool {
+        self.curve_type_amp == harmonic.curve_type_amp.value()
+            && self.curve_type_phase == harmonic.curve_type_phase.value()
+            && self.granularity_amp == harmonic.granularity_amp.value()
+            && self.granularity_phase == harmonic.granularity_phase.value()
+            && Self::float_params(harmonic)
+                .into_iter()
+                .zip(self.float_values())
+                .all(|(param, value)| (param.value() - value).abs() <= 1e-4 * value.abs().max(1.0))
+    }
+
+    /// The float params in file order (that of [`Self::float_values`]).
+    fn float_params(harmonic: &HarmonicParam) -> [&FloatParam; 10] {
+        [
+            &harmonic.curve_offset_amp,
+            &harmonic.curve_offset_phase,
+            &harmonic.sine_curve_amp_amp,
+            &harmonic.sine_curve_freq_amp,
+            &harmonic.sine_curve_amp_phase,
+            &harmonic.sine_curve_freq_phase,
+            &harmonic.wobble_amp_amp,
+            &harmonic.wobble_freq_amp,
+            &harmonic.wobble_amp_phase,
+            &harmonic.wobble_freq_phase,
+        ]
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(variant_index(&CurveType::VARIANTS, self.curve_type_amp));
+        out.push(variant_index(&CurveType::VARIANTS, self.curve_type_phase));
+        out.push(variant_index(&GranularityLevel::VARIANTS, self.granularity_amp));
+        out.push(variant_index(&GranularityLevel::VARIANTS, self.granularity_phase));
+        for v in self.float_values() {
+            put_f32(out, v);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, String> {
+        let curve_type_amp = variant_at(&CurveType::VARIANTS, r.u8()?);
+        let curve_type_phase = variant_at(&CurveType::VARIANTS, r.u8()?);
+        let granularity_amp = variant_at(&GranularityLevel::VARIANTS, r.u8()?);
+        let granularity_phase = variant_at(&GranularityLevel::VARIANTS, r.u8()?);
+        let f = r.f32s(10)?;
+        Ok(Self {
+            curve_type_amp,
+            curve_type_phase,
+            granularity_amp,
+            granularity_phase,
+            curve_offset_amp: f[0],
+            curve_offset_phase: f[1],
+            sine_curve_amp_amp: f[2],
+            sine_curve_freq_amp: f[3],
+            sine_curve_amp_phase: f[4],
+            sine_curve_freq_phase: f[5],
+            wobble_amp_amp: f[6],
+            wobble_freq_amp: f[7],
+            wobble_amp_phase: f[8],
+            wobble_freq_phase: f[9],
+        })
+    }
+
+    /// The float fields in file order.
+    fn float_values(&self) -> [f32; 10] {
+        [
+            self.curve_offset_amp,
+            self.curve_offset_phase,
+            self.sine_curve_amp_amp,
+            self.sine_curve_freq_amp,
+            self.sine_curve_amp_phase,
+            self.sine_curve_freq_phase,
+            self.wobble_amp_amp,
+            self.wobble_freq_amp,
+            self.wobble_amp_phase,
+            self.wobble_freq_phase,
+        ]
+    }
+}
+
+fn variant_index<T: PartialEq>(variants: &[T], value: T) -> u8 {
+    variants.iter().position(|v| *v == value).unwrap_or(0) as u8
+}
+
+/// Unknown indices (from a newer build) fall back to the default variant.
+fn variant_at<T: Copy + Default>(variants: &[T], index: u8) -> T {
+    variants.get(index as usize).copied().unwrap_or_default()
+}
+
+impl LesynthFile {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let state = &self.state;
+        let nh = state
+            .amplitude
+            .len()
+            .max(state.harmonic_ampl_enabled.len())
+            .max(self.nested_fourier.len());
+        let nb = state.num_buckets;
+        let amplitude = unpack_rows(&state.amplitude, nb);
+        let phase = unpack_rows(&state.phase, nb);
+
+        let mut out = Vec::with_capacity(64 + 4 * nb * (2 * nh + 1) + nh * (1 + 16 * NUM_NESTED_FOURIER_HARMONICS));
+        out.extend_from_slice(LESYNTH_FILE_MAGIC);
+        put_u32(&mut out, LESYNTH_FILE_VERSION);
+        put_u32(&mut out, nh as u32);
+        put_u32(&mut out, nb as u32);
+        put_f32(&mut out, state.base_freq);
+        put_f32(&mut out, state.duration_secs);
+        put_f32(&mut out, state.source_sample_rate);
+        out.push(state.execution_mode);
+
+        for b in 0..nb {
+            put_f32(&mut out, state.pitch_ratio.get(b).copied().unwrap_or(1.0));
+        }
+        for rows in [&amplitude, &phase] {
+            for h in 0..nh {
+                for b in 0..nb {
+                    put_f32(&mut out, rows.get(h).map_or(0.0, |r| r[b]));
+                }
+            }
+        }
+        let flag = |flags: &[bool], h: usize, bit: u8| if flags.get(h).copied().unwrap_or(false) { bit } else { 0 };
+        for h in 0..nh {
+            out.push(
+                flag(&state.harmonic_ampl_enabled, h, 1)
+                    | flag(&state.harmonic_phase_enabled, h, 2)
+                    | flag(&state.harmonic_ampl_custom, h, 4)
+                    | flag(&state.harmonic_phase_custom, h, 8),
+            );
+        }
+
+        put_u32(&mut out, NUM_NESTED_FOURIER_HARMONICS as u32);
+        let default_nested = NestedFourierState::default();
+        for h in 0..nh {
+            let nested = self.nested_fourier.get(h).unwrap_or(&default_nested);
+            for series in [&nested.amp_chart, &nested.phase_chart] {
+                series.amps.iter().chain(series.phases.iter()).for_each(|&v| put_f32(&mut out, v));
+            }
+        }
+
+        let default_curves = HarmonicCurveState::default();
+        for h in 0..nh {
+            self.curves.get(h).unwrap_or(&default_curves).write(&mut out);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(8)? != LESYNTH_FILE_MAGIC {
+            return Err("Not a .lesynth file".to_string());
+        }
+        let version = r.u32()?;
+        if version != LESYNTH_FILE_VERSION {
+            return Err(format!("Unsupported .lesynth version {}", version));
+        }
+        let nh = r.u32()? as usize;
+        let nb = r.u32()? as usize;
+        if nh > MAX_DIMENSION || nb > MAX_DIMENSION {
+            return Err(format!("Implausible grid size {} x {}", nh, nb));
+        }
+        let base_freq = r.f32()?;
+        let duration_secs = r.f32()?;
+        let source_sample_rate = r.f32()?;
+        let execution_mode = r.u8()?;
+
+        let pitch_ratio = r.f32s(nb)?;
+        let mut read_grid = || -> Result<Vec<Vec<f32>>, String> { (0..nh).map(|_| r.f32s(nb)).collect() };
+        let amplitude = read_grid()?;
+        let phase = read_grid()?;
+        let flags = r.take(nh)?.to_vec();
+        let flag = |bit: u8| flags.iter().map(|f| f & bit != 0).collect::<Vec<bool>>();
+
+        let subs = r.u32()? as usize;
+        if subs > MAX_DIMENSION {
+            return Err(format!("Implausible sub-harmonic count {}", subs));
+        }
+        let mut read_series = || -> Result<NestedFourierSeries, String> {
+            let mut series = NestedFourierSeries::default();
+            let amps = r.f32s(subs)?;
+            let phases = r.f32s(subs)?;
+            // Files with more sub-harmonics than this build drop the extras.
+            for (dst, src) in series.amps.iter_mut().zip(amps) {
+                *dst = src;
+            }
+            for (dst, src) in series.phases.iter_mut().zip(phases) {
+                *dst = src;
+            }
+            Ok(series)
+        };
+        let nested_fourier = (0..nh)
+            .map(|_| {
+                Ok(NestedFourierState {
+                    amp_chart: read_series()?,
+                    phase_chart: read_series()?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let curves = (0..nh).map(|_| HarmonicCurveState::read(&mut r)).collect::<Result<Vec<_>, String>>()?;
+
+        Ok(LesynthFile {
+            state: AnalysisState {
+                version: ANALYSIS_STATE_VERSION,
+                execution_mode,
+                duration_secs,
+                base_freq,
+                num_buckets: nb,
+                amplitude: pack_rows(&amplitude),
+                phase: pack_rows(&phase),
+                pitch_ratio,
+                harmonic_ampl_enabled: flag(1),
+                harmonic_phase_enabled: flag(2),
+                harmonic_ampl_custom: flag(4),
+                harmonic_phase_custom: flag(8),
+                source_sample_rate,
+                velocity_range: Default::default(),
+                velocity_layers: Vec::new(),
+                key_zone: None,
+                key_zones: Vec::new(),
+                zone_interpolation: false,
+                applied: false,
+            },
+            nested_fourier,
+            curves,
+        })
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+
+    pub fn read_from(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, v: f32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Bounds-checked little-endian cursor; every short read is a format error.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.bytes.len());
+        let end = end.ok_or_else(|| "Truncated .lesynth file".to_string())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        let b = self.take(4)?;
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32s(&mut self, n: usize) -> Result<Vec<f32>, String> {
+        let bytes = self.take(n.checked_mul(4).ok_or("Truncated .lesynth file")?)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_file() -> LesynthFile {
+        let mut nested = NestedFourierState::default();
+        nested.amp_chart.amps[3] = 0.5;
+        nested.phase_chart.phases[31] = -1.25;
+        LesynthFile {
+            state: AnalysisState {
+                version: ANALYSIS_STATE_VERSION,
+                execution_mode: 1,
+                duration_secs: 1.5,
+                base_freq: 220.0,
+                num_buckets: 3,
+                amplitude: pack_rows(&[vec![0.9, 0.5, 0.1], vec![0.0; 3]]),
+                phase: pack_rows(&[vec![0.1, 0.2, 0.3], vec![0.0, 0.0, -0.5]]),
+                pitch_ratio: vec![1.0, 1.01, 0.99],
+                harmonic_ampl_enabled: vec![true, false],
+                harmonic_phase_enabled: vec![true, true],
+                harmonic_ampl_custom: vec![false, true],
+                harmonic_phase_custom: vec![false, false],
+                source_sample_rate: 48_000.0,
+                velocity_range: Default::default(),
+                velocity_layers: Vec::new(),
+                key_zone: None,
+                key_zones: Vec::new(),
+                zone_interpolation: false,
+                applied: true,
+            },
+            nested_fourier: vec![NestedFourierState::default(), nested],
+            curves: vec![
+                HarmonicCurveState::default(),
+                HarmonicCurveState {
+                    curve_type_phase: CurveType::NestedFourier,
+                    granularity_amp: GranularityLevel::High,
+                    curve_offset_amp: 0.75,
+                    wobble_amp_phase: 0.1,
+                    wobble_freq_phase: 120.0,
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips_grid_flags_and_nested_state() {
+        let file = sample_file();
+        let back = LesynthFile::from_bytes(&file.to_bytes()).unwrap();
+        let (a, b) = (&file.state, &back.state);
+        assert_eq!((b.num_buckets, b.base_freq, b.duration_secs, b.execution_mode), (3, 220.0, 1.5, 1));
+        assert_eq!(b.source_sample_rate, 48_000.0);
+        assert_eq!(b.amplitude, a.amplitude);
+        assert_eq!(b.phase, a.phase);
+        assert_eq!(b.pitch_ratio, a.pitch_ratio);
+        assert_eq!(b.harmonic_ampl_enabled, a.harmonic_ampl_enabled);
+        assert_eq!(b.harmonic_ampl_custom, a.harmonic_ampl_custom);
+        assert_eq!(back.nested_fourier[1].amp_chart.amps[3], 0.5);
+        assert_eq!(back.nested_fourier[1].phase_chart.phases[31], -1.25);
+        assert_eq!(back.curves, file.curves);
+        assert!(!b.applied, "a loaded file still has to be applied");
+    }
+
+    #[test]
+    fn rejects_foreign_truncated_and_future_files() {
+        let bytes = sample_file().to_bytes();
+        assert!(LesynthFile::from_bytes(b"RIFF....WAVE").is_err());
+        assert!(LesynthFile::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut future = bytes.clone();
+        future[8..12].copy_from_slice(&(LESYNTH_FILE_VERSION + 1).to_le_bytes());
+        assert!(LesynthFile::from_bytes(&future).is_err());
+    }
+}
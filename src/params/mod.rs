@@ -12,12 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod analysis_state;
 pub mod curve_type;
+pub mod envelope_curve;
 pub mod harmonic;
+pub mod lesynth_file;
 pub mod nested_fourier;
 pub mod synth_params;
+pub mod tuning_state;
+pub mod voice_stealing;
 
+pub use analysis_state::{AnalysisState, GridState, KeyZoneState, VelocityLayerState, ANALYSIS_STATE_VERSION};
 pub use curve_type::{CurveType, GranularityLevel};
+pub use envelope_curve::EnvelopeCurve;
 pub use harmonic::HarmonicParam;
-pub use nested_fourier::{NestedFourierState, NUM_NESTED_FOURIER_HARMONICS};
+pub use lesynth_file::LesynthFile;
+pub use nested_fourier::{NestedFourierSeries, NestedFourierState, NUM_NESTED_FOURIER_HARMONICS};
 pub use synth_params::LeSynthParams;
+pub use tuning_state::TuningState;
+pub use voice_stealing::VoiceStealing;
@@ -30,8 +30,9 @@ pub const NUM_NESTED_FOURIER_HARMONICS: usize = 32;
 ///   V(t) = offset + Sum_{k=1}^{N} amps[k] * sin(2*pi * k * t + phases[k])
 /// where t = bucket / num_buckets.
 ///
-/// Amplitudes are in [0, 1]; phases are in radians [-pi, pi]. The offset lives
-/// on the harmonic's `curve_offset_*` parameter, not here.
+/// Amplitudes are in [0, 1] (up to 2π on a phase chart); phases are in
+/// radians [-pi, pi]. The offset lives on the harmonic's `curve_offset_*`
+/// parameter, not here.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct NestedFourierSeries {
     pub amps: [f32; NUM_NESTED_FOURIER_HARMONICS],
@@ -47,6 +48,49 @@ impl Default for NestedFourierSeries {
     }
 }
 
+impl NestedFourierSeries {
+    /// The envelope value at `t` (bucket / num_buckets) for the given offset.
+    pub fn value_at(&self, offset: f64, t: f64) -> f64 {
+        let mut value = offset;
+        for (k, (&amp, &phase)) in self.amps.iter().zip(self.phases.iter()).enumerate() {
+            value += amp as f64 * (2.0 * std::f64::consts::PI * (k + 1) as f64 * t + phase as f64).sin();
+        }
+        value
+    }
+
+    /// Least-squares fit of `offset + series` to `row`, one value per bucket.
+    /// On the uniform bucket grid the sub-harmonic sines are orthogonal, so the
+    /// fit is a plain projection: the offset is the row's mean and
+    /// sub-harmonic `k` its `k`-th DFT bin. Sub-harmonics above the row's
+    /// Nyquist (`k > len / 2`) cannot be resolved and stay zero; amplitudes
+    /// are clamped to `[0, max_amp]`, the top of the chart's value range.
+    /// Returns `(offset, series)`.
+    pub fn fit(row: &[f32], max_amp: f32) -> (f32, NestedFourierSeries) {
+        let mut series = NestedFourierSeries::default();
+        let len = row.len();
+        if len == 0 {
+            return (0.0, series);
+        }
+        let offset = row.iter().map(|&v| v as f64).sum::<f64>() / len as f64;
+        for k in 1..=NUM_NESTED_FOURIER_HARMONICS.min(len / 2) {
+            let (mut s, mut c) = (0.0f64, 0.0f64);
+            for (b, &v) in row.iter().enumerate() {
+                let angle = 2.0 * std::f64::consts::PI * (k * b) as f64 / len as f64;
+                let v = v as f64 - offset;
+                s += v * angle.sin();
+                c += v * angle.cos();
+            }
+            // The Nyquist sub-harmonic has a single (cosine) degree of freedom.
+            let scale = if 2 * k == len { 1.0 } else { 2.0 } / len as f64;
+            // s·sin + c·cos == A·sin(θ + φ) with A = |(s, c)|, φ = atan2(c, s).
+            let (s, c) = (s * scale, c * scale);
+            series.amps[k - 1] = s.hypot(c).min(max_amp as f64) as f32;
+            series.phases[k - 1] = c.atan2(s) as f32;
+        }
+        (offset as f32, series)
+    }
+}
+
 /// A harmonic's complete nested-Fourier state: one independent series for the
 /// amplitude chart and one for the phase chart.
 #[derive(Clone, Default, Serialize, Deserialize)]
@@ -74,3 +118,46 @@ impl NestedFourierState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_recovers_a_series_within_the_grid_resolution() {
+        let mut truth = NestedFourierSeries::default();
+        truth.amps[0] = 0.3;
+        truth.phases[0] = 1.2;
+        truth.amps[4] = 0.05;
+        truth.phases[4] = -2.0;
+        let row: Vec<f32> = (0..200).map(|b| truth.value_at(0.4, b as f64 / 200.0) as f32).collect();
+
+        let (offset, fitted) = NestedFourierSeries::fit(&row, 1.0);
+        assert!((offset - 0.4).abs() < 1e-5);
+        for k in 0..NUM_NESTED_FOURIER_HARMONICS {
+            assert!((fitted.amps[k] - truth.amps[k]).abs() < 1e-4, "sub-harmonic {}", k + 1);
+        }
+        assert!((fitted.phases[0] - 1.2).abs() < 1e-4);
+        assert!((fitted.phases[4] + 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fit_of_a_short_row_reproduces_it_exactly() {
+        // With fewer buckets than sub-harmonics the fit interpolates the row.
+        let row = [0.1f32, 0.7, 0.2, 0.5, 0.9, 0.0];
+        let (offset, fitted) = NestedFourierSeries::fit(&row, 1.0);
+        for (b, &v) in row.iter().enumerate() {
+            let t = b as f64 / row.len() as f64;
+            assert!((fitted.value_at(offset as f64, t) as f32 - v).abs() < 1e-5, "bucket {}", b);
+        }
+        assert!(fitted.amps[3..].iter().all(|&a| a == 0.0));
+        assert_eq!(NestedFourierSeries::fit(&[], 1.0).0, 0.0);
+    }
+
+    #[test]
+    fn fit_clamps_to_the_given_range_only() {
+        let row: Vec<f32> = (0..64).map(|b| 3.0 + 2.5 * (2.0 * std::f32::consts::PI * b as f32 / 64.0).sin()).collect();
+        assert!((NestedFourierSeries::fit(&row, 6.28).1.amps[0] - 2.5).abs() < 1e-4);
+        assert_eq!(NestedFourierSeries::fit(&row, 1.0).1.amps[0], 1.0);
+    }
+}
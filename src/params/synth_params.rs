@@ -17,7 +17,8 @@ use nih_plug::prelude::*;
 use nih_plug_egui::EguiState;
 
 use crate::constants::*;
-use super::{CurveType, GranularityLevel, HarmonicParam, NestedFourierState};
+use crate::voice::MAX_VOICES;
+use super::{AnalysisState, CurveType, EnvelopeCurve, GranularityLevel, HarmonicParam, NestedFourierState, TuningState, VoiceStealing};
 
 #[derive(Params)]
 pub struct LeSynthParams {
@@ -37,6 +38,110 @@ pub struct LeSynthParams {
     // pointer-sized; `#[nested(array)]` only needs `.iter()`, which `Vec` has.
     #[nested(array, group = "harmonics")]
     pub harmonics: Vec<HarmonicParam>,
+
+    /// How note-on velocity scales the output level (`velocity^curve`): 0
+    /// ignores velocity, 1 is linear, higher values favour hard hits.
+    #[id = "velocity_curve"]
+    pub velocity_curve: FloatParam,
+
+    /// How much softer notes darken: the spectrum tilts down from full
+    /// brightness at top velocity. 0 plays every velocity as drawn.
+    #[id = "velocity_tilt"]
+    pub velocity_tilt: FloatParam,
+
+    /// Keep an analysed grid's formants at fixed frequencies when it is played
+    /// away from its analysed pitch, instead of moving them with the key.
+    #[id = "formant_preserve"]
+    pub formant_preserve: BoolParam,
+
+    /// Formant shift in semitones, with formant preservation on.
+    #[id = "formant_shift"]
+    pub formant_shift: FloatParam,
+
+    /// Frequency (Hz) of A4 (MIDI note 69); every key is tuned from it,
+    /// unless a loaded keyboard mapping sets its own reference.
+    #[id = "reference_pitch"]
+    pub reference_pitch: FloatParam,
+
+    /// How far (semitones) a full pitch-bend moves notes either way.
+    #[id = "pitch_bend_range"]
+    pub pitch_bend_range: IntParam,
+
+    /// MPE: channel 1 is the master channel bending every note, channels
+    /// 2-16 each bend only their own notes, over `mpe_bend_range`.
+    #[id = "mpe"]
+    pub mpe: BoolParam,
+
+    /// Pitch-bend range (semitones) of MPE member channels.
+    #[id = "mpe_bend_range"]
+    pub mpe_bend_range: IntParam,
+
+    /// Most voices sounding at once; further notes steal one.
+    #[id = "max_polyphony"]
+    pub max_polyphony: IntParam,
+
+    /// Which voice a note steals at the polyphony limit.
+    #[id = "voice_stealing"]
+    pub voice_stealing: EnumParam<VoiceStealing>,
+
+    /// Mono mode: one voice, the last key held sounds; releasing it returns
+    /// to the key held before.
+    #[id = "mono"]
+    pub mono: BoolParam,
+
+    /// In mono mode, a note played over a held one carries the voice on
+    /// rather than restarting it with a fade-in.
+    #[id = "legato"]
+    pub legato: BoolParam,
+
+    /// In mono mode, time (ms) a note takes to glide from the previous
+    /// note's pitch; 0 jumps.
+    #[id = "portamento_time"]
+    pub portamento_time: FloatParam,
+
+    /// Amplitude envelope times (ms). Attack and release never go below the
+    /// short anti-click fade.
+    #[id = "attack_time"]
+    pub attack_time: FloatParam,
+
+    #[id = "decay_time"]
+    pub decay_time: FloatParam,
+
+    /// Level (0..1) a held note settles at after its decay.
+    #[id = "sustain_level"]
+    pub sustain_level: FloatParam,
+
+    #[id = "release_time"]
+    pub release_time: FloatParam,
+
+    /// Shape of the attack, decay and release segments.
+    #[id = "envelope_curve"]
+    pub envelope_curve: EnumParam<EnvelopeCurve>,
+
+    /// Analysis mode: a released note plays out the decaying tail of the
+    /// analysed sound instead of the envelope release.
+    #[id = "release_tail"]
+    pub release_tail: BoolParam,
+
+    // Analysis-mode grid and per-harmonic flags. Plain serde state (like the
+    // nested-Fourier series): the engine keeps it in sync with `SharedParams`
+    // and restores it from `initialize()` after the host loads a project.
+    #[persist = "analysis-state"]
+    pub analysis_state: Arc<RwLock<AnalysisState>>,
+
+    // Scala tuning loaded from the editor (`None` = equal temperament),
+    // re-applied from `initialize()` like the analysis state.
+    #[persist = "tuning"]
+    pub tuning: Arc<RwLock<Option<TuningState>>>,
+
+    // Key buffer renderer (`RenderMode::as_u8`), switched from the editor and
+    // re-applied from `initialize()`.
+    #[persist = "render-mode"]
+    pub render_mode: Arc<RwLock<u8>>,
+
+    // Streaming playback (voices synthesised from the live grid), likewise.
+    #[persist = "streaming"]
+    pub streaming: Arc<RwLock<bool>>,
 }
 
 impl Default for LeSynthParams {
@@ -168,6 +273,70 @@ impl Default for LeSynthParams {
                 },
             ),
             harmonics,
+            velocity_curve: FloatParam::new(
+                "Velocity Curve",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 4.0 },
+            ),
+            velocity_tilt: FloatParam::new(
+                "Velocity Brightness",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            formant_preserve: BoolParam::new("Preserve Formants", false),
+            formant_shift: FloatParam::new(
+                "Formant Shift",
+                0.0,
+                FloatRange::Linear { min: -12.0, max: 12.0 },
+            ),
+            reference_pitch: FloatParam::new(
+                "Reference Pitch",
+                DEFAULT_REFERENCE_PITCH,
+                FloatRange::Linear { min: 400.0, max: 480.0 },
+            )
+            .with_step_size(0.1)
+            .with_unit(" Hz"),
+            pitch_bend_range: IntParam::new("Pitch Bend Range", 2, IntRange::Linear { min: 0, max: 48 })
+                .with_unit(" st"),
+            mpe: BoolParam::new("MPE", false),
+            mpe_bend_range: IntParam::new("MPE Bend Range", 48, IntRange::Linear { min: 0, max: 96 })
+                .with_unit(" st"),
+            max_polyphony: IntParam::new(
+                "Max Polyphony",
+                32,
+                IntRange::Linear { min: 1, max: MAX_VOICES as i32 },
+            ),
+            voice_stealing: EnumParam::new("Voice Stealing", VoiceStealing::Oldest),
+            mono: BoolParam::new("Mono", false),
+            legato: BoolParam::new("Legato", true),
+            portamento_time: FloatParam::new(
+                "Portamento Time",
+                0.0,
+                FloatRange::Skewed { min: 0.0, max: 2000.0, factor: FloatRange::skew_factor(-2.0) },
+            )
+            .with_step_size(1.0)
+            .with_unit(" ms"),
+            attack_time: envelope_time_param("Attack Time", 0.0),
+            decay_time: envelope_time_param("Decay Time", 200.0),
+            sustain_level: FloatParam::new("Sustain Level", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            release_time: envelope_time_param("Release Time", 0.0),
+            envelope_curve: EnumParam::new("Envelope Curve", EnvelopeCurve::Linear),
+            release_tail: BoolParam::new("Release Tail", false),
+            analysis_state: Arc::new(RwLock::new(AnalysisState::default())),
+            tuning: Arc::new(RwLock::new(None)),
+            render_mode: Arc::new(RwLock::new(0)),
+            streaming: Arc::new(RwLock::new(false)),
         }
     }
 }
+
+/// An envelope time (ms), skewed towards short times.
+fn envelope_time_param(name: &str, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed { min: 0.0, max: 10_000.0, factor: FloatRange::skew_factor(-2.0) },
+    )
+    .with_step_size(1.0)
+    .with_unit(" ms")
+}
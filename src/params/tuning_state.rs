@@ -0,0 +1,32 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persisted microtuning.
+//!
+//! The active Scala tuning (see `engine::tuning`) is stored as the text of
+//! the files it was loaded from, so a project keeps playing in tune without
+//! the files, and re-parsed in `Plugin::initialize`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TuningState {
+    /// Shown in the editor.
+    pub name: String,
+    /// `.scl` file text.
+    pub scl: String,
+    /// `.kbm` file text, if a keyboard mapping was loaded.
+    #[serde(default)]
+    pub kbm: Option<String>,
+}
@@ -22,14 +22,27 @@ use nih_plug_egui::{
 };
 
 use crate::constants::*;
-use crate::engine::{ChartType, ExecutionMode, SynthComputeEngine};
-use crate::gui::{draw_analysis_controls, draw_assembled_chart, draw_curve_controls, draw_harmonic_plot, draw_nested_fourier_controls, draw_piano_keyboard, draw_metallic_background, section, section_with_header};
+use crate::engine::oscillator::GridView;
+use crate::engine::shared_params::NoteRequest;
+use crate::engine::{ChartType, ExecutionMode, RenderMode, SynthComputeEngine};
+use crate::gui::{draw_analysis_controls, draw_assembled_chart, draw_curve_controls, draw_file_controls, draw_harmonic_plot, draw_nested_fourier_controls, draw_piano_keyboard, draw_metallic_background, draw_tuning_controls, section, section_with_header};
 use crate::params::LeSynthParams;
-use crate::voice::Voice;
+use crate::voice::{PitchBends, VoicePool, EDITOR_CHANNEL, MAX_VOICES};
+
+/// MIDI CCs of the sustain and sostenuto pedals.
+const SUSTAIN_CC: u8 = 64;
+const SOSTENUTO_CC: u8 = 66;
 
 pub struct LeSynth {
     synth_params: Arc<LeSynthParams>,
     pub synth_compute_engine: Arc<SynthComputeEngine>,
+    /// Voices, owned by the audio thread; the editor sees which keys sound
+    /// through `SharedParams::voice_active` and plays notes via `request_note`.
+    voices: VoicePool,
+    /// Pitch bend of each MIDI channel, applied to the voices every block.
+    pitch_bends: PitchBends,
+    /// Host sample rate, for the portamento and envelope times.
+    sample_rate: f32,
 }
 
 impl Default for LeSynth {
@@ -44,17 +57,28 @@ impl Default for LeSynth {
         Self {
             synth_params,
             synth_compute_engine,
+            voices: VoicePool::new(MAX_VOICES, NUM_KEYS, NUM_HARMONICS),
+            pitch_bends: PitchBends::default(),
+            sample_rate: 44100.0,
         }
     }
 }
 
+impl Drop for LeSynth {
+    fn drop(&mut self) {
+        // Stop the engine's background threads so a removed instance neither
+        // leaks them nor keeps claiming host-pushed analysis jobs.
+        self.synth_compute_engine.shutdown();
+    }
+}
+
 impl Plugin for LeSynth {
     const NAME: &'static str = "LeSynth";
     const VENDOR: &'static str = "Jakub Hlavnicka";
     const URL: &'static str = "https://donothaveany.com";
     const EMAIL: &'static str = "hlavnickajakub@gmail.com";
     const VERSION: &'static str = "1.2.0";
-    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
 
     const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
         main_input_channels: None,
@@ -77,12 +101,24 @@ impl Plugin for LeSynth {
         buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
+        self.synth_compute_engine
+            .shared_params
+            .set_reference_pitch(self.synth_params.reference_pitch.value());
+        self.synth_compute_engine.restore_tuning();
+        self.synth_compute_engine.restore_render_settings();
+        self.sample_rate = buffer_config.sample_rate;
         self.synth_compute_engine
             .shared_params
             .update_sample_rate(buffer_config.sample_rate);
         self.synth_compute_engine
             .shared_params
             .mark_all_buffers_dirty();
+        // nih-plug re-runs initialize() after loading state: bring back any
+        // persisted analysis grid and per-harmonic flags.
+        self.synth_compute_engine.restore_analysis_state();
+        // Same for the Synth grid: regenerate whatever the loaded params
+        // describe, since the editor may never be opened.
+        self.synth_compute_engine.rebuild_grid_from_params();
         true
     }
 
@@ -92,144 +128,113 @@ impl Plugin for LeSynth {
         _aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        let shared = &self.synth_compute_engine.shared_params;
+        let engine = &self.synth_compute_engine;
+        let shared = &engine.shared_params;
         let fade_duration = shared.fade_duration;
         let repeat_playback = shared.repeat_playback();
 
-        // --- Handle incoming MIDI events (build/stop voices) ---
+        self.voices.configure(
+            self.synth_params.max_polyphony.value() as usize,
+            self.synth_params.voice_stealing.value(),
+        );
+        self.voices.set_mono(self.synth_params.mono.value(), self.synth_params.legato.value());
+        let glide_samples = self.synth_params.portamento_time.value() * 0.001 * self.sample_rate;
+        // Envelope params reach sounding notes too, so automation is heard.
+        self.voices.set_envelope(engine.envelope(self.sample_rate));
+
+        // --- Notes played on the editor's keyboard ---
+        for key_idx in 0..NUM_KEYS {
+            let note = match shared.take_note_request(key_idx) {
+                Some(NoteRequest::On) => self.voices.note_on(key_idx, EDITOR_CHANNEL, None, 1.0),
+                Some(NoteRequest::Off) => self.voices.note_off(key_idx, EDITOR_CHANNEL, None),
+                None => None,
+            };
+            if let Some(note) = note {
+                engine.start_note(note, glide_samples);
+            }
+        }
+
+        // --- Handle incoming MIDI events (start/release voices) ---
         // Wake the idle editor once after the batch if any voice changed.
         let mut voices_changed = false;
+        self.pitch_bends.mpe = self.synth_params.mpe.value();
         while let Some(event) = context.next_event() {
             match event {
-                NoteEvent::NoteOn { note, .. } => {
-                    // MIDI A0 = note 21, our key 0 = A0, so subtract 21
-                    let key_idx = (note as usize).saturating_sub(21);
-                    if key_idx < NUM_KEYS {
-                        // Get pre-computed buffer or compute synchronously as fallback
-                        let buf = self.synth_compute_engine.get_buffer_for_key(key_idx);
-                        let mut voices = shared.voices.lock().unwrap();
-                        voices[key_idx] = Some(Voice {
-                            buffer: buf,
-                            idx: 0,
-                            fade_in_active: true,
-                            fade_in_pos: 0,
-                            fade_out_active: false,
-                            fade_out_pos: 0,
-                        });
+                NoteEvent::NoteOn { voice_id, channel, note, velocity, .. } => {
+                    // One key per MIDI note.
+                    let key_idx = note as usize;
+                    // The key's ready buffer (silent until it lands), or
+                    // oscillators when streaming; never rendered here.
+                    if let Some(note) = self.voices.note_on(key_idx, channel, voice_id, velocity) {
+                        engine.start_note(note, glide_samples);
                         voices_changed = true;
                     }
                 }
-                NoteEvent::NoteOff { note, .. } => {
-                    let key_idx = (note as usize).saturating_sub(21);
-                    if key_idx < NUM_KEYS {
-                        let mut voices = shared.voices.lock().unwrap();
-                        if let Some(v) = voices[key_idx].as_mut() {
-                            v.fade_out_active = true;
-                            v.fade_out_pos = 0;
-                        }
-                        voices_changed = true;
+                NoteEvent::NoteOff { voice_id, channel, note, .. } => {
+                    // In mono mode this may hand the voice back to a held key.
+                    if let Some(note) = self.voices.note_off(note as usize, channel, voice_id) {
+                        engine.start_note(note, glide_samples);
                     }
+                    voices_changed = true;
+                }
+                NoteEvent::MidiCC { cc, value, .. } => match cc {
+                    SUSTAIN_CC => self.voices.set_sustain(value >= 0.5),
+                    SOSTENUTO_CC => self.voices.set_sostenuto(value >= 0.5),
+                    _ => {}
+                },
+                NoteEvent::MidiPitchBend { channel, value, .. } => {
+                    let range = if self.pitch_bends.is_member(channel) {
+                        self.synth_params.mpe_bend_range.value()
+                    } else {
+                        self.synth_params.pitch_bend_range.value()
+                    };
+                    self.pitch_bends.set(channel, value, range as f32);
+                }
+                // CLAP per-note tuning expression, in semitones.
+                NoteEvent::PolyTuning { voice_id, channel, note, tuning, .. } => {
+                    self.voices.set_tuning(note as usize, channel, voice_id, tuning);
                 }
                 _ => {}
             }
         }
-        // Wake the editor so the key highlight appears immediately.
-        if voices_changed {
-            crate::wake_editor();
-        }
-
-        // --- Mixdown all active voices into the output buffer with headroom ---
-        {
-            let mut voices = shared.voices.lock().unwrap();
-
-            for mut frame in buffer.iter_samples() {
-                // Count active voices this frame (cheap; keeps headroom stable)
-                let active_count = voices.iter().filter(|o| o.is_some()).count();
-                
-                // Per-voice scaling with safe loudness compensation
-                // Scale each voice down, then boost final mix carefully to avoid clipping
-                let (voice_gain, master_gain) = if active_count > 0 {
-                    let n = active_count as f32;
-                    // Each voice gets 1/N scaling to prevent clipping
-                    let voice_scaling = 0.8 / n;  // More conservative base scaling
-                    // Safer loudness compensation that won't exceed ±1.0
-                    let loudness_compensation = match active_count {
-                        1 => 1.0,   // Single voice: 0.8 * 1.0 = 0.8
-                        2 => 1.5,   // 2 voices: 0.4 * 1.5 = 0.6  
-                        3 => 2.0,   // 3 voices: 0.267 * 2.0 = 0.53
-                        4 => 2.4,   // 4 voices: 0.2 * 2.4 = 0.48
-                        5 => 2.8,   // 5 voices: 0.16 * 2.8 = 0.45
-                        _ => 3.0,   // 6+ voices: 0.133 * 3.0 = 0.4 max
-                    };
-                    (voice_scaling, loudness_compensation)
-                } else {
-                    (1.0, 1.0)
-                };
-
-                let mut mixed = 0.0f32;
-
-                for opt in voices.iter_mut() {
-                    if let Some(v) = opt.as_mut() {
-                        let len = v.buffer.len();
-                        if len == 0 {
-                            continue;
-                        }
-
-                        // One-shot playback: once the whole buffer has played,
-                        // begin a clean fade-out (holding the last sample) rather
-                        // than looping. Repeat mode keeps wrapping as before.
-                        if !repeat_playback && v.idx >= len && !v.fade_out_active {
-                            v.fade_out_active = true;
-                            v.fade_out_pos = 0;
-                        }
-
-                        let sample_idx = if repeat_playback {
-                            v.idx % len
-                        } else {
-                            v.idx.min(len - 1)
-                        };
-                        let mut s = v.buffer[sample_idx];
-
-                        // Apply per-voice scaling FIRST to prevent intermediate clipping
-                        s *= voice_gain;
-
-                        // Fade in
-                        if v.fade_in_active && v.fade_in_pos < fade_duration {
-                            let g = v.fade_in_pos as f32 / fade_duration as f32;
-                            s *= g;
-                            v.fade_in_pos += 1;
-                        } else {
-                            v.fade_in_active = false;
-                        }
-
-                        // Fade out
-                        if v.fade_out_active {
-                            if v.fade_out_pos < fade_duration {
-                                let g = 1.0 - (v.fade_out_pos as f32 / fade_duration as f32);
-                                s *= g;
-                                v.fade_out_pos += 1;
-                            } else {
-                                // Voice finished after fade; remove it
-                                *opt = None;
-                                continue;
-                            }
-                        }
 
-                        mixed += s;
-                        v.idx = v.idx.wrapping_add(1);
-                    }
-                }
+        // Bends apply per block: buffer voices change playback rate,
+        // streaming voices retune their oscillators.
+        self.voices.apply_pitch(&self.pitch_bends);
 
-                // Apply loudness compensation
-                mixed *= master_gain;
+        // Buffer voices follow re-renders of their key, so edits are heard.
+        for (key_idx, voice) in self.voices.buffered_mut() {
+            engine.refresh_voice_buffer(voice, key_idx);
+        }
 
-                // Final clamp - should rarely trigger now
-                mixed = mixed.clamp(-1.0, 1.0);
+        // --- Mixdown all active voices into the output buffer with headroom ---
+        // Streaming voices synthesise from the published grid snapshot.
+        if self.voices.any_streamed() {
+            let voices = &mut self.voices;
+            engine.with_stream_grid(|grid| {
+                mix_voices(buffer, voices, Some(grid), fade_duration, repeat_playback)
+            });
+        } else {
+            mix_voices(buffer, &mut self.voices, None, fade_duration, repeat_playback);
+        }
 
-                for (_, sample) in frame.iter_mut().enumerate() {
-                    *sample = mixed;
-                }
-            }
+        // Tell the host which of its notes ended, so it can free their IDs.
+        self.voices.drain_finished(|finished| {
+            context.send_event(NoteEvent::VoiceTerminated {
+                timing: 0,
+                voice_id: finished.voice_id,
+                channel: finished.channel,
+                note: finished.key as u8,
+            });
+        });
+
+        // Publish which keys sound, for the editor's key highlight.
+        for key_idx in 0..NUM_KEYS {
+            shared.set_voice_active(key_idx, self.voices.is_playing(key_idx));
+        }
+        // Wake the editor so the key highlight appears immediately.
+        if voices_changed {
+            crate::wake_editor();
         }
 
         ProcessStatus::Normal
@@ -253,13 +258,8 @@ impl Plugin for LeSynth {
                 let size_changed =
                     egui_ctx.memory(|m| m.data.get_temp::<egui::Vec2>(size_id)) != Some(screen_size);
 
-                // Poll voices (set off-thread by MIDI) to sustain frames while audible.
-                let has_active_voice = synth_compute_engine
-                    .shared_params
-                    .voices
-                    .lock()
-                    .map(|v| v.iter().any(|slot| slot.is_some()))
-                    .unwrap_or(true);
+                // Poll the audio thread's voice snapshot to sustain frames while audible.
+                let has_active_voice = synth_compute_engine.shared_params.any_voice_active();
 
                 let computation_active = synth_compute_engine
                     .shared_params
@@ -273,10 +273,13 @@ impl Plugin for LeSynth {
                     .unwrap_or(false);
 
                 // Register this context so off-thread events can wake the idle editor.
+                // Host-pushed analysis jobs run on the engine's worker, which wakes
+                // the editor when a result lands.
                 crate::register_editor_waker(egui_ctx.clone());
 
-                // Drain any host-pushed analysis job.
-                let pending_job = crate::claim_analysis_job();
+                // Curve params of a `.lesynth` file loaded since the last frame
+                // (here or through the FFI) can only reach the host from here.
+                synth_compute_engine.apply_pending_curves(setter);
 
                 // The reactive gate lives in our egui-baseview fork's `on_frame`; this
                 // closure only runs on frames that will render, so always build a full UI.
@@ -285,20 +288,15 @@ impl Plugin for LeSynth {
                 // Remember the built size.
                 egui_ctx.memory_mut(|m| m.data.insert_temp(size_id, screen_size));
 
-                // Sustain repaints while voices sound or buffers recompute.
-                if has_active_voice || computation_active {
-                    egui_ctx.request_repaint();
-                }
+                // An analysis running on the engine's worker reports progress.
+                let analysis_active = synth_compute_engine
+                    .shared_params
+                    .analysis_progress()
+                    .is_some();
 
-                // Run any host-pushed analysis and repaint to show the result.
-                if let Some(job) = pending_job {
-                    synth_compute_engine.analyze_and_load(
-                        &job.samples,
-                        job.sample_rate,
-                        job.base_freq,
-                        &job.contour,
-                        0,
-                    );
+                // Sustain repaints while voices sound, buffers recompute or an
+                // analysis runs.
+                if has_active_voice || computation_active || analysis_active {
                     egui_ctx.request_repaint();
                 }
 
@@ -306,7 +304,7 @@ impl Plugin for LeSynth {
                 let last_key_id_persist = egui::Id::new("last_pressed_key_persist");
 
                 let mut last_pressed_key: Option<usize> = None;
-                let mut last_pressed_key_persist: Option<usize> = Some(15);
+                let mut last_pressed_key_persist: Option<usize> = Some(36);
 
                 // The following params are changed when the window is resized
                 let (win_w, win_h) = synth_params.editor_state.size();
@@ -330,14 +328,12 @@ impl Plugin for LeSynth {
                         *mem.data.get_temp_mut_or_insert_with(last_key_id, || None);
                     last_pressed_key_persist = *mem
                         .data
-                        .get_temp_mut_or_insert_with(last_key_id_persist, || Some(15));
+                        .get_temp_mut_or_insert_with(last_key_id_persist, || Some(36));
                 });
                 egui::CentralPanel::default().show(egui_ctx, |ui| {
                         // Draw metallic background
                         draw_metallic_background(ui, window_width, window_height);
 
-                        // (Analysis jobs are claimed + run at the top of this closure.)
-
                         // Width available to section content once the card's
                         // horizontal inner margin is subtracted, so nothing
                         // overflows the consistent section borders.
@@ -345,7 +341,8 @@ impl Plugin for LeSynth {
                         let content_w = window_width - 2.0 * pad;
 
                         // ── Execution-mode switch ─────────────────────────────────
-                        let mut mode = synth_compute_engine.shared_params.execution_mode();
+                        let shown_mode = synth_compute_engine.shared_params.execution_mode();
+                        let mut mode = shown_mode;
                         section(ui, "Mode", |ui| {
                             ui.horizontal(|ui| {
                                 if ui
@@ -360,9 +357,36 @@ impl Plugin for LeSynth {
                                 {
                                     mode = ExecutionMode::Analysis;
                                 }
+                                ui.separator();
+                                let render_mode = synth_compute_engine.shared_params.render_mode();
+                                for (choice, label, hint) in [
+                                    (RenderMode::Periodic, "Periodic", "Render each bucket as its own period"),
+                                    (
+                                        RenderMode::Continuous,
+                                        "Continuous",
+                                        "Run each harmonic as one oscillator across the note, \
+                                         gliding between buckets (no zipper noise)",
+                                    ),
+                                ] {
+                                    if ui
+                                        .selectable_label(render_mode == choice, label)
+                                        .on_hover_text(hint)
+                                        .clicked()
+                                    {
+                                        synth_compute_engine.set_render_mode(choice);
+                                    }
+                                }
+                                ui.separator();
+                                draw_file_controls(ui, &synth_compute_engine);
                             });
                         });
-                        synth_compute_engine.shared_params.set_execution_mode(mode);
+                        if mode != shown_mode {
+                            synth_compute_engine.shared_params.set_execution_mode(mode);
+                            synth_compute_engine.persist_analysis_state();
+                        } else {
+                            // A file loaded from the same row may have switched it.
+                            mode = synth_compute_engine.shared_params.execution_mode();
+                        }
                         ui.add_space(10.0);
 
                         // Whether analysed input audio is loaded. When it is, the grid
@@ -379,23 +403,12 @@ impl Plugin for LeSynth {
 
                         let params_changed_action = || {
                             synth_compute_engine.set_normalization_needed(true);
+                            // Playing voices pick up their key's re-rendered
+                            // buffer on the audio thread (streaming voices
+                            // already follow the live grid).
 
-                            // Rebuild buffers for currently active voices so changes are audible immediately
-                            {
-                                let shared = &synth_compute_engine.shared_params;
-                                let mut voices = shared.voices.lock().unwrap();
-                                for (key_idx, slot) in voices.iter_mut().enumerate() {
-                                    if let Some(v) = slot.as_mut() {
-                                        let buf = synth_compute_engine
-                                            .get_buffer_for_key(key_idx);
-                                        v.buffer = buf;
-                                        // keep current idx and fade states
-                                    }
-                                }
-                            }
-
-                            // Update assembled chart with key 24 for immediate preview
-                            synth_compute_engine.update_assembled_chart_with_key24();
+                            // Update assembled chart with the preview key for immediate preview
+                            synth_compute_engine.update_assembled_chart_preview();
                         };
 
                         // Keep original structure but make it responsive, wrapped
@@ -581,6 +594,8 @@ impl Plugin for LeSynth {
                                             draw_analysis_controls(
                                                 ui,
                                                 &synth_compute_engine,
+                                                &synth_params,
+                                                setter,
                                                 content_w - 12.0,
                                                 window_height,
                                             );
@@ -592,6 +607,81 @@ impl Plugin for LeSynth {
 
                         // ── Keyboard ──────────────────────────────────────────────
                         section(ui, "Keyboard", |ui| {
+                            // Velocity response (host-automatable).
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new("Velocity curve:").color(egui::Color32::WHITE))
+                                    .on_hover_text("0 ignores velocity, 1 is linear, higher favours hard hits");
+                                ui.add(ParamSlider::for_param(&synth_params.velocity_curve, setter));
+                                ui.label(egui::RichText::new("Velocity brightness:").color(egui::Color32::WHITE))
+                                    .on_hover_text("How much darker softer notes sound");
+                                ui.add(ParamSlider::for_param(&synth_params.velocity_tilt, setter));
+                                ui.label(egui::RichText::new("A4:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Reference pitch every key is tuned from");
+                                ui.add(ParamSlider::for_param(&synth_params.reference_pitch, setter));
+                            });
+                            // Pitch bend and MPE (host-automatable).
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new("Bend range:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Semitones a full pitch bend moves notes either way");
+                                ui.add(ParamSlider::for_param(&synth_params.pitch_bend_range, setter));
+                                ui.label(egui::RichText::new("MPE:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Channel 1 bends every note, channels 2-16 only their own");
+                                ui.add(ParamSlider::for_param(&synth_params.mpe, setter));
+                                ui.label(egui::RichText::new("MPE bend range:").color(egui::Color32::WHITE));
+                                ui.add(ParamSlider::for_param(&synth_params.mpe_bend_range, setter));
+                            });
+                            // Polyphony (host-automatable).
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new("Max voices:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Most voices sounding at once; further notes steal one");
+                                ui.add(ParamSlider::for_param(&synth_params.max_polyphony, setter));
+                                ui.label(egui::RichText::new("Steal:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Released voices go first, then the oldest or quietest");
+                                ui.add(ParamSlider::for_param(&synth_params.voice_stealing, setter));
+                            });
+                            // Mono mode for leads and bass lines.
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new("Mono:").color(egui::Color32::WHITE))
+                                    .on_hover_text("One voice; the last key held sounds, releasing it returns to the previous one");
+                                ui.add(ParamSlider::for_param(&synth_params.mono, setter));
+                                ui.label(egui::RichText::new("Legato:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Overlapping notes carry the voice on instead of restarting it");
+                                ui.add(ParamSlider::for_param(&synth_params.legato, setter));
+                                ui.label(egui::RichText::new("Glide:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Portamento time from the previous note's pitch");
+                                ui.add(ParamSlider::for_param(&synth_params.portamento_time, setter));
+                            });
+                            // Amplitude envelope of every note.
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new("A:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Attack time");
+                                ui.add(ParamSlider::for_param(&synth_params.attack_time, setter));
+                                ui.label(egui::RichText::new("D:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Decay time");
+                                ui.add(ParamSlider::for_param(&synth_params.decay_time, setter));
+                                ui.label(egui::RichText::new("S:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Sustain level");
+                                ui.add(ParamSlider::for_param(&synth_params.sustain_level, setter));
+                                ui.label(egui::RichText::new("R:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Release time");
+                                ui.add(ParamSlider::for_param(&synth_params.release_time, setter));
+                                ui.label(egui::RichText::new("Curve:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Shape of the attack, decay and release");
+                                ui.add(ParamSlider::for_param(&synth_params.envelope_curve, setter));
+                                ui.label(egui::RichText::new("Tail:").color(egui::Color32::WHITE))
+                                    .on_hover_text("Analysis mode: released notes play out the analysed sound's decaying tail");
+                                ui.add(ParamSlider::for_param(&synth_params.release_tail, setter));
+                            });
+                            // Scala microtuning (persisted with the project).
+                            ui.horizontal(|ui| {
+                                ui.add_space(10.0);
+                                draw_tuning_controls(ui, &synth_compute_engine);
+                            });
                             let input = ui.input(|i| i.clone());
                             let gutter = 10.0;
                             draw_piano_keyboard(
@@ -737,6 +827,23 @@ impl Plugin for LeSynth {
     }
 }
 
+/// Mix every active voice into `buffer` (mono, copied to all channels), with
+/// the shared headroom scaling. Streaming voices read `grid`.
+fn mix_voices(
+    buffer: &mut Buffer,
+    voices: &mut VoicePool,
+    grid: Option<&GridView>,
+    fade_duration: usize,
+    repeat_playback: bool,
+) {
+    for mut frame in buffer.iter_samples() {
+        let mixed = voices.next_mix_sample(grid, fade_duration, repeat_playback);
+        for sample in frame.iter_mut() {
+            *sample = mixed;
+        }
+    }
+}
+
 impl ClapPlugin for LeSynth {
     const CLAP_ID: &'static str = "com.hlavnicka.lesynth";
     const CLAP_DESCRIPTION: Option<&'static str> = Some("A LeSynth plugin");
@@ -0,0 +1,122 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SFZ multisample mapping, so a patch can be played in any SFZ sampler.
+//!
+//! The engine renders one WAV per sampled key (see
+//! `SynthComputeEngine::export_sfz`); this module decides which keys are
+//! sampled, which key range each sample covers, and writes the `.sfz` text.
+
+use crate::constants::NUM_KEYS;
+
+/// One `<region>`: a sample and the keys it plays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SfzRegion {
+    /// Sample path relative to the `.sfz` file.
+    pub sample: String,
+    /// Key (MIDI note) the sample was rendered at.
+    pub key: usize,
+    /// Lowest / highest key the region covers (inclusive).
+    pub lo_key: usize,
+    pub hi_key: usize,
+    /// Loop end (inclusive sample index) for a sample that loops from its
+    /// start, or `None` for a one-shot sample.
+    pub loop_end: Option<usize>,
+}
+
+/// Keys to sample when taking every `step`-th key, each with the key range
+/// it covers: every key up to the midpoint towards the next sampled key, so
+/// no key is transposed by more than half a step. `step == 1` samples every
+/// key; `0` is treated as 1.
+pub fn sampled_key_ranges(step: usize) -> Vec<(usize, usize, usize)> {
+    let keys: Vec<usize> = (0..NUM_KEYS).step_by(step.max(1)).collect();
+    let mut ranges = Vec::with_capacity(keys.len());
+    let mut lo = 0;
+    for (i, &key) in keys.iter().enumerate() {
+        let hi = match keys.get(i + 1) {
+            Some(&next) => (key + next) / 2,
+            None => NUM_KEYS - 1,
+        };
+        ranges.push((key, lo, hi));
+        lo = hi + 1;
+    }
+    ranges
+}
+
+/// The `.sfz` text for `regions`. `fade_secs` becomes the amp envelope's
+/// attack and release, matching the plugin's note fade-in / fade-out.
+pub fn build_sfz(regions: &[SfzRegion], fade_secs: f32) -> String {
+    let mut out = String::new();
+    out.push_str("// LeSynth Fourier multisample export\n");
+    out.push_str(&format!(
+        "<global> ampeg_attack={:.4} ampeg_release={:.4}\n",
+        fade_secs, fade_secs
+    ));
+    for region in regions {
+        out.push_str(&format!(
+            "<region> sample={} lokey={} hikey={} pitch_keycenter={}",
+            region.sample,
+            region.lo_key,
+            region.hi_key,
+            region.key
+        ));
+        match region.loop_end {
+            Some(end) => out.push_str(&format!(" loop_mode=loop_continuous loop_start=0 loop_end={}\n", end)),
+            None => out.push_str(" loop_mode=no_loop\n"),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_maps_to_itself() {
+        let ranges = sampled_key_ranges(1);
+        assert_eq!(ranges.len(), NUM_KEYS);
+        assert!(ranges.iter().all(|&(k, lo, hi)| k == lo && k == hi));
+    }
+
+    #[test]
+    fn stepped_ranges_tile_the_keyboard_without_gaps() {
+        for step in [0, 3, 5, 12, 100] {
+            let ranges = sampled_key_ranges(step);
+            assert_eq!(ranges.first().map(|r| r.1), Some(0));
+            assert_eq!(ranges.last().map(|r| r.2), Some(NUM_KEYS - 1));
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].2 + 1, pair[1].1, "step {}", step);
+            }
+            for &(key, lo, hi) in &ranges {
+                assert!(lo <= key && key <= hi);
+            }
+        }
+        assert_eq!(sampled_key_ranges(3)[..2], [(0, 0, 1), (3, 2, 4)]);
+    }
+
+    #[test]
+    fn sfz_text_uses_midi_notes_and_loops() {
+        let regions = [
+            SfzRegion { sample: "a.wav".into(), key: 21, lo_key: 21, hi_key: 22, loop_end: None },
+            SfzRegion { sample: "b.wav".into(), key: 69, lo_key: 68, hi_key: 70, loop_end: Some(99) },
+        ];
+        let text = build_sfz(&regions, 0.0029);
+        assert!(text.contains("<global> ampeg_attack=0.0029 ampeg_release=0.0029"));
+        assert!(text.contains("sample=a.wav lokey=21 hikey=22 pitch_keycenter=21 loop_mode=no_loop"));
+        assert!(text.contains(
+            "sample=b.wav lokey=68 hikey=70 pitch_keycenter=69 loop_mode=loop_continuous loop_start=0 loop_end=99"
+        ));
+    }
+}
@@ -12,10 +12,85 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+use crate::constants::TWO_PI;
+use crate::engine::oscillator::{GridView, OscillatorBank};
+use crate::params::{EnvelopeCurve, VoiceStealing};
+
 #[derive(Clone)]
 pub struct Voice {
-    pub buffer: Vec<f32>,
+    /// Key buffer being played, shared with the engine; `None` while it is
+    /// still being rendered (the voice is silent until it lands) and for
+    /// streaming voices.
+    pub buffer: Option<Arc<[f32]>>,
+    /// Oscillators of a streaming voice, synthesising from the live grid
+    /// instead of `buffer`. Kept (and reset in place) between notes.
+    pub bank: OscillatorBank,
+    pub streamed: bool,
+    /// Velocity layer `buffer` was rendered from (0 = the live grid), so
+    /// re-renders of that layer reach the note.
+    pub layer: usize,
+    /// Buffer of a second velocity layer (`blend_layer`), crossfaded in at
+    /// `layer_mix` (0..1) when the note's velocity falls where two layers
+    /// overlap; `None` otherwise.
+    pub blend_buffer: Option<Arc<[f32]>>,
+    pub blend_layer: usize,
+    pub layer_mix: f32,
+    /// Level from the note-on velocity (see [`velocity_gain`]).
+    pub velocity_gain: f32,
+    /// Velocity tilt (see [`velocity_darkness`]), applied by a one-pole
+    /// low-pass at the note's fundamental with coefficient `tilt_coeff`.
+    /// Rather than weighting each harmonic of the grid, the filter blends
+    /// the note towards its low-passed self: harmonic `n` ends up scaled by
+    /// about `|1 - d + d / (1 + i·n)|`, falling ~6 dB per octave at `d = 1`.
+    /// That holds well below Nyquist; the digital pole flattens off near it.
+    /// One filter per voice costs the same for streamed and buffer voices and
+    /// keeps key buffers shared across velocities.
+    pub darkness: f32,
+    tilt_coeff: f32,
+    tilt_state: f32,
+    /// MIDI channel the note came in on, whose pitch bend it follows.
+    pub channel: u8,
+    /// Per-note tuning offset (semitones) from a host note expression.
+    pub tuning: f32,
+    /// Bend plus tuning (semitones) last set by [`Self::set_pitch`].
+    pitch: f32,
+    /// Portamento still to go (semitones from the note's pitch), shrinking
+    /// by `glide_step` each sample.
+    glide: f32,
+    glide_step: f32,
+    /// Playback rate that brings the key's whole-sample period to its exact
+    /// pitch (see `SharedParams::period_rate`), folded into `rate`.
+    period_rate: f64,
+    /// Buffer samples advanced per output sample: a buffer voice is bent by
+    /// playing it faster or slower.
+    rate: f64,
+    /// Fractional part of the buffer position (`idx` is the whole part).
+    frac: f64,
+    /// Smoothed output level, for stealing the quietest voice.
+    level: f32,
     pub idx: usize,
+    /// Amplitude envelope: its attack and decay run while `fade_in_active`,
+    /// its release while `fade_out_active`, each at least the fade duration
+    /// long so notes never click.
+    pub envelope: Adsr,
+    /// Where the note's decaying tail starts, as a fraction of its length
+    /// (Analysis mode's release tail): a released voice crossfades there and
+    /// plays it out instead of the envelope release.
+    pub release_tail: Option<f32>,
+    /// Envelope level of the last sample, which a release starts from.
+    env_level: f32,
+    release_from: f32,
+    /// Release length (samples) of the running fade-out, below the fade
+    /// duration for a plain fade.
+    release_len: usize,
+    /// Playing the release tail. For its first `tail_fade` samples it
+    /// crossfades from the position the note was released at
+    /// (`from_idx` / `from_frac`).
+    in_tail: bool,
+    tail_fade: usize,
+    from_idx: usize,
+    from_frac: f64,
     pub fade_in_active: bool,
     pub fade_in_pos: usize,
     pub fade_out_active: bool,
@@ -23,10 +98,52 @@ pub struct Voice {
 }
 
 impl Voice {
-    pub fn new(buffer: Vec<f32>) -> Self {
+    pub fn new(buffer: Arc<[f32]>) -> Self {
+        Self::with_source(Some(buffer), OscillatorBank::with_capacity(0), false)
+    }
+
+    /// A voice synthesised on the fly by `bank`; see [`Self::next_streamed_sample`].
+    pub fn streaming(bank: OscillatorBank) -> Self {
+        Self::with_source(None, bank, true)
+    }
+
+    /// A silent voice with room for `harmonics` oscillators, for a [`VoicePool`].
+    fn idle(harmonics: usize) -> Self {
+        Self::with_source(None, OscillatorBank::with_capacity(harmonics), false)
+    }
+
+    fn with_source(buffer: Option<Arc<[f32]>>, bank: OscillatorBank, streamed: bool) -> Self {
         Self {
             buffer,
+            bank,
+            streamed,
+            layer: 0,
+            blend_buffer: None,
+            blend_layer: 0,
+            layer_mix: 0.0,
+            velocity_gain: 1.0,
+            darkness: 0.0,
+            tilt_coeff: 1.0,
+            tilt_state: 0.0,
+            channel: 0,
+            tuning: 0.0,
+            pitch: 0.0,
+            glide: 0.0,
+            glide_step: 0.0,
+            period_rate: 1.0,
+            rate: 1.0,
+            frac: 0.0,
+            level: 0.0,
             idx: 0,
+            envelope: Adsr::default(),
+            release_tail: None,
+            env_level: 0.0,
+            release_from: 0.0,
+            release_len: 0,
+            in_tail: false,
+            tail_fade: 0,
+            from_idx: 0,
+            from_frac: 0.0,
             fade_in_active: true,
             fade_in_pos: 0,
             fade_out_active: false,
@@ -34,14 +151,838 @@ impl Voice {
         }
     }
 
+    /// Rewind to the start of a new note (fading in); the caller points the
+    /// voice at its buffer or resets its oscillators.
+    pub fn restart(&mut self) {
+        self.tilt_state = 0.0;
+        self.tuning = 0.0;
+        self.pitch = 0.0;
+        self.glide = 0.0;
+        self.glide_step = 0.0;
+        self.rate = 1.0;
+        self.frac = 0.0;
+        self.level = 0.0;
+        self.idx = 0;
+        self.env_level = 0.0;
+        self.in_tail = false;
+        self.fade_in_active = true;
+        self.fade_in_pos = 0;
+        self.fade_out_active = false;
+        self.fade_out_pos = 0;
+    }
+
+    /// Set the note's velocity response: level `gain` and spectral tilt
+    /// `darkness`, the latter relative to a fundamental of `base_period`
+    /// samples.
+    pub fn set_velocity(&mut self, gain: f32, darkness: f32, base_period: usize) {
+        self.velocity_gain = gain;
+        self.darkness = darkness;
+        self.tilt_coeff = 1.0 - (-TWO_PI / base_period.max(1) as f32).exp();
+    }
+
+    /// Bend the note by `semitones`: a buffer voice plays its buffer at the
+    /// matching rate (its timeline stretches with it), a streaming voice
+    /// retunes its oscillators.
+    pub fn set_pitch(&mut self, semitones: f32) {
+        self.pitch = semitones;
+        self.update_rate();
+    }
+
+    /// Glide (portamento) to the note's pitch from `semitones` away, over
+    /// `samples` samples; at once below one sample. A glide already under
+    /// way carries on from where it is, so a legato note change moves on
+    /// from the pitch sounding now.
+    pub fn glide_from(&mut self, semitones: f32, samples: f32) {
+        self.glide = if samples >= 1.0 { self.glide + semitones } else { 0.0 };
+        self.glide_step = self.glide.abs() / samples.max(1.0);
+        self.update_rate();
+    }
+
+    /// Correct the key's whole-sample period to its exact pitch by playing
+    /// it `period_rate` times as fast; see `SharedParams::period_rate`.
+    pub fn set_period_rate(&mut self, period_rate: f64) {
+        self.period_rate = period_rate;
+        self.update_rate();
+    }
+
+    fn update_rate(&mut self) {
+        let ratio = 2f64.powf((self.pitch + self.glide) as f64 / 12.0) * self.period_rate;
+        self.rate = ratio;
+        if self.streamed {
+            self.bank.set_pitch(ratio);
+        }
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        self.streamed
+    }
+
     pub fn is_fading(&self) -> bool {
         self.fade_in_active || self.fade_out_active
     }
 
+    /// Released: fading out or playing its release tail.
+    pub fn is_released(&self) -> bool {
+        self.fade_out_active || self.in_tail
+    }
+
+    /// Fade out from the current envelope level over the fade duration.
     pub fn start_fade_out(&mut self) {
         self.fade_out_active = true;
         self.fade_out_pos = 0;
+        self.release_from = self.env_level;
+        self.release_len = 0;
+    }
+
+    /// Length of a buffer voice's note: its buffer's or, while that is still
+    /// being rendered, its blend layer's; 0 with neither.
+    fn timeline_len(&self) -> usize {
+        match self.buffer.as_deref().filter(|b| !b.is_empty()) {
+            Some(buffer) => buffer.len(),
+            None => self.blend_buffer.as_deref().map_or(0, <[f32]>::len),
+        }
+    }
+
+    /// Play `buffer`, crossfaded with `blend_buffer`, on from the same point
+    /// of the note: a position in a note of another length is rescaled to it.
+    pub fn set_buffers(&mut self, buffer: Option<Arc<[f32]>>, blend_buffer: Option<Arc<[f32]>>) {
+        let old_len = self.timeline_len();
+        self.buffer = buffer;
+        self.blend_buffer = blend_buffer;
+        let new_len = self.timeline_len();
+        if old_len > 0 && new_len > 0 && new_len != old_len {
+            let scale = new_len as f64 / old_len as f64;
+            (self.idx, self.frac) = rescale_position(self.idx, self.frac, scale);
+            (self.from_idx, self.from_frac) = rescale_position(self.from_idx, self.from_frac, scale);
+        }
+    }
+
+    /// Release the note: it fades out over the envelope's release or, with a
+    /// [`release_tail`](Self::release_tail), skips ahead to the tail (unless
+    /// it is already there) and plays it out once.
+    pub fn release(&mut self) {
+        let len = if self.streamed { self.bank.len() } else { self.timeline_len() };
+        let Some(tail) = self.release_tail.filter(|_| len > 0) else {
+            self.start_fade_out();
+            self.release_len = self.envelope.release;
+            return;
+        };
+        let start = (tail.clamp(0.0, 1.0) * len as f32) as usize;
+        let pos = self.idx % len;
+        if pos < start {
+            self.from_idx = self.idx;
+            self.from_frac = self.frac;
+            self.tail_fade = 0;
+            self.idx = start;
+        } else {
+            self.tail_fade = usize::MAX;
+            self.idx = pos;
+        }
+        if self.streamed {
+            self.bank.seek(self.idx);
+        }
+        self.in_tail = true;
+    }
+
+    /// Advance the voice by one sample and return its contribution to the mix
+    /// (scaled by `voice_gain` and its envelope), or `None` once its
+    /// fade-out has finished and it should be removed. Without any buffer
+    /// the voice contributes silence until it is released.
+    pub fn next_sample(&mut self, voice_gain: f32, fade_duration: usize, repeat_playback: bool) -> Option<f32> {
+        let len = self.timeline_len();
+        if len == 0 {
+            return if self.is_released() { None } else { Some(0.0) };
+        }
+        // The release tail plays once, to the end of the note.
+        let repeat = repeat_playback && !self.in_tail;
+        let mut raw = self.source(self.idx, self.frac, repeat);
+        if self.in_tail && self.tail_fade < fade_duration {
+            let from = self.source(self.from_idx, self.from_frac, repeat_playback);
+            raw = from + (raw - from) * (self.tail_fade as f32 / fade_duration as f32);
+            self.tail_fade += 1;
+            let advanced = self.from_frac + self.rate;
+            self.from_idx = self.from_idx.wrapping_add(advanced as usize);
+            self.from_frac = advanced.fract();
+        }
+        self.shape(raw, len, self.rate, voice_gain, fade_duration, repeat)
+    }
+
+    /// The voice's buffer, crossfaded with its second velocity layer, at
+    /// position `idx + frac` of the note. Layers of different lengths are read
+    /// at the same fraction of their length, so they stay in step and end
+    /// together. Until the buffer lands the blend layer plays alone; one of
+    /// the two is non-empty.
+    fn source(&self, idx: usize, frac: f64, repeat_playback: bool) -> f32 {
+        let blend = self.blend_buffer.as_deref().filter(|b| !b.is_empty());
+        let Some(buffer) = self.buffer.as_deref().filter(|b| !b.is_empty()) else {
+            return blend.map_or(0.0, |other| Self::read(other, idx, frac, repeat_playback));
+        };
+        let raw = Self::read(buffer, idx, frac, repeat_playback);
+        match blend {
+            Some(other) => {
+                let scale = other.len() as f64 / buffer.len() as f64;
+                let (idx, frac) = rescale_position(idx, frac, scale);
+                raw + self.layer_mix * (Self::read(other, idx, frac, repeat_playback) - raw)
+            }
+            None => raw,
+        }
+    }
+
+    /// `buffer` (non-empty) at position `idx + frac`, linearly interpolated.
+    fn read(buffer: &[f32], idx: usize, frac: f64, repeat_playback: bool) -> f32 {
+        let len = buffer.len();
+        let a = buffer[Self::buffer_index(idx, len, repeat_playback)];
+        if frac == 0.0 {
+            return a;
+        }
+        let b = buffer[Self::buffer_index(idx.wrapping_add(1), len, repeat_playback)];
+        a + (b - a) * frac as f32
+    }
+
+    /// Position `idx` in a buffer of `len > 0` samples: wrapped when
+    /// repeating, held on the last sample otherwise.
+    fn buffer_index(idx: usize, len: usize, repeat_playback: bool) -> usize {
+        if repeat_playback {
+            idx % len
+        } else {
+            idx.min(len - 1)
+        }
+    }
+
+    /// [`Self::next_sample`] for a streaming voice: the sample is synthesised
+    /// from `grid` by the voice's oscillators, so grid edits are heard at
+    /// once. Same envelope, gain and one-shot/repeat behaviour (a release
+    /// tail jumps without a crossfade, the oscillators running on); a buffer
+    /// voice plays its buffer.
+    pub fn next_streamed_sample(
+        &mut self,
+        grid: &GridView,
+        voice_gain: f32,
+        fade_duration: usize,
+        repeat_playback: bool,
+    ) -> Option<f32> {
+        if !self.streamed {
+            return self.next_sample(voice_gain, fade_duration, repeat_playback);
+        }
+        let len = self.bank.len();
+        if len == 0 {
+            return if self.is_released() { None } else { Some(0.0) };
+        }
+        let repeat = repeat_playback && !self.in_tail;
+        let raw = self.bank.next_sample(grid, repeat);
+        self.shape(raw, len, 1.0, voice_gain, fade_duration, repeat)
+    }
+
+    /// Apply gain and the envelope to the raw sample at the voice's position
+    /// in a note of `len` samples, and advance it by `step` samples.
+    fn shape(
+        &mut self,
+        raw: f32,
+        len: usize,
+        step: f64,
+        voice_gain: f32,
+        fade_duration: usize,
+        repeat_playback: bool,
+    ) -> Option<f32> {
+        // One-shot playback: once the whole note has played, begin a clean
+        // fade-out (holding the last sample) rather than looping. Repeat mode
+        // keeps wrapping.
+        if !repeat_playback && self.idx >= len && !self.fade_out_active {
+            self.start_fade_out();
+        }
+
+        // Velocity tilt: blend towards the low-passed signal, darker the
+        // softer the note (see `darkness`).
+        let raw = if self.darkness > 0.0 {
+            self.tilt_state += self.tilt_coeff * (raw - self.tilt_state);
+            raw - self.darkness * (raw - self.tilt_state)
+        } else {
+            raw
+        };
+
+        // Apply per-voice scaling FIRST to prevent intermediate clipping
+        let mut s = raw * voice_gain * self.velocity_gain;
+
+        if self.fade_out_active {
+            // The release, from wherever the envelope was.
+            let release = self.release_len.max(fade_duration);
+            if self.fade_out_pos < release {
+                let t = self.fade_out_pos as f32 / release as f32;
+                s *= self.release_from * (1.0 - curve_progress(self.envelope.curve, t));
+                self.fade_out_pos += 1;
+            } else {
+                return None;
+            }
+        } else {
+            // Attack and decay, then the sustain level; a release tail holds
+            // the level it was released at.
+            if !self.in_tail {
+                if self.fade_in_active && self.fade_in_pos < self.envelope.sustain_at(fade_duration) {
+                    self.env_level = self.envelope.held_level(self.fade_in_pos, fade_duration);
+                    self.fade_in_pos += 1;
+                } else {
+                    self.fade_in_active = false;
+                    self.env_level = self.envelope.sustain;
+                }
+            }
+            s *= self.env_level;
+        }
+
+        self.level += 0.001 * (s.abs() - self.level);
+        let advanced = self.frac + step;
+        self.idx = self.idx.wrapping_add(advanced as usize);
+        self.frac = advanced.fract();
+        if self.glide != 0.0 {
+            self.glide = if self.glide > 0.0 {
+                (self.glide - self.glide_step).max(0.0)
+            } else {
+                (self.glide + self.glide_step).min(0.0)
+            };
+            self.update_rate();
+        }
+        Some(s)
+    }
+}
+
+/// Position `idx + frac` scaled by `scale`, split back into index and fraction.
+fn rescale_position(idx: usize, frac: f64, scale: f64) -> (usize, f64) {
+    let pos = (idx as f64 + frac) * scale;
+    (pos as usize, pos.fract())
+}
+
+/// A note's amplitude envelope: attack, decay and release times in samples,
+/// the sustain level (0..1) and the curve of each segment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    pub attack: usize,
+    pub decay: usize,
+    pub sustain: f32,
+    pub release: usize,
+    pub curve: EnvelopeCurve,
+}
+
+impl Default for Adsr {
+    /// Full level while held, with only the fades at either end.
+    fn default() -> Self {
+        Self { attack: 0, decay: 0, sustain: 1.0, release: 0, curve: EnvelopeCurve::Linear }
+    }
+}
+
+impl Adsr {
+    /// From the envelope params: times in ms at `sample_rate`.
+    pub fn from_millis(attack: f32, decay: f32, sustain: f32, release: f32, curve: EnvelopeCurve, sample_rate: f32) -> Self {
+        let samples = |ms: f32| (ms.max(0.0) * 0.001 * sample_rate) as usize;
+        Self {
+            attack: samples(attack),
+            decay: samples(decay),
+            sustain: sustain.clamp(0.0, 1.0),
+            release: samples(release),
+            curve,
+        }
+    }
+
+    /// Samples into a held note at which the decay ends, the attack lasting
+    /// at least `min_attack`.
+    fn sustain_at(&self, min_attack: usize) -> usize {
+        self.attack.max(min_attack) + self.decay
+    }
+
+    /// Level `pos` samples into a held note, before [`Self::sustain_at`].
+    fn held_level(&self, pos: usize, min_attack: usize) -> f32 {
+        let attack = self.attack.max(min_attack);
+        if pos < attack {
+            return curve_progress(self.curve, pos as f32 / attack as f32);
+        }
+        let t = (pos - attack) as f32 / self.decay.max(1) as f32;
+        1.0 - (1.0 - self.sustain) * curve_progress(self.curve, t)
+    }
+}
+
+/// How far (0..1) an envelope segment of `curve` has moved at `t` (0..1) of
+/// its length.
+fn curve_progress(curve: EnvelopeCurve, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match curve {
+        EnvelopeCurve::Linear => t,
+        EnvelopeCurve::Exponential => 1.0 - (1.0 - t).powi(3),
+        EnvelopeCurve::Swell => t * t * t,
+    }
+}
+
+/// Most voices an instance can sound at once; the max-polyphony param sets a
+/// limit up to it.
+pub const MAX_VOICES: usize = 64;
+
+/// Extra pool voices a stolen note fades out on, over the fade duration;
+/// past that many steals within one fade, a stolen note stops dead.
+const STEAL_FADE_VOICES: usize = 4;
+
+/// Channel of the notes played on the editor's keyboard: outside MIDI's
+/// 0..16, so host events never match them and they aren't reported back to
+/// the host when they finish.
+pub const EDITOR_CHANNEL: u8 = 16;
+
+/// What a pool voice is playing, besides its sound.
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    playing: bool,
+    key: usize,
+    channel: u8,
+    /// Host note ID, matched by note-offs and expressions that carry one.
+    voice_id: Option<i32>,
+    /// Note-on order, for stealing the oldest voice.
+    age: u64,
+    /// The key is still down; a released voice may ring on under a pedal.
+    held: bool,
+    /// Held when the sostenuto pedal went down, so it rings until it lifts.
+    sostenuto: bool,
+    /// Fading out after its voice was stolen; already reported finished.
+    stolen: bool,
+}
+
+impl Slot {
+    /// Whether this voice plays the note `key` / `channel` / `voice_id`
+    /// refers to: by note ID when the event has one, else by key and channel.
+    fn matches(&self, key: usize, channel: u8, voice_id: Option<i32>) -> bool {
+        self.playing
+            && match voice_id {
+                Some(id) => self.voice_id == Some(id),
+                None => self.key == key && self.channel == channel,
+            }
+    }
+}
+
+/// A note held down in mono mode, to return to when the notes played over
+/// it are released.
+#[derive(Debug, Clone, Copy)]
+struct HeldNote {
+    key: usize,
+    channel: u8,
+    voice_id: Option<i32>,
+    velocity: f32,
+}
+
+impl HeldNote {
+    /// Same rule as [`Slot::matches`].
+    fn matches(&self, key: usize, channel: u8, voice_id: Option<i32>) -> bool {
+        match voice_id {
+            Some(id) => self.voice_id == Some(id),
+            None => self.key == key && self.channel == channel,
+        }
+    }
+}
+
+/// A voice the pool (re)started for a note, to be pointed at it by
+/// `SynthComputeEngine::start_note`.
+pub struct NoteStart<'a> {
+    pub voice: &'a mut Voice,
+    pub key: usize,
+    pub velocity: f32,
+    /// Key the mono voice sounded before, for the portamento to glide from.
+    pub from_key: Option<usize>,
+    /// The voice carries on from `from_key` (legato) instead of restarting.
+    pub legato: bool,
+}
+
+/// A voice that finished playing, to report back to the host.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FinishedVoice {
+    pub voice_id: Option<i32>,
+    pub channel: u8,
+    pub key: usize,
+}
+
+/// The audio thread's voices: a fixed set, reused note after note, so
+/// starting, releasing and finishing notes never allocates. A key can sound
+/// several voices at once (a retriggered key keeps ringing); past the
+/// polyphony limit a note steals a voice. Nothing is freed here either: a
+/// finished voice lets go of its buffer, but the engine keeps replaced
+/// buffers alive until no voice holds them.
+///
+/// In mono mode the first voice plays every note with last-note priority:
+/// the held keys are stacked, and releasing the top one goes back to the
+/// one below.
+pub struct VoicePool {
+    voices: Vec<Voice>,
+    slots: Vec<Slot>,
+    num_keys: usize,
+    /// Voices notes can sound at once; the rest only fade out stolen notes.
+    num_voices: usize,
+    max_voices: usize,
+    stealing: VoiceStealing,
+    next_age: u64,
+    sustain: bool,
+    sostenuto: bool,
+    mono: bool,
+    legato: bool,
+    /// Keys held in mono mode, latest last.
+    held_notes: Vec<HeldNote>,
+    finished: Vec<FinishedVoice>,
+}
+
+impl VoicePool {
+    /// `num_voices` voices for keys `0..num_keys`, whose oscillator banks
+    /// have room for `max_harmonics`.
+    pub fn new(num_voices: usize, num_keys: usize, max_harmonics: usize) -> Self {
+        Self {
+            voices: (0..num_voices + STEAL_FADE_VOICES).map(|_| Voice::idle(max_harmonics)).collect(),
+            slots: vec![Slot::default(); num_voices + STEAL_FADE_VOICES],
+            num_keys,
+            num_voices,
+            max_voices: num_voices,
+            stealing: VoiceStealing::Oldest,
+            next_age: 0,
+            sustain: false,
+            sostenuto: false,
+            mono: false,
+            legato: true,
+            held_notes: Vec::with_capacity(num_keys),
+            finished: Vec::with_capacity(num_voices),
+        }
+    }
+
+    /// Sound at most `max_voices` voices, stealing by `stealing` beyond that.
+    pub fn configure(&mut self, max_voices: usize, stealing: VoiceStealing) {
+        self.max_voices = max_voices.clamp(1, self.num_voices);
+        self.stealing = stealing;
+    }
+
+    /// Switch mono mode on or off; with `legato`, a mono note played over a
+    /// sounding one carries its voice on instead of restarting it.
+    pub fn set_mono(&mut self, mono: bool, legato: bool) {
+        if !mono {
+            self.held_notes.clear();
+        }
+        self.mono = mono;
+        self.legato = legato;
+    }
+
+    /// A note-on: [`Self::start`] a voice for it, or in mono mode move the
+    /// mono voice over to it.
+    pub fn note_on(&mut self, key: usize, channel: u8, voice_id: Option<i32>, velocity: f32) -> Option<NoteStart<'_>> {
+        if !self.mono {
+            return self
+                .start(key, channel, voice_id)
+                .map(|voice| NoteStart { voice, key, velocity, from_key: None, legato: false });
+        }
+        if key >= self.num_keys {
+            return None;
+        }
+        if self.held_notes.len() == self.held_notes.capacity() {
+            self.held_notes.remove(0);
+        }
+        let note = HeldNote { key, channel, voice_id, velocity };
+        self.held_notes.push(note);
+        Some(self.play_mono(note))
+    }
+
+    /// Start a voice for a note on `key` and hand it back to be pointed at
+    /// its buffer or oscillators (see `SynthComputeEngine::start_voice`): a
+    /// free voice if the polyphony limit allows, else a stolen one.
+    pub fn start(&mut self, key: usize, channel: u8, voice_id: Option<i32>) -> Option<&mut Voice> {
+        if key >= self.num_keys {
+            return None;
+        }
+        let sounding = self.slots.iter().filter(|s| s.playing && !s.stolen).count();
+        let free = self.slots.iter().position(|s| !s.playing).filter(|_| sounding < self.max_voices);
+        let index = free.or_else(|| self.victim())?;
+        if self.slots[index].playing {
+            self.steal(index);
+        }
+        self.slots[index] = Slot {
+            playing: true,
+            key,
+            channel,
+            voice_id,
+            age: self.next_age,
+            held: true,
+            sostenuto: false,
+            stolen: false,
+        };
+        self.next_age += 1;
+        let voice = &mut self.voices[index];
+        voice.restart();
+        voice.channel = channel;
+        Some(voice)
+    }
+
+    /// Free voice `index` for a new note: its note ends for the host, and
+    /// fades out over the fade duration on a spare voice so it doesn't
+    /// click (or stops dead when none is spare).
+    fn steal(&mut self, index: usize) {
+        let Some(spare) = self.slots.iter().position(|s| !s.playing) else {
+            self.finish(index);
+            return;
+        };
+        self.report_finished(index);
+        self.voices.swap(index, spare);
+        self.slots[spare] = Slot { held: false, sostenuto: false, stolen: true, ..self.slots[index] };
+        self.slots[index].playing = false;
+        self.voices[spare].start_fade_out();
+    }
+
+    /// The voice a new note takes over: a released one first, then the
+    /// oldest or quietest.
+    fn victim(&self) -> Option<usize> {
+        let sounding = || self.slots.iter().enumerate().filter(|(_, s)| s.playing && !s.stolen);
+        let fading = sounding().filter(|&(i, _)| self.voices[i].is_released()).min_by_key(|(_, s)| s.age);
+        let stolen = match self.stealing {
+            VoiceStealing::Oldest => sounding().min_by_key(|(_, s)| s.age),
+            VoiceStealing::Quietest => {
+                sounding().min_by(|(a, _), (b, _)| self.voices[*a].level.total_cmp(&self.voices[*b].level))
+            }
+        };
+        fading.or(stolen).map(|(i, _)| i)
+    }
+
+    /// Point the mono voice at `note`, carrying it on if legato applies and
+    /// it still sounds, else restarting it.
+    fn play_mono(&mut self, note: HeldNote) -> NoteStart<'_> {
+        let previous = self.slots[0];
+        let from_key = previous.playing.then_some(previous.key);
+        let legato = self.legato && previous.playing && !self.voices[0].is_released();
+        if legato {
+            // The old note ends for the host even though its sound goes on.
+            self.report_finished(0);
+        } else if previous.playing {
+            self.steal(0);
+        }
+        self.slots[0] = Slot {
+            playing: true,
+            key: note.key,
+            channel: note.channel,
+            voice_id: note.voice_id,
+            age: self.next_age,
+            held: true,
+            sostenuto: false,
+            stolen: false,
+        };
+        self.next_age += 1;
+        let voice = &mut self.voices[0];
+        if legato {
+            voice.tuning = 0.0;
+        } else {
+            voice.restart();
+        }
+        voice.channel = note.channel;
+        NoteStart { voice, key: note.key, velocity: note.velocity, from_key, legato }
+    }
+
+    /// A note-off: its voices fade out, unless a pedal holds them. In mono
+    /// mode, releasing the sounding note while others are held returns the
+    /// voice to the latest of them, handed back to be pointed at it.
+    pub fn note_off(&mut self, key: usize, channel: u8, voice_id: Option<i32>) -> Option<NoteStart<'_>> {
+        if self.mono {
+            self.held_notes.retain(|n| !n.matches(key, channel, voice_id));
+            if self.slots[0].matches(key, channel, voice_id) && self.slots[0].held {
+                if let Some(&previous) = self.held_notes.last() {
+                    return Some(self.play_mono(previous));
+                }
+            }
+        }
+        for i in 0..self.slots.len() {
+            if self.slots[i].matches(key, channel, voice_id) && self.slots[i].held {
+                self.slots[i].held = false;
+                self.release_unless_pedalled(i);
+            }
+        }
+        None
+    }
+
+    /// Sustain pedal (CC64): while down, released notes ring on.
+    pub fn set_sustain(&mut self, down: bool) {
+        self.sustain = down;
+        if !down {
+            (0..self.slots.len()).for_each(|i| self.release_unless_pedalled(i));
+        }
+    }
+
+    /// Sostenuto pedal (CC66): notes held as it goes down ring on until it
+    /// lifts; later notes are unaffected.
+    pub fn set_sostenuto(&mut self, down: bool) {
+        if down == self.sostenuto {
+            return;
+        }
+        self.sostenuto = down;
+        for i in 0..self.slots.len() {
+            let slot = &mut self.slots[i];
+            slot.sostenuto = down && slot.playing && slot.held;
+            if !down {
+                self.release_unless_pedalled(i);
+            }
+        }
+    }
+
+    fn release_unless_pedalled(&mut self, index: usize) {
+        let slot = self.slots[index];
+        if slot.playing && !slot.held && !slot.sostenuto && !self.sustain {
+            let voice = &mut self.voices[index];
+            if !voice.is_released() {
+                voice.release();
+            }
+        }
+    }
+
+    /// Shape every voice by `envelope`, so automating it reaches sounding
+    /// notes.
+    pub fn set_envelope(&mut self, envelope: Adsr) {
+        self.voices.iter_mut().for_each(|v| v.envelope = envelope);
+    }
+
+    /// Set the per-note tuning (semitones) of the note's voices.
+    pub fn set_tuning(&mut self, key: usize, channel: u8, voice_id: Option<i32>, semitones: f32) {
+        for (voice, slot) in self.voices.iter_mut().zip(&self.slots) {
+            if slot.matches(key, channel, voice_id) {
+                voice.tuning = semitones;
+            }
+        }
+    }
+
+    /// Bend every playing voice by its channel's bend plus its own tuning.
+    pub fn apply_pitch(&mut self, bends: &PitchBends) {
+        for (voice, _) in self.voices.iter_mut().zip(&self.slots).filter(|(_, s)| s.playing) {
+            voice.set_pitch(bends.for_channel(voice.channel) + voice.tuning);
+        }
+    }
+
+    /// Whether any voice sounds `key`.
+    pub fn is_playing(&self, key: usize) -> bool {
+        self.slots.iter().any(|s| s.playing && s.key == key)
+    }
+
+    pub fn any_streamed(&self) -> bool {
+        self.voices.iter().zip(&self.slots).any(|(v, s)| s.playing && v.streamed)
+    }
+
+    /// Playing voices that play a key buffer, with their key.
+    pub fn buffered_mut(&mut self) -> impl Iterator<Item = (usize, &mut Voice)> {
+        self.voices
+            .iter_mut()
+            .zip(&self.slots)
+            .filter(|(v, s)| s.playing && !v.streamed)
+            .map(|(v, s)| (s.key, v))
     }
+
+    /// Queue the note of voice `index` for [`Self::drain_finished`], unless
+    /// it came from the editor's keyboard.
+    fn report_finished(&mut self, index: usize) {
+        let slot = self.slots[index];
+        if slot.channel != EDITOR_CHANNEL {
+            self.finished.push(FinishedVoice { voice_id: slot.voice_id, channel: slot.channel, key: slot.key });
+        }
+    }
+
+    /// Hand every host note that finished since the last call to `f`.
+    pub fn drain_finished(&mut self, mut f: impl FnMut(FinishedVoice)) {
+        self.finished.drain(..).for_each(&mut f);
+    }
+
+    fn finish(&mut self, index: usize) {
+        if !self.slots[index].stolen {
+            self.report_finished(index);
+        }
+        self.slots[index].playing = false;
+        let voice = &mut self.voices[index];
+        voice.buffer = None;
+        voice.blend_buffer = None;
+    }
+
+    /// Mix one sample of every playing voice with the shared headroom
+    /// scaling. Streaming voices read `grid` (a buffer voice ignores it);
+    /// a voice whose release has finished stops playing.
+    pub fn next_mix_sample(&mut self, grid: Option<&GridView>, fade_duration: usize, repeat_playback: bool) -> f32 {
+        // Count sounding voices this frame (cheap; keeps headroom stable).
+        // A stolen note fading out doesn't count, or stealing would step the
+        // level of the note that took its place; a lone tail keeps the
+        // single-voice gain.
+        let active_count = self.slots.iter().filter(|s| s.playing && !s.stolen).count().max(1);
+        // Per-voice scaling with safe loudness compensation
+        let (voice_gain, master_gain) = mix_gains(active_count);
+
+        let mut mixed = 0.0f32;
+        for i in 0..self.voices.len() {
+            if !self.slots[i].playing {
+                continue;
+            }
+            let voice = &mut self.voices[i];
+            let next = match grid {
+                Some(grid) => voice.next_streamed_sample(grid, voice_gain, fade_duration, repeat_playback),
+                None => voice.next_sample(voice_gain, fade_duration, repeat_playback),
+            };
+            match next {
+                Some(s) => mixed += s,
+                // Voice finished after fade
+                None => self.finish(i),
+            }
+        }
+        // Apply loudness compensation; the final clamp should rarely trigger
+        (mixed * master_gain).clamp(-1.0, 1.0)
+    }
+}
+
+/// Pitch bend of each MIDI channel, in semitones. With `mpe` set, channel 1
+/// (index 0) is the MPE zone's master channel: its bend moves every note,
+/// while each member channel's bend moves only the notes on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PitchBends {
+    pub channels: [f32; 16],
+    pub mpe: bool,
+}
+
+impl PitchBends {
+    /// Record a pitch-bend message on `channel`: `value` in 0..1 (0.5 =
+    /// centre) over `range` semitones either way.
+    pub fn set(&mut self, channel: u8, value: f32, range: f32) {
+        if let Some(bend) = self.channels.get_mut(channel as usize) {
+            *bend = (value.clamp(0.0, 1.0) - 0.5) * 2.0 * range;
+        }
+    }
+
+    /// Whether `channel` is an MPE member channel, bent over the MPE range.
+    pub fn is_member(&self, channel: u8) -> bool {
+        self.mpe && channel != 0
+    }
+
+    /// Bend (semitones) of a note on `channel`.
+    pub fn for_channel(&self, channel: u8) -> f32 {
+        let own = self.channels.get(channel as usize).copied().unwrap_or(0.0);
+        if self.is_member(channel) {
+            own + self.channels[0]
+        } else {
+            own
+        }
+    }
+}
+
+/// Output level for a note-on `velocity` (0..1) through the velocity curve:
+/// `velocity^curve`, so a curve of 0 ignores velocity and 1 is linear.
+pub fn velocity_gain(velocity: f32, curve: f32) -> f32 {
+    velocity.clamp(0.0, 1.0).powf(curve.max(0.0))
+}
+
+/// How far a note of `velocity` is darkened by the velocity tilt `amount`
+/// (both 0..1): nothing at full velocity, `amount` at the softest.
+pub fn velocity_darkness(velocity: f32, amount: f32) -> f32 {
+    (amount * (1.0 - velocity.clamp(0.0, 1.0))).clamp(0.0, 1.0)
+}
+
+/// `(voice_gain, master_gain)` for a mix of `active_count` voices. Each voice
+/// is scaled down by 1/N so the sum can't clip, then the mix gets a loudness
+/// compensation chosen so the product never exceeds the 0.8 of a single voice.
+pub fn mix_gains(active_count: usize) -> (f32, f32) {
+    if active_count == 0 {
+        return (1.0, 1.0);
+    }
+    let voice_scaling = 0.8 / active_count as f32; // More conservative base scaling
+    let loudness_compensation = match active_count {
+        1 => 1.0, // Single voice: 0.8 * 1.0 = 0.8
+        2 => 1.5, // 2 voices: 0.4 * 1.5 = 0.6
+        3 => 2.0, // 3 voices: 0.267 * 2.0 = 0.53
+        4 => 2.4, // 4 voices: 0.2 * 2.4 = 0.48
+        5 => 2.8, // 5 voices: 0.16 * 2.8 = 0.45
+        _ => 3.0, // 6+ voices: 0.133 * 3.0 = 0.4 max
+    };
+    (voice_scaling, loudness_compensation)
 }
 
 #[cfg(test)]
@@ -51,9 +992,9 @@ mod tests {
     #[test]
     fn test_voice_new() {
         let buffer = vec![0.1, 0.2, 0.3, 0.4];
-        let voice = Voice::new(buffer.clone());
+        let voice = Voice::new(buffer.clone().into());
         
-        assert_eq!(voice.buffer, buffer);
+        assert_eq!(voice.buffer.as_deref(), Some(&buffer[..]));
         assert_eq!(voice.idx, 0);
         assert_eq!(voice.fade_in_active, true);
         assert_eq!(voice.fade_in_pos, 0);
@@ -63,7 +1004,7 @@ mod tests {
 
     #[test]
     fn test_voice_is_fading() {
-        let mut voice = Voice::new(vec![0.0; 10]);
+        let mut voice = Voice::new(vec![0.0; 10].into());
         
         // Initially fading in
         assert!(voice.is_fading());
@@ -81,7 +1022,7 @@ mod tests {
 
     #[test]
     fn test_voice_start_fade_out() {
-        let mut voice = Voice::new(vec![0.0; 5]);
+        let mut voice = Voice::new(vec![0.0; 5].into());
         
         assert!(!voice.fade_out_active);
         
@@ -91,9 +1032,430 @@ mod tests {
         assert_eq!(voice.fade_out_pos, 0);
     }
 
+    #[test]
+    fn test_next_sample_fades_in_plays_once_and_finishes() {
+        let mut voice = Voice::new(vec![1.0; 8].into());
+        let out: Vec<f32> = std::iter::from_fn(|| voice.next_sample(0.5, 4, false)).collect();
+        // 8 buffer samples, then a 4-sample fade-out holding the last one.
+        assert_eq!(out.len(), 12);
+        assert_eq!(&out[..5], &[0.0, 0.125, 0.25, 0.375, 0.5]);
+        assert_eq!(&out[8..], &[0.5, 0.375, 0.25, 0.125]);
+    }
+
+    #[test]
+    fn test_next_sample_repeat_wraps_until_released() {
+        let mut voice = Voice::new(vec![1.0, -1.0].into());
+        voice.fade_in_active = false;
+        let looped: Vec<f32> = (0..5).filter_map(|_| voice.next_sample(1.0, 2, true)).collect();
+        assert_eq!(looped, vec![1.0, -1.0, 1.0, -1.0, 1.0]);
+        voice.start_fade_out();
+        assert_eq!(voice.next_sample(1.0, 2, true), Some(-1.0));
+        assert_eq!(voice.next_sample(1.0, 2, true), Some(0.5));
+        assert_eq!(voice.next_sample(1.0, 2, true), None);
+    }
+
+    #[test]
+    fn test_streamed_voice_plays_once_then_fades() {
+        let ampl = vec![vec![1.0; 2]];
+        let phase = vec![vec![std::f32::consts::FRAC_PI_2; 2]]; // a cosine: 1.0 at cycle starts
+        let on = [true];
+        let grid = GridView { ampl: &ampl, phase: &phase, ampl_enabled: &on, phase_enabled: &on, ratios: &[] };
+        let mut voice = Voice::streaming(OscillatorBank::new(&grid, 4, 1, 8, 8.0));
+        assert!(voice.is_streamed() && voice.buffer.is_none());
+        let out: Vec<f32> =
+            std::iter::from_fn(|| voice.next_streamed_sample(&grid, 0.5, 2, false)).collect();
+        // 8 note samples, then a 2-sample fade-out.
+        assert_eq!(out.len(), 10);
+        assert_eq!(out[0], 0.0); // fade-in starts silent
+        assert!((out[4] - 0.5).abs() < 1e-5);
+        assert!((out[8] - 0.5).abs() < 1e-5 && (out[9] - 0.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_voice_without_buffer_is_silent_until_released() {
+        let mut voice = Voice::new(vec![1.0; 4].into());
+        voice.buffer = None;
+        assert_eq!(voice.next_sample(1.0, 2, true), Some(0.0));
+        voice.buffer = Some(vec![1.0; 4].into());
+        // The fade-in starts once the buffer lands.
+        assert_eq!(voice.next_sample(1.0, 2, true), Some(0.0));
+        assert_eq!(voice.next_sample(1.0, 2, true), Some(0.5));
+        voice.buffer = None;
+        voice.start_fade_out();
+        assert_eq!(voice.next_sample(1.0, 2, true), None);
+    }
+
+    #[test]
+    fn test_pool_reuses_voices_and_stops_finished_ones() {
+        let mut pool = VoicePool::new(4, 4, 8);
+        assert!(pool.start(4, 0, None).is_none());
+        let voice = pool.start(1, 0, Some(7)).unwrap();
+        voice.buffer = Some(vec![1.0; 3].into());
+        voice.fade_in_active = false;
+        assert!(pool.is_playing(1) && !pool.is_playing(0) && !pool.any_streamed());
+        assert_eq!(pool.buffered_mut().map(|(k, _)| k).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(pool.next_mix_sample(None, 2, true), 0.8);
+
+        pool.note_off(1, 0, None);
+        pool.note_off(2, 0, None); // not playing: no-op
+        let tail: Vec<f32> = (0..3).map(|_| pool.next_mix_sample(None, 2, true)).collect();
+        assert_eq!(tail, vec![0.8, 0.4, 0.0]);
+        assert!(!pool.is_playing(1));
+        assert!(pool.voices[0].buffer.is_none());
+        let mut finished = Vec::new();
+        pool.drain_finished(|f| finished.push(f));
+        assert_eq!(finished, vec![FinishedVoice { voice_id: Some(7), channel: 0, key: 1 }]);
+
+        // The freed voice is reused, rewound.
+        let voice = pool.start(1, 0, None).unwrap();
+        assert!(voice.fade_in_active && !voice.fade_out_active && voice.idx == 0);
+    }
+
+    /// A pool whose voices all play a steady buffer of `level`.
+    fn start_steady(pool: &mut VoicePool, key: usize, voice_id: Option<i32>, level: f32) {
+        let voice = pool.start(key, 0, voice_id).unwrap();
+        voice.buffer = Some(vec![level; 4].into());
+        voice.fade_in_active = false;
+    }
+
+    /// Keys of the sounding notes, leaving out stolen ones fading out.
+    fn keys_playing(pool: &VoicePool) -> Vec<usize> {
+        pool.slots.iter().filter(|s| s.playing && !s.stolen).map(|s| s.key).collect()
+    }
+
+    #[test]
+    fn test_retriggered_keys_ring_on_and_note_ids_pick_the_voice() {
+        let mut pool = VoicePool::new(4, 8, 8);
+        start_steady(&mut pool, 3, Some(1), 1.0);
+        start_steady(&mut pool, 3, Some(2), 1.0);
+        assert_eq!(keys_playing(&pool), vec![3, 3]);
+
+        pool.note_off(3, 0, Some(2));
+        assert!(!pool.voices[0].fade_out_active && pool.voices[1].fade_out_active);
+        // Without an ID every held voice of the key and channel is released.
+        start_steady(&mut pool, 3, None, 1.0);
+        pool.note_off(3, 1, None);
+        assert!(!pool.voices[2].fade_out_active, "other channel");
+        pool.note_off(3, 0, None);
+        assert!(pool.voices[0].fade_out_active && pool.voices[2].fade_out_active);
+    }
+
+    #[test]
+    fn test_polyphony_limit_steals_released_then_oldest_or_quietest() {
+        let mut pool = VoicePool::new(4, 8, 8);
+        pool.configure(2, VoiceStealing::Oldest);
+        start_steady(&mut pool, 1, None, 1.0);
+        start_steady(&mut pool, 2, None, 1.0);
+        start_steady(&mut pool, 3, None, 1.0);
+        assert_eq!(keys_playing(&pool), vec![3, 2], "the oldest voice went");
+        let mut stolen = Vec::new();
+        pool.drain_finished(|f| stolen.push(f.key));
+        assert_eq!(stolen, vec![1]);
+
+        pool.note_off(3, 0, None);
+        start_steady(&mut pool, 4, None, 1.0);
+        assert_eq!(keys_playing(&pool), vec![4, 2], "a released voice goes first");
+
+        pool.configure(2, VoiceStealing::Quietest);
+        pool.voices[1].buffer = Some(vec![0.1; 4].into());
+        (0..1000).for_each(|_| { pool.next_mix_sample(None, 2, true); });
+        start_steady(&mut pool, 5, None, 1.0);
+        assert_eq!(keys_playing(&pool), vec![4, 5], "the quieter voice went");
+    }
+
+    #[test]
+    fn test_stolen_note_fades_out_on_a_spare_voice() {
+        let mut pool = VoicePool::new(1, 8, 8);
+        start_steady(&mut pool, 1, Some(1), 1.0);
+        pool.next_mix_sample(None, 2, true);
+        start_steady(&mut pool, 2, Some(2), 0.0);
+        assert_eq!(keys_playing(&pool), vec![2]);
+        let mut ended = Vec::new();
+        pool.drain_finished(|f| ended.push(f.voice_id));
+        assert_eq!(ended, vec![Some(1)], "the stolen note ends for the host at once");
+
+        // Its sound ramps down over the fade instead of cutting off.
+        let tail: Vec<f32> = (0..3).map(|_| pool.next_mix_sample(None, 2, true)).collect();
+        assert!(tail[0] > 0.5 && tail[1] < tail[0] && tail[2] == 0.0, "{:?}", tail);
+        assert_eq!(pool.slots.iter().filter(|s| s.playing).count(), 1);
+        pool.drain_finished(|f| ended.push(f.voice_id));
+        assert_eq!(ended.len(), 1, "reported once");
+    }
+
+    #[test]
+    fn test_stealing_keeps_the_new_note_at_a_lone_voice_level() {
+        let mut lone = VoicePool::new(1, 8, 8);
+        start_steady(&mut lone, 2, None, 1.0);
+        let expected = lone.next_mix_sample(None, 8, true);
+
+        let mut pool = VoicePool::new(1, 8, 8);
+        start_steady(&mut pool, 1, None, 0.0);
+        pool.next_mix_sample(None, 8, true);
+        start_steady(&mut pool, 2, None, 1.0);
+        assert_eq!(pool.slots.iter().filter(|s| s.playing).count(), 2, "the stolen note is still fading");
+        assert_eq!(pool.next_mix_sample(None, 8, true), expected);
+    }
+
+    #[test]
+    fn test_editor_notes_are_not_reported_to_the_host() {
+        let mut pool = VoicePool::new(4, 8, 8);
+        let voice = pool.start(3, EDITOR_CHANNEL, None).unwrap();
+        voice.buffer = Some(vec![1.0; 4].into());
+        voice.fade_in_active = false;
+        pool.note_off(3, 0, None);
+        assert!(!pool.voices[0].is_released(), "host notes don't match it");
+        pool.note_off(3, EDITOR_CHANNEL, None);
+        (0..4).for_each(|_| { pool.next_mix_sample(None, 2, true); });
+        assert!(!pool.is_playing(3));
+        pool.drain_finished(|f| panic!("reported {:?}", f));
+    }
+
+    #[test]
+    fn test_sustain_and_sostenuto_hold_released_notes() {
+        let mut pool = VoicePool::new(4, 8, 8);
+        start_steady(&mut pool, 1, None, 1.0);
+        pool.set_sustain(true);
+        pool.note_off(1, 0, None);
+        assert!(!pool.voices[0].fade_out_active, "sustained");
+        pool.set_sustain(false);
+        assert!(pool.voices[0].fade_out_active);
+
+        // Sostenuto holds only the notes down as it is pressed.
+        start_steady(&mut pool, 2, None, 1.0);
+        pool.set_sostenuto(true);
+        start_steady(&mut pool, 3, None, 1.0);
+        pool.note_off(2, 0, None);
+        pool.note_off(3, 0, None);
+        assert!(!pool.voices[1].fade_out_active && pool.voices[2].fade_out_active);
+        pool.set_sostenuto(false);
+        assert!(pool.voices[1].fade_out_active);
+    }
+
+    #[test]
+    fn test_bent_voice_plays_its_buffer_faster() {
+        let mut voice = Voice::new(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0].into());
+        voice.fade_in_active = false;
+        voice.set_pitch(12.0);
+        let octave_up: Vec<f32> = (0..3).filter_map(|_| voice.next_sample(1.0, 2, true)).collect();
+        assert_eq!(octave_up, vec![0.0, 2.0, 4.0]);
+
+        voice.restart();
+        voice.fade_in_active = false;
+        voice.set_pitch(-12.0);
+        let octave_down: Vec<f32> = (0..4).filter_map(|_| voice.next_sample(1.0, 2, true)).collect();
+        assert_eq!(octave_down, vec![0.0, 0.5, 1.0, 1.5]);
+    }
+
+    #[test]
+    fn test_adsr_shapes_the_held_note_and_its_release() {
+        let mut voice = Voice::new(vec![1.0; 64].into());
+        voice.envelope = Adsr { attack: 4, decay: 4, sustain: 0.5, release: 4, curve: EnvelopeCurve::Linear };
+        let held: Vec<f32> = (0..10).filter_map(|_| voice.next_sample(1.0, 2, true)).collect();
+        assert_eq!(held, vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.875, 0.75, 0.625, 0.5, 0.5]);
+        voice.release();
+        let released: Vec<f32> = std::iter::from_fn(|| voice.next_sample(1.0, 2, true)).collect();
+        assert_eq!(released, vec![0.5, 0.375, 0.25, 0.125]);
+
+        // Attack and release never drop below the fade duration.
+        let mut voice = Voice::new(vec![1.0; 64].into());
+        assert_eq!(voice.next_sample(1.0, 2, true), Some(0.0));
+        assert_eq!(Adsr::from_millis(10.0, 0.0, 2.0, 1.0, EnvelopeCurve::Swell, 1000.0).sustain, 1.0);
+        assert_eq!(curve_progress(EnvelopeCurve::Exponential, 0.5), 0.875);
+        assert_eq!(curve_progress(EnvelopeCurve::Swell, 0.5), 0.125);
+    }
+
+    #[test]
+    fn test_release_tail_crossfades_to_the_tail_and_plays_it_once() {
+        let buffer: Vec<f32> = [[1.0; 8], [0.5; 8]].concat();
+        let mut voice = Voice::new(buffer.into());
+        voice.fade_in_active = false;
+        voice.release_tail = Some(0.5);
+        (0..2).for_each(|_| { voice.next_sample(1.0, 4, true); });
+        voice.release();
+        assert!(voice.is_released() && !voice.fade_out_active);
+        let out: Vec<f32> = std::iter::from_fn(|| voice.next_sample(1.0, 4, true)).collect();
+        // Crossfade into the tail, the rest of it once, then the end fade.
+        assert_eq!(out, vec![1.0, 0.875, 0.75, 0.625, 0.5, 0.5, 0.5, 0.5, 0.5, 0.375, 0.25, 0.125]);
+
+        // Released past the tail's start, it plays on from where it is.
+        let mut voice = Voice::new(vec![1.0; 8].into());
+        voice.release_tail = Some(0.25);
+        (0..20).for_each(|_| { voice.next_sample(1.0, 2, true); });
+        voice.release();
+        assert_eq!(voice.idx, 4);
+        assert_eq!(std::iter::from_fn(|| voice.next_sample(1.0, 2, true)).count(), 6);
+    }
+
+    #[test]
+    fn test_glide_reaches_the_note_pitch_in_time() {
+        let mut voice = Voice::new((0..64).map(|i| i as f32).collect::<Vec<_>>().into());
+        voice.fade_in_active = false;
+        voice.set_pitch(0.5);
+        voice.glide_from(-12.0, 4.0);
+        assert!((voice.rate - 2f64.powf(-11.5 / 12.0)).abs() < 1e-9);
+        (0..4).for_each(|_| { voice.next_sample(1.0, 2, true); });
+        assert_eq!(voice.glide, 0.0);
+        assert!((voice.rate - 2f64.powf(0.5 / 12.0)).abs() < 1e-9);
+
+        // A new glide mid-way starts from the pitch sounding now.
+        voice.glide_from(6.0, 4.0);
+        voice.next_sample(1.0, 2, true);
+        voice.glide_from(-6.0, 2.0);
+        assert!((voice.glide - -1.5).abs() < 1e-6 && (voice.glide_step - 0.75).abs() < 1e-6);
+        voice.glide_from(3.0, 0.0);
+        assert_eq!(voice.glide, 0.0, "no portamento jumps");
+    }
+
+    #[test]
+    fn test_mono_plays_the_last_held_note_legato() {
+        let mut pool = VoicePool::new(4, 8, 8);
+        pool.set_mono(true, true);
+        let first = pool.note_on(1, 0, Some(1), 0.5).unwrap();
+        assert_eq!((first.from_key, first.legato), (None, false));
+        first.voice.buffer = Some(vec![1.0; 8].into());
+        first.voice.fade_in_active = false;
+        let second = pool.note_on(3, 0, Some(2), 0.7).unwrap();
+        assert_eq!((second.key, second.from_key, second.legato), (3, Some(1), true));
+        assert!(!second.voice.fade_in_active, "no fade-in restart");
+        assert_eq!(keys_playing(&pool), vec![3]);
+
+        // Releasing the top note returns to the one held below it.
+        let back = pool.note_off(3, 0, Some(2)).unwrap();
+        assert_eq!((back.key, back.velocity, back.from_key, back.legato), (1, 0.5, Some(3), true));
+        assert!(pool.note_off(5, 0, None).is_none());
+        assert!(pool.note_off(1, 0, Some(1)).is_none());
+        assert!(pool.voices[0].fade_out_active);
+        let mut ended = Vec::new();
+        pool.drain_finished(|f| ended.push(f.voice_id));
+        assert_eq!(ended, vec![Some(1), Some(2)]);
+
+        // Without legato, or once released, the voice restarts.
+        pool.set_mono(true, false);
+        let next = pool.note_on(2, 0, None, 1.0).unwrap();
+        assert_eq!((next.from_key, next.legato), (Some(1), false));
+        assert!(next.voice.fade_in_active && !next.voice.fade_out_active);
+        pool.set_mono(false, true);
+        assert!(pool.held_notes.is_empty());
+        assert!(pool.note_on(2, 0, None, 1.0).is_some());
+        assert_eq!(keys_playing(&pool), vec![2, 2]);
+    }
+
+    #[test]
+    fn test_pitch_bends_follow_channels() {
+        let mut bends = PitchBends::default();
+        bends.set(0, 1.0, 2.0);
+        bends.set(3, 0.25, 48.0);
+        bends.set(16, 1.0, 2.0); // no such channel
+        assert_eq!((bends.for_channel(0), bends.for_channel(3)), (2.0, -24.0));
+        bends.mpe = true;
+        assert_eq!(bends.for_channel(3), -22.0, "member channels add the master bend");
+        assert!(bends.is_member(3) && !bends.is_member(0));
+
+        let mut pool = VoicePool::new(4, 4, 8);
+        let voice = pool.start(2, 3, None).unwrap();
+        voice.buffer = Some(vec![0.0; 8].into());
+        pool.set_tuning(2, 3, None, 0.5);
+        pool.set_tuning(1, 3, None, 7.0); // not playing: no-op
+        pool.apply_pitch(&bends);
+        assert!((pool.voices[0].rate - 2f64.powf(-21.5 / 12.0)).abs() < 1e-9);
+        assert_eq!(pool.voices[1].tuning, 0.0);
+    }
+
+    #[test]
+    fn test_velocity_curve_and_darkness() {
+        assert_eq!(velocity_gain(0.5, 0.0), 1.0);
+        assert_eq!(velocity_gain(0.5, 1.0), 0.5);
+        assert_eq!(velocity_gain(0.5, 2.0), 0.25);
+        assert_eq!(velocity_gain(1.5, 3.0), 1.0);
+        assert_eq!(velocity_darkness(1.0, 1.0), 0.0);
+        assert_eq!(velocity_darkness(0.25, 0.8), 0.6);
+        assert_eq!(tilt_weight(5, 0.0), 1.0);
+        assert!(tilt_weight(1, 1.0) > tilt_weight(2, 1.0) && tilt_weight(2, 1.0) > tilt_weight(8, 1.0));
+    }
+
+    /// Gain the tilt filter approximates for harmonic `n` (1 = fundamental)
+    /// at `darkness` (see `Voice::darkness`).
+    fn tilt_weight(n: usize, darkness: f32) -> f32 {
+        let n = n as f32;
+        let d = darkness.clamp(0.0, 1.0);
+        let re = 1.0 - d + d / (1.0 + n * n);
+        let im = d * n / (1.0 + n * n);
+        (re * re + im * im).sqrt()
+    }
+
+    #[test]
+    fn test_tilt_filter_follows_the_harmonic_weights() {
+        let period = 400;
+        for (n, darkness) in [(1, 1.0), (2, 0.5), (6, 1.0)] {
+            let sine: Vec<f32> = (0..period)
+                .map(|i| (TWO_PI * (n * i) as f32 / period as f32).sin())
+                .collect();
+            let mut voice = Voice::new(sine.into());
+            voice.fade_in_active = false;
+            voice.set_velocity(0.5, darkness, period);
+            let out: Vec<f32> = (0..20 * period).filter_map(|_| voice.next_sample(1.0, 4, true)).collect();
+            let peak = out[19 * period..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+            let expected = 0.5 * tilt_weight(n, darkness);
+            assert!((peak - expected).abs() < 0.01 * expected + 1e-3, "n {}: {} vs {}", n, peak, expected);
+        }
+    }
+
+    #[test]
+    fn test_blend_buffer_crossfades_layers() {
+        let mut voice = Voice::new(vec![1.0; 4].into());
+        voice.fade_in_active = false;
+        voice.blend_buffer = Some(vec![0.0; 2].into());
+        voice.layer_mix = 0.25;
+        let out: Vec<f32> = (0..6).filter_map(|_| voice.next_sample(1.0, 4, true)).collect();
+        assert!(out.iter().all(|&s| (s - 0.75).abs() < 1e-6), "{:?}", out);
+    }
+
+    #[test]
+    fn test_blend_layer_of_another_length_keeps_in_step() {
+        let mut voice = Voice::new((0..4).map(|i| i as f32).collect::<Vec<_>>().into());
+        voice.fade_in_active = false;
+        voice.blend_buffer = Some((0..8).map(|i| 10.0 + i as f32).collect::<Vec<_>>().into());
+        voice.layer_mix = 1.0;
+        let out: Vec<f32> = (0..4).filter_map(|_| voice.next_sample(1.0, 4, false)).collect();
+        // Half-way through the buffer is half-way through the blend layer.
+        assert_eq!(out, vec![10.0, 12.0, 14.0, 16.0]);
+        // Both end with the buffer: the one-shot note fades out from here.
+        voice.next_sample(1.0, 4, false);
+        assert!(voice.is_released());
+    }
+
+    #[test]
+    fn test_blend_layer_plays_until_the_buffer_lands() {
+        let mut voice = Voice::new(Arc::from([]));
+        voice.buffer = None;
+        voice.fade_in_active = false;
+        voice.blend_buffer = Some(vec![0.5; 8].into());
+        voice.layer_mix = 0.25;
+        assert_eq!(voice.next_sample(1.0, 4, true), Some(0.5));
+        voice.idx = 4;
+
+        // The buffer lands half-way through the note: playback goes on from
+        // half-way through it.
+        let blend = voice.blend_buffer.clone();
+        voice.set_buffers(Some(vec![1.0; 4].into()), blend);
+        assert_eq!((voice.idx, voice.frac), (2, 0.0));
+        assert_eq!(voice.next_sample(1.0, 4, true), Some(0.875));
+    }
+
+    #[test]
+    fn test_mix_gains_keep_headroom() {
+        assert_eq!(mix_gains(0), (1.0, 1.0));
+        assert_eq!(mix_gains(1), (0.8, 1.0));
+        // A single voice never ends up louder than it would be alone.
+        for n in 1..=16 {
+            let (voice, master) = mix_gains(n);
+            assert!(voice * master <= 0.8 + 1e-6, "{} voices", n);
+        }
+    }
+
     #[test]
     fn test_voice_clone() {
-        let original = Voice::new(vec![1.0, 2.0, 3.0]);
+        let original = Voice::new(vec![1.0, 2.0, 3.0].into());
         let cloned = original.clone();
         
         assert_eq!(original.buffer, cloned.buffer);
@@ -0,0 +1,313 @@
+// Copyright 2025 Jakub Hlavnicka
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal RIFF/WAVE reader so Analysis mode can load audio from disk without
+//! a custom host. Handles integer PCM (8/16/24/32-bit), IEEE float (32/64-bit)
+//! and their `WAVE_FORMAT_EXTENSIBLE` variants; any channel count is
+//! downmixed to mono, which is all the analyser consumes. The writer side
+//! produces mono 32-bit float files for offline renders and wavetables.
+
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Decoded audio, downmixed to mono.
+#[derive(Debug, Clone)]
+pub struct WavData {
+    pub samples: Vec<f32>,
+    pub sample_rate: f32,
+    /// Channel count of the file before downmixing.
+    pub channels: u16,
+    /// Wavetable frame length from a `clm ` chunk, if the file has one.
+    pub cycle_len: Option<usize>,
+}
+
+impl WavData {
+    pub fn duration_secs(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate
+    }
+
+    /// Samples between `start_secs` and `end_secs`, clamped to the file;
+    /// `end_secs <= start_secs` means "to the end".
+    pub fn range(&self, start_secs: f32, end_secs: f32) -> &[f32] {
+        let to_index = |secs: f32| ((secs.max(0.0) * self.sample_rate) as usize).min(self.samples.len());
+        let start = to_index(start_secs);
+        let end = if end_secs > start_secs { to_index(end_secs) } else { self.samples.len() };
+        &self.samples[start..end.max(start)]
+    }
+}
+
+/// Read and decode a WAV file. Errors are human-readable, for the editor.
+pub fn read_wav(path: &Path) -> Result<WavData, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    parse_wav(&bytes)
+}
+
+/// Encode mono samples as a 32-bit IEEE float WAV file.
+pub fn encode_wav_f32(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    encode_f32_with_chunks(samples, sample_rate, &[])
+}
+
+/// Encode a wavetable: consecutive single-cycle frames of `frame_len`
+/// samples, tagged with the `clm ` chunk wavetable synths read the cycle
+/// length from (`<!>` followed by the length, as written by Serum).
+pub fn encode_wavetable_f32(frames: &[f32], frame_len: usize, sample_rate: u32) -> Vec<u8> {
+    let clm = format!("<!>{} 00000000 wavetable (LeSynth Fourier)", frame_len);
+    encode_f32_with_chunks(frames, sample_rate, &[(*b"clm ", clm.into_bytes())])
+}
+
+/// Mono 32-bit float WAV with `extra` chunks placed between `fmt ` and `data`.
+fn encode_f32_with_chunks(samples: &[f32], sample_rate: u32, extra: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let data_len = (samples.len() * 4) as u32;
+    let extra_len: usize = extra.iter().map(|(_, body)| 8 + body.len() + body.len() % 2).sum();
+    let mut out = Vec::with_capacity(44 + extra_len + samples.len() * 4);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + extra_len as u32 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&FORMAT_FLOAT.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // channels
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 4).to_le_bytes()); // byte rate
+    out.extend_from_slice(&4u16.to_le_bytes()); // block align
+    out.extend_from_slice(&32u16.to_le_bytes()); // bits per sample
+    for (id, body) in extra {
+        out.extend_from_slice(id);
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for s in samples {
+        out.extend_from_slice(&s.to_le_bytes());
+    }
+    out
+}
+
+/// Write mono samples to `path` as a 32-bit float WAV file.
+pub fn write_wav_f32(path: &Path, samples: &[f32], sample_rate: u32) -> Result<(), String> {
+    std::fs::write(path, encode_wav_f32(samples, sample_rate))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits: u16,
+}
+
+/// Decode an in-memory WAV file.
+pub fn parse_wav(bytes: &[u8]) -> Result<WavData, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a RIFF/WAVE file".to_string());
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut cycle_len = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]]) as usize;
+        let body_start = pos + 8;
+        // A truncated final chunk (common for interrupted recordings) is read
+        // up to the end of the file.
+        let body = &bytes[body_start..(body_start.saturating_add(size)).min(bytes.len())];
+        match id {
+            b"fmt " => format = Some(parse_format(body)?),
+            b"data" => data = Some(body),
+            b"clm " => cycle_len = parse_clm(body),
+            _ => {}
+        }
+        // Chunks are word-aligned: odd sizes carry a pad byte.
+        pos = body_start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    let format = format.ok_or("Missing fmt chunk")?;
+    let data = data.ok_or("Missing data chunk")?;
+    if format.channels == 0 || format.sample_rate == 0 {
+        return Err("Invalid channel count or sample rate".to_string());
+    }
+
+    let decode: fn(&[u8]) -> f32 = match (format.tag, format.bits) {
+        (FORMAT_PCM, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (FORMAT_PCM, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (FORMAT_PCM, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (FORMAT_FLOAT, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (FORMAT_FLOAT, 64) => |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        (tag, bits) => return Err(format!("Unsupported WAV encoding (format {}, {} bits)", tag, bits)),
+    };
+
+    let width = format.bits as usize / 8;
+    let channels = format.channels as usize;
+    let scale = 1.0 / channels as f32;
+    let samples = data
+        .chunks_exact(width * channels)
+        .map(|frame| frame.chunks_exact(width).map(decode).sum::<f32>() * scale)
+        .collect();
+
+    Ok(WavData {
+        samples,
+        sample_rate: format.sample_rate as f32,
+        channels: format.channels,
+        cycle_len,
+    })
+}
+
+/// Cycle length from a `clm ` chunk body: `<!>` followed by decimal digits.
+fn parse_clm(body: &[u8]) -> Option<usize> {
+    let digits = body.strip_prefix(b"<!>")?;
+    let end = digits.iter().position(|b| !b.is_ascii_digit()).unwrap_or(digits.len());
+    std::str::from_utf8(&digits[..end]).ok()?.parse().ok().filter(|&n| n > 0)
+}
+
+fn parse_format(body: &[u8]) -> Result<Format, String> {
+    if body.len() < 16 {
+        return Err("Truncated fmt chunk".to_string());
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+    let mut tag = u16_at(0);
+    if tag == FORMAT_EXTENSIBLE {
+        // The real format is the first two bytes of the sub-format GUID.
+        if body.len() < 26 {
+            return Err("Truncated WAVE_FORMAT_EXTENSIBLE header".to_string());
+        }
+        tag = u16_at(24);
+    }
+    Ok(Format {
+        tag,
+        channels: u16_at(2),
+        sample_rate: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+        bits: u16_at(14),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a WAV file around raw little-endian sample bytes.
+    fn wav_bytes(tag: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&48_000u32.to_le_bytes());
+        let block = channels as u32 * bits as u32 / 8;
+        fmt.extend_from_slice(&(48_000 * block).to_le_bytes());
+        fmt.extend_from_slice(&(block as u16).to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        if tag == FORMAT_EXTENSIBLE {
+            fmt.extend_from_slice(&22u16.to_le_bytes());
+            fmt.extend_from_slice(&bits.to_le_bytes());
+            fmt.extend_from_slice(&0u32.to_le_bytes());
+            fmt.extend_from_slice(&FORMAT_FLOAT.to_le_bytes());
+            fmt.extend_from_slice(&[0; 14]);
+        }
+
+        let mut out = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, body) in [(b"fmt ", &fmt[..]), (b"LIST", &[1u8, 2, 3][..]), (b"data", data)] {
+            out.extend_from_slice(id);
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            out.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                out.push(0);
+            }
+        }
+        let riff_size = (out.len() - 8) as u32;
+        out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn pcm16_stereo_is_downmixed() {
+        let data: Vec<u8> = [16384i16, -16384, 32767, 32767]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let wav = parse_wav(&wav_bytes(FORMAT_PCM, 2, 16, &data)).unwrap();
+        assert_eq!((wav.channels, wav.sample_rate, wav.samples.len()), (2, 48_000.0, 2));
+        assert!(wav.samples[0].abs() < 1e-6);
+        assert!((wav.samples[1] - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pcm24_and_pcm32_decode_signed_full_scale() {
+        let data24 = [0x00, 0x00, 0x80, 0xFF, 0xFF, 0x7F]; // min, max
+        let wav = parse_wav(&wav_bytes(FORMAT_PCM, 1, 24, &data24)).unwrap();
+        assert_eq!(wav.samples[0], -1.0);
+        assert!((wav.samples[1] - 1.0).abs() < 1e-6);
+
+        let data32: Vec<u8> = i32::MIN.to_le_bytes().into_iter().chain(0i32.to_le_bytes()).collect();
+        let wav = parse_wav(&wav_bytes(FORMAT_PCM, 1, 32, &data32)).unwrap();
+        assert_eq!(wav.samples, vec![-1.0, 0.0]);
+    }
+
+    #[test]
+    fn float_and_extensible_float_decode() {
+        let data: Vec<u8> = [0.25f32, -0.5].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let plain = parse_wav(&wav_bytes(FORMAT_FLOAT, 1, 32, &data)).unwrap();
+        let ext = parse_wav(&wav_bytes(FORMAT_EXTENSIBLE, 1, 32, &data)).unwrap();
+        assert_eq!(plain.samples, vec![0.25, -0.5]);
+        assert_eq!(ext.samples, plain.samples);
+    }
+
+    #[test]
+    fn float_writer_round_trips_through_the_reader() {
+        let samples = vec![0.0f32, 0.5, -0.25, 1.0];
+        let wav = parse_wav(&encode_wav_f32(&samples, 44_100)).unwrap();
+        assert_eq!((wav.channels, wav.sample_rate), (1, 44_100.0));
+        assert_eq!(wav.samples, samples);
+    }
+
+    #[test]
+    fn wavetable_carries_clm_chunk_and_still_parses() {
+        let frames = vec![0.5f32; 2 * 8];
+        let bytes = encode_wavetable_f32(&frames, 8, 44_100);
+        let clm = bytes.windows(4).position(|w| w == b"clm ").expect("clm chunk");
+        assert_eq!(&bytes[clm + 8..clm + 12], b"<!>8");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize, bytes.len() - 8);
+        let wav = parse_wav(&bytes).unwrap();
+        assert_eq!(wav.samples, frames);
+        assert_eq!(wav.cycle_len, Some(8));
+        assert_eq!(parse_wav(&encode_wav_f32(&frames, 44_100)).unwrap().cycle_len, None);
+    }
+
+    #[test]
+    fn rejects_non_wav_and_unsupported_encodings() {
+        assert!(parse_wav(b"not a wav file at all").is_err());
+        assert!(parse_wav(&wav_bytes(FORMAT_PCM, 1, 12, &[0, 0])).is_err());
+        assert!(parse_wav(&wav_bytes(0x55, 1, 16, &[0, 0])).is_err()); // MP3-in-WAV
+    }
+
+    #[test]
+    fn range_clamps_and_open_end_reads_to_the_end() {
+        let wav = WavData {
+            samples: (0..10).map(|i| i as f32).collect(),
+            sample_rate: 10.0,
+            channels: 1,
+            cycle_len: None,
+        };
+        assert_eq!(wav.range(0.2, 0.5), &[2.0, 3.0, 4.0]);
+        assert_eq!(wav.range(0.7, 0.0), &[7.0, 8.0, 9.0]);
+        assert_eq!(wav.range(0.5, 99.0).len(), 5);
+        assert!(wav.range(5.0, 0.0).is_empty());
+    }
+}